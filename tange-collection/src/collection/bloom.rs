@@ -0,0 +1,127 @@
+//! BloomFilter
+//! ---
+//! A BloomFilter records a set of keys in a fixed number of bits.  Looking up a key
+//! which was added always finds it, while a key which wasn't may be found anyway, at
+//! a rate falling as more bits are given to each key: about 1 in 50 at 8 bits per
+//! key, and 1 in 2000 at 16.
+//!
+//! Collections build them with `to_bloom`, and use them to drop values before a
+//! shuffle with `filter_bloom` and `join_with_bloom`.
+//!
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash,Hasher};
+
+/// A set of keys which may report keys it holds no record of.  Filters of the same
+/// size merge with `union`.
+/// ```rust
+///   extern crate tange_collection;
+///   use tange_collection::collection::bloom::BloomFilter;
+///
+///   let mut filter = BloomFilter::new(100, 10);
+///   for word in ["apple", "pear", "plum"].iter() {
+///       filter.insert(word);
+///   }
+///   assert!(filter.contains(&"pear"));
+///   assert_eq!(filter.n_bits(), 1024);
+/// ```
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32
+}
+
+impl BloomFilter {
+    /// Creates an empty BloomFilter sized to hold `n_keys` keys with `bits_per_key`
+    /// bits each.  The size is rounded up to a multiple of 64 bits.
+    pub fn new(n_keys: usize, bits_per_key: usize) -> Self {
+        let bits_per_key = bits_per_key.max(1);
+        let words = (n_keys.max(1) * bits_per_key).div_ceil(64);
+
+        // k = ln(2) * bits per key minimizes the false positive rate
+        let hashes = ((bits_per_key as f64 * 0.69).round() as u32).clamp(1, 16);
+        BloomFilter { bits: vec![0; words], hashes }
+    }
+
+    /// Returns the number of bits in the filter
+    pub fn n_bits(&self) -> usize {
+        self.bits.len() * 64
+    }
+
+    /// Returns the number of bits set for each key
+    pub fn n_hashes(&self) -> u32 {
+        self.hashes
+    }
+
+    /// Adds a key to the filter
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) {
+        self.insert_hash(hash_key(key));
+    }
+
+    /// Returns false if the key was never added, and true if it may have been
+    pub fn contains<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.contains_hash(hash_key(key))
+    }
+
+    /// Adds every key of `other` to the filter.  Panics if the filters differ in size.
+    pub fn union(&mut self, other: &BloomFilter) {
+        assert!(self.bits.len() == other.bits.len() && self.hashes == other.hashes,
+                "can't merge BloomFilters of different sizes");
+        for (word, o) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word |= *o;
+        }
+    }
+
+    // Adds a key already hashed with `hash_key`
+    pub(crate) fn insert_hash(&mut self, hash: u64) {
+        for bit in self.bit_indices(hash) {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+
+    fn contains_hash(&self, hash: u64) -> bool {
+        self.bit_indices(hash).all(|bit| self.bits[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+
+    // Derives each of the key's bits from the two halves of its hash
+    fn bit_indices(&self, hash: u64) -> impl Iterator<Item=usize> {
+        let n_bits = self.n_bits() as u64;
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % n_bits) as usize)
+    }
+}
+
+// Hashes a key for a BloomFilter or a HyperLogLog, the same way in every process
+pub(crate) fn hash_key<K: Hash + ?Sized>(key: &K) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test_bloom {
+    use super::*;
+
+    #[test]
+    fn test_bloom() {
+        let mut evens = BloomFilter::new(1000, 10);
+        let mut odds = BloomFilter::new(1000, 10);
+        for i in 0..1000usize {
+            if i % 2 == 0 { evens.insert(&i) } else { odds.insert(&i) }
+        }
+        assert!((0..1000usize).step_by(2).all(|i| evens.contains(&i)));
+        let false_positives = (1..1000usize).step_by(2).filter(|i| evens.contains(i)).count();
+        assert!(false_positives < 25, "{}", false_positives);
+
+        evens.union(&odds);
+        assert!((0..1000usize).all(|i| evens.contains(&i)));
+        assert_eq!(evens.n_hashes(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't merge BloomFilters of different sizes")]
+    fn test_union_sizes() {
+        let mut filter = BloomFilter::new(10, 8);
+        filter.union(&BloomFilter::new(100, 8));
+    }
+}
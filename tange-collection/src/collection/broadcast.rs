@@ -0,0 +1,58 @@
+//! Broadcast
+//! ---
+//! Broadcast holds a value every partition of a collection reads, such as a large
+//! lookup table, created with `Broadcast::new` and read with
+//! `MemoryCollection::map_broadcast`.  The value is kept once and handed to each
+//! task by reference, rather than copied into every clone of the closure using it.
+//!
+
+use std::any::Any;
+use std::sync::Arc;
+
+use tange::deferred::Deferred;
+
+/// A value shared by the tasks of a run without being copied.  Clones share the
+/// value.
+/// ```rust
+///   extern crate tange;
+///   extern crate tange_collection;
+///   use std::collections::HashMap;
+///   use tange::scheduler::GreedyScheduler;
+///   use tange_collection::collection::broadcast::Broadcast;
+///   use tange_collection::collection::memory::MemoryCollection;
+///   
+///   let names: HashMap<usize, &str> = vec![(1, "one"), (2, "two")].into_iter().collect();
+///   let names = Broadcast::new(names);
+///   let col = MemoryCollection::from_vec_chunked(vec![1, 2, 1usize], 2);
+///   let named = col.map_broadcast(&names, |x, names| names[x].to_owned());
+///   assert_eq!(named.run(&GreedyScheduler::new()), Some(vec!["one".into(), "two".into(), "one".into()]));
+///   assert_eq!(names.value().len(), 2);
+/// ```
+#[derive(Clone)]
+pub struct Broadcast<T> {
+    value: Arc<T>,
+    deferred: Deferred<Arc<T>>
+}
+
+impl <T: Any + Send + Sync> Broadcast<T> {
+    /// Creates a Broadcast holding `value`
+    pub fn new(value: T) -> Self {
+        Broadcast::from_arc(Arc::new(value))
+    }
+
+    /// Creates a Broadcast holding a value which is already shared
+    pub fn from_arc(value: Arc<T>) -> Self {
+        let deferred = Deferred::lift(value.clone(), "Broadcast".into());
+        Broadcast { value, deferred }
+    }
+
+    /// Returns the value
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    // The step tasks read the value from
+    pub(crate) fn deferred(&self) -> &Deferred<Arc<T>> {
+        &self.deferred
+    }
+}
@@ -0,0 +1,133 @@
+//! CountMinSketch
+//! ---
+//! A CountMinSketch counts how many times each key was added, in `depth` rows of
+//! `width` counters however many keys there are.  Each key adds to one counter in
+//! every row, and its estimate is the smallest of them: other keys sharing those
+//! counters can only add to it, so estimates never fall below the true count.  With
+//! probability 1 - e^-depth an estimate is over by no more than e / width of the
+//! total count, so about 0.3% of it at a width of 1000.
+//!
+//! Sketches of the same size merge by adding their counters, which is how
+//! `heavy_hitters` combines the sketches of each partition.
+//!
+
+use std::hash::Hash;
+
+use super::bloom::hash_key;
+
+/// Estimates how many times each key was added to it, never underestimating.
+/// ```rust
+///   extern crate tange_collection;
+///   use tange_collection::collection::countmin::CountMinSketch;
+///
+///   let mut sketch = CountMinSketch::new(1000, 4);
+///   for i in 0..10000usize {
+///       sketch.insert(&(i % 100), 1);
+///   }
+///   let estimate = sketch.estimate(&7usize);
+///   assert!(estimate >= 100 && estimate < 150);
+///   assert_eq!(sketch.total(), 10000);
+/// ```
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct CountMinSketch {
+    width: usize,
+    counters: Vec<u64>,
+    total: u64
+}
+
+impl CountMinSketch {
+    /// Creates an empty CountMinSketch of `depth` rows with `width` counters each
+    pub fn new(width: usize, depth: usize) -> Self {
+        let (width, depth) = (width.max(1), depth.max(1));
+        CountMinSketch { width, counters: vec![0; width * depth], total: 0 }
+    }
+
+    /// Returns the number of counters in each row
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows
+    pub fn depth(&self) -> usize {
+        self.counters.len() / self.width
+    }
+
+    /// Returns the sum of the counts added
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Adds `count` to the key's counters
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K, count: u64) {
+        for idx in self.counter_indices(hash_key(key)) {
+            self.counters[idx] += count;
+        }
+        self.total += count;
+    }
+
+    /// Returns the estimated count of the key, which may be more than was added but
+    /// never less
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        self.counter_indices(hash_key(key)).map(|idx| self.counters[idx]).min().unwrap_or(0)
+    }
+
+    /// Adds every count of `other` to the sketch.  Panics if the sketches differ in
+    /// size.
+    pub fn merge(&mut self, other: &CountMinSketch) {
+        assert!(self.width == other.width && self.counters.len() == other.counters.len(),
+                "can't merge CountMinSketches of different sizes");
+        for (c, o) in self.counters.iter_mut().zip(other.counters.iter()) {
+            *c += *o;
+        }
+        self.total += other.total;
+    }
+
+    // Derives the key's counter in each row from the two halves of its hash, as
+    // BloomFilter does
+    fn counter_indices(&self, hash: u64) -> impl Iterator<Item=usize> {
+        let width = self.width as u64;
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..self.depth() as u64).map(move |row| {
+            (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_countmin {
+    use super::*;
+
+    #[test]
+    fn test_countmin() {
+        let mut sketch = CountMinSketch::new(200, 5);
+        for i in 0..5000usize {
+            sketch.insert(&(i % 1000), 1);
+        }
+        sketch.insert("heavy", 3000);
+        assert_eq!((sketch.width(), sketch.depth(), sketch.total()), (200, 5, 8000));
+        assert!((0..1000usize).all(|i| sketch.estimate(&i) >= 5));
+        let over = (0..1000usize).map(|i| sketch.estimate(&i) - 5).max().unwrap();
+        assert!(over < 8000 * 3 / 200, "{}", over);
+        assert!(sketch.estimate("heavy") >= 3000 && sketch.estimate("heavy") < 3000 + 120);
+        assert_eq!(CountMinSketch::new(200, 5).estimate(&1), 0);
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut left, mut right) = (CountMinSketch::new(64, 3), CountMinSketch::new(64, 3));
+        let mut both = CountMinSketch::new(64, 3);
+        for i in 0..3000usize {
+            if i < 2000 { left.insert(&(i % 70), 2) } else { right.insert(&(i % 70), 2) }
+            both.insert(&(i % 70), 2);
+        }
+        left.merge(&right);
+        assert_eq!(left, both);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't merge CountMinSketches of different sizes")]
+    fn test_merge_sizes() {
+        let mut sketch = CountMinSketch::new(64, 3);
+        sketch.merge(&CountMinSketch::new(64, 4));
+    }
+}
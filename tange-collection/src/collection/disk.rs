@@ -11,30 +11,52 @@
 //!
 
 extern crate serde;
-use std::fs;
+#[cfg(feature = "regex")]
+extern crate regex;
 use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{HashMap,HashSet};
+use std::fmt;
 use std::io::prelude::*;
-use std::io::BufWriter;
+use std::iter::{self,Product,Sum};
+use std::io;
 use std::hash::Hash;
+use std::ops::{Add,Mul};
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use std::future::Future;
 
 use self::serde::Deserialize;
 use self::serde::Serialize;
+#[cfg(feature = "regex")]
+use self::regex::Regex;
 
-use tange::deferred::{Deferred, batch_apply, tree_reduce};
-use tange::scheduler::Scheduler;
+use tange::deferred::{Deferred, batch_apply, gather, tree_reduce};
+use tange::scheduler::{Scheduler,Progress,CancellationToken,Cancelled,ConcurrencyLimit,RunError,TimeoutError};
+#[cfg(feature = "tokio")]
+use tange::scheduler::AsyncScheduler;
 
+use collection::bloom::BloomFilter;
+use collection::hyperloglog::HyperLogLog;
+use collection::broadcast::Broadcast;
+use collection::fallible::ParseError;
 use collection::memory::MemoryCollection;
-use partitioned::{join_on_key as jok, partition, partition_by_key, fold_by, concat};
+use partitioned::{join_on_key as jok, partition, partition_by_key, fold_by, fold_local, combine_by_key, combine_local, group_local, rolling_local, cross_where, concat, rebalance, split_with};
 use interfaces::*;
-use super::emit;
+use random::RandomOptions;
+use super::{Encoding,Evaluate,FileNaming,Integer,Partitioning,PartWriter,PlanNode,PlanStats,StageKind};
+use super::{all,cache_root,cached_parts,cancelled_or_panic,checkpoint,collect_groups,dedup_sorted,emit,filter_with,fold_monoid,heavy_hitters,is_sorted_by,join_strings,keep_top,kth,key_id,key_shares,KeyRoutes,map_with,parse,salt,sample,sample_with_replacement,to_bloom,split_results,tag_order,tag_random,unzip,validate,validate_warn,zip,finish_sink,fmt_plan,stage_name,sink_bincode,stored_parts,target_dir};
 
 
 /// DiskCollection struct.
 #[derive(Clone)]
 pub struct DiskCollection<A: Clone + Send + Sync>  {
     path: Arc<String>,
-    partitions: Vec<Deferred<Arc<FileStore<A>>>>
+    partitions: Vec<Deferred<Arc<FileStore<A>>>>,
+    plan: Arc<PlanNode>
 }
 
 impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskCollection<A> {
@@ -53,6 +75,13 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
         MemoryCollection::from_vec(vec).to_disk(path)
     }
 
+    /// Create a new DiskCollection from a Vector of objects, split into
+    /// `n_partitions` contiguous partitions of nearly equal size, as with
+    /// `MemoryCollection::from_vec_chunked`.
+    pub fn from_vec_chunked(path: String, vec: Vec<A>, n_partitions: usize) -> DiskCollection<A> {
+        MemoryCollection::from_vec_chunked(vec, n_partitions).to_disk(path)
+    }
+
     /// Converts a collection of Deferred objects into a DiskCollection
     /// This is usually best used from the `MemoryCollection`
     pub fn from_memory(path: String, mc: &Vec<Deferred<Vec<A>>>) -> DiskCollection<A> {
@@ -62,12 +91,31 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
         let defs = batch_apply(&mc, move |_idx, vs| {
             acc.write_vec(vs.clone())
         });
-        DiskCollection { path: shared, partitions: defs }
+        let plan = PlanNode::new("from_memory", StageKind::Source, defs.len(), Vec::new());
+        DiskCollection { path: shared, partitions: defs, plan }
+    }
+
+    /// Creates a DiskCollection with no partitions, spilling anything derived from
+    /// it into `path`, as with `MemoryCollection::empty`.
+    pub fn empty(path: String) -> DiskCollection<A> {
+        let plan = PlanNode::new("empty", StageKind::Source, 0, Vec::new());
+        DiskCollection { path: Arc::new(path), partitions: Vec::new(), plan }
     }
 
     /// Creats a DiskCollection for a set of FileStores.
     pub fn from_stores(path: String, fs: Vec<Deferred<Arc<FileStore<A>>>>) -> DiskCollection<A> {
-        DiskCollection { path: Arc::new(path), partitions: fs }
+        let plan = PlanNode::new("from_stores", StageKind::Source, fs.len(), Vec::new());
+        DiskCollection { path: Arc::new(path), partitions: fs, plan }
+    }
+
+    /// Loads files of bincode records written by `sink_bincode`, or by Disk
+    /// accumulators, from the directory `root`, with one partition per file, as
+    /// with `MemoryCollection::from_stored`.  The files are left in place when the
+    /// collection is dropped.
+    pub fn from_stored(root: &str) -> io::Result<DiskCollection<A>> {
+        let partitions = stored_parts(root)?;
+        let plan = PlanNode::new("from_stored", StageKind::Source, partitions.len(), Vec::new());
+        Ok(DiskCollection { path: Arc::new(root.into()), partitions, plan })
     }
 
     /// Provides raw access to the underlying partitions
@@ -75,12 +123,58 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
         &self.partitions
     }
 
+    /// Runs the collection, writing each partition into a file within `root`, and
+    /// returns a collection with the same data and partitions backed by those
+    /// files.  Work done downstream of the checkpoint starts from the files rather
+    /// than recomputing everything before it, however many times it's run.  The
+    /// files are removed once the returned collection, and anything built from it,
+    /// is dropped.
+    pub fn checkpoint<S: Scheduler>(&self, root: &str, s: &S) -> io::Result<DiskCollection<A>> {
+        let stores = checkpoint(&self.partitions, root, s)?;
+        let partitions: Vec<_> = stores.into_iter().map(|store| Deferred::lift(store, None)).collect();
+        let plan = PlanNode::new("checkpoint", StageKind::Source, partitions.len(), Vec::new());
+        Ok(DiskCollection { path: Arc::new(root.into()), partitions, plan })
+    }
+
+    /// Keeps the partitions of the collection in a cache directory under `key`, so
+    /// later runs, including those of other processes, read them back rather than
+    /// running anything upstream, as with `MemoryCollection::cached`.
+    pub fn cached(&self, key: &str) -> io::Result<DiskCollection<A>> {
+        self.cached_in(cache_root(), key)
+    }
+
+    /// Keeps the partitions of the collection under `key` within `root`, as with
+    /// `cached`
+    pub fn cached_in<P: Into<PathBuf>>(&self, root: P, key: &str) -> io::Result<DiskCollection<A>> {
+        let partitions = cached_parts(&self.partitions, &root.into(), key)?;
+        Ok(self.derive("cached", StageKind::ElementWise, partitions))
+    }
+
+    /// Keeps the files holding each partition once a run writes them, so later runs
+    /// of this collection or of anything built from it read them rather than
+    /// running anything upstream again, as with `MemoryCollection::cache`.
+    /// Temporary files are removed once the cached collection and everything built
+    /// from it are dropped.
+    pub fn cache(&self) -> DiskCollection<A> {
+        let partitions = self.partitions.iter().map(|d| d.cache()).collect();
+        self.derive("cache", StageKind::ElementWise, partitions)
+    }
+
+    /// Computes no more than `n` partitions of the collection at once, as with
+    /// `MemoryCollection::with_max_concurrency`
+    pub fn with_max_concurrency(&self, n: usize) -> DiskCollection<A> {
+        let limit = ConcurrencyLimit::new(n);
+        let partitions = self.partitions.iter().map(|d| d.with_limit(&limit)).collect();
+        DiskCollection { path: self.path.clone(), partitions, plan: self.plan.clone() }
+    }
+
     /// Converts a DiskCollection to a MemoryCollection
     pub fn to_memory(&self) -> MemoryCollection<A> {
         let defs = batch_apply(&self.partitions, |_idx, vs| {
-            vs.stream().into_iter().collect()
+            stream_or_panic(vs).into_iter().collect()
         });
         MemoryCollection::from_defs(defs)
+            .with_plan("to_memory", StageKind::ElementWise, vec![self.plan.clone()])
     }
 
     /// Returns the current number of data partitions 
@@ -88,8 +182,34 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
         self.partitions.len()
     }
 
+    /// Summarizes the stages the collection is built from, as with
+    /// `MemoryCollection::explain`.
+    pub fn explain(&self) -> String {
+        self.plan.explain()
+    }
+
+    /// Measures the plan behind the collection without running any of it, as with
+    /// `MemoryCollection::dry_run`.
+    pub fn dry_run(&self) -> PlanStats {
+        let nodes: Vec<_> = self.partitions.iter().map(|p| p.node()).collect();
+        self.plan.stats(&nodes)
+    }
+
     fn from_defs<B: Clone + Send + Sync>(&self, defs: Vec<Deferred<Arc<FileStore<B>>>>) -> DiskCollection<B> {
-        DiskCollection { path: self.path.clone(), partitions: defs }
+        let plan = PlanNode::new("from_defs", StageKind::Source, defs.len(), Vec::new());
+        DiskCollection { path: self.path.clone(), partitions: defs, plan }
+    }
+
+    // Builds the collection produced from this one by a stage
+    fn derive<B: Any + Clone + Send + Sync>(
+        &self, 
+        op: &str, 
+        kind: StageKind, 
+        defs: Vec<Deferred<Arc<FileStore<B>>>>
+    ) -> DiskCollection<B> {
+        self.from_defs(defs)
+            .with_plan(op, kind, vec![self.plan.clone()])
+            .stage(op)
     }
 
     /// Concatentates two collections into a single Collection
@@ -113,6 +233,47 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
         }
 
         self.from_defs(nps)
+            .with_plan("concat", StageKind::Union, vec![self.plan.clone(), other.plan.clone()])
+    }
+
+    /// Pairs every value with the value in the same place of `other`, partition by
+    /// partition, as with `MemoryCollection::zip`.
+    pub fn zip<
+        B: Any + Send + Sync + Clone + Serialize + for<'de> Deserialize<'de>
+    >(&self, other: &DiskCollection<B>) -> DiskCollection<(A, B)> {
+        assert_eq!(self.n_partitions(), other.n_partitions(), 
+                   "can't zip collections with different numbers of partitions");
+        let parts = zip(&self.partitions, &other.partitions, Disk(self.path.clone()));
+        self.from_defs(parts)
+            .with_plan("zip", StageKind::ElementWise, vec![self.plan.clone(), other.plan.clone()])
+            .stage("zip")
+    }
+
+    /// Concatenates two collections and redistributes the values into `n_partitions`
+    /// partitions of nearly equal size, keeping their order, as with
+    /// `MemoryCollection::concat_rebalanced`.
+    pub fn concat_rebalanced(&self, other: &DiskCollection<A>, n_partitions: usize) -> DiskCollection<A> {
+        let cat = self.concat(other);
+        let parts = rebalance(&cat.partitions, n_partitions);
+        cat.derive("concat_rebalanced", StageKind::Shuffle, parts)
+    }
+
+    /// Concatenates any number of collections into a single Collection in one pass,
+    /// as with `MemoryCollection::concat_all`.  No collections give an empty
+    /// Collection spilling into `path`.
+    pub fn concat_all(path: String, collections: &[DiskCollection<A>]) -> DiskCollection<A> {
+        match collections.len() {
+            0 => DiskCollection::empty(path),
+            1 => collections[0].clone(),
+            _ => {
+                let nps = collections.iter()
+                    .flat_map(|c| c.partitions.iter().cloned())
+                    .collect();
+                let plans = collections.iter().map(|c| c.plan.clone()).collect();
+                collections[0].from_defs(nps)
+                    .with_plan("concat_all", StageKind::Union, plans)
+            }
+        }
     }
     
     /// Maps a function over the values in the DiskCollection, returning a new DiskCollection
@@ -133,7 +294,43 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
     >(&self, f: F) -> DiskCollection<B> {
         self.emit(move |x, emitter| {
             emitter(f(x))
-        })
+        }).stage("map")
+    }
+
+    /// Maps a function over the values in the collection along with every value of
+    /// `other`, gathered into one Vec, as with `MemoryCollection::map_with`.
+    pub fn map_with<
+        B: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>,
+        C: Any + Send + Sync + Clone + Serialize,
+        F: 'static + Sync + Send + Clone + Fn(&A, &Vec<B>) -> C
+    >(&self, other: &DiskCollection<B>, f: F) -> DiskCollection<C> {
+        let parts = map_with(&self.partitions, &other.gathered(), Disk(self.path.clone()), f);
+        self.from_defs(parts)
+            .with_plan("map_with", StageKind::ElementWise, vec![self.plan.clone(), other.plan.clone()])
+            .stage("map_with")
+    }
+
+    /// Maps a function over the values in the collection along with the value held
+    /// by `b`, as with `MemoryCollection::map_broadcast`.
+    pub fn map_broadcast<
+        T: Any + Send + Sync,
+        B: Any + Send + Sync + Clone + Serialize,
+        F: 'static + Sync + Send + Clone + Fn(&A, &T) -> B
+    >(&self, b: &Broadcast<T>, f: F) -> DiskCollection<B> {
+        let parts = map_with(&self.partitions, b.deferred(), Disk(self.path.clone()), move |x, v: &Arc<T>| f(x, v));
+        self.derive("map_broadcast", StageKind::ElementWise, parts)
+    }
+
+    /// Maps a fallible function over the values in the collection, keeping the
+    /// Result for each, as with `MemoryCollection::try_map`.
+    pub fn try_map<
+        B: Any + Send + Sync + Clone + Serialize, 
+        E: Any + Send + Sync + Clone + Serialize, 
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<B, E>
+    >(&self, f: F) -> DiskCollection<Result<B, E>> {
+        self.emit(move |x, emitter| {
+            emitter(f(x))
+        }).stage("try_map")
     }
 
     /// Filters out items in the collection that fail the predicate.
@@ -156,7 +353,39 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
             if f(x) { 
                 emitter(x.clone())
             }
-        })
+        }).stage("filter")
+    }
+
+    /// Checks every value with `f`, failing the run with a report of the values
+    /// rejected if there are any, as with `MemoryCollection::validate`.
+    pub fn validate<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+    >(&self, f: F) -> DiskCollection<A> {
+        let parts = validate(&self.partitions, f);
+        self.derive("validate", StageKind::ElementWise, parts)
+    }
+
+    /// Checks every value with `f`, only logging the values rejected, as with
+    /// `MemoryCollection::validate_warn`.
+    pub fn validate_warn<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+    >(&self, f: F) -> DiskCollection<A> {
+        let parts = validate_warn(&self.partitions, f);
+        self.derive("validate_warn", StageKind::ElementWise, parts)
+    }
+
+    /// Keeps each value with a chance of `fraction`, independently of the others,
+    /// as with `MemoryCollection::sample`.
+    pub fn sample(&self, fraction: f64, opts: RandomOptions) -> DiskCollection<A> {
+        let parts = sample(&self.partitions, Disk(self.path.clone()), fraction, opts.master_seed());
+        self.derive("sample", StageKind::ElementWise, parts)
+    }
+
+    /// Draws exactly `n` values uniformly at random from the whole collection, with
+    /// replacement, as with `MemoryCollection::sample_with_replacement`.
+    pub fn sample_with_replacement(&self, n: usize, opts: RandomOptions) -> DiskCollection<A> {
+        let parts = sample_with_replacement(&self.partitions, Disk(self.path.clone()), n, opts.master_seed());
+        self.derive("sample_with_replacement", StageKind::ElementWise, parts)
     }
     
     /// Re-partitions a collection by the number of provided chunks.  It uniformly distributes data from each old partition into each new partition.
@@ -175,7 +404,7 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
     /// ```
 
     pub fn split(&self, n_chunks: usize) -> DiskCollection<A> {
-        self.partition(n_chunks, |idx, _k| idx)
+        self.partition(n_chunks, |idx, _k| idx).stage("split")
     }
 
     /// Maps over all items in a collection, optionally emitting new values.  It can be used
@@ -201,7 +430,7 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
 
         let parts = emit(&self.partitions, Disk(self.path.clone()), f);
 
-        self.from_defs(parts)
+        self.derive("emit", StageKind::ElementWise, parts)
     }
 
     /// Re-partitions data into N new partitions by the given function.  The user provided
@@ -227,7 +456,7 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
                                    partitions, 
                                    f);
         // Loop over each bucket
-        self.from_defs(new_chunks)
+        self.derive("partition", StageKind::Shuffle, new_chunks)
     }
 
     /// Folds and accumulates values across multiple partitions into K new partitions.
@@ -236,7 +465,8 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
     /// DiskCollection first performs a block aggregation: that is, it combines values
     /// within each partition first using the `binop` function.  It then hashes
     /// each key to a new partition index, where it will then aggregate all keys using the
-    /// `reduce` function.
+    /// `reduce` function.  `partitions` is a count or a `Partitioning`; if the collection
+    /// is already partitioned by `key` into that many partitions, the shuffle is skipped.
     ///
     /// ```rust
     ///   extern crate tange;
@@ -262,17 +492,82 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
                    D: 'static + Sync + Send + Clone + Fn() -> B,
                    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
                    O: 'static + Sync + Send + Clone + Fn(&mut B, &A) -> (),
-                   R: 'static + Sync + Send + Clone + Fn(&mut B, &B) -> ()>(
-        &self, key: F, default: D, binop: O, reduce: R, partitions: usize
+                   R: 'static + Sync + Send + Clone + Fn(&mut B, &B) -> (),
+                   P: Into<Partitioning>>(
+        &self, key: F, default: D, binop: O, reduce: R, partitions: P
     ) -> DiskCollection<(K,B)> {
+        let partitions = partitions.into().count(self.partitions.len());
         let fs = Arc::new(FileStore::empty(self.path.clone()));
+        if self.plan.is_hashed(key_id(&key), partitions) {
+            let results = fold_local(&self.partitions, key, default, binop, fs);
+            return self.derive("fold_by", StageKind::ElementWise, results);
+        }
         let results = fold_by(&self.partitions, key, default, binop, 
                               reduce, fs, partitions);
-        self.from_defs(results)
+        self.derive("fold_by", StageKind::Shuffle, results)
+    }
+
+    /// Folds values by key, salting the keys of each partition to spread them over
+    /// `salts` partitions first, as with `MemoryCollection::fold_by_salted`.  The
+    /// result matches `fold_by` only if `reduce` is associative and commutative.
+    pub fn fold_by_salted<K: Any + Sync + Send + Clone + Hash + Eq + Serialize + for<'de> Deserialize<'de>,
+                          B: Any + Sync + Send + Clone + Serialize + for<'de> Deserialize<'de>,
+                          D: 'static + Sync + Send + Clone + Fn() -> B,
+                          F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+                          O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+                          R: 'static + Sync + Send + Clone + Fn(&mut B, &B),
+                          P: Into<Partitioning>>(
+        &self, salts: usize, key: F, default: D, binop: O, reduce: R, partitions: P
+    ) -> DiskCollection<(K,B)> {
+        let partitions = partitions.into().count(self.partitions.len());
+        let salted = salt(&self.partitions, Disk(self.path.clone()), salts.max(1));
+        let (r1, r2) = (reduce.clone(), reduce.clone());
+        self.derive("fold_by_salted", StageKind::ElementWise, salted)
+            .fold_by(move |x| (key(&x.1), x.0), default, move |acc, x| binop(acc, &x.1), reduce, partitions)
+            .combine_by_key(|x| x.0 .0.clone(), |x| x.1.clone(), move |acc, x| r1(acc, &x.1), r2, partitions)
+            .stage("fold_by_salted")
+    }
+
+    /// Combines the values of each key into a combiner created from the key's first
+    /// value, as with `MemoryCollection::combine_by_key`.
+    pub fn combine_by_key<
+        K: Any + Sync + Send + Clone + Hash + Eq + Serialize + for<'de> Deserialize<'de>,
+        C: Any + Sync + Send + Clone + Serialize + for<'de> Deserialize<'de>,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        I: 'static + Sync + Send + Clone + Fn(&A) -> C,
+        O: 'static + Sync + Send + Clone + Fn(&mut C, &A),
+        R: 'static + Sync + Send + Clone + Fn(&mut C, &C),
+        P: Into<Partitioning>
+    >(
+        &self, key: F, create: I, merge_value: O, merge_combiners: R, partitions: P
+    ) -> DiskCollection<(K,C)> {
+        let partitions = partitions.into().count(self.partitions.len());
+        let fs = Arc::new(FileStore::empty(self.path.clone()));
+        if self.plan.is_hashed(key_id(&key), partitions) {
+            let results = combine_local(&self.partitions, key, create, merge_value, fs);
+            return self.derive("combine_by_key", StageKind::ElementWise, results);
+        }
+        let results = combine_by_key(&self.partitions, key, create, merge_value,
+                                     merge_combiners, fs, partitions);
+        self.derive("combine_by_key", StageKind::Shuffle, results)
+    }
+
+    /// Groups the values of the collection by a key, hashed by key into
+    /// `partitions`, as with `MemoryCollection::group_by`.
+    pub fn group_by<
+        K: Any + Sync + Send + Clone + Hash + Eq + Serialize + for<'de> Deserialize<'de>,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        P: Into<Partitioning>
+    >(&self, key: F, partitions: P) -> DiskCollection<(K, Vec<A>)> {
+        let hashed = self.partition_by_key(partitions, key.clone());
+        let groups = group_local(&hashed.partitions, key, |x| x, Disk(self.path.clone()));
+        hashed.derive("group_by", StageKind::ElementWise, groups)
     }
 
     /// Simple function to re-partition values by a given key.  The return key is hashed
-    /// and moduloed by the new partition count to determine where it will end up.
+    /// and moduloed by the new partition count to determine where it will end up.  The
+    /// result remembers `key`, when it captures nothing, so keyed operations on the same
+    /// key need not shuffle again; see `Partitioning`.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
@@ -288,11 +583,34 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
 
     pub fn partition_by_key<
         K: Any + Sync + Send + Clone + Hash + Eq,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        P: Into<Partitioning>
+    >(&self, partitions: P, key: F) -> DiskCollection<A> {
+        let partitions = partitions.into().count(self.partitions.len());
+        let id = key_id(&key);
+        let col = if self.plan.is_hashed(id, partitions) {
+            self.derive("partition_by_key", StageKind::ElementWise, self.partitions.clone())
+        } else {
+            let results = partition_by_key(&self.partitions, partitions, key);
+            let groups = results.into_iter().filter_map(|part| concat(&part)).collect();
+            self.derive("partition_by_key", StageKind::Shuffle, groups)
+        };
+        DiskCollection { plan: col.plan.hashed(id), ..col }
+    }
+
+    // Pairs each value with its key, hashed by the key into `partitions` unless the
+    // values already are
+    fn keyed<
+        K: Any + Sync + Send + Clone + Hash + Eq + Serialize + for<'de> Deserialize<'de>,
         F: 'static + Sync + Send + Clone + Fn(&A) -> K
-    >(&self, n_chunks: usize, key: F) -> DiskCollection<A> {
-        let results = partition_by_key(&self.partitions, n_chunks, key);
-        let groups = results.into_iter().map(|part| concat(&part).unwrap()).collect();
-        self.from_defs(groups)
+    >(&self, key: F, partitions: usize) -> DiskCollection<(K, A)> {
+        let hashed = self.plan.is_hashed(key_id(&key), partitions);
+        let pairs = self.map(move |x| (key(x), x.clone()));
+        if hashed {
+            pairs
+        } else {
+            pairs.partition_by_key(partitions, |x| x.0.clone())
+        }
     }
 
     /// Sorts values within each partition by a key function.  If a global sort is desired,
@@ -315,19 +633,42 @@ pub fn sort_by<
         let acc = Arc::new(FileStore::empty(self.path.clone()));
         let nps = batch_apply(&self.partitions, move |_idx, vs| {
             let mut out = acc.writer();
-            let mut v2: Vec<_> = vs.stream().into_iter().collect();
+            let mut v2: Vec<_> = stream_or_panic(vs).into_iter().collect();
             v2.sort_by_key(|v| key(v));
             for vi in v2 {
                 out.add(vi);
             }
             out.finish()
         });
-        self.from_defs(nps)
+        self.derive("sort_by", StageKind::ElementWise, nps)
+    }
+
+    /// Runs a check that the collection is sorted by a key function across all of
+    /// its partitions, as with `MemoryCollection::is_sorted_by`.
+    pub fn is_sorted_by<
+        K: Any + Send + Sync + Clone + Ord,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        S: Scheduler
+    >(&self, key: F, s: &S) -> Option<bool> {
+        is_sorted_by(&self.partitions, key).run(s)
+    }
+
+    /// Drops each value with the same key as the value before it, across partitions,
+    /// as with `MemoryCollection::dedup_sorted`.  Only adjacent duplicates are
+    /// dropped from input not sorted by the key.
+    pub fn dedup_sorted<
+        K: Any + Send + Sync + Clone + PartialEq,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, key: F) -> DiskCollection<A> {
+        let parts = dedup_sorted(&self.partitions, Disk(self.path.clone()), key);
+        self.derive("dedup_sorted", StageKind::ElementWise, parts)
     }
 
     /// Inner Joins two collections by the provided key function.
     /// If multiple values of the same key are found, they will be cross product for each
-    /// pair found.
+    /// pair found.  `partitions` is a count or a `Partitioning`, with `Preserve` taking
+    /// the left collection's count.  Either side already partitioned by its key into
+    /// that many partitions is not shuffled again.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
@@ -353,19 +694,19 @@ pub fn sort_by<
         KF1: 'static + Sync + Send + Clone + Fn(&A) -> K,
         KF2: 'static + Sync + Send + Clone + Fn(&B) -> K,
         J:   'static + Sync + Send + Clone + Fn(&A, &B) -> C,
+        P:   Into<Partitioning>
     >(
         &self, 
         other: &DiskCollection<B>, 
         key1: KF1, 
         key2: KF2,
         joiner: J,
-        partitions: usize, 
+        partitions: P, 
     ) -> DiskCollection<(K,C)> {
         // Group each by a common key
-        let p1 = self.map(move |x| (key1(x), x.clone()))
-            .partition_by_key(partitions, |x| x.0.clone());
-        let p2 = other.map(move |x| (key2(x), x.clone()))
-            .partition_by_key(partitions, |x| x.0.clone());
+        let partitions = partitions.into().count(self.partitions.len());
+        let p1 = self.keyed(key1, partitions);
+        let p2 = other.keyed(key2, partitions);
 
         let mut new_parts = Vec::with_capacity(p1.partitions.len());
         for (l, r) in p1.partitions.iter().zip(p2.partitions.iter()) {
@@ -374,21 +715,187 @@ pub fn sort_by<
         }
 
         self.from_defs(new_parts)
+            .with_plan("join_on", StageKind::Shuffle, vec![p1.plan.clone(), p2.plan.clone()])
+            .stage("join_on")
+    }
+
+    /// Pairs every value of this collection with every value of `other` for which
+    /// `f` holds, calling it on every pair, as with `MemoryCollection::join_where`.
+    pub fn join_where<
+        B: Any + Send + Sync + Clone + Serialize + for<'de> Deserialize<'de>,
+        F: 'static + Sync + Send + Clone + Fn(&A, &B) -> bool
+    >(&self, other: &DiskCollection<B>, f: F) -> DiskCollection<(A, B)> {
+        let crossed = cross_where(&self.partitions, &other.partitions, Disk(self.path.clone()), f);
+        let parts = crossed.iter().filter_map(|pieces| concat(pieces)).collect();
+        self.from_defs(parts)
+            .with_plan("join_where", StageKind::Shuffle, vec![self.plan.clone(), other.plan.clone()])
+            .stage("join_where")
+    }
+
+    /// Joins two collections like `join_on`, first dropping the values of this
+    /// collection whose keys aren't found in a BloomFilter of `other`'s keys, as with
+    /// `MemoryCollection::join_with_bloom`.
+    pub fn join_with_bloom<
+        K: Any + Sync + Send + Clone + Hash + Eq + Serialize + for<'de> Deserialize<'de>,
+        B: Any + Sync + Send + Clone + Serialize + for<'de> Deserialize<'de>,
+        C: Any + Sync + Send + Clone + Serialize,
+        KF1: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        KF2: 'static + Sync + Send + Clone + Fn(&B) -> K,
+        J:   'static + Sync + Send + Clone + Fn(&A, &B) -> C,
+        P:   Into<Partitioning>
+    >(
+        &self, 
+        other: &DiskCollection<B>, 
+        key1: KF1, 
+        key2: KF2,
+        joiner: J,
+        partitions: P, 
+        bits_per_key: usize
+    ) -> DiskCollection<(K,C)> {
+        let bloom = other.to_bloom(key2.clone(), bits_per_key);
+        self.filter_bloom(&bloom, key1.clone())
+            .join_on(other, key1, key2, joiner, partitions)
+    }
+
+    /// Builds a BloomFilter of the keys of every value, with `bits_per_key` bits for
+    /// each distinct key, as with `MemoryCollection::to_bloom`.
+    pub fn to_bloom<
+        K: Hash,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, key: F, bits_per_key: usize) -> DiskCollection<BloomFilter> {
+        let acc = Arc::new(FileStore::empty(self.path.clone()));
+        let out = to_bloom(&self.partitions, key, bits_per_key).apply(move |f| {
+            acc.write_vec(vec![f.clone()])
+        });
+        self.derive("to_bloom", StageKind::Reduce, vec![out])
+    }
+
+    /// Keeps the values whose keys may be held by one of the filters in `bloom`, as
+    /// with `MemoryCollection::filter_bloom`.
+    pub fn filter_bloom<
+        K: Hash,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, bloom: &DiskCollection<BloomFilter>, key: F) -> DiskCollection<A> {
+        let parts = filter_with(&self.partitions, &bloom.gathered(), Disk(self.path.clone()), move |x, filters: &Vec<BloomFilter>| {
+            let k = key(x);
+            filters.iter().any(|f| f.contains(&k))
+        });
+        self.from_defs(parts)
+            .with_plan("filter_bloom", StageKind::ElementWise, vec![self.plan.clone(), bloom.plan.clone()])
+            .stage("filter_bloom")
     }
 
     /// Executes the Collection, returning the result of the computation
     pub fn run<S: Scheduler>(&self, s: &S) -> Option<Vec<A>> {
+        self.run_with_progress(s, &Progress::new())
+    }
+
+    /// Executes the Collection as with `run`, reporting to `progress` as each task
+    /// finishes, as with `MemoryCollection::run_with_progress`.
+    pub fn run_with_progress<S: Scheduler>(&self, s: &S, progress: &Progress) -> Option<Vec<A>> {
+        self.run_until(s, progress, &CancellationToken::new()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Executes the Collection as with `run`, until `token` is cancelled, as with
+    /// `MemoryCollection::run_cancellable`.
+    pub fn run_cancellable<S: Scheduler>(
+        &self, 
+        s: &S, 
+        token: &CancellationToken
+    ) -> Result<Option<Vec<A>>, Cancelled> {
+        self.run_until(s, &Progress::new(), token).map_err(cancelled_or_panic)
+    }
+
+    /// Executes the Collection as with `run`, giving up once `timeout` has passed,
+    /// as with `MemoryCollection::run_timeout`.
+    pub fn run_timeout<S: Scheduler>(
+        &self, 
+        s: &S, 
+        timeout: Duration
+    ) -> Result<Option<Vec<A>>, TimeoutError> {
+        self.run_until(s, &Progress::new(), &CancellationToken::with_timeout(timeout))
+            .map_err(|e| TimeoutError::new(timeout, cancelled_or_panic(e)))
+    }
+
+    /// Executes the Collection as with `run`, returning an error naming the first
+    /// task to panic rather than panicking, as with `MemoryCollection::try_run`.
+    pub fn try_run<S: Scheduler>(&self, s: &S) -> Result<Option<Vec<A>>, RunError> {
+        self.run_until(s, &Progress::new(), &CancellationToken::new())
+    }
+
+    /// Executes the Collection on an AsyncScheduler without blocking the calling
+    /// thread, as with `MemoryCollection::run_async`.
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self, s: &AsyncScheduler) -> impl Future<Output = Option<Vec<A>>> + Send {
+        self.gathered().run_async(s)
+    }
+
+    fn run_until<S: Scheduler>(
+        &self, 
+        s: &S, 
+        progress: &Progress, 
+        token: &CancellationToken
+    ) -> Result<Option<Vec<A>>, RunError> {
+        match self.concatenated() {
+            Some(x) => x.try_run_until(s, progress, token),
+            None    => {
+                progress.start(0);
+                Ok(Some(Vec::new()))
+            }
+        }
+    }
+
+    // Reads the partitions and joins them into one, or None if there aren't any
+    fn concatenated(&self) -> Option<Deferred<Vec<A>>> {
         let defs = batch_apply(&self.partitions, |_idx, vs| {
-            vs.stream().into_iter().collect::<Vec<_>>()
+            stream_or_panic(vs).into_iter().collect::<Vec<_>>()
         });
-        let cat = tree_reduce(&defs, |x, y| {
+        tree_reduce(&defs, |x, y| {
             let mut v1: Vec<_> = (*x).clone();
             for yi in y {
                 v1.push(yi.clone());
             }
             v1
-        });
-        cat.and_then(|x| x.run(s))
+        })
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> Evaluate for DiskCollection<A> {
+    type Item = A;
+
+    fn gathered(&self) -> Deferred<Vec<A>> {
+        self.concatenated().unwrap_or_else(|| Deferred::lift(Vec::new(), None))
+    }
+}
+
+impl <A: Any + Send + Sync + Clone> DiskCollection<A> {
+    /// Names the stage producing the collection, as with `MemoryCollection::named`.
+    pub fn named(&self, name: &str) -> DiskCollection<A> {
+        let partitions = self.partitions.iter().enumerate()
+            .map(|(idx, p)| p.clone().named_part(name, idx))
+            .collect();
+        DiskCollection { path: self.path.clone(), partitions, plan: self.plan.renamed(name) }
+    }
+
+    // Describes the collection as produced by `op` from `inputs`
+    pub(crate) fn with_plan(mut self, op: &str, kind: StageKind, inputs: Vec<Arc<PlanNode>>) -> DiskCollection<A> {
+        self.plan = PlanNode::new(op, kind, self.partitions.len(), inputs);
+        self
+    }
+
+    // Attributes the stage producing the collection to `op`, giving it a generated
+    // name
+    fn stage(&self, op: &str) -> DiskCollection<A> {
+        let col = self.named(&stage_name(op));
+        DiskCollection { plan: self.plan.relabeled(op), ..col }
+    }
+}
+
+/// Shows the shape of the plan behind the collection, as for MemoryCollection.
+impl <A: Any + Send + Sync + Clone> fmt::Debug for DiskCollection<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nodes: Vec<_> = self.partitions.iter().map(|p| p.node()).collect();
+        fmt_plan(f, "DiskCollection", &nodes)
     }
 }
 
@@ -409,7 +916,216 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
             for xi in x {
                 emitter(xi.clone());
             }
-        })
+        }).stage("flatten")
+    }
+}
+
+impl <
+    T: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>, 
+    E: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>
+> DiskCollection<Result<T, E>> {
+
+    /// Keeps the values of the successful Results, in the same partitions, as with
+    /// `MemoryCollection::oks`.
+    pub fn oks(&self) -> DiskCollection<T> {
+        self.emit(|x, emitter| {
+            if let Ok(t) = x {
+                emitter(t.clone());
+            }
+        }).stage("oks")
+    }
+
+    /// Keeps the errors of the failed Results, in the same partitions, as with
+    /// `MemoryCollection::errs`.
+    pub fn errs(&self) -> DiskCollection<E> {
+        self.emit(|x, emitter| {
+            if let Err(e) = x {
+                emitter(e.clone());
+            }
+        }).stage("errs")
+    }
+
+    /// Splits the collection into the values of the successful Results and the
+    /// errors of the failed ones, reading each partition once, as with
+    /// `MemoryCollection::split_results`.
+    pub fn split_results(&self) -> (DiskCollection<T>, DiskCollection<E>) {
+        let (oks, errs) = split_results(&self.partitions, 
+                                        Disk(self.path.clone()), 
+                                        Disk(self.path.clone()));
+        (self.derive("split_results", StageKind::ElementWise, oks),
+         self.derive("split_results", StageKind::ElementWise, errs))
+    }
+}
+
+impl <
+    K: Any + Send + Sync + Clone + Hash + Eq + Serialize + for<'de>Deserialize<'de>, 
+    V: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>
+> DiskCollection<(K, V)> {
+
+    /// Groups the values of each key, hashed by key into `partitions`, as with
+    /// `MemoryCollection::group_by_key`.
+    pub fn group_by_key<P: Into<Partitioning>>(&self, partitions: P) -> DiskCollection<(K, Vec<V>)> {
+        let key = |x: &(K, V)| x.0.clone();
+        let hashed = self.partition_by_key(partitions, key);
+        let groups = group_local(&hashed.partitions, key, |x| x.1, Disk(self.path.clone()));
+        hashed.derive("group_by_key", StageKind::ElementWise, groups)
+    }
+
+    /// Folds the values of each key in order of `sort_key`, giving the state after
+    /// each value, as with `MemoryCollection::rolling_by_key`.
+    pub fn rolling_by_key<
+        S: Ord,
+        B: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>,
+        FS: 'static + Sync + Send + Clone + Fn(&V) -> S,
+        FB: 'static + Sync + Send + Clone + Fn(&B, &V) -> B,
+        P: Into<Partitioning>
+    >(&self, sort_key: FS, init: B, step: FB, partitions: P) -> DiskCollection<(K, B)> {
+        let hashed = self.partition_by_key(partitions, |x: &(K, V)| x.0.clone());
+        let states = rolling_local(&hashed.partitions, sort_key, init, step, Disk(self.path.clone()));
+        hashed.derive("rolling_by_key", StageKind::ElementWise, states)
+    }
+
+    /// Keeps the `k` highest scoring values of each key, highest first, as with
+    /// `MemoryCollection::top_k_by_key`.
+    pub fn top_k_by_key<
+        S: Ord,
+        F: 'static + Sync + Send + Clone + Fn(&V) -> S,
+        P: Into<Partitioning>
+    >(&self, k: usize, score: F, partitions: P) -> DiskCollection<(K, Vec<V>)> {
+        let (s1, s2, s3) = (score.clone(), score.clone(), score);
+        self.combine_by_key(|x| x.0.clone(),
+                            move |x| { let mut top = Vec::new(); keep_top(&mut top, x.1.clone(), k, &s1); top },
+                            move |top, x| keep_top(top, x.1.clone(), k, &s2),
+                            move |top, other| for v in other.iter() { keep_top(top, v.clone(), k, &s3) },
+                            partitions)
+            .stage("top_k_by_key")
+    }
+
+    /// Keeps a uniform sample of at most `n` values of each key, as with
+    /// `MemoryCollection::sample_per_key`.
+    pub fn sample_per_key<P: Into<Partitioning>>(
+        &self, n: usize, opts: RandomOptions, partitions: P
+    ) -> DiskCollection<(K, V)> {
+        let tagged = tag_random(&self.partitions, Disk(self.path.clone()), "sample_per_key", opts.master_seed());
+        self.derive("sample_per_key", StageKind::ElementWise, tagged)
+            .top_k_by_key(n, |v| Reverse(v.0), partitions)
+            .emit(|x, emitter| {
+                for v in x.1.iter() {
+                    emitter((x.0.clone(), v.1.clone()));
+                }
+            })
+            .stage("sample_per_key")
+    }
+
+    /// Estimates each key's share of the pairs from a uniform sample of
+    /// `sample_size` pairs, as with `MemoryCollection::key_skew_report`.
+    pub fn key_skew_report(&self, sample_size: usize, opts: RandomOptions) -> DiskCollection<(K, f64)> {
+        let shares = key_shares(&self.partitions, sample_size, opts.master_seed());
+        let acc = Arc::new(FileStore::empty(self.path.clone()));
+        let out = shares.apply(move |shares| acc.write_vec(shares.clone()));
+        self.derive("key_skew_report", StageKind::Reduce, vec![out])
+    }
+
+    /// Redistributes the pairs into `n_partitions` partitions, giving the keys
+    /// sampled as holding the most pairs partitions of their own, as with
+    /// `MemoryCollection::rebalance_by_key_sample`.  A heavy key's values may be
+    /// spread over several partitions.
+    pub fn rebalance_by_key_sample(
+        &self, n_partitions: usize, sample_size: usize, opts: RandomOptions
+    ) -> DiskCollection<(K, V)> {
+        let n = n_partitions.max(1);
+        let shares = key_shares(&self.partitions, sample_size, opts.master_seed());
+        let routes = shares.apply(move |shares| KeyRoutes::new(shares, n));
+        let parts = split_with(&self.partitions, &routes, n, |routes: &KeyRoutes<K>, part, idx, x: &(K, V)| {
+            routes.route(&x.0, part, idx)
+        });
+        self.derive("rebalance_by_key_sample", StageKind::Shuffle, parts)
+    }
+
+    /// Runs the collection, grouping the values of each key into a map, as with
+    /// `MemoryCollection::collect_groups`.
+    pub fn collect_groups<S: Scheduler>(&self, s: &S) -> Option<HashMap<K, Vec<V>>> {
+        collect_groups(&self.partitions).run(s)
+    }
+}
+
+impl <
+    K: Any + Send + Sync + Clone + Hash + Eq + Serialize + for<'de>Deserialize<'de>, 
+    V: Any + Send + Sync + Clone + Hash + Eq + Serialize + for<'de>Deserialize<'de>
+> DiskCollection<(K, V)> {
+
+    /// Counts the distinct values of each key, exactly, as with
+    /// `MemoryCollection::count_distinct_by_key`.
+    pub fn count_distinct_by_key<P: Into<Partitioning>>(&self, partitions: P) -> DiskCollection<(K, usize)> {
+        self.combine_by_key(|x| x.0.clone(),
+                            |x| { let mut seen = HashSet::new(); seen.insert(x.1.clone()); seen },
+                            |seen, x| { seen.insert(x.1.clone()); },
+                            |seen, other| seen.extend(other.iter().cloned()),
+                            partitions)
+            .map(|x| (x.0.clone(), x.1.len()))
+            .stage("count_distinct_by_key")
+    }
+
+    /// Estimates the distinct values of each key with a HyperLogLog of `precision`,
+    /// as with `MemoryCollection::count_distinct_by_key_approx`.
+    pub fn count_distinct_by_key_approx<P: Into<Partitioning>>(
+        &self, precision: u8, partitions: P
+    ) -> DiskCollection<(K, usize)> {
+        self.combine_by_key(|x| x.0.clone(),
+                            move |x| { let mut hll = HyperLogLog::new(precision); hll.insert(&x.1); hll },
+                            |hll, x| hll.insert(&x.1),
+                            |hll, other| hll.merge(other),
+                            partitions)
+            .map(|x| (x.0.clone(), x.1.count()))
+            .stage("count_distinct_by_key_approx")
+    }
+}
+
+impl <
+    A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>, 
+    B: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>
+> DiskCollection<(A, B)> {
+
+    /// Splits the pairs into a collection of their first halves and one of their
+    /// second, reading each partition once, as with `MemoryCollection::unzip`.
+    pub fn unzip(&self) -> (DiskCollection<A>, DiskCollection<B>) {
+        let (firsts, seconds) = unzip(&self.partitions, 
+                                      Disk(self.path.clone()), 
+                                      Disk(self.path.clone()));
+        (self.derive("unzip", StageKind::ElementWise, firsts),
+         self.derive("unzip", StageKind::ElementWise, seconds))
+    }
+
+    /// Swaps the halves of every pair, as with `MemoryCollection::swap`.
+    pub fn swap(&self) -> DiskCollection<(B, A)> {
+        self.map(|(a, b)| (b.clone(), a.clone())).stage("swap")
+    }
+
+    /// Maps the first half of every pair, keeping the second.
+    pub fn map_keys<
+        C: Any + Send + Sync + Clone + Serialize,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> C
+    >(&self, f: F) -> DiskCollection<(C, B)> {
+        self.map(move |(a, b)| (f(a), b.clone())).stage("map_keys")
+    }
+
+    /// Maps the second half of every pair, keeping the first.
+    pub fn map_values<
+        C: Any + Send + Sync + Clone + Serialize,
+        F: 'static + Sync + Send + Clone + Fn(&B) -> C
+    >(&self, f: F) -> DiskCollection<(A, C)> {
+        self.map(move |(a, b)| (a.clone(), f(b))).stage("map_values")
+    }
+}
+
+impl <K: Any + Send + Sync + Clone + Hash + Eq + Serialize + for<'de>Deserialize<'de>> DiskCollection<(K, f64)> {
+
+    /// Replaces each value with the running total of its key's values, in the order
+    /// of the collection, as with `MemoryCollection::cumsum_by_key`.
+    pub fn cumsum_by_key<P: Into<Partitioning>>(&self, partitions: P) -> DiskCollection<(K, f64)> {
+        let tagged = tag_order(&self.partitions, Disk(self.path.clone()));
+        self.derive("cumsum_by_key", StageKind::ElementWise, tagged)
+            .rolling_by_key(|v| v.0, 0.0, |sum, v| sum + v.1, partitions)
     }
 }
 
@@ -428,14 +1144,109 @@ impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> DiskC
     /// ```
     pub fn count(&self) -> DiskCollection<usize> {
         let nps = batch_apply(&self.partitions, |_idx, vs| {
-            vs.stream().into_iter().map(|_| 1usize).sum::<usize>()
+            stream_or_panic(vs).into_iter().map(|_| 1usize).sum::<usize>()
         });
-        let count = tree_reduce(&nps, |x, y| x + y).unwrap();
+        let count = tree_reduce(&nps, |x, y| x + y)
+            .unwrap_or_else(|| Deferred::lift(0, None));
         let acc = Arc::new(FileStore::empty(self.path.clone()));
         let out = count.apply(move |x| {
             acc.write_vec(vec![*x])
         });
-        self.from_defs(vec![out])
+        self.derive("count", StageKind::Reduce, vec![out])
+    }
+
+    /// Runs the collection, returning the number of values in each partition, in
+    /// order, as with `MemoryCollection::sizes`.  The counts are read from each
+    /// partition's FileStore without reading back its values.
+    pub fn sizes<S: Scheduler>(&self, s: &S) -> Option<Vec<usize>> {
+        gather(&batch_apply(&self.partitions, |_idx, vs| vs.len())).run(s)
+    }
+
+    /// Runs the collection, returning whether every value satisfies `f`, as with
+    /// `MemoryCollection::all`.  An empty collection returns true.
+    pub fn all<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> bool, 
+        S: Scheduler
+    >(&self, f: F, s: &S) -> Option<bool> {
+        all(&self.partitions, f).run(s)
+    }
+
+    /// Runs the collection, returning whether any value satisfies `f`, as with
+    /// `MemoryCollection::any`.  An empty collection returns false.
+    pub fn any<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> bool, 
+        S: Scheduler
+    >(&self, f: F, s: &S) -> Option<bool> {
+        all(&self.partitions, move |x| !f(x)).apply(|none| !none).run(s)
+    }
+
+    /// Folds every value into a single one with `op`, starting from `identity`, as
+    /// with `MemoryCollection::fold_monoid`.
+    pub fn fold_monoid<
+        F: 'static + Sync + Send + Clone + Fn(&A, &A) -> A
+    >(&self, identity: A, op: F) -> DiskCollection<A> {
+        let acc = Arc::new(FileStore::empty(self.path.clone()));
+        let out = fold_monoid(&self.partitions, identity, op).apply(move |x| acc.write_vec(vec![x.clone()]));
+        self.derive("fold_monoid", StageKind::Reduce, vec![out])
+    }
+
+    /// Sums the values in the collection, as a monoid fold from zero.
+    pub fn sum(&self) -> DiskCollection<A> 
+        where A: Sum + Add<Output = A>
+    {
+        self.fold_monoid(iter::empty().sum(), |x, y| x.clone() + y.clone()).stage("sum")
+    }
+
+    /// Multiplies the values in the collection, as a monoid fold from one.  Integer
+    /// products overflow as with `MemoryCollection::product`.
+    pub fn product(&self) -> DiskCollection<A> 
+        where A: Product + Mul<Output = A>
+    {
+        self.fold_monoid(iter::empty().product(), |x, y| x.clone() * y.clone()).stage("product")
+    }
+
+    /// Multiplies the integers in the collection, returning None if the product
+    /// overflows.
+    pub fn checked_product(&self) -> DiskCollection<Option<A>> 
+        where A: Integer
+    {
+        self.map(|x| Some(*x))
+            .fold_monoid(Some(A::ONE), |x, y| x.and_then(|x| y.and_then(|y| x.checked_mul(y))))
+            .stage("checked_product")
+    }
+
+    /// Multiplies the integers in the collection, wrapping around on overflow.
+    pub fn wrapping_product(&self) -> DiskCollection<A> 
+        where A: Integer
+    {
+        self.fold_monoid(A::ONE, |x, y| x.wrapping_mul(*y)).stage("wrapping_product")
+    }
+
+    /// Writes each partition to a file of bincode records within `path`, in the
+    /// same format DiskCollection spills to, followed by a `manifest.json` listing
+    /// the files and their record counts once every partition is written.
+    /// Returns the absolute path and record count of each file, in partition
+    /// order, within a single partition.
+    pub fn sink_bincode<P: Into<PathBuf>>(&self, path: P) -> DiskCollection<(String, usize)> {
+        let written = sink_bincode(&self.partitions, path.into());
+        let acc = Arc::new(FileStore::empty(self.path.clone()));
+        let out = written.apply(move |files| acc.write_vec(files.clone()));
+        self.derive("sink_bincode", StageKind::Sink, vec![out])
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Ord + Serialize + for<'de>Deserialize<'de>> DiskCollection<A> {
+
+    /// Runs the collection, returning the value at index `k` of its values were they
+    /// sorted, or None if there are no more than `k`, as with `MemoryCollection::kth`.
+    pub fn kth<S: Scheduler>(&self, k: usize, s: &S) -> Option<A> {
+        kth(&self.partitions, k, s)
+    }
+
+    /// Runs the collection, returning its lower median, or None if it's empty.
+    pub fn median<S: Scheduler>(&self, s: &S) -> Option<A> {
+        let n = self.count().run(s)?[0];
+        self.kth(n.checked_sub(1)? / 2, s)
     }
 }
 
@@ -452,13 +1263,22 @@ impl <A: Any + Send + Sync + Clone + PartialEq + Hash + Eq + Serialize + for<'de
     ///   let freqs = col.frequencies(1).sort_by(|x| x.0);
     ///   assert_eq!(freqs.run(&GreedyScheduler::new()), Some(vec![(1, 3), (2, 2), (5, 1)]));
     /// ```
-pub fn frequencies(&self, partitions: usize) -> DiskCollection<(A, usize)> {
+pub fn frequencies<P: Into<Partitioning>>(&self, partitions: P) -> DiskCollection<(A, usize)> {
         //self.partition(chunks, |x| x);
         self.fold_by(|s| s.clone(), 
                      || 0usize, 
                      |acc, _l| *acc += 1, 
                      |x, y| *x += *y, 
-                     partitions)
+                     partitions).stage("frequencies")
+    }
+
+    /// Finds the values making up at least `threshold` of the collection, with
+    /// their estimated counts, as with `MemoryCollection::heavy_hitters`.  Estimates
+    /// never undercount.
+    pub fn heavy_hitters(&self, threshold: f64, width: usize, depth: usize) -> DiskCollection<(A, usize)> {
+        let acc = Arc::new(FileStore::empty(self.path.clone()));
+        let out = heavy_hitters(&self.partitions, threshold, width, depth).apply(move |hs| acc.write_vec(hs.clone()));
+        self.derive("heavy_hitters", StageKind::Reduce, vec![out])
     }
 }
 
@@ -466,33 +1286,142 @@ pub fn frequencies(&self, partitions: usize) -> DiskCollection<(A, usize)> {
 impl DiskCollection<String> {
     /// Writes each record in a collection to disk, newline delimited.
     /// DiskCollection will create anew file within the path for each partition written.
-    pub fn sink(&self, path: &'static str) -> DiskCollection<usize> {
+    /// As with `MemoryCollection::sink`, no overwrite policy is applied, so files
+    /// left by an earlier run with more partitions stay in the path.
+    pub fn sink<P: Into<PathBuf>>(&self, path: P) -> DiskCollection<usize> {
+        self.sink_paths(path).map(|p| p.1)
+    }
+
+    /// Writes each record in a collection to disk, newline delimited, as with
+    /// `sink`.  Returns the absolute path of each partition's file along with the
+    /// number of lines written to it.  Like `sink`, applies no overwrite policy.
+    pub fn sink_paths<P: Into<PathBuf>>(&self, path: P) -> DiskCollection<(String, usize)> {
+        let target = target_dir(path.into(), None);
         let acc = Arc::new(FileStore::empty(self.path.clone()));
-        let pats = batch_apply(&self.partitions, move |idx, vs| {
-            fs::create_dir_all(path)
-                .expect("Welp, something went terribly wrong when creating directory");
-
-            let file = fs::File::create(&format!("{}/{}", path, idx))
-                .expect("Issues opening file!");
-            let mut bw = BufWriter::new(file);
-
-            let mut size = 0usize;
-            for line in vs.stream() {
-                bw.write(line.as_bytes()).expect("Error writing out line");
-                bw.write(b"\n").expect("Error writing out line");
-                size += 1;
+        let naming = FileNaming::default();
+        let n_parts = self.partitions.len();
+        let pats = self.partitions.iter().enumerate().map(|(idx, part)| {
+            let name = naming.name(idx, n_parts);
+            part.join(&target, move |vs, dir| {
+                let (name, mut bw) = PartWriter::create(dir, &name, idx, Encoding::Plain);
+
+                let mut size = 0usize;
+                for line in stream_or_panic(vs) {
+                    let written = bw.write_all(line.as_bytes()).and_then(|_| bw.write_all(b"\n"));
+                    bw.check(written);
+                    size += 1;
+                }
+                bw.finish();
+
+                vec![(name.to_string_lossy().into_owned(), size)]
+            })
+        }).collect::<Vec<_>>();
+
+        let written = finish_sink(&target, &pats).apply(move |files| acc.write_vec(files.clone()));
+        self.derive("sink", StageKind::Sink, vec![written])
+    }
+
+    /// Splits every string into its words, separated by whitespace, as with
+    /// `MemoryCollection::split_whitespace`.
+    pub fn split_whitespace(&self) -> DiskCollection<String> {
+        self.emit(|s, emitter| {
+            for token in s.split_whitespace() {
+                emitter(token.to_owned());
             }
+        }).stage("split_whitespace")
+    }
 
-            acc.write_vec(vec![size])
-        });
-        
-        self.from_defs(pats)
+    /// Splits every string on `delim`, skipping empty tokens, as with
+    /// `MemoryCollection::split_on`.
+    pub fn split_on(&self, delim: char) -> DiskCollection<String> {
+        self.split_on_with(delim, false)
+    }
+
+    /// Splits every string on `delim`, keeping empty tokens if `keep_empty` is set.
+    pub fn split_on_with(&self, delim: char, keep_empty: bool) -> DiskCollection<String> {
+        self.emit(move |s, emitter| {
+            for token in s.split(delim) {
+                if keep_empty || !token.is_empty() {
+                    emitter(token.to_owned());
+                }
+            }
+        }).stage("split_on")
+    }
+
+    /// Splits every string into its lines, skipping empty lines, as with
+    /// `MemoryCollection::lines`.
+    pub fn lines(&self) -> DiskCollection<String> {
+        self.lines_with(false)
+    }
+
+    /// Splits every string into its lines, keeping empty lines if `keep_empty` is
+    /// set.
+    pub fn lines_with(&self, keep_empty: bool) -> DiskCollection<String> {
+        self.emit(move |s, emitter| {
+            for line in s.lines() {
+                if keep_empty || !line.is_empty() {
+                    emitter(line.to_owned());
+                }
+            }
+        }).stage("lines")
+    }
+
+    /// Keeps the strings matching `pattern`, as with `MemoryCollection::grep`.
+    /// Requires the "regex" feature.
+    #[cfg(feature = "regex")]
+    pub fn grep(&self, pattern: &str) -> Result<DiskCollection<String>, regex::Error> {
+        let re = Regex::new(pattern)?;
+        Ok(self.filter(move |s| re.is_match(s)).stage("grep"))
+    }
+
+    /// Emits the capture groups of each string matching `pattern`, as with
+    /// `MemoryCollection::extract`.  Requires the "regex" feature.
+    #[cfg(feature = "regex")]
+    pub fn extract(&self, pattern: &str) -> Result<DiskCollection<Vec<String>>, regex::Error> {
+        let re = Regex::new(pattern)?;
+        Ok(self.emit(move |s, emitter| {
+            if let Some(caps) = re.captures(s) {
+                emitter(caps.iter().skip(1)
+                    .map(|m| m.map(|m| m.as_str().to_owned()).unwrap_or_default())
+                    .collect());
+            }
+        }).stage("extract"))
+    }
+
+    /// Parses every string with `FromStr`, keeping the Result for each, as with
+    /// `MemoryCollection::parse`.
+    pub fn parse<
+        T: Any + Send + Sync + Clone + FromStr + Serialize + for<'de> Deserialize<'de>
+    >(&self) -> DiskCollection<Result<T, ParseError>> 
+        where T::Err: fmt::Display
+    {
+        let parts = parse(&self.partitions, Disk(self.path.clone()));
+        self.derive("parse", StageKind::ElementWise, parts)
+    }
+
+    /// Parses every string with `FromStr`, splitting the values which parsed from
+    /// the errors of those which didn't, as with `MemoryCollection::parse_lossy`.
+    pub fn parse_lossy<
+        T: Any + Send + Sync + Clone + FromStr + Serialize + for<'de> Deserialize<'de>
+    >(&self) -> (DiskCollection<T>, DiskCollection<ParseError>) 
+        where T::Err: fmt::Display
+    {
+        let (oks, errs) = self.parse::<T>().split_results();
+        (oks.stage("parse_lossy"), errs.stage("parse_lossy"))
+    }
+
+    /// Runs the collection, joining all of its strings into one with `sep` between
+    /// each, as with `MemoryCollection::join_strings`.
+    pub fn join_strings<S: Scheduler>(&self, sep: &str, s: &S) -> Option<String> {
+        join_strings(&self.partitions, sep).run(s)
     }
 }
 
 #[cfg(test)]
 mod test_lib {
+    use std::fs;
     use super::*;
+    use store::LocalFs;
     use tange::scheduler::{GreedyScheduler,LeveledScheduler};
 
     fn make_col() -> DiskCollection<usize> {
@@ -518,6 +1447,103 @@ mod test_lib {
         assert_eq!(results, vec![(1, 2), (2, 2), (3, 1)]);
     }
 
+    #[test]
+    fn test_fold_by_salted() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..1000usize).map(|x| x % 3 * x % 7).collect(), 6);
+        let plain = col.fold_by(|x| *x, || 0usize, |acc, x| *acc += x, |a, b| *a += b, 3).sort_by(|x| x.0);
+        let salted = col.fold_by_salted(3, |x| *x, || 0usize, |acc, x| *acc += x, |a, b| *a += b, 3).sort_by(|x| x.0);
+        assert_eq!(salted.run(&LeveledScheduler), plain.run(&LeveledScheduler));
+    }
+
+    #[test]
+    fn test_combine_by_key() {
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1usize, 4.0f64), (2, 1.0), (1, 2.0), (2, 5.0), (1, 3.0)])
+            .split(2);
+        let averages = col.combine_by_key(|x| x.0, |x| (x.1, 1usize),
+                                          |c, x| { c.0 += x.1; c.1 += 1; },
+                                          |c, o| { c.0 += o.0; c.1 += o.1; }, 2)
+            .map(|x| (x.0, (x.1).0 / (x.1).1 as f64))
+            .split(1)
+            .sort_by(|x| x.0);
+        assert_eq!(averages.run(&LeveledScheduler).unwrap(), vec![(1, 3.0), (2, 3.0)]);
+    }
+
+    #[test]
+    fn test_group_by() {
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1usize, 'a'), (2, 'b'), (1, 'c'), (3, 'd')])
+            .split(2);
+        let grouped = col.group_by_key(2);
+        assert_eq!(grouped.n_partitions(), 2);
+        let mut results = grouped.run(&LeveledScheduler).unwrap();
+        for r in results.iter_mut() {
+            r.1.sort();
+        }
+        results.sort();
+        assert_eq!(results, vec![(1, vec!['a', 'c']), (2, vec!['b']), (3, vec!['d'])]);
+
+        let groups = col.collect_groups(&LeveledScheduler).unwrap();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&1], vec!['a', 'c']);
+
+        let mut results = col.group_by(|x| x.1 > 'b', 3).map(|g| (g.0, g.1.len())).run(&LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, vec![(false, 2), (true, 2)]);
+    }
+
+    #[test]
+    fn test_rolling_by_key() {
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1usize, 3u32), (2, 1), (1, 1), (1, 2), (2, 5)])
+            .split(2);
+        let sums = col.rolling_by_key(|t| *t, 0u32, |sum, t| sum + t, 2);
+        let mut results = sums.run(&LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, vec![(1, 1), (1, 3), (1, 6), (2, 1), (2, 6)]);
+    }
+
+    #[test]
+    fn test_cumsum_by_key() {
+        let col = MemoryCollection::from_vec_chunked(vec![(1usize, 0.5f64), (2, 1.0), (1, 2.0), (1, 0.25), (2, 3.0)], 3)
+            .to_disk("/tmp".into());
+        let mut results = col.cumsum_by_key(2).run(&LeveledScheduler).unwrap();
+        results.sort_by(|x, y| x.partial_cmp(y).unwrap());
+        assert_eq!(results, vec![(1, 0.5), (1, 2.5), (1, 2.75), (2, 1.0), (2, 4.0)]);
+    }
+
+    #[test]
+    fn test_count_distinct_by_key() {
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1usize, 'a'), (2, 'b'), (1, 'c'), (1, 'a'), (2, 'b')])
+            .split(2);
+        let mut exact = col.count_distinct_by_key(2).run(&LeveledScheduler).unwrap();
+        exact.sort();
+        assert_eq!(exact, vec![(1, 2), (2, 1)]);
+        let mut approx = col.count_distinct_by_key_approx(8, 2).run(&LeveledScheduler).unwrap();
+        approx.sort();
+        assert_eq!(approx, exact);
+    }
+
+    #[test]
+    fn test_top_k_by_key() {
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1usize, 5u32), (2, 1), (1, 9), (1, 7), (2, 3), (1, 2)])
+            .split(3);
+        let top = col.top_k_by_key(2, |v| *v, 2).split(1).sort_by(|x| x.0);
+        assert_eq!(top.run(&LeveledScheduler).unwrap(), vec![(1, vec![9, 7]), (2, vec![3, 1])]);
+    }
+
+    #[test]
+    fn test_sample_per_key() {
+        let col = DiskCollection::from_vec("/tmp".into(), (0..100usize).map(|x| (x % 3 == 0, x)).collect())
+            .split(3);
+        let sampled = col.sample_per_key(10, RandomOptions::seeded(5), 2);
+        let mut results = sampled.run(&LeveledScheduler).unwrap();
+        assert_eq!(results.iter().filter(|x| x.0).count(), 10);
+        assert_eq!(results.iter().filter(|x| !x.0).count(), 10);
+        assert!(results.iter().all(|x| (x.1 % 3 == 0) == x.0));
+        let mut again = sampled.run(&LeveledScheduler).unwrap();
+        results.sort();
+        again.sort();
+        assert_eq!(again, results);
+    }
+
     #[test]
     fn test_partition_by_key() {
         let col = make_col();
@@ -545,6 +1571,297 @@ mod test_lib {
         assert_eq!(results, vec![5]);
     }
 
+    #[test]
+    fn test_cached() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let root = "/tmp/tange-test-disk-cached";
+        let _ = fs::remove_dir_all(root);
+        let calls = Arc::new(AtomicUsize::new(0));
+        let pipeline = || {
+            let counter = calls.clone();
+            make_col().split(2)
+                .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); x + 1 })
+                .cached_in(root, "plus-one")
+                .unwrap()
+        };
+        assert_eq!(pipeline().run(&LeveledScheduler), Some(vec![2, 4, 3, 3, 2]));
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+
+        // Only the partition missing from the cache is computed again
+        let dir = fs::read_dir(root).unwrap().next().unwrap().unwrap().path();
+        fs::remove_file(dir.join("part-00001")).unwrap();
+        assert_eq!(pipeline().run(&GreedyScheduler::new()), Some(vec![2, 4, 3, 3, 2]));
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+        assert_eq!(pipeline().run(&LeveledScheduler), Some(vec![2, 4, 3, 3, 2]));
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+
+        // Cached partitions know how many records they hold
+        let lens: Vec<_> = pipeline().to_defs().iter()
+            .map(|p| p.run(&LeveledScheduler).unwrap().len())
+            .collect();
+        assert_eq!(lens, vec![3, 2]);
+        assert_eq!(calls.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    fn test_cached_failed() {
+        let root = "/tmp/tange-test-disk-cached-failed";
+        let _ = fs::remove_dir_all(root);
+        let failed = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            make_col()
+                .map(|x| if *x == 3 { panic!("bad record") } else { *x })
+                .cached_in(root, "failed")
+                .unwrap()
+                .run(&LeveledScheduler)
+        }));
+        assert!(failed.is_err());
+
+        // Nothing half written is left in the cache
+        let dir = fs::read_dir(root).unwrap().next().unwrap().unwrap().path();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_cache() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let col = make_col().split(2)
+            .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); x + 1 })
+            .cache();
+        assert_eq!(col.count().run(&LeveledScheduler), Some(vec![5]));
+        assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![2, 4, 3, 3, 2]));
+        assert_eq!(calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_with_max_concurrency() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (r, m) = (running.clone(), most.clone());
+        let col = make_col().split(5)
+            .with_max_concurrency(2)
+            .map(move |x| {
+                let now = r.fetch_add(1, Ordering::SeqCst) + 1;
+                m.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                r.fetch_sub(1, Ordering::SeqCst);
+                x + 1
+            });
+        let mut out = col.run(&GreedyScheduler::new()).unwrap();
+        out.sort();
+        assert_eq!(out, vec![2, 2, 3, 3, 4]);
+        let most = most.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&most), "{} partitions ran at once", most);
+    }
+
+    #[test]
+    fn test_run_all() {
+        use collection::run_all;
+
+        let col = make_col().split(2);
+        let doubled = col.map(|x| x * 2).to_memory();
+        let (plain, twice) = run_all(&LeveledScheduler, (&col, &doubled)).unwrap();
+        assert_eq!(plain, vec![1, 3, 2, 2, 1]);
+        assert_eq!(twice, vec![2, 6, 4, 4, 2]);
+    }
+
+    #[test]
+    fn test_split_results() {
+        let col = make_col().split(2).try_map(|x| if x % 2 == 0 { Ok(*x) } else { Err(x.to_string()) });
+        let (good, bad) = col.split_results();
+        assert_eq!(good.partitions.len(), 2);
+        assert_eq!(good.run(&LeveledScheduler), Some(vec![2, 2]));
+        assert_eq!(bad.run(&LeveledScheduler), Some(vec!["1".into(), "3".into(), "1".into()]));
+        assert_eq!(col.oks().run(&LeveledScheduler), Some(vec![2, 2]));
+        assert_eq!(col.errs().count().run(&LeveledScheduler), Some(vec![3]));
+    }
+
+    #[test]
+    fn test_concat_all() {
+        let none = DiskCollection::<usize>::concat_all("/tmp".into(), &[]);
+        assert_eq!(none.n_partitions(), 0);
+        assert_eq!(none.run(&LeveledScheduler), Some(vec![]));
+
+        let parts: Vec<_> = (0..5usize)
+            .map(|i| DiskCollection::from_vec("/tmp".into(), vec![i, i + 5]))
+            .collect();
+        assert_eq!(DiskCollection::concat_all("/tmp".into(), &parts[..1]).run(&LeveledScheduler), Some(vec![0, 5]));
+        let all = DiskCollection::concat_all("/tmp".into(), &parts);
+        assert_eq!(all.n_partitions(), 5);
+        assert_eq!(all.run(&LeveledScheduler), Some(vec![0, 5, 1, 6, 2, 7, 3, 8, 4, 9]));
+    }
+
+    #[test]
+    fn test_concat_rebalanced() {
+        let many = DiskCollection::from_vec_chunked("/tmp".into(), (0..30usize).collect(), 10);
+        let one = DiskCollection::from_vec("/tmp".into(), (30..90usize).collect());
+        let cat = many.concat_rebalanced(&one, 3);
+        assert_eq!(cat.n_partitions(), 3);
+        let sizes: Vec<_> = cat.to_defs().iter()
+            .map(|d| d.run(&LeveledScheduler).unwrap().stream().into_iter().count())
+            .collect();
+        assert_eq!(sizes, vec![30, 30, 30]);
+        assert_eq!(cat.run(&LeveledScheduler), Some((0..90).collect()));
+    }
+
+    #[test]
+    fn test_sizes() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..100usize).map(|i| i % 2 * i).collect(), 2);
+        assert_eq!(col.sizes(&LeveledScheduler), Some(vec![50, 50]));
+        let sizes = col.partition_by_key(3, |x| *x).sizes(&LeveledScheduler).unwrap();
+        assert_eq!(sizes.iter().sum::<usize>(), 100);
+        assert!(*sizes.iter().max().unwrap() >= 50, "{:?}", sizes);
+    }
+
+    #[test]
+    fn test_rebalance_by_key_sample() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..2000usize).map(|x| (x % 2 * x, x)).collect(), 2);
+        let report = col.key_skew_report(200, RandomOptions::seeded(2)).run(&LeveledScheduler).unwrap();
+        assert_eq!(report[0].0, 0);
+        assert!(report[0].1 > 0.4 && report[0].1 < 0.6);
+
+        let spread = col.rebalance_by_key_sample(4, 200, RandomOptions::seeded(2));
+        let sizes = spread.sizes(&LeveledScheduler).unwrap();
+        assert!(sizes.iter().all(|s| *s < 750), "{:?}", sizes);
+        let mut results = spread.run(&LeveledScheduler).unwrap();
+        results.sort();
+        let mut expected: Vec<_> = (0..2000).map(|x| (x % 2 * x, x)).collect();
+        expected.sort();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_is_sorted_by() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..20usize).collect(), 3);
+        assert_eq!(col.is_sorted_by(|x| *x, &LeveledScheduler), Some(true));
+        assert_eq!(col.split(2).is_sorted_by(|x| *x, &LeveledScheduler), Some(false));
+        assert_eq!(col.is_sorted_by(|x| *x % 5, &LeveledScheduler), Some(false));
+    }
+
+    #[test]
+    fn test_validate() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..10usize).collect(), 2);
+        let positive = col.validate(|x| if *x < 10 { Ok(()) } else { Err("too big".into()) });
+        assert_eq!(positive.run(&LeveledScheduler), Some((0..10).collect()));
+        match col.validate(|x| if *x < 8 { Ok(()) } else { Err(format!("{} is too big", x)) }).try_run(&LeveledScheduler) {
+            Err(RunError::Failed(e)) => assert_eq!(e.message, "2 records failed validation: \
+                record 3 of partition 1 failed: 8 is too big; record 4 of partition 1 failed: 9 is too big"),
+            other                    => panic!("expected a failure, got {:?}", other)
+        }
+        assert_eq!(col.validate_warn(|_| Err("bad".into())).run(&LeveledScheduler), Some((0..10).collect()));
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), vec![1, 1, 1, 2, 2, 5, 5, 5usize], 4);
+        assert_eq!(col.dedup_sorted(|x| *x).run(&LeveledScheduler), Some(vec![1, 2, 5]));
+        assert_eq!(col.dedup_sorted(|x| *x / 3).run(&LeveledScheduler), Some(vec![1, 5]));
+    }
+
+    #[test]
+    fn test_tokenize() {
+        let docs = DiskCollection::from_vec("/tmp".into(), vec!["a b\n\nb,c".to_string()]);
+        assert_eq!(docs.lines().run(&LeveledScheduler), Some(vec!["a b".into(), "b,c".into()]));
+        assert_eq!(docs.lines_with(true).split_on(',').split_whitespace().run(&LeveledScheduler),
+                   Some(vec!["a".into(), "b".into(), "b".into(), "c".into()]));
+        assert_eq!(docs.split_on_with('\n', true).run(&LeveledScheduler).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_parse() {
+        let rows = vec!["1".to_string(), "2".into(), "-".into(), "4".into(), "5x".into()];
+        let (values, errors) = DiskCollection::from_vec_chunked("/tmp".into(), rows, 2).parse_lossy::<i64>();
+        assert_eq!(values.run(&LeveledScheduler), Some(vec![1, 2, 4]));
+        let errors = errors.run(&LeveledScheduler).unwrap();
+        assert_eq!(errors.iter().map(|e| (e.partition, e.index, e.input.as_str())).collect::<Vec<_>>(),
+                   vec![(0, 2, "-"), (1, 1, "5x")]);
+    }
+
+    #[test]
+    fn test_join_strings() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..5usize).map(|x| x.to_string()).collect(), 3)
+            .filter(|x| x != "2" && x != "3");
+        assert_eq!(col.join_strings("|", &LeveledScheduler), Some("0|1|4".into()));
+    }
+
+    #[test]
+    fn test_unzip() {
+        let pairs = DiskCollection::from_vec_chunked("/tmp".into(), (0..7usize).map(|x| (x, x % 3 == 0)).collect(), 2);
+        let (nums, flags) = pairs.unzip();
+        assert_eq!(nums.run(&LeveledScheduler), Some((0..7).collect()));
+        assert_eq!(flags.filter(|f| *f).count().run(&LeveledScheduler), Some(vec![3]));
+        assert_eq!(nums.zip(&flags).run(&LeveledScheduler), pairs.run(&LeveledScheduler));
+    }
+
+    #[test]
+    fn test_swap() {
+        let pairs = DiskCollection::from_vec_chunked("/tmp".into(), vec![(1usize, 'a'), (2, 'b'), (3, 'a')], 2);
+        assert_eq!(pairs.swap().swap().run(&LeveledScheduler), pairs.run(&LeveledScheduler));
+        let counts = pairs.swap().group_by_key(1).map_values(|ids| ids.len())
+            .map_keys(|c| c.to_ascii_uppercase()).sort_by(|x| x.0);
+        assert_eq!(counts.run(&LeveledScheduler), Some(vec![('A', 2), ('B', 1)]));
+    }
+
+    #[test]
+    fn test_all_any() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (1..10usize).collect(), 3);
+        assert_eq!(col.all(|x| *x > 0, &LeveledScheduler), Some(true));
+        assert_eq!(col.all(|x| *x < 9, &LeveledScheduler), Some(false));
+        assert_eq!(col.any(|x| *x == 5, &LeveledScheduler), Some(true));
+        assert_eq!(col.any(|x| *x > 9, &LeveledScheduler), Some(false));
+        let none = col.filter(|_| false);
+        assert_eq!((none.all(|_| false, &LeveledScheduler), none.any(|_| true, &LeveledScheduler)), (Some(true), Some(false)));
+    }
+
+    #[test]
+    fn test_products() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), vec![2.0, 0.25, 8.0], 2);
+        assert_eq!(col.product().run(&LeveledScheduler), Some(vec![4.0]));
+        assert_eq!(col.sum().run(&LeveledScheduler), Some(vec![10.25]));
+        let ints = DiskCollection::from_vec_chunked("/tmp".into(), vec![u64::MAX / 2, 3, 1], 2);
+        assert_eq!(ints.checked_product().run(&LeveledScheduler), Some(vec![None]));
+        assert_eq!(ints.wrapping_product().run(&LeveledScheduler), Some(vec![(u64::MAX / 2).wrapping_mul(3)]));
+        assert_eq!(ints.filter(|x| *x < 10).checked_product().run(&LeveledScheduler), Some(vec![Some(3)]));
+    }
+
+    #[test]
+    fn test_sample_with_replacement() {
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), (0..5usize).collect(), 2);
+        let resampled = col.sample_with_replacement(50, RandomOptions::seeded(1));
+        let drawn = resampled.run(&LeveledScheduler).unwrap();
+        assert_eq!(drawn.len(), 50);
+        assert!(drawn.iter().all(|x| *x < 5));
+        assert_eq!(resampled.run(&LeveledScheduler), Some(drawn));
+    }
+
+    #[test]
+    fn test_kth() {
+        let values: Vec<usize> = (0..6000).map(|x| (x * 7919) % 6000).collect();
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), values, 3);
+        for k in [0, 2999, 5999].iter() {
+            assert_eq!(col.kth(*k, &LeveledScheduler), Some(*k));
+        }
+        assert_eq!(col.kth(6000, &LeveledScheduler), None);
+        assert_eq!(col.median(&LeveledScheduler), Some(2999));
+    }
+
+    #[test]
+    fn test_heavy_hitters() {
+        let values: Vec<u32> = (0..3000).chain((0..900).map(|i| i % 3)).collect();
+        let col = DiskCollection::from_vec_chunked("/tmp".into(), values, 3);
+        let hitters = col.heavy_hitters(0.05, 500, 4).run(&LeveledScheduler).unwrap();
+        let mut found: Vec<u32> = hitters.iter().map(|x| x.0).collect();
+        found.sort();
+        assert_eq!(found, vec![0, 1, 2]);
+        assert!(hitters.iter().all(|x| x.1 >= 301));
+    }
+
     #[test]
     fn test_join() {
         let col1 = make_col();
@@ -558,6 +1875,55 @@ mod test_lib {
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn test_join_with_bloom() {
+        let col1 = DiskCollection::from_vec("/tmp".into(), (0..1000usize).collect()).split(3);
+        let col2 = DiskCollection::from_vec("/tmp".into(), vec![(7usize, 'a'), (700, 'b'), (2000, 'c')]);
+        let bloom = col1.join_with_bloom(&col2, |x| *x, |y| y.0, |x, y| (*x, y.1), 2, 8)
+            .split(1).sort_by(|x| x.0);
+        assert_eq!(bloom.run(&LeveledScheduler).unwrap(), vec![(7, (7, 'a')), (700, (700, 'b'))]);
+
+        let allowed = col2.to_bloom(|y| y.0, 8);
+        let kept = col1.filter_bloom(&allowed, |x| *x).run(&LeveledScheduler).unwrap();
+        assert!(kept.contains(&7) && kept.contains(&700));
+        assert!(kept.len() < 100, "{}", kept.len());
+    }
+
+    #[test]
+    fn test_co_partitioned() {
+        let key = |x: &(usize, usize)| x.0;
+        let col1 = DiskCollection::from_vec("/tmp".into(), (0..20).map(|x| (x % 5, x)).collect())
+            .split(3)
+            .partition_by_key(3, key);
+        let col2 = DiskCollection::from_vec("/tmp".into(), (0..5).map(|x| (x, x * 10)).collect())
+            .partition_by_key(3, key);
+        let joined = col1.join_on(&col2, key, key, |x, y| x.1 + y.1, Partitioning::Preserve);
+        let explain = joined.explain();
+        assert_eq!(explain.matches("partition_by_key").count(), 2, "{}", explain);
+        assert_eq!(joined.n_partitions(), 3);
+
+        let mut results = joined.run(&LeveledScheduler).unwrap();
+        results.sort();
+        let mut expected: Vec<_> = (0..20).map(|x| (x % 5, x + (x % 5) * 10)).collect();
+        expected.sort();
+        assert_eq!(results, expected);
+
+        let folded = col1.fold_by(key, || 0, |x, _y| *x += 1, |x, y| *x += y, Partitioning::Preserve);
+        assert!(folded.explain().contains("fold_by           element-wise"));
+        let mut results = folded.run(&LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, (0..5).map(|k| (k, 4)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_join_where() {
+        let events = DiskCollection::from_vec("/tmp".into(), vec![1u32, 5, 9, 14]).split(2);
+        let ranges = DiskCollection::from_vec("/tmp".into(), vec![(0u32, 6u32), (4, 10)]);
+        let mut results = events.join_where(&ranges, |t, r| r.0 <= *t && *t < r.1).run(&LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, vec![(1, (0, 6)), (5, (0, 6)), (5, (4, 10)), (9, (4, 10))]);
+    }
+
     #[test]
     fn test_emit() {
         let results = DiskCollection::from_vec("/tmp".into(), vec![1,2,3usize])
@@ -572,6 +1938,117 @@ mod test_lib {
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn test_sink_runtime_path() {
+        let dir = format!("/tmp/tange-test-disk-sink-{}", 2 + 2);
+        let col = DiskCollection::from_vec("/tmp".into(), vec!["a".to_owned(), "b".into()]);
+        assert_eq!(col.sink(dir.clone()).run(&LeveledScheduler).unwrap(), vec![2]);
+        assert_eq!(fs::read_to_string(format!("{}/0", dir)).unwrap(), "a\nb\n");
+
+        let written = col.sink_paths(&dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(written, vec![(format!("{}/0", dir), 2)]);
+    }
+
+    #[test]
+    fn test_sink_without_policy() {
+        let dir = "/tmp/tange-test-disk-sink-no-policy";
+        let _ = fs::remove_dir_all(dir);
+        let col = DiskCollection::from_vec("/tmp".into(), (0..9).map(|i| i.to_string()).collect());
+        col.split(3).sink(dir).run(&LeveledScheduler);
+
+        // A smaller rerun leaves the earlier run's extra partitions in place
+        assert_eq!(col.sink(dir).run(&LeveledScheduler).unwrap(), vec![9]);
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, vec!["0", "1", "2", "_SUCCESS", "manifest.json"]);
+    }
+
+    #[test]
+    fn test_sink_bincode() {
+        let dir = "/tmp/tange-test-disk-sink-bincode";
+        let col = DiskCollection::from_vec("/tmp".into(), vec![(1u8, vec![0.5f32]), (2, vec![])])
+            .split(2);
+        let written = col.sink_bincode(dir).run(&LeveledScheduler).unwrap();
+        let expected = col.run(&LeveledScheduler).unwrap();
+        let mut results = Vec::new();
+        for (path, count) in written {
+            let store = Arc::new(LocalFs::new(dir));
+            let name = ::std::path::Path::new(&path).file_name().unwrap().to_str().unwrap();
+            let fs = Arc::new(FileStore::<(u8, Vec<f32>)>::stored(store, name, count).unwrap());
+            for r in fs.try_stream().unwrap() {
+                results.push(r);
+            }
+        }
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_debug() {
+        let col = make_col().split(2).map(|x| x * 2);
+        let plan = format!("{:?}", col);
+        assert!(plan.starts_with("DiskCollection { partitions: 2, depth: "), "{}", plan);
+        assert!(plan.contains("\n  map#"), "{}", plan);
+        assert!(plan.contains("\n    split#"), "{}", plan);
+        assert!(format!("{:?}", col.named("twice")).contains("\n  twice\n    split#"));
+    }
+
+    #[test]
+    fn test_sample() {
+        let opts = RandomOptions::seeded(3);
+        let sampled = DiskCollection::from_vec("/tmp".into(), (0..200usize).collect()).sample(0.5, opts);
+        let expected = MemoryCollection::from_vec((0..200usize).collect()).sample(0.5, opts);
+        assert_eq!(sampled.run(&LeveledScheduler), expected.run(&LeveledScheduler));
+    }
+
+    #[test]
+    fn test_map_with() {
+        let col = make_col().split(2);
+        let total = col.fold_by(|_| (), || 0usize, |acc, x| *acc += x, |x, y| *x += y, 1);
+        let shifted = col.map_with(&total, |x, t| t[0].1 - x).to_memory();
+        let mut out = shifted.run(&LeveledScheduler).unwrap();
+        out.sort();
+        assert_eq!(out, vec![6, 7, 7, 8, 8]);
+    }
+
+    #[test]
+    fn test_explain() {
+        let col = make_col().split(2).map(|x| x * 2).count();
+        assert_eq!(col.explain(), "\
+            stage  operation  kind          partitions  inputs\n\
+            0      from_vec   source        1\n\
+            1      to_disk    element-wise  1           0\n\
+            2      split      shuffle       2           1\n\
+            3      map        element-wise  2           2\n\
+            4      count      reduce        1           3       <- single partition\n");
+        assert!(col.to_memory().explain().ends_with("to_memory  element-wise  1           4\n"));
+
+        let stats = col.dry_run();
+        let tasks: Vec<_> = stats.stages.iter().map(|s| s.tasks).collect();
+        assert_eq!(tasks, vec![1, 1, 2, 2, 1]);
+        assert_eq!(stats.bottlenecks, 1);
+        assert!(stats.stages[4].bottleneck);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let upstream = DiskCollection::from_vec("/tmp".into(), (0..20usize).collect()).split(2)
+            .map(move |x| { c.fetch_add(1, Ordering::SeqCst); x + 1 });
+        let expected = upstream.run(&LeveledScheduler).unwrap();
+
+        let saved = upstream.checkpoint("/tmp/tange-test-disk-checkpoint", &LeveledScheduler).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 40);
+        assert_eq!(saved.n_partitions(), 2);
+        assert_eq!(saved.run(&LeveledScheduler).unwrap(), expected);
+        assert_eq!(saved.map(|x| x * 2).run(&LeveledScheduler).unwrap().len(), 20);
+        assert_eq!(calls.load(Ordering::SeqCst), 40);
+    }
+
     #[test]
     fn test_sort() {
         let results = DiskCollection::from_vec("/tmp".into(), vec![1, 3, 2usize])
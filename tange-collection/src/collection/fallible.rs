@@ -0,0 +1,153 @@
+//! Fallible
+//! ---
+//! Fallible wraps a MemoryCollection built from a fallible map, created with
+//! `MemoryCollection::try_map_all`.  Running it stops scheduling tasks as soon as
+//! any record fails, and returns that record's error rather than the collection.
+//!
+
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc,Mutex};
+
+use tange::scheduler::{Scheduler,CancellationToken};
+
+use collection::memory::MemoryCollection;
+
+/// The error a record failed with, along with where the record was found
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct RecordError<E> {
+    /// Partition holding the record
+    pub partition: usize,
+
+    /// Position of the record within its partition
+    pub index: usize,
+
+    /// Error returned for the record
+    pub error: E
+}
+
+impl <E: fmt::Display> fmt::Display for RecordError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {} of partition {} failed: {}", self.index, self.partition, self.error)
+    }
+}
+
+impl <E: Error> Error for RecordError<E> {}
+
+/// How many characters of a string failing to parse are kept in its ParseError
+const MAX_PARSE_INPUT: usize = 64;
+
+/// A string which failed to parse, as returned by `MemoryCollection::parse`
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct ParseError {
+    /// Partition holding the string
+    pub partition: usize,
+
+    /// Position of the string within its partition
+    pub index: usize,
+
+    /// The string, cut short after 64 characters
+    pub input: String,
+
+    /// Error returned for the string
+    pub message: String
+}
+
+impl ParseError {
+    pub(crate) fn new<E: fmt::Display>(partition: usize, index: usize, input: &str, error: E) -> ParseError {
+        let input = match input.char_indices().nth(MAX_PARSE_INPUT) {
+            Some((end, _)) => format!("{}...", &input[..end]),
+            None           => input.to_owned()
+        };
+        ParseError { partition, index, input, message: error.to_string() }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "record {} of partition {} failed to parse {:?}: {}", 
+               self.index, self.partition, self.input, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+// State of the current run, shared with the tasks which may fail it
+pub(crate) struct Attempt<E> {
+    token: CancellationToken,
+    error: Option<RecordError<E>>
+}
+
+impl <E> Attempt<E> {
+    pub(crate) fn new() -> Arc<Mutex<Attempt<E>>> {
+        Arc::new(Mutex::new(Attempt { token: CancellationToken::new(), error: None }))
+    }
+
+    // Records the failure of a run, keeping the first error if several tasks fail
+    pub(crate) fn fail(&mut self, error: RecordError<E>) {
+        if self.error.is_none() {
+            self.error = Some(error);
+        }
+        self.token.cancel();
+    }
+}
+
+/// A MemoryCollection whose records may have failed to compute, as returned by
+/// `MemoryCollection::try_map_all`.  Further stages are added with `then`, and
+/// `run` returns the first error found, if any.  A Fallible runs once at a time:
+/// starting a run while another is still going interferes with it.
+#[derive(Clone)]
+pub struct Fallible<A, E> {
+    col: MemoryCollection<A>,
+    attempt: Arc<Mutex<Attempt<E>>>
+}
+
+impl <A: Any + Send + Sync + Clone, E> Fallible<A, E> {
+
+    pub(crate) fn new(col: MemoryCollection<A>, attempt: Arc<Mutex<Attempt<E>>>) -> Fallible<A, E> {
+        Fallible { col, attempt }
+    }
+
+    /// Adds stages to the collection.  Once a record fails, tasks belonging to these
+    /// stages stop being scheduled as well.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///
+    ///   let col = MemoryCollection::from_vec(vec!["1", "2", "3"])
+    ///       .try_map_all(|s| s.parse::<u32>())
+    ///       .then(|col| col.map(|x| x * 2).count());
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Ok(Some(vec![3])));
+    /// ```
+    pub fn then<
+        B: Any + Send + Sync + Clone,
+        F: FnOnce(&MemoryCollection<A>) -> MemoryCollection<B>
+    >(&self, f: F) -> Fallible<B, E> {
+        Fallible { col: f(&self.col), attempt: self.attempt.clone() }
+    }
+
+    /// Returns the collection computed when no record fails
+    pub fn collection(&self) -> &MemoryCollection<A> {
+        &self.col
+    }
+
+    /// Executes the Collection, returning the error of the first record to fail.
+    /// Once a record fails, no new tasks are started, though those already running
+    /// are allowed to finish.
+    pub fn run<S: Scheduler>(&self, s: &S) -> Result<Option<Vec<A>>, RecordError<E>> {
+        let token = {
+            let mut attempt = self.attempt.lock().unwrap();
+            attempt.token = CancellationToken::new();
+            attempt.error = None;
+            attempt.token.clone()
+        };
+        let out = self.col.run_cancellable(s, &token);
+        match self.attempt.lock().unwrap().error.take() {
+            Some(e) => Err(e),
+            None    => Ok(out.unwrap_or(None))
+        }
+    }
+}
@@ -0,0 +1,121 @@
+//! HyperLogLog
+//! ---
+//! A HyperLogLog estimates how many distinct values it has seen, in a fixed amount
+//! of memory however many there are: 2^precision registers of one byte each.  The
+//! estimate is within about 1.04 / sqrt(2^precision) of the true count, so around
+//! 1.6% at the default precision of 12, which takes 4 KiB.
+//!
+//! Sketches of separate sets of values merge into the sketch of their union, which
+//! is what `count_distinct_by_key_approx` relies on to combine partitions.
+//!
+
+use std::hash::Hash;
+
+use super::bloom::hash_key;
+
+/// Estimates the number of distinct values added to it.
+/// ```rust
+///   extern crate tange_collection;
+///   use tange_collection::collection::hyperloglog::HyperLogLog;
+///
+///   let mut words = HyperLogLog::new(12);
+///   for i in 0..10000 {
+///       words.insert(&(i % 2500));
+///   }
+///   let estimate = words.count();
+///   assert!(estimate > 2400 && estimate < 2600);
+/// ```
+#[derive(Clone,Debug,PartialEq,Eq,Serialize,Deserialize)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>
+}
+
+impl HyperLogLog {
+    /// Creates an empty HyperLogLog with 2^precision registers, holding the precision
+    /// between 4 and 16
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(4, 16);
+        HyperLogLog { precision, registers: vec![0; 1 << precision] }
+    }
+
+    /// Returns the precision of the sketch
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Adds a value to the sketch
+    pub fn insert<V: Hash + ?Sized>(&mut self, value: &V) {
+        let hash = hash_key(value);
+        let idx = (hash >> (64 - self.precision)) as usize;
+        // Rank of the first set bit among those left, with a marker bit ending the
+        // run of zeros at the last one
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[idx] {
+            self.registers[idx] = rank;
+        }
+    }
+
+    /// Adds every value seen by `other` to the sketch.  Panics if the sketches'
+    /// precisions differ.
+    pub fn merge(&mut self, other: &HyperLogLog) {
+        assert_eq!(self.precision, other.precision, "can't merge HyperLogLogs of different precisions");
+        for (r, o) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *r = (*r).max(*o);
+        }
+    }
+
+    /// Returns the estimated number of distinct values added
+    pub fn count(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _  => 0.7213 / (1.0 + 1.079 / m)
+        };
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+
+        // Counting empty registers is more accurate for small sets
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as usize
+        } else {
+            estimate.round() as usize
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_hyperloglog {
+    use super::*;
+
+    #[test]
+    fn test_hyperloglog() {
+        assert_eq!(HyperLogLog::new(12).count(), 0);
+        for &n in &[1usize, 100, 5000, 200_000] {
+            let mut sketch = HyperLogLog::new(12);
+            for i in 0..n {
+                sketch.insert(&i);
+                sketch.insert(&i);
+            }
+            let error = (sketch.count() as f64 - n as f64).abs() / n as f64;
+            assert!(error < 0.05, "{} for {}", sketch.count(), n);
+        }
+    }
+
+    #[test]
+    fn test_merge() {
+        let (mut left, mut right) = (HyperLogLog::new(10), HyperLogLog::new(10));
+        let mut both = HyperLogLog::new(10);
+        for i in 0..3000usize {
+            if i < 2000 { left.insert(&i) } else { right.insert(&i) }
+            both.insert(&i);
+        }
+        left.merge(&right);
+        assert_eq!(left, both);
+        assert_eq!(HyperLogLog::new(2).precision(), 4);
+    }
+}
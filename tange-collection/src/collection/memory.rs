@@ -6,26 +6,54 @@
 //!
 
 extern crate serde;
+extern crate csv;
+extern crate serde_json;
+#[cfg(feature = "regex")]
+extern crate regex;
 use std::fs;
 use std::any::Any;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap,HashMap,HashSet};
 use std::io::prelude::*;
-use std::io::BufWriter;
+use std::io::{self,BufWriter};
+use std::fmt::{self,Display};
 use std::hash::Hash;
+use std::iter::{self,FromIterator,Product,Sum};
+use std::ops::{Add,Mul,Range};
+use std::path::{Path,PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+#[cfg(feature = "tokio")]
+use std::future::Future;
 
 use self::serde::{Deserialize,Serialize};
+#[cfg(feature = "regex")]
+use self::regex::Regex;
 
+use collection::broadcast::Broadcast;
 use collection::disk::DiskCollection;
-use tange::deferred::{Deferred, batch_apply, tree_reduce};
-use tange::scheduler::Scheduler;
-use partitioned::{join_on_key as jok, partition, partition_by_key, fold_by, concat};
-use interfaces::{Memory,Disk};
-use super::emit;
+use collection::fallible::{Attempt,Fallible,ParseError,RecordError};
+use tange::deferred::{Deferred, batch_apply, gather, tree_reduce};
+use tange::scheduler::{Scheduler,Progress,CancellationToken,Cancelled,ConcurrencyLimit,RunError,TimeoutError};
+#[cfg(feature = "tokio")]
+use tange::scheduler::AsyncScheduler;
+use partitioned::{join_on_key as jok, partition, partition_by_key, fold_by, fold_local, combine_by_key, combine_local, group_local, rolling_local, cross_where, concat, rebalance, split_with};
+use interfaces::{Memory,Disk,stream_or_panic};
+use random::RandomOptions;
+use utils::{CsvHeaders,TextReader,csv_deserialize,read_csv,read_fixed,read_framed};
+use super::{Encoding,FileNaming,OverwritePolicy,PartWriter};
+use super::{Evaluate,Integer,Partitioning,PlanNode,PlanStats,StageKind};
+use super::bloom::BloomFilter;
+use super::hyperloglog::HyperLogLog;
+use super::{all,cache_root,cached_parts,cancelled_or_panic,checkpoint,collect_groups,dedup_sorted,emit,filter_with,fold_monoid,heavy_hitters,is_sorted_by,join_strings,keep_top,kth,key_id,key_shares,KeyRoutes,map_with,parse,salt,sample,sample_with_replacement,to_bloom,split_results,tag_order,tag_random,unzip,validate,validate_warn,zip,finish_sink,fmt_plan,stage_name,key_dir,sink_bincode,sink_error,stored_parts,target_dir,temp_path,TempFile};
 
 
 /// MemoryCollection struct
 #[derive(Clone)]
 pub struct MemoryCollection<A>  {
-    partitions: Vec<Deferred<Vec<A>>>
+    partitions: Vec<Deferred<Vec<A>>>,
+    plan: Arc<PlanNode>
 }
 
 impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
@@ -33,15 +61,127 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     /// Creates a MemoryCollection from a set of Deferred objects.
     pub fn from_defs(vs: Vec<Deferred<Vec<A>>>) -> MemoryCollection<A> {
         MemoryCollection {
+            plan: PlanNode::new("from_defs", StageKind::Source, vs.len(), Vec::new()),
             partitions: vs
         }
     }
 
+    /// Creates a MemoryCollection with no partitions.  Every operation accepts it,
+    /// producing empty results, and running it yields an empty Vec.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::<usize>::empty();
+    ///   assert_eq!(col.n_partitions(), 0);
+    ///   assert_eq!(col.count().run(&GreedyScheduler::new()), Some(vec![0]));
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![]));
+    /// ```
+    pub fn empty() -> MemoryCollection<A> {
+        MemoryCollection::from_defs(Vec::new()).source("empty")
+    }
+
     /// Provides raw access to the underlying Deferred objects
     pub fn to_defs(&self) -> &Vec<Deferred<Vec<A>>> {
         &self.partitions
     }
 
+    /// Names the stage producing the collection, which otherwise gets a generated
+    /// name like "map#3".  Names show up in the collection's Debug output, in run
+    /// metrics alongside the number of records the stage produced, and in the 
+    /// message of any panic it raises, along with the failing partition.  The
+    /// named collection is computed separately from this one, so it's best named
+    /// when it's created.
+    /// ```rust
+    ///   extern crate tange_collection;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1", "2"]).map(|s| s.len()).named("lengths");
+    ///   assert!(format!("{:?}", col).contains("lengths"));
+    /// ```
+    pub fn named(&self, name: &str) -> MemoryCollection<A> {
+        let partitions = self.partitions.iter().enumerate()
+            .map(|(idx, p)| p.clone().named_part(name, idx).sized(|vs| vs.len()))
+            .collect();
+        MemoryCollection { partitions, plan: self.plan.renamed(name) }
+    }
+
+    /// Summarizes the stages producing the collection, one per line, with the
+    /// operation that created each, how it moves records between partitions, the
+    /// number of partitions it produces, and the stages it reads from.  Stages that
+    /// funnel several partitions into one, and so run without any parallelism, are
+    /// marked.
+    /// ```rust
+    ///   extern crate tange_collection;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 4)
+    ///       .map(|x| (x % 10, *x))
+    ///       .fold_by(|x| x.0, || 0, |acc, x| *acc += x.1, |x, y| *x += y, 1);
+    ///   assert_eq!(col.explain(), "\
+    ///       stage  operation         kind          partitions  inputs\n\
+    ///       0      from_vec_chunked  source        4\n\
+    ///       1      map               element-wise  4           0\n\
+    ///       2      fold_by           shuffle       1           1       <- single partition\n");
+    /// ```
+    pub fn explain(&self) -> String {
+        self.plan.explain()
+    }
+
+    /// Measures the plan behind the collection without running any of it: how many
+    /// tasks it takes, the longest chain of them, the tasks of each stage, and how
+    /// many stages funnel several partitions into one.  This is the structured
+    /// counterpart of `explain`, for checking a plan before running it.
+    /// ```rust
+    ///   extern crate tange_collection;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 4)
+    ///       .map(|x| (x % 10, *x))
+    ///       .fold_by(|x| x.0, || 0, |acc, x| *acc += x.1, |x, y| *x += y, 1);
+    ///   let stats = col.dry_run();
+    ///   let tasks: Vec<_> = stats.stages.iter().map(|s| (s.op.as_str(), s.tasks)).collect();
+    ///   assert_eq!(tasks, vec![("from_vec_chunked", 4), ("map", 4), ("fold_by", 1)]);
+    ///   assert_eq!(stats.bottlenecks, 1);
+    ///   assert!(stats.tasks > 9);
+    /// ```
+    pub fn dry_run(&self) -> PlanStats {
+        let nodes: Vec<_> = self.partitions.iter().map(|p| p.node()).collect();
+        self.plan.stats(&nodes)
+    }
+
+    // Describes the collection as produced by `op` from `inputs`
+    pub(crate) fn with_plan(mut self, op: &str, kind: StageKind, inputs: Vec<Arc<PlanNode>>) -> MemoryCollection<A> {
+        self.plan = PlanNode::new(op, kind, self.partitions.len(), inputs);
+        self
+    }
+
+    // Describes the collection as read or generated by `op`
+    pub(crate) fn source(self, op: &str) -> MemoryCollection<A> {
+        self.with_plan(op, StageKind::Source, Vec::new())
+    }
+
+    // Builds the collection produced from this one by a stage
+    fn derive<B: Any + Send + Sync + Clone>(
+        &self, 
+        op: &str, 
+        kind: StageKind, 
+        partitions: Vec<Deferred<Vec<B>>>
+    ) -> MemoryCollection<B> {
+        MemoryCollection::from_defs(partitions)
+            .with_plan(op, kind, vec![self.plan.clone()])
+            .stage(op)
+    }
+
+    // Attributes the stage producing the collection to `op`, giving it a generated
+    // name
+    fn stage(&self, op: &str) -> MemoryCollection<A> {
+        let col = self.named(&stage_name(op));
+        MemoryCollection { partitions: col.partitions, plan: self.plan.relabeled(op) }
+    }
+
     /// Creates a new MemoryCollection from a Vec of items
     /// ```rust
     ///   extern crate tange;
@@ -53,9 +193,112 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![1,2,3usize]));
     /// ```
     pub fn from_vec(vs: Vec<A>) -> MemoryCollection<A> {
-        MemoryCollection {
-            partitions: vec![Deferred::lift(vs, None)],
+        MemoryCollection::from_defs(vec![Deferred::lift(vs, None)]).source("from_vec")
+    }
+
+    /// Creates a new MemoryCollection from a Vec of items, split into `n_partitions`
+    /// contiguous partitions of nearly equal size.  Items are moved, not copied.
+    /// There are never more partitions than items, so none are empty, though an
+    /// empty Vec still yields a single, empty, partition.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1,2,3,4,5usize], 2);
+    ///   assert_eq!(col.n_partitions(), 2);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![1,2,3,4,5usize]));
+    /// ```
+    pub fn from_vec_chunked(mut vs: Vec<A>, n_partitions: usize) -> MemoryCollection<A> {
+        let n = n_partitions.min(vs.len()).max(1);
+        let (size, extra) = (vs.len() / n, vs.len() % n);
+        let mut partitions = Vec::with_capacity(n);
+        // Split off from the back so each piece is moved exactly once
+        for idx in (0..n).rev() {
+            let start = idx * size + idx.min(extra);
+            partitions.push(Deferred::lift(vs.split_off(start), None));
+        }
+        partitions.reverse();
+        MemoryCollection::from_defs(partitions).source("from_vec_chunked")
+    }
+
+    /// Creates a new MemoryCollection by consuming an iterator, starting a new
+    /// partition every `chunk_size` items.  The iterator is read to the end now,
+    /// holding only finished partitions and the one being filled, so there's no
+    /// need to collect it into a Vec first.  An empty iterator yields a single,
+    /// empty, partition.  Panics if `chunk_size` is zero.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_iter_chunked((1..6usize).map(|x| x * x), 2);
+    ///   assert_eq!(col.n_partitions(), 3);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![1,4,9,16,25usize]));
+    /// ```
+    pub fn from_iter_chunked<I: IntoIterator<Item=A>>(iter: I, chunk_size: usize) -> MemoryCollection<A> {
+        assert!(chunk_size > 0, "from_iter_chunked requires a chunk_size of at least 1");
+        let mut partitions = Vec::new();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for item in iter {
+            chunk.push(item);
+            if chunk.len() == chunk_size {
+                partitions.push(Deferred::lift(chunk, None));
+                chunk = Vec::with_capacity(chunk_size);
+            }
         }
+        if !chunk.is_empty() || partitions.is_empty() {
+            partitions.push(Deferred::lift(chunk, None));
+        }
+        MemoryCollection::from_defs(partitions).source("from_iter_chunked")
+    }
+
+    /// Creates a new single partition MemoryCollection whose items come from the
+    /// iterator returned by `f`, which is called each time the partition is run
+    /// rather than now.  Use `split` to spread the items over more partitions.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_iter_lazy(|| (0..4usize).filter(|x| x % 2 == 0));
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![0, 2usize]));
+    /// ```
+    pub fn from_iter_lazy<
+        I: IntoIterator<Item=A>,
+        F: 'static + Sync + Send + Fn() -> I
+    >(f: F) -> MemoryCollection<A> {
+        let partitions = vec![Deferred::lift((), None).apply(move |_| f().into_iter().collect())];
+        MemoryCollection::from_defs(partitions).source("from_iter_lazy")
+    }
+
+    /// Creates a new MemoryCollection of `n_partitions` partitions, each holding
+    /// the items `f` returns when called with that partition's index.  `f` runs
+    /// inside each partition's task, every time it's run, rather than now, which
+    /// makes it the place for work like querying one shard of a database or
+    /// drawing a seeded batch of samples.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_fn(3, |idx| vec![idx; idx]);
+    ///   assert_eq!(col.n_partitions(), 3);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![1, 2, 2usize]));
+    /// ```
+    pub fn from_fn<
+        F: 'static + Sync + Send + Fn(usize) -> Vec<A>
+    >(n_partitions: usize, f: F) -> MemoryCollection<A> {
+        let f = Arc::new(f);
+        let partitions = (0..n_partitions).map(|idx| {
+            let f = f.clone();
+            Deferred::lift(idx, None).apply(move |idx| f(*idx))
+        }).collect();
+        MemoryCollection::from_defs(partitions).source("from_fn")
     }
 
     /// Returns the current number of data partitions 
@@ -83,7 +326,83 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
             nps.push(p.clone());
         }
 
-        MemoryCollection { partitions: nps }
+        MemoryCollection::from_defs(nps)
+            .with_plan("concat", StageKind::Union, vec![self.plan.clone(), other.plan.clone()])
+    }
+
+    /// Pairs every value with the value in the same place of `other`, partition by
+    /// partition, as `Iterator::zip` does.  Partitions of different lengths pair up
+    /// to the end of the shorter, dropping the rest, and the collections must have
+    /// the same number of partitions.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let nums = MemoryCollection::from_vec(vec![1, 2, 3usize]);
+    ///   let squares = nums.map(|x| x * x);
+    ///   assert_eq!(nums.zip(&squares).run(&GreedyScheduler::new()), Some(vec![(1, 1), (2, 4), (3, 9)]));
+    /// ```
+    pub fn zip<B: Any + Send + Sync + Clone>(&self, other: &MemoryCollection<B>) -> MemoryCollection<(A, B)> {
+        assert_eq!(self.n_partitions(), other.n_partitions(), 
+                   "can't zip collections with different numbers of partitions");
+        let parts = zip(&self.partitions, &other.partitions, Memory);
+        MemoryCollection::from_defs(parts)
+            .with_plan("zip", StageKind::ElementWise, vec![self.plan.clone(), other.plan.clone()])
+            .stage("zip")
+    }
+
+    /// Concatenates two collections and redistributes the values into `n_partitions`
+    /// partitions of nearly equal size in a single shuffle, rather than keeping the
+    /// partitions of both.  Partitions are balanced by count, not by hashing, and the
+    /// values keep their order: the first partition holds the first values of `self`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let big = MemoryCollection::from_vec_chunked((0..90usize).collect(), 30);
+    ///   let small = MemoryCollection::from_vec((90..100usize).collect());
+    ///   let cat = big.concat_rebalanced(&small, 4);
+    ///   assert_eq!(cat.n_partitions(), 4);
+    ///   assert_eq!(cat.run(&GreedyScheduler::new()), Some((0..100).collect()));
+    /// ```
+    pub fn concat_rebalanced(&self, other: &MemoryCollection<A>, n_partitions: usize) -> MemoryCollection<A> {
+        let cat = self.concat(other);
+        let parts = rebalance(&cat.partitions, n_partitions);
+        cat.derive("concat_rebalanced", StageKind::Shuffle, parts)
+    }
+
+    /// Concatenates any number of collections into a single Collection, in order,
+    /// building the combined partition list in one pass rather than one `concat`
+    /// at a time.  No collections give an empty Collection, and one gives a clone
+    /// of it.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let days: Vec<_> = (0..3usize).map(|d| MemoryCollection::from_vec(vec![d * 10, d * 10 + 1])).collect();
+    ///   let all = MemoryCollection::concat_all(&days);
+    ///   assert_eq!(all.n_partitions(), 3);
+    ///   assert_eq!(all.run(&GreedyScheduler::new()), Some(vec![0, 1, 10, 11, 20, 21]));
+    /// ```
+    pub fn concat_all(collections: &[MemoryCollection<A>]) -> MemoryCollection<A> {
+        match collections.len() {
+            0 => MemoryCollection::empty(),
+            1 => collections[0].clone(),
+            _ => {
+                let nps = collections.iter()
+                    .flat_map(|c| c.partitions.iter().cloned())
+                    .collect();
+                let plans = collections.iter().map(|c| c.plan.clone()).collect();
+                MemoryCollection::from_defs(nps)
+                    .with_plan("concat_all", StageKind::Union, plans)
+            }
+        }
     }
     
     /// Maps a function over the values in the DiskCollection, returning a new DiskCollection
@@ -104,7 +423,116 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     >(&self, f: F) -> MemoryCollection<B> {
         self.emit(move |x, emitter| {
             emitter(f(x))
-        })
+        }).stage("map")
+    }
+
+    /// Maps a function over the values in the collection along with every value of
+    /// `other`, gathered into one Vec, such as a total computed from the same
+    /// collection.  Both are computed in the same run, `other` once for all of the
+    /// partitions.  Reductions like `count` give collections of a single value.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1, 2, 3, 4usize], 2);
+    ///   let shares = col.map_with(&col.count(), |x, n| *x as f64 / n[0] as f64);
+    ///   assert_eq!(shares.run(&GreedyScheduler::new()), Some(vec![0.25, 0.5, 0.75, 1.0]));
+    /// ```
+    pub fn map_with<
+        B: Any + Send + Sync + Clone,
+        C: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&A, &Vec<B>) -> C
+    >(&self, other: &MemoryCollection<B>, f: F) -> MemoryCollection<C> {
+        let parts = map_with(&self.partitions, &other.gathered(), Memory, f);
+        MemoryCollection::from_defs(parts)
+            .with_plan("map_with", StageKind::ElementWise, vec![self.plan.clone(), other.plan.clone()])
+            .stage("map_with")
+    }
+
+    /// Maps a function over the values in the collection along with the value held
+    /// by `b`, which every task reads rather than a copy of its own.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::broadcast::Broadcast;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let offsets = Broadcast::new(vec![10, 20, 30usize]);
+    ///   let col = MemoryCollection::from_vec_chunked(vec![0, 1, 2usize], 3);
+    ///   let shifted = col.map_broadcast(&offsets, |x, offsets| x + offsets[*x]);
+    ///   assert_eq!(shifted.run(&GreedyScheduler::new()), Some(vec![10, 21, 32]));
+    /// ```
+    pub fn map_broadcast<
+        T: Any + Send + Sync,
+        B: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&A, &T) -> B
+    >(&self, b: &Broadcast<T>, f: F) -> MemoryCollection<B> {
+        let parts = map_with(&self.partitions, b.deferred(), Memory, move |x, v: &Arc<T>| f(x, v));
+        self.derive("map_broadcast", StageKind::ElementWise, parts)
+    }
+
+    /// Maps a fallible function over the values in the collection, keeping the
+    /// Result for each.  See `try_map_all` to stop at the first error instead.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1", "x", "3"]);
+    ///   let parsed = col.try_map(|s| s.parse::<u32>()).run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(parsed[0], Ok(1));
+    ///   assert!(parsed[1].is_err());
+    /// ```
+    pub fn try_map<
+        B: Any + Send + Sync + Clone, 
+        E: Any + Send + Sync + Clone, 
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<B, E>
+    >(&self, f: F) -> MemoryCollection<Result<B, E>> {
+        self.emit(move |x, emitter| {
+            emitter(f(x))
+        }).stage("try_map")
+    }
+
+    /// Maps a fallible function over the values in the collection, failing the
+    /// whole run on the first error.  Once a record fails, the rest of its
+    /// partition is skipped and no further tasks are scheduled, and `run` returns
+    /// the error along with the position of the record.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec!["1", "2", "x", "4"], 2);
+    ///   let err = col.try_map_all(|s| s.parse::<u32>()).run(&GreedyScheduler::new()).unwrap_err();
+    ///   assert_eq!((err.partition, err.index), (1, 0));
+    ///   assert_eq!(err.to_string(), "record 0 of partition 1 failed: invalid digit found in string");
+    /// ```
+    pub fn try_map_all<
+        B: Any + Send + Sync + Clone, 
+        E: Any + Send + Sync, 
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<B, E>
+    >(&self, f: F) -> Fallible<B, E> {
+        let attempt = Attempt::new();
+        let failed = attempt.clone();
+        let parts = batch_apply(&self.partitions, move |idx, vs| {
+            let mut out = Vec::with_capacity(vs.len());
+            for (i, v) in vs.iter().enumerate() {
+                match f(v) {
+                    Ok(b)  => out.push(b),
+                    Err(e) => {
+                        failed.lock().unwrap().fail(RecordError { partition: idx, index: i, error: e });
+                        break;
+                    }
+                }
+            }
+            out
+        });
+        Fallible::new(self.derive("try_map_all", StageKind::ElementWise, parts), attempt)
     }
 
     /// Filters out items in the collection that fail the predicate.
@@ -127,9 +555,94 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
             if f(x) { 
                 emitter(x.clone())
             }
-        })
+        }).stage("filter")
     }
     
+    /// Checks every value with `f`, passing the values on unchanged unless any of
+    /// them is rejected.  If any is, the run fails rather than panicking with the
+    /// first bad value: `try_run` returns a `TaskError` giving how many values were
+    /// rejected, and the first ten along with where they were found and why.  Every
+    /// partition is checked before any is passed on, so nothing built from the
+    /// collection sees its values until all of them are accepted.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::{GreedyScheduler,RunError};
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let ages = MemoryCollection::from_vec_chunked(vec![31, -2, 47, 210i32], 2);
+    ///   let checked = ages.validate(|a| if (0..150).contains(a) { Ok(()) } else { Err(format!("bad age {}", a)) });
+    ///   match checked.try_run(&GreedyScheduler::new()) {
+    ///       Err(RunError::Failed(e)) => assert_eq!(e.message, "2 records failed validation: \
+    ///           record 1 of partition 0 failed: bad age -2; record 1 of partition 1 failed: bad age 210"),
+    ///       _                        => panic!("expected a failure")
+    ///   }
+    /// ```
+    pub fn validate<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+    >(&self, f: F) -> MemoryCollection<A> {
+        let parts = validate(&self.partitions, f);
+        self.derive("validate", StageKind::ElementWise, parts)
+    }
+
+    /// Checks every value with `f` as `validate` does, but only logs a warning for
+    /// each partition with values `f` rejects, giving how many there were and the
+    /// first few, when the "log" feature is enabled.  Values are passed on
+    /// unchanged, rejected or not, and each partition as soon as it's checked.
+    pub fn validate_warn<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+    >(&self, f: F) -> MemoryCollection<A> {
+        let parts = validate_warn(&self.partitions, f);
+        self.derive("validate_warn", StageKind::ElementWise, parts)
+    }
+
+    /// Keeps each value with a chance of `fraction`, independently of the others.
+    /// With a seed, the same values are kept each time the collection runs, as long
+    /// as it's partitioned the same way; see `RandomOptions`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   use tange_collection::random::RandomOptions;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..1000usize).collect(), 4);
+    ///   let sampled = col.sample(0.1, RandomOptions::seeded(7));
+    ///   let kept = sampled.run(&GreedyScheduler::new()).unwrap();
+    ///   assert!(kept.len() > 50 && kept.len() < 150);
+    ///   assert_eq!(sampled.run(&GreedyScheduler::new()), Some(kept));
+    /// ```
+    pub fn sample(&self, fraction: f64, opts: RandomOptions) -> MemoryCollection<A> {
+        let parts = sample(&self.partitions, Memory, fraction, opts.master_seed());
+        self.derive("sample", StageKind::ElementWise, parts)
+    }
+
+    /// Draws exactly `n` values uniformly at random from the whole collection, with
+    /// replacement, as for bootstrap resampling.  The partitions are counted first,
+    /// the `n` draws divided between them by their sizes, and each partition draws
+    /// its share from its own values, so nothing is gathered.  Each partition's
+    /// draws come out in the order of its values.  With a seed, the same values are
+    /// drawn each time the collection runs, as long as it's partitioned the same
+    /// way; an empty collection has nothing to draw and gives an empty sample.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   use tange_collection::random::RandomOptions;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 3);
+    ///   let resampled = col.sample_with_replacement(25, RandomOptions::seeded(3));
+    ///   let drawn = resampled.run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(drawn.len(), 25);
+    ///   assert!(drawn.iter().all(|x| *x < 10));
+    ///   assert_eq!(resampled.run(&GreedyScheduler::new()), Some(drawn));
+    /// ```
+    pub fn sample_with_replacement(&self, n: usize, opts: RandomOptions) -> MemoryCollection<A> {
+        let parts = sample_with_replacement(&self.partitions, Memory, n, opts.master_seed());
+        self.derive("sample_with_replacement", StageKind::ElementWise, parts)
+    }
+
     /// Re-partitions a collection by the number of provided chunks.  It uniformly distributes data from each old partition into each new partition.
     /// ```rust
     ///   extern crate tange;
@@ -143,7 +656,7 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     ///   assert_eq!(two.n_partitions(), 2);
     /// ```
     pub fn split(&self, n_chunks: usize) -> MemoryCollection<A> {
-        self.partition(n_chunks, |idx, _k| idx)
+        self.partition(n_chunks, |idx, _k| idx).stage("split")
     }
 
     /// Maps over all items in a collection, optionally emitting new values.  It can be used
@@ -169,7 +682,7 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     >(&self, f: F) -> MemoryCollection<B> {
         let parts = emit(&self.partitions, Memory, f);
 
-        MemoryCollection { partitions: parts }
+        self.derive("emit", StageKind::ElementWise, parts)
     }
 
     /// Maps over all items in a collection, emitting new values.  It can be used
@@ -198,6 +711,7 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
         let parts = emit(&self.partitions, Disk::from_str(&path), f);
 
         DiskCollection::from_stores(path, parts)
+            .with_plan("emit_to_disk", StageKind::ElementWise, vec![self.plan.clone()])
     }
 
     /// Re-partitions data into N new partitions by the given function.  The user provided
@@ -222,7 +736,7 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
                                    partitions, 
                                    f);
         // Loop over each bucket
-        MemoryCollection { partitions: new_chunks }
+        self.derive("partition", StageKind::Shuffle, new_chunks)
     }
 
     /// Folds and accumulates values across multiple partitions into K new partitions.
@@ -231,7 +745,8 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     /// MemoryCollection first performs a block aggregation: that is, it combines values
     /// within each partition first using the `binop` function.  It then hashes
     /// each key to a new partition index, where it will then aggregate all keys using the
-    /// `reduce` function.
+    /// `reduce` function.  `partitions` is a count or a `Partitioning`; if the collection
+    /// is already partitioned by `key` into that many partitions, the shuffle is skipped.
     ///
     /// ```rust
     ///   extern crate tange;
@@ -257,16 +772,141 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
                    D: 'static + Sync + Send + Clone + Fn() -> B, 
                    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
                    O: 'static + Sync + Send + Clone + Fn(&mut B, &A) -> (),
-                   R: 'static + Sync + Send + Clone + Fn(&mut B, &B) -> ()>(
-        &self, key: F, default: D, binop: O, reduce: R, partitions: usize
+                   R: 'static + Sync + Send + Clone + Fn(&mut B, &B) -> (),
+                   P: Into<Partitioning>>(
+        &self, key: F, default: D, binop: O, reduce: R, partitions: P
     ) -> MemoryCollection<(K,B)> {
+        let partitions = partitions.into().count(self.partitions.len());
+        if self.plan.is_hashed(key_id(&key), partitions) {
+            let results = fold_local(&self.partitions, key, default, binop, Vec::with_capacity(0));
+            return self.derive("fold_by", StageKind::ElementWise, results);
+        }
         let results = fold_by(&self.partitions, key, default, binop, 
                               reduce, Vec::with_capacity(0), partitions);
-        MemoryCollection { partitions: results }
+        self.derive("fold_by", StageKind::Shuffle, results)
+    }
+
+    /// Folds values by key as `fold_by` does, spreading each key over `salts`
+    /// partitions before bringing it together again, for keys holding a large share
+    /// of the values.  The values of every partition are salted, adding one of
+    /// `salts` numbers to their key, and folded on the salted keys.  The folds of
+    /// each key's salts are then combined with `reduce` on the key alone, so merging
+    /// the partitions' folds of a heavy key is shared among the tasks of `salts`
+    /// partitions, leaving `salts` folds of it to merge at the end.
+    ///
+    /// The result matches `fold_by` only if `reduce` is associative and
+    /// commutative: folds are merged in a different order and grouping, and no
+    /// longer start from `default`.  A `reduce` that isn't, like one keeping the
+    /// first value it sees, gives different results for the same input.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..1000usize).map(|x| if x % 4 == 0 { x } else { 0 }).collect(), 8);
+    ///   let salted = col.fold_by_salted(4, |x| *x == 0, || 0usize, |acc, _| *acc += 1, |a, b| *a += b, 2)
+    ///       .sort_by(|x| x.0);
+    ///   assert_eq!(salted.run(&GreedyScheduler::new()), Some(vec![(false, 249), (true, 751)]));
+    /// ```
+    pub fn fold_by_salted<K: Any + Sync + Send + Clone + Hash + Eq,
+                          B: Any + Sync + Send + Clone,
+                          D: 'static + Sync + Send + Clone + Fn() -> B, 
+                          F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+                          O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+                          R: 'static + Sync + Send + Clone + Fn(&mut B, &B),
+                          P: Into<Partitioning>>(
+        &self, salts: usize, key: F, default: D, binop: O, reduce: R, partitions: P
+    ) -> MemoryCollection<(K,B)> {
+        let partitions = partitions.into().count(self.partitions.len());
+        let salted = salt(&self.partitions, Vec::with_capacity(0), salts.max(1));
+        let (r1, r2) = (reduce.clone(), reduce.clone());
+        self.derive("fold_by_salted", StageKind::ElementWise, salted)
+            .fold_by(move |x| (key(&x.1), x.0), default, move |acc, x| binop(acc, &x.1), reduce, partitions)
+            .combine_by_key(|x| x.0 .0.clone(), |x| x.1.clone(), move |acc, x| r1(acc, &x.1), r2, partitions)
+            .stage("fold_by_salted")
+    }
+
+    /// Combines the values of each key into a combiner of type `C`, created from the
+    /// key's first value with `create`, rather than from a default as with `fold_by`.
+    /// Later values are added with `merge_value`, within each partition, and the
+    /// combiners of each partition are merged with `merge_combiners` after hashing
+    /// the keys into `partitions`.  Suits combiners with no natural empty value, like
+    /// the smallest value seen.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let temps = MemoryCollection::from_vec_chunked(
+    ///       vec![("oslo", -3i32), ("rome", 12), ("oslo", -8), ("rome", 9), ("oslo", 1)], 2);
+    ///   // Range of temperatures in each city
+    ///   let ranges = temps.combine_by_key(|t| t.0,
+    ///                                     |t| (t.1, t.1),
+    ///                                     |r, t| *r = (r.0.min(t.1), r.1.max(t.1)),
+    ///                                     |r, o| *r = (r.0.min(o.0), r.1.max(o.1)),
+    ///                                     1)
+    ///       .sort_by(|x| x.0);
+    ///   assert_eq!(ranges.run(&GreedyScheduler::new()),
+    ///              Some(vec![("oslo", (-8, 1)), ("rome", (9, 12))]));
+    /// ```
+    pub fn combine_by_key<
+        K: Any + Sync + Send + Clone + Hash + Eq,
+        C: Any + Sync + Send + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        I: 'static + Sync + Send + Clone + Fn(&A) -> C,
+        O: 'static + Sync + Send + Clone + Fn(&mut C, &A),
+        R: 'static + Sync + Send + Clone + Fn(&mut C, &C),
+        P: Into<Partitioning>
+    >(
+        &self, key: F, create: I, merge_value: O, merge_combiners: R, partitions: P
+    ) -> MemoryCollection<(K,C)> {
+        let partitions = partitions.into().count(self.partitions.len());
+        if self.plan.is_hashed(key_id(&key), partitions) {
+            let results = combine_local(&self.partitions, key, create, merge_value, Vec::with_capacity(0));
+            return self.derive("combine_by_key", StageKind::ElementWise, results);
+        }
+        let results = combine_by_key(&self.partitions, key, create, merge_value,
+                                     merge_combiners, Vec::with_capacity(0), partitions);
+        self.derive("combine_by_key", StageKind::Shuffle, results)
+    }
+
+    /// Groups the values of the collection by a key, giving every key once along
+    /// with all of its values.  The values are hashed by key into `partitions`, a
+    /// count or a `Partitioning`, each of which groups its own keys, so no partition
+    /// holds more than its share of the groups.  The order of values within a group
+    /// is unspecified.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec!["ant", "bee", "asp", "bat", "cow"], 2);
+    ///   let groups = col.group_by(|x| x.chars().next().unwrap(), 2);
+    ///   assert_eq!(groups.n_partitions(), 2);
+    ///   let mut groups = groups.run(&GreedyScheduler::new()).unwrap();
+    ///   for g in groups.iter_mut() {
+    ///       g.1.sort();
+    ///   }
+    ///   groups.sort();
+    ///   assert_eq!(groups, vec![('a', vec!["ant", "asp"]), ('b', vec!["bat", "bee"]), ('c', vec!["cow"])]);
+    /// ```
+    pub fn group_by<
+        K: Any + Sync + Send + Clone + Hash + Eq,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        P: Into<Partitioning>
+    >(&self, key: F, partitions: P) -> MemoryCollection<(K, Vec<A>)> {
+        let hashed = self.partition_by_key(partitions, key.clone());
+        let groups = group_local(&hashed.partitions, key, |x| x, Vec::with_capacity(0));
+        hashed.derive("group_by", StageKind::ElementWise, groups)
     }
 
     /// Simple function to re-partition values by a given key.  The return key is hashed
-    /// and moduloed by the new partition count to determine where it will end up.
+    /// and moduloed by the new partition count to determine where it will end up.  The
+    /// result remembers `key`, when it captures nothing, so keyed operations on the same
+    /// key need not shuffle again; see `Partitioning`.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
@@ -280,12 +920,35 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
     ///   assert_eq!(new_col.run(&GreedyScheduler::new()), Some(vec![4, 1, 2, 3]));
     /// ```
     pub fn partition_by_key<
+        K: Any + Sync + Send + Clone + Hash + Eq,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        P: Into<Partitioning>
+    >(&self, partitions: P, key: F) -> MemoryCollection<A> {
+        let partitions = partitions.into().count(self.partitions.len());
+        let id = key_id(&key);
+        let col = if self.plan.is_hashed(id, partitions) {
+            self.derive("partition_by_key", StageKind::ElementWise, self.partitions.clone())
+        } else {
+            let results = partition_by_key(&self.partitions, partitions, key);
+            let groups = results.into_iter().filter_map(|part| concat(&part)).collect();
+            self.derive("partition_by_key", StageKind::Shuffle, groups)
+        };
+        MemoryCollection { plan: col.plan.hashed(id), ..col }
+    }
+
+    // Pairs each value with its key, hashed by the key into `partitions` unless the
+    // values already are
+    fn keyed<
         K: Any + Sync + Send + Clone + Hash + Eq,
         F: 'static + Sync + Send + Clone + Fn(&A) -> K
-    >(&self, n_chunks: usize, key: F) -> MemoryCollection<A> {
-        let results = partition_by_key(&self.partitions, n_chunks, key);
-        let groups = results.into_iter().map(|part| concat(&part).unwrap()).collect();
-        MemoryCollection {partitions: groups}
+    >(&self, key: F, partitions: usize) -> MemoryCollection<(K, A)> {
+        let hashed = self.plan.is_hashed(key_id(&key), partitions);
+        let pairs = self.map(move |x| (key(x), x.clone()));
+        if hashed {
+            pairs
+        } else {
+            pairs.partition_by_key(partitions, |x| x.0.clone())
+        }
     }
 
     /// Sorts values within each partition by a key function.  If a global sort is desired,
@@ -310,12 +973,65 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
             v2.sort_by_key(|v| key(v));
             v2
         });
-        MemoryCollection { partitions: nps }
+        self.derive("sort_by", StageKind::ElementWise, nps)
+    }
+
+    /// Runs a check that the collection is sorted by a key function, as a whole and
+    /// not only within each partition: every partition must be sorted, and each must
+    /// end with a key no greater than the one the next nonempty partition starts
+    /// with.  Partitions are checked in parallel, passing on only their first and
+    /// last keys.  Empty collections and partitions are sorted.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1, 2, 3, 4usize], 2);
+    ///   assert_eq!(col.is_sorted_by(|x| *x, &GreedyScheduler::new()), Some(true));
+    ///   assert_eq!(col.is_sorted_by(|x| *x % 3, &GreedyScheduler::new()), Some(false));
+    ///
+    ///   // Sorting within each partition leaves [1, 3] before [2, 4]
+    ///   let each = MemoryCollection::from_vec_chunked(vec![3, 1, 4, 2usize], 2).sort_by(|x| *x);
+    ///   assert_eq!(each.is_sorted_by(|x| *x, &GreedyScheduler::new()), Some(false));
+    /// ```
+    pub fn is_sorted_by<
+        K: Any + Send + Sync + Clone + Ord,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        S: Scheduler
+    >(&self, key: F, s: &S) -> Option<bool> {
+        is_sorted_by(&self.partitions, key).run(s)
+    }
+
+    /// Drops each value with the same key as the value before it, for collections
+    /// already sorted by the key, such as after a global sort or when read from a
+    /// sorted file.  Each partition is read once, without building a set of keys,
+    /// and compares its first value against the last key of the partitions before
+    /// it.  On sorted input the result is distinct by key, keeping the first value of
+    /// each key, but on unsorted input it drops only adjacent duplicates.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1, 1, 2, 2, 2, 3, 3, 1usize], 3);
+    ///   let deduped = col.dedup_sorted(|x| *x);
+    ///   assert_eq!(deduped.run(&GreedyScheduler::new()), Some(vec![1, 2, 3, 1]));
+    /// ```
+    pub fn dedup_sorted<
+        K: Any + Send + Sync + Clone + PartialEq,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, key: F) -> MemoryCollection<A> {
+        let parts = dedup_sorted(&self.partitions, Memory, key);
+        self.derive("dedup_sorted", StageKind::ElementWise, parts)
     }
 
     /// Inner Joins two collections by the provided key function.
     /// If multiple values of the same key are found, they will be cross product for each
-    /// pair found.
+    /// pair found.  `partitions` is a count or a `Partitioning`, with `Preserve` taking
+    /// the left collection's count.  Either side already partitioned by its key into
+    /// that many partitions is not shuffled again.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
@@ -343,40 +1059,415 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
         KF1: 'static + Sync + Send + Clone + Fn(&A) -> K,
         KF2: 'static + Sync + Send + Clone + Fn(&B) -> K,
         J:   'static + Sync + Send + Clone + Fn(&A, &B) -> C,
+        P:   Into<Partitioning>
     >(
         &self, 
         other: &MemoryCollection<B>, 
         key1: KF1, 
         key2: KF2,
         joiner: J,
-        partitions: usize, 
+        partitions: P, 
     ) -> MemoryCollection<(K,C)> {
         // Group each by a common key
-        let p1 = self.map(move |x| (key1(x), x.clone()))
-            .partition_by_key(partitions, |x| x.0.clone());
-        let p2 = other.map(move |x| (key2(x), x.clone()))
-           .partition_by_key(partitions, |x| x.0.clone());
+        let partitions = partitions.into().count(self.partitions.len());
+        let p1 = self.keyed(key1, partitions);
+        let p2 = other.keyed(key2, partitions);
 
         let mut new_parts = Vec::with_capacity(p1.partitions.len());
         for (l, r) in p1.partitions.iter().zip(p2.partitions.iter()) {
             new_parts.push(jok(l, r, Memory, joiner.clone()));
         }
 
-        MemoryCollection { partitions: new_parts }
+        MemoryCollection::from_defs(new_parts)
+            .with_plan("join_on", StageKind::Shuffle, vec![p1.plan.clone(), p2.plan.clone()])
+            .stage("join_on")
     }
 
-    /// Executes the Collection, returning the result of the computation
-    pub fn run<S: Scheduler>(&self, s: &S) -> Option<Vec<A>> {
-        let cat = tree_reduce(&self.partitions, |x, y| {
-            let mut v1: Vec<_> = (*x).clone();
-            for yi in y {
-                v1.push(yi.clone());
-            }
-            v1
-        });
-        cat.and_then(|x| x.run(s))
-    }
-}
+    /// Pairs every value of this collection with every value of `other` for which
+    /// `f` holds, such as events with the time ranges containing them.  Each
+    /// partition is crossed with each of `other`'s in a task of its own, which keeps
+    /// only the matching pairs, giving one partition per partition of this collection.
+    /// This calls `f` on every pair, |self| x |other| times, so when values match on
+    /// equal keys, `join_on` is far cheaper.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let events = MemoryCollection::from_vec_chunked(vec![3u32, 12, 25], 2);
+    ///   let ranges = MemoryCollection::from_vec(vec![(0u32, 10u32, "early"), (10, 30, "late")]);
+    ///   let labelled = events.join_where(&ranges, |t, r| r.0 <= *t && *t < r.1)
+    ///       .map(|x| (x.0, (x.1).2));
+    ///   assert_eq!(labelled.run(&GreedyScheduler::new()), Some(vec![(3, "early"), (12, "late"), (25, "late")]));
+    /// ```
+    pub fn join_where<
+        B: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&A, &B) -> bool
+    >(&self, other: &MemoryCollection<B>, f: F) -> MemoryCollection<(A, B)> {
+        let crossed = cross_where(&self.partitions, &other.partitions, Memory, f);
+        let parts = crossed.iter().filter_map(|pieces| concat(pieces)).collect();
+        MemoryCollection::from_defs(parts)
+            .with_plan("join_where", StageKind::Shuffle, vec![self.plan.clone(), other.plan.clone()])
+            .stage("join_where")
+    }
+
+    /// Joins two collections like `join_on`, first dropping the values of this
+    /// collection whose keys aren't found in a BloomFilter of `other`'s keys, built
+    /// with `bits_per_key` bits for each.  When `other` is much smaller, most values
+    /// are dropped rather than shuffled.  The results are those of `join_on`: values
+    /// the filter lets through by mistake find nothing to join with.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///
+    ///   let visits = MemoryCollection::from_vec_chunked((0..1000usize).map(|x| (x % 500, x)).collect(), 4);
+    ///   let flagged = MemoryCollection::from_vec(vec![(3usize, "spam"), (7, "bot")]);
+    ///   let joined = visits.join_with_bloom(&flagged, |v| v.0, |f| f.0, |v, f| (v.1, f.1), 4, 10)
+    ///       .sort_by(|x| x.1);
+    ///   assert_eq!(joined.run(&GreedyScheduler::new()),
+    ///              Some(vec![(3, (3, "spam")), (3, (503, "spam")), (7, (7, "bot")), (7, (507, "bot"))]));
+    /// ```
+    pub fn join_with_bloom<
+        B: Any + Send + Sync + Clone,
+        K: Any + Sync + Send + Clone + Hash + Eq,
+        C: Any + Sync + Send + Clone,
+        KF1: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        KF2: 'static + Sync + Send + Clone + Fn(&B) -> K,
+        J:   'static + Sync + Send + Clone + Fn(&A, &B) -> C,
+        P:   Into<Partitioning>
+    >(
+        &self, 
+        other: &MemoryCollection<B>, 
+        key1: KF1, 
+        key2: KF2,
+        joiner: J,
+        partitions: P, 
+        bits_per_key: usize
+    ) -> MemoryCollection<(K,C)> {
+        let bloom = other.to_bloom(key2.clone(), bits_per_key);
+        self.filter_bloom(&bloom, key1.clone())
+            .join_on(other, key1, key2, joiner, partitions)
+    }
+
+    /// Builds a BloomFilter of the keys of every value, with `bits_per_key` bits for
+    /// each distinct key, giving a collection holding the one filter.  Each
+    /// partition's keys are added to a filter of their own, merged into one after.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///
+    ///   let col = MemoryCollection::from_vec_chunked(vec!["a", "b", "c", "a"], 2);
+    ///   let filters = col.to_bloom(|x| *x, 8).run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(filters.len(), 1);
+    ///   assert!(filters[0].contains(&"c"));
+    /// ```
+    pub fn to_bloom<
+        K: Hash,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, key: F, bits_per_key: usize) -> MemoryCollection<BloomFilter> {
+        let out = to_bloom(&self.partitions, key, bits_per_key).apply(|f| vec![f.clone()]);
+        self.derive("to_bloom", StageKind::Reduce, vec![out])
+    }
+
+    /// Keeps the values whose keys may be held by one of the filters in `bloom`, as
+    /// built by `to_bloom`, and drops the rest.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///
+    ///   let allowed = MemoryCollection::from_vec(vec![2, 4usize]).to_bloom(|x| *x, 16);
+    ///   let col = MemoryCollection::from_vec(vec![(1usize, 'a'), (2, 'b'), (4, 'c')]);
+    ///   let kept = col.filter_bloom(&allowed, |x| x.0).run(&GreedyScheduler::new()).unwrap();
+    ///   assert!(kept.contains(&(2, 'b')) && kept.contains(&(4, 'c')));
+    /// ```
+    pub fn filter_bloom<
+        K: Hash,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K
+    >(&self, bloom: &MemoryCollection<BloomFilter>, key: F) -> MemoryCollection<A> {
+        let parts = filter_with(&self.partitions, &bloom.gathered(), Memory, move |x, filters: &Vec<BloomFilter>| {
+            let k = key(x);
+            filters.iter().any(|f| f.contains(&k))
+        });
+        MemoryCollection::from_defs(parts)
+            .with_plan("filter_bloom", StageKind::ElementWise, vec![self.plan.clone(), bloom.plan.clone()])
+            .stage("filter_bloom")
+    }
+
+    /// Keeps the partitions in memory once a run computes them, so later runs of
+    /// this collection or of anything built from it, such as several aggregations
+    /// over one cleaned dataset, read them rather than running anything upstream
+    /// again.  Unlike `tange::memo::MemoizingScheduler`, this works with any
+    /// scheduler.  The partitions are released once the cached collection and
+    /// everything built from it are dropped.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1,2,3usize]).map(|x| x * 10).cache();
+    ///   assert_eq!(col.count().run(&GreedyScheduler::new()), Some(vec![3]));
+    ///   // Reads the partitions kept by the first run
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![10, 20, 30]));
+    /// ```
+    pub fn cache(&self) -> MemoryCollection<A> {
+        let partitions = self.partitions.iter().map(|d| d.cache()).collect();
+        self.derive("cache", StageKind::ElementWise, partitions)
+    }
+
+    /// Computes no more than `n` partitions of the collection at once, for stages
+    /// which mustn't run wide, like a map calling a rate limited service, while
+    /// other stages run as wide as the scheduler allows.  The limit carries on
+    /// through the element-wise stages built from the collection, such as `map`,
+    /// `filter` and the files written by `sink`, but stops at stages combining
+    /// partitions, such as joins, sorts and shuffles; apply it again after one of
+    /// those to keep limiting what follows.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 10)
+    ///       .with_max_concurrency(4)
+    ///       .map(|x| x * 2);
+    ///   assert_eq!(col.count().run(&GreedyScheduler::new()), Some(vec![100]));
+    /// ```
+    pub fn with_max_concurrency(&self, n: usize) -> MemoryCollection<A> {
+        let limit = ConcurrencyLimit::new(n);
+        let partitions = self.partitions.iter().map(|d| d.with_limit(&limit)).collect();
+        MemoryCollection { partitions, plan: self.plan.clone() }
+    }
+
+    /// Estimates the bytes each partition of the collection takes in memory, so a
+    /// scheduler with a memory budget, such as a GreedyScheduler given
+    /// `max_live_bytes`, can hold back computing more partitions than fit at once.
+    /// The estimate covers this stage only; stages built from the collection need
+    /// estimates of their own.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let scheduler = GreedyScheduler::builder().max_live_bytes(4 * 80_000).build();
+    ///   let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 10)
+    ///       .map(|x| vec![*x; 1000])
+    ///       .size_hint_bytes(10 * 8000);
+    ///   assert_eq!(col.map(|v| v.len()).count().run(&scheduler), Some(vec![100]));
+    /// ```
+    pub fn size_hint_bytes(&self, bytes: usize) -> MemoryCollection<A> {
+        let partitions = self.partitions.iter().map(|d| d.size_hint_bytes(bytes)).collect();
+        MemoryCollection { partitions, plan: self.plan.clone() }
+    }
+
+    /// Executes the Collection, returning the result of the computation
+    pub fn run<S: Scheduler>(&self, s: &S) -> Option<Vec<A>> {
+        self.run_with_progress(s, &Progress::new())
+    }
+
+    /// Executes the Collection as with `run`, reporting to `progress` as each task
+    /// finishes.  Another thread can poll a clone of `progress` while it runs.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::{GreedyScheduler,Progress};
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 4).map(|x| x * 2);
+    ///   let progress = Progress::with_callback(|done, total| println!("{}/{} tasks", done, total));
+    ///   assert_eq!(col.count().run_with_progress(&GreedyScheduler::new(), &progress), Some(vec![10]));
+    ///   assert_eq!(progress.completed(), progress.total());
+    /// ```
+    pub fn run_with_progress<S: Scheduler>(&self, s: &S, progress: &Progress) -> Option<Vec<A>> {
+        self.run_until(s, progress, &CancellationToken::new()).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Executes the Collection as with `run`, until `token` is cancelled.  Once it
+    /// is, no new tasks start, though any already running are allowed to finish,
+    /// and the error returned counts the tasks which completed.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::{GreedyScheduler,CancellationToken};
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1, 2, 3usize]).map(|x| x + 1);
+    ///   let token = CancellationToken::new();
+    ///   assert_eq!(col.run_cancellable(&GreedyScheduler::new(), &token), Ok(Some(vec![2, 3, 4])));
+    ///   token.cancel();
+    ///   assert!(col.run_cancellable(&GreedyScheduler::new(), &token).is_err());
+    /// ```
+    pub fn run_cancellable<S: Scheduler>(
+        &self, 
+        s: &S, 
+        token: &CancellationToken
+    ) -> Result<Option<Vec<A>>, Cancelled> {
+        self.run_until(s, &Progress::new(), token).map_err(cancelled_or_panic)
+    }
+
+    /// Executes the Collection as with `run`, giving up once `timeout` has passed.
+    /// No new tasks start after that, though any running finish first, and the
+    /// error returned counts the tasks which completed, to help decide whether to
+    /// try again with more time.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use std::time::Duration;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1, 2, 3usize]).map(|x| x + 1);
+    ///   let out = col.run_timeout(&GreedyScheduler::new(), Duration::from_secs(60));
+    ///   assert_eq!(out, Ok(Some(vec![2, 3, 4])));
+    /// ```
+    pub fn run_timeout<S: Scheduler>(
+        &self, 
+        s: &S, 
+        timeout: Duration
+    ) -> Result<Option<Vec<A>>, TimeoutError> {
+        self.run_until(s, &Progress::new(), &CancellationToken::with_timeout(timeout))
+            .map_err(|e| TimeoutError::new(timeout, cancelled_or_panic(e)))
+    }
+
+    /// Executes the Collection as with `run`, returning an error naming the stage
+    /// and partition of the first task to panic rather than panicking.  Tasks not
+    /// yet started by then are skipped, and the scheduler can be reused afterwards.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::{GreedyScheduler,RunError};
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1", "2", "x"])
+    ///       .map(|s| s.parse::<u32>().unwrap())
+    ///       .named("parse");
+    ///   match col.try_run(&GreedyScheduler::new()) {
+    ///       Err(RunError::Failed(e)) => assert_eq!(e.stage, Some("parse".into())),
+    ///       _                        => panic!("expected a failure")
+    ///   }
+    /// ```
+    pub fn try_run<S: Scheduler>(&self, s: &S) -> Result<Option<Vec<A>>, RunError> {
+        self.run_until(s, &Progress::new(), &CancellationToken::new())
+    }
+
+    /// Executes the Collection on an AsyncScheduler without blocking the calling
+    /// thread, for use from async code.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   extern crate tokio;
+    ///   use tange::scheduler::AsyncScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let runtime = tokio::runtime::Runtime::new().unwrap();
+    ///   let scheduler = AsyncScheduler::with_handle(runtime.handle().clone(), 8);
+    ///   let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 4).map(|x| x * 2);
+    ///   let out = runtime.block_on(col.run_async(&scheduler));
+    ///   assert_eq!(out, Some(vec![0, 2, 4, 6, 8, 10, 12, 14, 16, 18]));
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self, s: &AsyncScheduler) -> impl Future<Output = Option<Vec<A>>> + Send {
+        self.gathered().run_async(s)
+    }
+
+    fn run_until<S: Scheduler>(
+        &self, 
+        s: &S, 
+        progress: &Progress, 
+        token: &CancellationToken
+    ) -> Result<Option<Vec<A>>, RunError> {
+        match self.concatenated() {
+            Some(x) => x.try_run_until(s, progress, token),
+            None    => {
+                progress.start(0);
+                Ok(Some(Vec::new()))
+            }
+        }
+    }
+
+    // Joins the partitions into one, or None if there aren't any
+    fn concatenated(&self) -> Option<Deferred<Vec<A>>> {
+        tree_reduce(&self.partitions, |x, y| {
+            let mut v1: Vec<_> = (*x).clone();
+            for yi in y {
+                v1.push(yi.clone());
+            }
+            v1
+        })
+    }
+}
+
+impl <A: Any + Send + Sync + Clone> Evaluate for MemoryCollection<A> {
+    type Item = A;
+
+    fn gathered(&self) -> Deferred<Vec<A>> {
+        self.concatenated().unwrap_or_else(|| Deferred::lift(Vec::new(), None))
+    }
+}
+
+/// Collects items into a single partition, as with `from_vec`.  Use
+/// `from_iter_chunked` to spread them over several partitions.
+/// ```rust
+///   extern crate tange;
+///   extern crate tange_collection;
+///   use tange::scheduler::GreedyScheduler;
+///   use tange_collection::collection::memory::MemoryCollection;
+///   
+///   let col: MemoryCollection<u64> = (0..4).map(|x| x * 2).collect();
+///   assert_eq!(col.n_partitions(), 1);
+///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![0, 2, 4, 6]));
+/// ```
+/// Shows the shape of the plan behind the collection: its number of partitions, the
+/// depth of its graph, and the named steps leading to its first partition.
+/// ```rust
+///   extern crate tange_collection;
+///   use tange_collection::collection::memory::MemoryCollection;
+///   
+///   let col = MemoryCollection::from_vec(vec![1,2,3usize]).map(|x| x + 1).named("increment");
+///   assert_eq!(format!("{:?}", col),
+///              "MemoryCollection { partitions: 1, depth: 2 }\n  increment\n    Input");
+/// ```
+impl <A: Any + Send + Sync> fmt::Debug for MemoryCollection<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let nodes: Vec<_> = self.partitions.iter().map(|p| p.node()).collect();
+        fmt_plan(f, "MemoryCollection", &nodes)
+    }
+}
+
+impl <A: Any + Send + Sync + Clone> FromIterator<A> for MemoryCollection<A> {
+    fn from_iter<I: IntoIterator<Item=A>>(iter: I) -> Self {
+        MemoryCollection::from_vec(iter.into_iter().collect())
+    }
+}
+
+/// Appends the items as one new partition after the existing ones.  Extending with
+/// no items leaves the partitions unchanged.
+impl <A: Any + Send + Sync + Clone> Extend<A> for MemoryCollection<A> {
+    fn extend<I: IntoIterator<Item=A>>(&mut self, iter: I) {
+        let vs: Vec<_> = iter.into_iter().collect();
+        if !vs.is_empty() {
+            self.partitions.push(Deferred::lift(vs, None));
+            let added = PlanNode::new("extend", StageKind::Source, 1, Vec::new());
+            self.plan = PlanNode::new("concat", StageKind::Union, self.partitions.len(), 
+                                      vec![self.plan.clone(), added]);
+        }
+    }
+}
+
+/// Creates a single partition collection, as with `from_vec`
+impl <A: Any + Send + Sync + Clone> From<Vec<A>> for MemoryCollection<A> {
+    fn from(vs: Vec<A>) -> Self {
+        MemoryCollection::from_vec(vs)
+    }
+}
 
 impl <A: Any + Send + Sync + Clone> MemoryCollection<Vec<A>> {
 
@@ -397,112 +1488,3798 @@ impl <A: Any + Send + Sync + Clone> MemoryCollection<Vec<A>> {
             for xi in x {
                 emitter(xi.clone());
             }
-        })
+        }).stage("flatten")
     }
 }
 
-impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
+impl <T: Any + Send + Sync + Clone, E: Any + Send + Sync + Clone> MemoryCollection<Result<T, E>> {
 
-    /// Returns the number of items in the collection.
+    /// Keeps the values of the successful Results, in the same partitions.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
     ///   use tange::scheduler::GreedyScheduler;
     ///   use tange_collection::collection::memory::MemoryCollection;
     ///   
-    ///   let col = MemoryCollection::from_vec(vec![vec![1usize,2],vec![3,4]]);
-    ///   assert_eq!(col.count().run(&GreedyScheduler::new()), Some(vec![2]));
-    ///   let flattened = col.flatten();
-    ///   assert_eq!(flattened.count().run(&GreedyScheduler::new()), Some(vec![4]));
+    ///   let col = MemoryCollection::from_vec(vec!["1", "x", "3"]).try_map(|s| s.parse::<u32>());
+    ///   assert_eq!(col.oks().run(&GreedyScheduler::new()), Some(vec![1, 3]));
     /// ```
-    pub fn count(&self) -> MemoryCollection<usize> {
-        let nps = batch_apply(&self.partitions, |_idx, vs| vs.len());
-        let count = tree_reduce(&nps, |x, y| x + y).unwrap();
-        let out = count.apply(|x| vec![*x]);
-        MemoryCollection { partitions: vec![out] }
+    pub fn oks(&self) -> MemoryCollection<T> {
+        self.emit(|x, emitter| {
+            if let Ok(t) = x {
+                emitter(t.clone());
+            }
+        }).stage("oks")
+    }
+
+    /// Keeps the errors of the failed Results, in the same partitions.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1", "x", "3"])
+    ///       .try_map(|s| s.parse::<u32>().map_err(|_| s.to_string()));
+    ///   assert_eq!(col.errs().run(&GreedyScheduler::new()), Some(vec!["x".to_string()]));
+    /// ```
+    pub fn errs(&self) -> MemoryCollection<E> {
+        self.emit(|x, emitter| {
+            if let Err(e) = x {
+                emitter(e.clone());
+            }
+        }).stage("errs")
+    }
+
+    /// Splits the collection into the values of the successful Results and the
+    /// errors of the failed ones, as with `oks` and `errs`.  Each partition is read
+    /// once for both halves, rather than once for each.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1", "x", "3"])
+    ///       .try_map(|s| s.parse::<u32>().map_err(|_| s.to_string()));
+    ///   let (good, bad) = col.split_results();
+    ///   assert_eq!(good.run(&GreedyScheduler::new()), Some(vec![1, 3]));
+    ///   assert_eq!(bad.run(&GreedyScheduler::new()), Some(vec!["x".to_string()]));
+    /// ```
+    pub fn split_results(&self) -> (MemoryCollection<T>, MemoryCollection<E>) {
+        let (oks, errs) = split_results(&self.partitions, Memory, Memory);
+        (self.derive("split_results", StageKind::ElementWise, oks),
+         self.derive("split_results", StageKind::ElementWise, errs))
     }
 }
 
-impl <A: Any + Send + Sync + Clone + PartialEq + Hash + Eq> MemoryCollection<A> {
+impl <A: Any + Send + Sync + Clone, B: Any + Send + Sync + Clone> MemoryCollection<(A, B)> {
 
-    /// Computes the frequencies of the items in collection.
+    /// Splits the pairs into a collection of their first halves and one of their
+    /// second, undoing `zip`.  Both keep the partitioning of this collection, and
+    /// each partition is read once for both, rather than once for each.
     /// ```rust
     ///   extern crate tange;
     ///   extern crate tange_collection;
     ///   use tange::scheduler::GreedyScheduler;
     ///   use tange_collection::collection::memory::MemoryCollection;
     ///   
-    ///   let col = MemoryCollection::from_vec(vec![1, 2, 1, 5, 1, 2]);
-    ///   let freqs = col.frequencies(1).sort_by(|x| x.0);
-    ///   assert_eq!(freqs.run(&GreedyScheduler::new()), Some(vec![(1, 3), (2, 2), (5, 1)]));
+    ///   let pairs = MemoryCollection::from_vec(vec![("a", 1), ("b", 2)]);
+    ///   let (letters, nums) = pairs.unzip();
+    ///   assert_eq!(letters.run(&GreedyScheduler::new()), Some(vec!["a", "b"]));
+    ///   assert_eq!(nums.run(&GreedyScheduler::new()), Some(vec![1, 2]));
     /// ```
-pub fn frequencies(&self, partitions: usize) -> MemoryCollection<(A, usize)> {
-        //self.partition(chunks, |x| x);
-        self.fold_by(|s| s.clone(), 
-                     || 0usize, 
-                     |acc, _l| *acc += 1, 
-                     |x, y| *x += *y, 
-                     partitions)
+    pub fn unzip(&self) -> (MemoryCollection<A>, MemoryCollection<B>) {
+        let (firsts, seconds) = unzip(&self.partitions, Memory, Memory);
+        (self.derive("unzip", StageKind::ElementWise, firsts),
+         self.derive("unzip", StageKind::ElementWise, seconds))
+    }
+
+    /// Swaps the halves of every pair, so the values become the keys of keyed
+    /// operations like `group_by_key`.  Each half is cloned once.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let ages = MemoryCollection::from_vec(vec![("ann", 30), ("bob", 25), ("cy", 30)]);
+    ///   let by_age = ages.swap().group_by_key(1).sort_by(|x| x.0);
+    ///   assert_eq!(by_age.run(&GreedyScheduler::new()), Some(vec![(25, vec!["bob"]), (30, vec!["ann", "cy"])]));
+    /// ```
+    pub fn swap(&self) -> MemoryCollection<(B, A)> {
+        self.map(|(a, b)| (b.clone(), a.clone())).stage("swap")
+    }
+
+    /// Maps the first half of every pair, keeping the second.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![("a", 1), ("b", 2)]);
+    ///   let upper = col.map_keys(|k| k.to_uppercase());
+    ///   assert_eq!(upper.run(&GreedyScheduler::new()), Some(vec![("A".into(), 1), ("B".into(), 2)]));
+    /// ```
+    pub fn map_keys<
+        C: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> C
+    >(&self, f: F) -> MemoryCollection<(C, B)> {
+        self.map(move |(a, b)| (f(a), b.clone())).stage("map_keys")
+    }
+
+    /// Maps the second half of every pair, keeping the first.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![("a", 1), ("b", 2)]);
+    ///   assert_eq!(col.map_values(|v| v * 10).run(&GreedyScheduler::new()), Some(vec![("a", 10), ("b", 20)]));
+    /// ```
+    pub fn map_values<
+        C: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(&B) -> C
+    >(&self, f: F) -> MemoryCollection<(A, C)> {
+        self.map(move |(a, b)| (a.clone(), f(b))).stage("map_values")
     }
 }
 
-// Writes out data
-impl MemoryCollection<String> {
+impl <K: Any + Send + Sync + Clone + Hash + Eq, V: Any + Send + Sync + Clone> MemoryCollection<(K, V)> {
+
+    /// Groups the values of each key, giving every key once along with all of its
+    /// values, hashed by key into `partitions` as with `group_by`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![(1, 'a'), (2, 'b'), (1, 'c')]);
+    ///   let mut groups = col.group_by_key(1).run(&GreedyScheduler::new()).unwrap();
+    ///   groups.sort();
+    ///   assert_eq!(groups, vec![(1, vec!['a', 'c']), (2, vec!['b'])]);
+    /// ```
+    pub fn group_by_key<P: Into<Partitioning>>(&self, partitions: P) -> MemoryCollection<(K, Vec<V>)> {
+        let key = |x: &(K, V)| x.0.clone();
+        let hashed = self.partition_by_key(partitions, key);
+        let groups = group_local(&hashed.partitions, key, |x| x.1, Vec::with_capacity(0));
+        hashed.derive("group_by_key", StageKind::ElementWise, groups)
+    }
+
+    /// Folds the values of each key in order of `sort_key`, starting from `init`,
+    /// giving one value per input value: the key along with the state once `step`
+    /// has added that value, such as the number of events so far in a session.
+    /// Values are hashed by key into `partitions`, as with `group_by_key`, then each
+    /// key's values are sorted on their own; values with equal sort keys keep their
+    /// order in the collection.  Each partition gives its keys in no particular
+    /// order, and each key's states in order.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   // (user, (timestamp, page))
+    ///   let events = MemoryCollection::from_vec_chunked(
+    ///       vec![("ann", (30, "cart")), ("bob", (5, "home")), ("ann", (10, "home")), ("ann", (20, "shoes"))], 2);
+    ///   let pages_so_far = events.rolling_by_key(|e| e.0, 0, |n, _e| n + 1, 1);
+    ///   let mut counts = pages_so_far.run(&GreedyScheduler::new()).unwrap();
+    ///   counts.sort();
+    ///   assert_eq!(counts, vec![("ann", 1), ("ann", 2), ("ann", 3), ("bob", 1)]);
+    /// ```
+    pub fn rolling_by_key<
+        S: Ord,
+        B: Any + Send + Sync + Clone,
+        FS: 'static + Sync + Send + Clone + Fn(&V) -> S,
+        FB: 'static + Sync + Send + Clone + Fn(&B, &V) -> B,
+        P: Into<Partitioning>
+    >(&self, sort_key: FS, init: B, step: FB, partitions: P) -> MemoryCollection<(K, B)> {
+        let hashed = self.partition_by_key(partitions, |x: &(K, V)| x.0.clone());
+        let states = rolling_local(&hashed.partitions, sort_key, init, step, Vec::with_capacity(0));
+        hashed.derive("rolling_by_key", StageKind::ElementWise, states)
+    }
+
+    /// Keeps the `k` highest scoring values of each key, highest first, hashing keys
+    /// into `partitions` as with `fold_by`.  Each partition keeps the top `k` of its
+    /// own values for each key before they're merged, so at most `k` values per key
+    /// are held at once.  Values scoring the same keep their order in the collection.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   // (query, (document, score))
+    ///   let hits = MemoryCollection::from_vec_chunked(
+    ///       vec![("cats", ("a", 3)), ("cats", ("b", 9)), ("dogs", ("c", 1)), ("cats", ("d", 5))], 2);
+    ///   let best = hits.top_k_by_key(2, |h| h.1, 1).sort_by(|x| x.0);
+    ///   assert_eq!(best.run(&GreedyScheduler::new()),
+    ///              Some(vec![("cats", vec![("b", 9), ("d", 5)]), ("dogs", vec![("c", 1)])]));
+    /// ```
+    pub fn top_k_by_key<
+        S: Ord,
+        F: 'static + Sync + Send + Clone + Fn(&V) -> S,
+        P: Into<Partitioning>
+    >(&self, k: usize, score: F, partitions: P) -> MemoryCollection<(K, Vec<V>)> {
+        let (s1, s2, s3) = (score.clone(), score.clone(), score);
+        self.combine_by_key(|x| x.0.clone(),
+                            move |x| { let mut top = Vec::new(); keep_top(&mut top, x.1.clone(), k, &s1); top },
+                            move |top, x| keep_top(top, x.1.clone(), k, &s2),
+                            move |top, other| for v in other.iter() { keep_top(top, v.clone(), k, &s3) },
+                            partitions)
+            .stage("top_k_by_key")
+    }
+
+    /// Keeps a uniform sample of at most `n` values of each key, passing every value
+    /// of keys with fewer.  Each value is given a random tag, and each key keeps the
+    /// `n` values with the lowest tags, so partitions sample their own values before
+    /// the samples are merged.  With a seed, the same values are kept each time, as
+    /// long as the collection is partitioned the same way, though not always in the
+    /// same order; see `RandomOptions`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   use tange_collection::random::RandomOptions;
+    ///   
+    ///   let labelled = MemoryCollection::from_vec_chunked((0..1000usize).map(|x| (x % 10 == 0, x)).collect(), 4);
+    ///   let balanced = labelled.sample_per_key(50, RandomOptions::seeded(3), 2);
+    ///   let mut examples = balanced.run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(examples.iter().filter(|x| x.0).count(), 50);
+    ///   assert_eq!(examples.iter().filter(|x| !x.0).count(), 50);
+    ///
+    ///   let mut again = balanced.run(&GreedyScheduler::new()).unwrap();
+    ///   examples.sort();
+    ///   again.sort();
+    ///   assert_eq!(again, examples);
+    /// ```
+    pub fn sample_per_key<P: Into<Partitioning>>(
+        &self, n: usize, opts: RandomOptions, partitions: P
+    ) -> MemoryCollection<(K, V)> {
+        let tagged = tag_random(&self.partitions, Vec::with_capacity(0), "sample_per_key", opts.master_seed());
+        self.derive("sample_per_key", StageKind::ElementWise, tagged)
+            .top_k_by_key(n, |v| Reverse(v.0), partitions)
+            .emit(|x, emitter| {
+                for v in x.1.iter() {
+                    emitter((x.0.clone(), v.1.clone()));
+                }
+            })
+            .stage("sample_per_key")
+    }
+
+    /// Estimates how the pairs are spread over their keys, from a uniform sample of
+    /// `sample_size` pairs.  Gives each sampled key with its estimated share of the
+    /// pairs, most common first, in a single partition.  A key whose share is well
+    /// over one in `n` will leave one of `n` partitions hashed by key far larger
+    /// than the rest; see `rebalance_by_key_sample`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   use tange_collection::random::RandomOptions;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..10000usize).map(|x| (x % 5 == 0 || x % 7 == 0, x)).collect(), 4);
+    ///   let report = col.key_skew_report(1000, RandomOptions::seeded(1)).run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(report[0].0, false);
+    ///   assert!(report[0].1 > 0.6 && report[0].1 < 0.75);
+    /// ```
+    pub fn key_skew_report(&self, sample_size: usize, opts: RandomOptions) -> MemoryCollection<(K, f64)> {
+        let shares = key_shares(&self.partitions, sample_size, opts.master_seed());
+        self.derive("key_skew_report", StageKind::Reduce, vec![shares])
+    }
+
+    /// Redistributes the pairs into `n_partitions` partitions, spreading the keys
+    /// holding the most pairs over partitions of their own.  The share of each key
+    /// is estimated from a uniform sample of `sample_size` pairs, as with
+    /// `key_skew_report`, which reads the collection once more.  A key sampled at
+    /// more than one in `n_partitions` of the pairs gets as many partitions as its
+    /// share fills, and the rest of the keys are hashed over the partitions left.
+    ///
+    /// The pairs of a heavy key are dealt across its partitions, so a key's values
+    /// may no longer be in one partition: per-key results computed on each
+    /// partition, like `fold_by` with the collection taken as already partitioned,
+    /// must be merged again afterwards.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   use tange_collection::random::RandomOptions;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..8000usize).map(|x| (x % 2 * x, x)).collect(), 4);
+    ///   let spread = col.rebalance_by_key_sample(4, 500, RandomOptions::seeded(1));
+    ///   let sizes = spread.sizes(&GreedyScheduler::new()).unwrap();
+    ///   assert!(sizes.iter().all(|s| *s < 3000), "{:?}", sizes);
+    ///   assert_eq!(sizes.iter().sum::<usize>(), 8000);
+    /// ```
+    pub fn rebalance_by_key_sample(
+        &self, n_partitions: usize, sample_size: usize, opts: RandomOptions
+    ) -> MemoryCollection<(K, V)> {
+        let n = n_partitions.max(1);
+        let shares = key_shares(&self.partitions, sample_size, opts.master_seed());
+        let routes = shares.apply(move |shares| KeyRoutes::new(shares, n));
+        let parts = split_with(&self.partitions, &routes, n, |routes: &KeyRoutes<K>, part, idx, x: &(K, V)| {
+            routes.route(&x.0, part, idx)
+        });
+        self.derive("rebalance_by_key_sample", StageKind::Shuffle, parts)
+    }
+
+    /// Runs the collection, grouping the values of each key into a map.  Each
+    /// partition groups its own values, and the maps are merged into one as they
+    /// finish, without first gathering every pair.  Values of a key appear in
+    /// partition order, otherwise in no particular order.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![("a", 1), ("b", 2), ("a", 3)], 3);
+    ///   let groups = col.collect_groups(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(groups["a"], vec![1, 3]);
+    ///   assert_eq!(groups["b"], vec![2]);
+    /// ```
+    pub fn collect_groups<S: Scheduler>(&self, s: &S) -> Option<HashMap<K, Vec<V>>> {
+        collect_groups(&self.partitions).run(s)
+    }
+}
+
+impl <K: Any + Send + Sync + Clone + Hash + Eq, V: Any + Send + Sync + Clone + Hash + Eq> MemoryCollection<(K, V)> {
+
+    /// Counts the distinct values of each key, exactly, hashing keys into
+    /// `partitions` as with `fold_by`.  Each key's distinct values are held in
+    /// memory; `count_distinct_by_key_approx` needs a fixed amount per key instead.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let orders = MemoryCollection::from_vec_chunked(
+    ///       vec![("ann", "tea"), ("bob", "jam"), ("ann", "jam"), ("ann", "tea")], 2);
+    ///   let mut products = orders.count_distinct_by_key(1).run(&GreedyScheduler::new()).unwrap();
+    ///   products.sort();
+    ///   assert_eq!(products, vec![("ann", 2), ("bob", 1)]);
+    /// ```
+    pub fn count_distinct_by_key<P: Into<Partitioning>>(&self, partitions: P) -> MemoryCollection<(K, usize)> {
+        self.combine_by_key(|x| x.0.clone(),
+                            |x| { let mut seen = HashSet::new(); seen.insert(x.1.clone()); seen },
+                            |seen, x| { seen.insert(x.1.clone()); },
+                            |seen, other| seen.extend(other.iter().cloned()),
+                            partitions)
+            .map(|x| (x.0.clone(), x.1.len()))
+            .stage("count_distinct_by_key")
+    }
+
+    /// Estimates the distinct values of each key with a HyperLogLog of `precision`,
+    /// taking 2^precision bytes per key however many values it has.  At a precision
+    /// of 12 the estimates are usually within 2% of the exact count.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let visits = MemoryCollection::from_vec_chunked((0..20000usize).map(|x| (x % 2, x / 4)).collect(), 4);
+    ///   let visitors = visits.count_distinct_by_key_approx(12, 2).run(&GreedyScheduler::new()).unwrap();
+    ///   assert!(visitors.iter().all(|v| v.1 > 4900 && v.1 < 5100));
+    /// ```
+    pub fn count_distinct_by_key_approx<P: Into<Partitioning>>(
+        &self, precision: u8, partitions: P
+    ) -> MemoryCollection<(K, usize)> {
+        self.combine_by_key(|x| x.0.clone(),
+                            move |x| { let mut hll = HyperLogLog::new(precision); hll.insert(&x.1); hll },
+                            |hll, x| hll.insert(&x.1),
+                            |hll, other| hll.merge(other),
+                            partitions)
+            .map(|x| (x.0.clone(), x.1.count()))
+            .stage("count_distinct_by_key_approx")
+    }
+}
+
+impl <K: Any + Send + Sync + Clone + Hash + Eq> MemoryCollection<(K, f64)> {
+
+    /// Replaces each value with the running total of its key's values, up to and
+    /// including it.  Each key's values are added in the order of the collection:
+    /// partition by partition, and in order within each.  Keys are hashed into
+    /// `partitions`, as with `group_by_key`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![("a", 1.0), ("b", 2.0), ("a", 0.5), ("a", 4.0)], 3);
+    ///   let mut totals = col.cumsum_by_key(2).run(&GreedyScheduler::new()).unwrap();
+    ///   totals.sort_by(|x, y| x.partial_cmp(y).unwrap());
+    ///   assert_eq!(totals, vec![("a", 1.0), ("a", 1.5), ("a", 5.5), ("b", 2.0)]);
+    /// ```
+    pub fn cumsum_by_key<P: Into<Partitioning>>(&self, partitions: P) -> MemoryCollection<(K, f64)> {
+        let tagged = tag_order(&self.partitions, Vec::with_capacity(0));
+        self.derive("cumsum_by_key", StageKind::ElementWise, tagged)
+            .rolling_by_key(|v| v.0, 0.0, |sum, v| sum + v.1, partitions)
+    }
+}
+
+impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
+
+    /// Returns the number of items in the collection.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![vec![1usize,2],vec![3,4]]);
+    ///   assert_eq!(col.count().run(&GreedyScheduler::new()), Some(vec![2]));
+    ///   let flattened = col.flatten();
+    ///   assert_eq!(flattened.count().run(&GreedyScheduler::new()), Some(vec![4]));
+    /// ```
+    pub fn count(&self) -> MemoryCollection<usize> {
+        let nps = batch_apply(&self.partitions, |_idx, vs| vs.len());
+        let count = tree_reduce(&nps, |x, y| x + y)
+            .unwrap_or_else(|| Deferred::lift(0, None));
+        let out = count.apply(|x| vec![*x]);
+        self.derive("count", StageKind::Reduce, vec![out])
+    }
+
+    /// Runs the collection, returning the number of values in each partition, in
+    /// order.  Each partition is counted on its own and the counts are gathered
+    /// rather than summed, which shows how evenly the data is spread: alongside
+    /// `explain`, it's how to tell why one task of a stage runs far longer than the
+    /// rest.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 3);
+    ///   assert_eq!(col.sizes(&GreedyScheduler::new()), Some(vec![4, 3, 3]));
+    ///   assert_eq!(col.filter(|x| *x < 5).sizes(&GreedyScheduler::new()), Some(vec![4, 1, 0]));
+    /// ```
+    pub fn sizes<S: Scheduler>(&self, s: &S) -> Option<Vec<usize>> {
+        gather(&batch_apply(&self.partitions, |_idx, vs| vs.len())).run(s)
+    }
+
+    /// Runs the collection, returning whether every value satisfies `f`.  Each
+    /// partition is checked where it's computed, stopping at its first failure, and
+    /// only the answers are combined.  An empty collection satisfies any `f`, so
+    /// returns true.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![2, 4, 6, 7usize], 2);
+    ///   assert_eq!(col.all(|x| *x > 0, &GreedyScheduler::new()), Some(true));
+    ///   assert_eq!(col.all(|x| x % 2 == 0, &GreedyScheduler::new()), Some(false));
+    ///   assert_eq!(col.filter(|_| false).all(|_| false, &GreedyScheduler::new()), Some(true));
+    /// ```
+    pub fn all<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> bool, 
+        S: Scheduler
+    >(&self, f: F, s: &S) -> Option<bool> {
+        all(&self.partitions, f).run(s)
+    }
+
+    /// Runs the collection, returning whether any value satisfies `f`, checking
+    /// partitions as `all` does.  An empty collection has no value to satisfy `f`,
+    /// so returns false.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![2, 4, 6, 7usize], 2);
+    ///   assert_eq!(col.any(|x| x % 2 == 1, &GreedyScheduler::new()), Some(true));
+    ///   assert_eq!(col.any(|x| *x > 10, &GreedyScheduler::new()), Some(false));
+    ///   assert_eq!(col.filter(|_| false).any(|_| true, &GreedyScheduler::new()), Some(false));
+    /// ```
+    pub fn any<
+        F: 'static + Sync + Send + Clone + Fn(&A) -> bool, 
+        S: Scheduler
+    >(&self, f: F, s: &S) -> Option<bool> {
+        all(&self.partitions, move |x| !f(x)).apply(|none| !none).run(s)
+    }
+
+    /// Folds every value into a single one with `op`, starting from `identity`:
+    /// each partition is folded where it's computed, and the folds combined in a
+    /// tree.  `op` must be associative, and `identity` must leave any value it's
+    /// combined with unchanged (0 for sums, 1 for products), since it starts every
+    /// partition's fold and is all an empty collection folds to.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![3, 9, 4usize], 2);
+    ///   let largest = col.fold_monoid(0, |x, y| *x.max(y));
+    ///   assert_eq!(largest.run(&GreedyScheduler::new()), Some(vec![9]));
+    ///   let words = MemoryCollection::from_vec(vec!["ab".to_string(), "c".into()]);
+    ///   assert_eq!(words.fold_monoid(String::new(), |x, y| x.clone() + y).run(&GreedyScheduler::new()),
+    ///              Some(vec!["abc".into()]));
+    /// ```
+    pub fn fold_monoid<
+        F: 'static + Sync + Send + Clone + Fn(&A, &A) -> A
+    >(&self, identity: A, op: F) -> MemoryCollection<A> {
+        let out = fold_monoid(&self.partitions, identity, op).apply(|x| vec![x.clone()]);
+        self.derive("fold_monoid", StageKind::Reduce, vec![out])
+    }
+
+    /// Sums the values in the collection, as a monoid fold from zero.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1.5, 2.0, 3.5], 2);
+    ///   assert_eq!(col.sum().run(&GreedyScheduler::new()), Some(vec![7.0]));
+    /// ```
+    pub fn sum(&self) -> MemoryCollection<A> 
+        where A: Sum + Add<Output = A>
+    {
+        self.fold_monoid(iter::empty().sum(), |x, y| x.clone() + y.clone()).stage("sum")
+    }
+
+    /// Multiplies the values in the collection, as a monoid fold from one.  Integer
+    /// products overflow as `*` does, panicking in debug builds and wrapping in
+    /// release builds; `checked_product` and `wrapping_product` choose explicitly.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![1.5, 2.0, 4.0], 2);
+    ///   assert_eq!(col.product().run(&GreedyScheduler::new()), Some(vec![12.0]));
+    /// ```
+    pub fn product(&self) -> MemoryCollection<A> 
+        where A: Product + Mul<Output = A>
+    {
+        self.fold_monoid(iter::empty().product(), |x, y| x.clone() * y.clone()).stage("product")
+    }
+
+    /// Multiplies the integers in the collection, returning None if the product
+    /// overflows.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![2, 3, 7u8], 2);
+    ///   assert_eq!(col.checked_product().run(&GreedyScheduler::new()), Some(vec![Some(42)]));
+    ///   assert_eq!(col.map(|x| x * 2).checked_product().run(&GreedyScheduler::new()), Some(vec![None]));
+    /// ```
+    pub fn checked_product(&self) -> MemoryCollection<Option<A>> 
+        where A: Integer
+    {
+        self.map(|x| Some(*x))
+            .fold_monoid(Some(A::ONE), |x, y| x.and_then(|x| y.and_then(|y| x.checked_mul(y))))
+            .stage("checked_product")
+    }
+
+    /// Multiplies the integers in the collection, wrapping around on overflow.
+    pub fn wrapping_product(&self) -> MemoryCollection<A> 
+        where A: Integer
+    {
+        self.fold_monoid(A::ONE, |x, y| x.wrapping_mul(*y)).stage("wrapping_product")
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Ord> MemoryCollection<A> {
+
+    /// Runs the collection, returning the value at index `k` of its values were they
+    /// sorted, or None if there are no more than `k`.  Rather than sorting, values
+    /// are selected between narrowing bounds: each round samples them to choose a
+    /// pivot, and counts those below it, until few enough are left to gather and
+    /// sort.  The collection runs twice each round, usually for a handful of rounds,
+    /// so expensive collections are worth caching first.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![5, 1, 4, 2, 3usize], 2);
+    ///   assert_eq!(col.kth(0, &GreedyScheduler::new()), Some(1));
+    ///   assert_eq!(col.kth(3, &GreedyScheduler::new()), Some(4));
+    ///   assert_eq!(col.kth(5, &GreedyScheduler::new()), None);
+    /// ```
+    pub fn kth<S: Scheduler>(&self, k: usize, s: &S) -> Option<A> {
+        kth(&self.partitions, k, s)
+    }
+
+    /// Runs the collection, returning its median, or None if it's empty.  With an
+    /// even number of values, the lower of the middle two is returned.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec_chunked(vec![9, 1, 7, 3usize], 2);
+    ///   assert_eq!(col.median(&GreedyScheduler::new()), Some(3));
+    /// ```
+    pub fn median<S: Scheduler>(&self, s: &S) -> Option<A> {
+        let n = self.count().run(s)?[0];
+        self.kth(n.checked_sub(1)? / 2, s)
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + PartialEq + Hash + Eq> MemoryCollection<A> {
+
+    /// Computes the frequencies of the items in collection.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1, 2, 1, 5, 1, 2]);
+    ///   let freqs = col.frequencies(1).sort_by(|x| x.0);
+    ///   assert_eq!(freqs.run(&GreedyScheduler::new()), Some(vec![(1, 3), (2, 2), (5, 1)]));
+    /// ```
+pub fn frequencies<P: Into<Partitioning>>(&self, partitions: P) -> MemoryCollection<(A, usize)> {
+        //self.partition(chunks, |x| x);
+        self.fold_by(|s| s.clone(), 
+                     || 0usize, 
+                     |acc, _l| *acc += 1, 
+                     |x, y| *x += *y, 
+                     partitions).stage("frequencies")
+    }
+
+    /// Finds the values making up at least `threshold` of the collection, such as
+    /// 0.01 for those in 1% of its records or more, along with their estimated
+    /// counts, most frequent first.  Rather than counting every distinct value, each
+    /// partition counts them in a CountMinSketch of `depth` rows of `width`
+    /// counters, and tracks the few candidates frequent enough to qualify; the
+    /// sketches are summed and the candidates checked against the total.
+    ///
+    /// Estimates can only overcount, never undercount, so every value with a true
+    /// count of at least `threshold` of the total is returned, and no count is
+    /// below the true one.  Values a little less frequent may be returned as well:
+    /// with probability 1 - e^-depth, an estimate is over by at most e / `width` of
+    /// the total.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let mut clicks: Vec<usize> = (0..10000).collect();
+    ///   clicks.extend(vec![7; 500]);
+    ///   clicks.extend(vec![42; 300]);
+    ///   let col = MemoryCollection::from_vec_chunked(clicks, 4);
+    ///   let top = col.heavy_hitters(0.02, 2000, 4).run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(top.iter().map(|x| x.0).collect::<Vec<_>>(), vec![7, 42]);
+    ///   assert!(top[0].1 >= 501 && top[1].1 >= 301);
+    /// ```
+    pub fn heavy_hitters(&self, threshold: f64, width: usize, depth: usize) -> MemoryCollection<(A, usize)> {
+        let out = heavy_hitters(&self.partitions, threshold, width, depth).apply(|hs| hs.clone());
+        self.derive("heavy_hitters", StageKind::Reduce, vec![out])
+    }
+}
+
+impl <A: Any + Send + Sync + Clone> MemoryCollection<A> {
+
+    /// Writes each record in a collection to disk by calling `f` with the record and
+    /// a buffered writer for its partition's file, avoiding an intermediate String
+    /// per record.  The formatter writes any separators itself, which allows for
+    /// custom or binary formats.  Returns the number of records written to each
+    /// partition's file, as with `sink`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use std::io::Write;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![(1, 'a'), (2, 'b')]);
+    ///   let counts = col.sink_with("/tmp/tange-sink-with-doc", |(n, c), w| write!(w, "{}={};", n, c));
+    ///   assert_eq!(counts.run(&GreedyScheduler::new()), Some(vec![2]));
+    ///   assert_eq!(std::fs::read_to_string("/tmp/tange-sink-with-doc/0").unwrap(), "1=a;2=b;");
+    /// ```
+    pub fn sink_with<
+        P: Into<PathBuf>,
+        F: 'static + Sync + Send + Clone + Fn(&A, &mut dyn Write) -> io::Result<()>
+    >(&self, path: P, f: F) -> MemoryCollection<usize> {
+        self.write_parts(target_dir(path.into(), None), FileNaming::default(), Encoding::Plain, f)
+            .map(|p| p.1)
+    }
+
+    // Writes each partition to its own file within the target directory using the
+    // formatter, returning the absolute path and record count of each file
+    fn write_parts<
+        F: 'static + Sync + Send + Clone + Fn(&A, &mut dyn Write) -> io::Result<()>
+    >(
+        &self, 
+        target: Deferred<PathBuf>, 
+        naming: FileNaming, 
+        encoding: Encoding, 
+        f: F
+    ) -> MemoryCollection<(String, usize)> {
+        let n_parts = self.partitions.len();
+        let pats = self.partitions.iter().enumerate().map(|(idx, part)| {
+            let f = f.clone();
+            let name = naming.name(idx, n_parts);
+            part.join(&target, move |vs, dir| {
+                let (name, mut out) = PartWriter::create(dir, &name, idx, encoding);
+
+                for record in vs {
+                    let written = f(record, &mut out);
+                    out.check(written);
+                }
+                out.finish();
+
+                vec![(name.to_string_lossy().into_owned(), vs.len())]
+            })
+        }).collect::<Vec<_>>();
+        
+        self.derive("sink", StageKind::Sink, vec![finish_sink(&target, &pats)])
+    }
+
+    /// Writes each record beneath `root` into a subdirectory named by its key, such
+    /// as `root/date=2024-01-01/part-00003`, using the formatter.  Each partition
+    /// writes its own file within every key's subdirectory, named by the partition
+    /// index.  Characters in keys which could escape `root`, such as path separators,
+    /// are replaced with underscores.  Returns the absolute path of each file
+    /// written along with its record count.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use std::io::Write;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![("2024-01-01", 3), ("2024-01-02", 4)]);
+    ///   let written = col.sink_partitioned("/tmp/tange-sink-partitioned-doc", 
+    ///       |(date, _)| format!("date={}", date),
+    ///       |(_, n), w| writeln!(w, "{}", n));
+    ///   assert_eq!(written.run(&GreedyScheduler::new()), Some(vec![
+    ///       ("/tmp/tange-sink-partitioned-doc/date=2024-01-01/part-00000".into(), 1),
+    ///       ("/tmp/tange-sink-partitioned-doc/date=2024-01-02/part-00000".into(), 1)
+    ///   ]));
+    /// ```
+    pub fn sink_partitioned<
+        P: Into<PathBuf>,
+        K: Display,
+        F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+        W: 'static + Sync + Send + Clone + Fn(&A, &mut dyn Write) -> io::Result<()>
+    >(&self, root: P, key: F, fmt: W) -> MemoryCollection<(String, usize)> {
+        let target = target_dir(root.into(), None);
+        let naming = FileNaming::new("part-", 5, "");
+        let n_parts = self.partitions.len();
+        let pats = self.partitions.iter().enumerate().map(|(idx, part)| {
+            let key = key.clone();
+            let fmt = fmt.clone();
+            let name = naming.name(idx, n_parts);
+            part.join(&target, move |vs, root| {
+                let mut groups = BTreeMap::new();
+                for record in vs {
+                    let k = key_dir(&key(record).to_string());
+                    groups.entry(k).or_insert_with(Vec::new).push(record);
+                }
+
+                groups.into_iter().map(|(k, records)| {
+                    let (path, mut out) = PartWriter::create(
+                        &root.join(k), &name, idx, Encoding::Plain);
+                    for record in records.iter() {
+                        let written = fmt(record, &mut out);
+                        out.check(written);
+                    }
+                    out.finish();
+                    (path.to_string_lossy().into_owned(), records.len())
+                }).collect::<Vec<_>>()
+            })
+        }).collect::<Vec<_>>();
+        
+        self.derive("sink", StageKind::Sink, vec![finish_sink(&target, &pats)])
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Display> MemoryCollection<A> {
+
+    /// Writes each record in a collection to disk using its Display implementation,
+    /// newline delimited.  MemoryCollection will create a new file within the path
+    /// for each partition.
+    pub fn sink_display<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        self.sink_with(path, |record, w| writeln!(w, "{}", record))
+    }
+}
+
+impl MemoryCollection<u64> {
+    /// Creates a new MemoryCollection of the numbers in `range`, split into
+    /// `n_partitions` contiguous sub-ranges whose sizes differ by at most one.
+    /// Each partition's numbers are only generated when its task runs, so
+    /// building even a very large collection is free.  As with
+    /// `from_vec_chunked`, there are never more partitions than numbers, and an
+    /// empty range yields a single, empty, partition.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_range(3..8, 2);
+    ///   assert_eq!(col.n_partitions(), 2);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec![3,4,5,6,7u64]));
+    /// ```
+    pub fn from_range(range: Range<u64>, n_partitions: usize) -> MemoryCollection<u64> {
+        let len = range.end.saturating_sub(range.start);
+        let n = (n_partitions as u64).min(len).max(1);
+        let (size, extra) = (len / n, len % n);
+        MemoryCollection::from_fn(n as usize, move |idx| {
+            let idx = idx as u64;
+            let start = range.start + idx * size + idx.min(extra);
+            let end = start + size + if idx < extra { 1 } else { 0 };
+            (start..end).collect()
+        }).source("from_range")
+    }
+}
+
+// Writes out data
+impl MemoryCollection<String> {
+
+    /// Reads a new-line delimited text file into a collection of lines, without their
+    /// trailing newlines.  The file is split into `n_partitions` byte ranges which
+    /// are read lazily, in parallel, when the collection is run.  See TextReader for
+    /// how compressed files and overly long lines are handled.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   std::fs::write("/tmp/tange-read-text-doc", "one\ntwo\nthree\n").unwrap();
+    ///   let col = MemoryCollection::read_text("/tmp/tange-read-text-doc", 2).unwrap();
+    ///   assert_eq!(col.n_partitions(), 2);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec!["one".into(), "two".into(), "three".into()]));
+    /// ```
+    pub fn read_text(path: &str, n_partitions: usize) -> io::Result<MemoryCollection<String>> {
+        TextReader::new().read_text(path, n_partitions)
+    }
+
+    /// Reads lines from standard input into partitions of up to `chunk_size` lines,
+    /// for use at the end of a shell pipeline.  Standard input can't be read again
+    /// when the collection is run, so all of it is read, and held in memory, before
+    /// this returns.  Empty input yields an empty collection.
+    pub fn from_stdin(chunk_size: usize) -> io::Result<MemoryCollection<String>> {
+        TextReader::new().read_stdin(chunk_size)
+    }
+
+    /// Reads lines from any buffered reader as with `from_stdin`, reading all of it
+    /// before returning.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use std::io::Cursor;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_reader(Cursor::new("one\ntwo\nthree\n"), 2).unwrap();
+    ///   assert_eq!(col.n_partitions(), 2);
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec!["one".into(), "two".into(), "three".into()]));
+    /// ```
+    pub fn from_reader<R: BufRead>(reader: R, chunk_size: usize) -> io::Result<MemoryCollection<String>> {
+        TextReader::new().read_reader(reader, chunk_size)
+    }
+
+    /// Reads each regular file within a directory into its own partition of lines,
+    /// ordered by file name.  Subdirectories are skipped, and an empty directory
+    /// yields an empty collection.  Files are read when the collection is run, and
+    /// those ending in `.gz` are decompressed.
+    pub fn read_dir(path: &str) -> io::Result<MemoryCollection<String>> {
+        TextReader::new().read_dir(path)
+    }
+
+    /// Reads each regular file matching a glob pattern, such as
+    /// `logs/2024-*/part-*.txt`, into its own partition of lines, ordered by path.
+    /// The pattern is expanded immediately, returning a `NotFound` error if it
+    /// matches nothing, while files are read when the collection is run.  Files
+    /// ending in `.gz` are decompressed.
+    pub fn read_glob(pattern: &str) -> io::Result<MemoryCollection<String>> {
+        TextReader::new().read_glob(pattern)
+    }
+
+    /// Writes each record in a collection to disk, newline delimited.
+    /// MemoryCollection will create a new file within the path for each partition.
+    /// Each file is written under a temporary name and renamed into place once
+    /// complete; files already in the path are replaced only as partitions finish.
+    /// No overwrite policy is applied, so nothing else is removed: files left by an
+    /// earlier run with more partitions stay in the path, and are missing from the
+    /// new manifest.  Use `sink_to` to choose what happens to existing files.
+    /// Once every partition has been written, a `manifest.json` listing each file's
+    /// name, record count and size in bytes is added to the path, followed by an
+    /// empty `_SUCCESS` marker; neither is written if any partition fails.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let out = std::env::temp_dir().join(format!("tange-sink-doc-{}", std::process::id()));
+    ///   let col = MemoryCollection::from_vec(vec!["a".to_owned(), "b".to_owned()]);
+    ///   assert_eq!(col.sink(&out).run(&GreedyScheduler::new()), Some(vec![2]));
+    ///   assert_eq!(std::fs::read_to_string(out.join("0")).unwrap(), "a\nb\n");
+    /// ```
+    pub fn sink<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        self.sink_paths(path).map(|p| p.1)
+    }
+
+    /// Writes each record in a collection to disk, newline delimited, as with
+    /// `sink`.  Returns the absolute path of each partition's file along with the
+    /// number of lines written to it, so later steps can upload or register them.
+    /// Like `sink`, applies no overwrite policy, leaving other files in the path.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["a".to_owned(), "b".to_owned()]);
+    ///   let written = col.sink_paths("/tmp/tange-sink-paths-doc").run(&GreedyScheduler::new());
+    ///   assert_eq!(written, Some(vec![("/tmp/tange-sink-paths-doc/0".into(), 2)]));
+    /// ```
+    pub fn sink_paths<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<(String, usize)> {
+        self.write_parts(target_dir(path.into(), None), FileNaming::default(), Encoding::Plain, write_line)
+    }
+
+    /// Writes each record in a collection to disk, newline delimited, as with
+    /// `sink_paths`, naming each partition's file with `prefix`, its index padded
+    /// with zeros to `width` digits, and `suffix`.  The width grows to fit the
+    /// largest index, so files list in partition order.  Like `sink`, applies no
+    /// overwrite policy, leaving other files in the path.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["a".to_owned()]);
+    ///   let written = col.sink_named("/tmp/tange-sink-named-doc", "part-", 5, ".txt")
+    ///       .run(&GreedyScheduler::new());
+    ///   assert_eq!(written, Some(vec![("/tmp/tange-sink-named-doc/part-00000.txt".into(), 1)]));
+    /// ```
+    pub fn sink_named<P: Into<PathBuf>>(
+        &self, 
+        path: P, 
+        prefix: &str, 
+        width: usize, 
+        suffix: &str
+    ) -> MemoryCollection<(String, usize)> {
+        let naming = FileNaming::new(prefix, width, suffix);
+        self.write_parts(target_dir(path.into(), None), naming, Encoding::Plain, write_line)
+    }
+
+    /// Writes each record in a collection to disk, newline delimited, as with
+    /// `sink_paths`, first applying `policy` to any files already within `path`.
+    /// With `ErrorIfExists`, an existing directory which isn't empty is reported
+    /// immediately, and checked again when the collection is run.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::OverwritePolicy;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["a".to_owned()]);
+    ///   let written = col.sink_to("/tmp/tange-sink-to-doc", OverwritePolicy::Overwrite).unwrap()
+    ///       .run(&GreedyScheduler::new());
+    ///   assert_eq!(written, Some(vec![("/tmp/tange-sink-to-doc/0".into(), 1)]));
+    ///   assert!(col.sink_to("/tmp/tange-sink-to-doc", OverwritePolicy::ErrorIfExists).is_err());
+    /// ```
+    pub fn sink_to<P: Into<PathBuf>>(
+        &self, 
+        path: P, 
+        policy: OverwritePolicy
+    ) -> io::Result<MemoryCollection<(String, usize)>> {
+        let path = path.into();
+        if policy == OverwritePolicy::ErrorIfExists {
+            policy.prepare(&path, &FileNaming::default(), Encoding::Plain)?;
+        }
+        let target = target_dir(path, Some((policy, FileNaming::default(), Encoding::Plain)));
+        Ok(self.write_parts(target, FileNaming::default(), Encoding::Plain, write_line))
+    }
+
+    /// Writes each record in a collection to disk, newline delimited, gzipping each
+    /// partition's file at the given compression `level`, from 0 (none) to 9 (best).
+    /// Files are named `{idx}.gz`, and their absolute paths are returned along with
+    /// the number of lines written to each.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["a".to_owned(), "b".to_owned()]);
+    ///   let written = col.sink_gz("/tmp/tange-sink-gz-doc", 6).run(&GreedyScheduler::new());
+    ///   assert_eq!(written, Some(vec![("/tmp/tange-sink-gz-doc/0.gz".into(), 2)]));
+    ///   let back = MemoryCollection::read_text("/tmp/tange-sink-gz-doc/0.gz", 1).unwrap();
+    ///   assert_eq!(back.run(&GreedyScheduler::new()), Some(vec!["a".into(), "b".into()]));
+    /// ```
+    pub fn sink_gz<P: Into<PathBuf>>(&self, path: P, level: u32) -> MemoryCollection<(String, usize)> {
+        let encoding = Encoding::Gzip(level.min(9));
+        self.write_parts(target_dir(path.into(), None), FileNaming::default(), encoding, write_line)
+    }
+
+    /// Writes every record in a collection into the single file at `path`, newline
+    /// delimited, in the same order as `run`.  Partitions are written in parallel
+    /// to temporary files alongside `path`, which are concatenated in partition
+    /// order once all are written.  The combined file is also written under a
+    /// temporary name and moved to `path` once complete, and temporary files are
+    /// removed whether or not the run succeeds.  Returns the total number of lines
+    /// written.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let one = MemoryCollection::from_vec(vec!["a".to_owned(), "b".to_owned()]);
+    ///   let col = one.concat(&MemoryCollection::from_vec(vec!["c".to_owned()]));
+    ///   let total = col.sink_single("/tmp/tange-sink-single-doc/out.txt").run(&GreedyScheduler::new());
+    ///   assert_eq!(total, Some(vec![3]));
+    ///   assert_eq!(std::fs::read_to_string("/tmp/tange-sink-single-doc/out.txt").unwrap(), "a\nb\nc\n");
+    /// ```
+    pub fn sink_single<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        let path = path.into();
+        let dir = match path.parent() {
+            Some(p) if p != Path::new("") => p.to_path_buf(),
+            _ => PathBuf::from(".")
+        };
+        let name = path.file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| panic!("{} isn't a file path", path.display()));
+
+        let (part_dir, part_name) = (dir.clone(), name.clone());
+        let parts = batch_apply(&self.partitions, move |idx, vs| {
+            let tmp = TempFile(temp_path(&part_dir, &format!("{}-{}", part_name, idx)));
+            let written = fs::create_dir_all(&part_dir)
+                .and_then(|_| fs::File::create(&tmp.0))
+                .and_then(|file| {
+                    let mut bw = BufWriter::new(file);
+                    for line in vs {
+                        write_line(line, &mut bw)?;
+                    }
+                    bw.flush()
+                });
+            if let Err(e) = written {
+                sink_error(&tmp.0, idx, &e);
+            }
+            vec![(Arc::new(tmp), vs.len())]
+        });
+
+        let all = tree_reduce(&parts, |x, y| x.iter().chain(y.iter()).cloned().collect())
+            .unwrap_or_else(|| Deferred::lift(Vec::new(), None));
+
+        let total = all.apply(move |parts| {
+            let tmp = TempFile(temp_path(&dir, &name));
+            let file = fs::create_dir_all(&dir)
+                .and_then(|_| fs::File::create(&tmp.0))
+                .unwrap_or_else(|e| panic!("Error writing out {}: {}", path.display(), e));
+            let mut out = BufWriter::new(file);
+            let mut total = 0;
+            for (idx, &(ref part, count)) in parts.iter().enumerate() {
+                let copied = fs::File::open(&part.0)
+                    .and_then(|mut f| io::copy(&mut f, &mut out));
+                if let Err(e) = copied {
+                    sink_error(&path, idx, &e);
+                }
+                total += count;
+            }
+            out.flush()
+                .and_then(|_| fs::rename(&tmp.0, &path))
+                .unwrap_or_else(|e| panic!("Error writing out {}: {}", path.display(), e));
+            vec![total]
+        });
+        self.derive("sink", StageKind::Sink, vec![total])
+    }
+
+    /// Splits every string into its words, separated by whitespace, in a single
+    /// pass over each partition.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let text = MemoryCollection::from_vec(vec!["the cat  sat".to_string(), "\tthe end ".into()]);
+    ///   let counts = text.split_whitespace().frequencies(1).sort_by(|x| x.0.clone());
+    ///   assert_eq!(counts.run(&GreedyScheduler::new()), Some(vec![
+    ///       ("cat".into(), 1), ("end".into(), 1), ("sat".into(), 1), ("the".into(), 2)]));
+    /// ```
+    pub fn split_whitespace(&self) -> MemoryCollection<String> {
+        self.emit(|s, emitter| {
+            for token in s.split_whitespace() {
+                emitter(token.to_owned());
+            }
+        }).stage("split_whitespace")
+    }
+
+    /// Splits every string on `delim`, skipping the empty tokens between
+    /// consecutive delimiters and at either end.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let tags = MemoryCollection::from_vec(vec!["rust,,data,".to_string()]);
+    ///   assert_eq!(tags.split_on(',').run(&GreedyScheduler::new()), Some(vec!["rust".into(), "data".into()]));
+    ///   assert_eq!(tags.split_on_with(',', true).run(&GreedyScheduler::new()).unwrap().len(), 4);
+    /// ```
+    pub fn split_on(&self, delim: char) -> MemoryCollection<String> {
+        self.split_on_with(delim, false)
+    }
+
+    /// Splits every string on `delim`, as `split_on` does, keeping empty tokens if
+    /// `keep_empty` is set.
+    pub fn split_on_with(&self, delim: char, keep_empty: bool) -> MemoryCollection<String> {
+        self.emit(move |s, emitter| {
+            for token in s.split(delim) {
+                if keep_empty || !token.is_empty() {
+                    emitter(token.to_owned());
+                }
+            }
+        }).stage("split_on")
+    }
+
+    /// Splits every string into its lines, ended by "\n" or "\r\n", skipping empty
+    /// lines.  Suits values holding whole documents, like those of `read_dir`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let docs = MemoryCollection::from_vec(vec!["one\r\n\ntwo\n".to_string()]);
+    ///   assert_eq!(docs.lines().run(&GreedyScheduler::new()), Some(vec!["one".into(), "two".into()]));
+    ///   assert_eq!(docs.lines_with(true).run(&GreedyScheduler::new()).unwrap().len(), 3);
+    /// ```
+    pub fn lines(&self) -> MemoryCollection<String> {
+        self.lines_with(false)
+    }
+
+    /// Splits every string into its lines, as `lines` does, keeping empty lines if
+    /// `keep_empty` is set.
+    pub fn lines_with(&self, keep_empty: bool) -> MemoryCollection<String> {
+        self.emit(move |s, emitter| {
+            for line in s.lines() {
+                if keep_empty || !line.is_empty() {
+                    emitter(line.to_owned());
+                }
+            }
+        }).stage("lines")
+    }
+
+    /// Keeps the strings matching the regular expression `pattern`, anywhere in the
+    /// string.  The pattern is compiled once, here, and shared by every partition
+    /// task, so an invalid pattern is returned as an error rather than failing the
+    /// run.  Requires the "regex" feature.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let log = MemoryCollection::from_vec(vec![
+    ///       "INFO started".to_string(), "ERROR disk full".into(), "INFO done".into()]);
+    ///   let errors = log.grep("^(ERROR|WARN) ").unwrap();
+    ///   assert_eq!(errors.run(&GreedyScheduler::new()), Some(vec!["ERROR disk full".into()]));
+    ///   assert!(log.grep("(unclosed").is_err());
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn grep(&self, pattern: &str) -> Result<MemoryCollection<String>, regex::Error> {
+        let re = Regex::new(pattern)?;
+        Ok(self.filter(move |s| re.is_match(s)).stage("grep"))
+    }
+
+    /// Matches every string against the regular expression `pattern`, emitting the
+    /// capture groups of the first match, in the order they open, and dropping the
+    /// strings which don't match.  Named groups are included in the same order, and
+    /// a group which took no part in the match yields an empty string.  As with
+    /// `grep`, an invalid pattern is returned as an error.  Requires the "regex"
+    /// feature.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let log = MemoryCollection::from_vec(vec![
+    ///       "GET /index.html 200".to_string(), "garbage".into(), "POST /login 403".into()]);
+    ///   let requests = log.extract(r"^(\w+) (?P<path>\S+) (\d{3})$").unwrap();
+    ///   assert_eq!(requests.run(&GreedyScheduler::new()), Some(vec![
+    ///       vec!["GET".into(), "/index.html".into(), "200".into()],
+    ///       vec!["POST".into(), "/login".into(), "403".into()]]));
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn extract(&self, pattern: &str) -> Result<MemoryCollection<Vec<String>>, regex::Error> {
+        let re = Regex::new(pattern)?;
+        Ok(self.emit(move |s, emitter| {
+            if let Some(caps) = re.captures(s) {
+                emitter(caps.iter().skip(1)
+                    .map(|m| m.map(|m| m.as_str().to_owned()).unwrap_or_default())
+                    .collect());
+            }
+        }).stage("extract"))
+    }
+
+    /// Parses every string with `FromStr`, keeping the Result for each.  Failures
+    /// hold the string, cut short if long, along with where it was found.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["1.5".to_string(), "x".into()]);
+    ///   let parsed = col.parse::<f64>().run(&GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(parsed[0], Ok(1.5));
+    ///   assert_eq!(parsed[1].as_ref().unwrap_err().to_string(),
+    ///              "record 1 of partition 0 failed to parse \"x\": invalid float literal");
+    /// ```
+    pub fn parse<T: Any + Send + Sync + Clone + FromStr>(&self) -> MemoryCollection<Result<T, ParseError>> 
+        where T::Err: Display
+    {
+        let parts = parse(&self.partitions, Memory);
+        self.derive("parse", StageKind::ElementWise, parts)
+    }
+
+    /// Parses every string with `FromStr`, splitting the values which parsed from
+    /// the errors of those which didn't.  Each partition is parsed once for both,
+    /// as with `split_results`, so counting the errors costs nothing extra.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec!["3".to_string(), "three".into(), "4".into()]);
+    ///   let (nums, errors) = col.parse_lossy::<u32>();
+    ///   assert_eq!(nums.run(&GreedyScheduler::new()), Some(vec![3, 4]));
+    ///   assert_eq!(errors.count().run(&GreedyScheduler::new()), Some(vec![1]));
+    /// ```
+    pub fn parse_lossy<T: Any + Send + Sync + Clone + FromStr>(&self) -> (MemoryCollection<T>, MemoryCollection<ParseError>) 
+        where T::Err: Display
+    {
+        let (oks, errs) = self.parse::<T>().split_results();
+        (oks.stage("parse_lossy"), errs.stage("parse_lossy"))
+    }
+
+    /// Runs the collection, joining all of its strings into one with `sep` between
+    /// each, in partition order.  Partitions are joined where they're computed and
+    /// the pieces concatenated as they're reduced, so the strings aren't gathered
+    /// first.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let words = MemoryCollection::from_vec_chunked(vec!["a".to_string(), "b".into(), "c".into()], 2);
+    ///   assert_eq!(words.join_strings(", ", &GreedyScheduler::new()), Some("a, b, c".into()));
+    /// ```
+    pub fn join_strings<S: Scheduler>(&self, sep: &str, s: &S) -> Option<String> {
+        join_strings(&self.partitions, sep).run(s)
+    }
+}
+
+fn write_line(line: &String, w: &mut dyn Write) -> io::Result<()> {
+    w.write_all(line.as_bytes())?;
+    w.write_all(b"\n")
+}
+
+impl MemoryCollection<Vec<u8>> {
+
+    /// Reads a file of records separated by an arbitrary `delimiter`, such as the NUL
+    /// separated output of `find -print0`, split into `n_partitions` byte ranges.
+    /// A final record without a trailing delimiter is kept.  Use
+    /// TextReader::read_delimited_str to decode the records as Strings.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   std::fs::write("/tmp/tange-read-delimited-doc", b"a\0b c\0d").unwrap();
+    ///   let col = MemoryCollection::read_delimited("/tmp/tange-read-delimited-doc", b"\0", 2)
+    ///       .unwrap();
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec![b"a".to_vec(), b"b c".to_vec(), b"d".to_vec()]));
+    /// ```
+    pub fn read_delimited(
+        path: &str, 
+        delimiter: &[u8], 
+        n_partitions: usize
+    ) -> io::Result<MemoryCollection<Vec<u8>>> {
+        TextReader::new().read_delimited(path, delimiter, n_partitions)
+    }
+
+    /// Reads a file of length-prefixed frames, as written by `sink_framed`, split into
+    /// about `n_partitions` partitions of whole frames.  Frame lengths are checked
+    /// when the collection is created, returning an `InvalidData` error if one runs
+    /// past the end of the file.
+    pub fn read_framed(path: &str, n_partitions: usize) -> io::Result<MemoryCollection<Vec<u8>>> {
+        read_framed(path, n_partitions)
+    }
+
+    /// Reads a file of fixed-size binary records, each `record_len` bytes, split
+    /// into about `n_partitions` partitions of whole records.  Returns an
+    /// `InvalidData` error if the file's length isn't a multiple of `record_len`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   std::fs::write("/tmp/tange-read-fixed-doc", b"aabbcc").unwrap();
+    ///   let col = MemoryCollection::read_fixed("/tmp/tange-read-fixed-doc", 2, 2).unwrap();
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec![b"aa".to_vec(), b"bb".to_vec(), b"cc".to_vec()]));
+    ///   assert!(MemoryCollection::read_fixed("/tmp/tange-read-fixed-doc", 4, 2).is_err());
+    /// ```
+    pub fn read_fixed(
+        path: &str, 
+        record_len: usize, 
+        n_partitions: usize
+    ) -> io::Result<MemoryCollection<Vec<u8>>> {
+        read_fixed(path, record_len, n_partitions)
+    }
+
+    /// Writes each partition's records back to back, with no separators or
+    /// lengths, as read by `read_fixed`.  MemoryCollection will create a new file
+    /// within the path for each partition.
+    pub fn sink_raw<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        let target = target_dir(path.into(), None);
+        self.write_parts(target, FileNaming::default(), Encoding::Plain, |record, w| {
+            w.write_all(record)
+        }).map(|p| p.1)
+    }
+
+    /// Writes each record as a frame, a u32 little-endian length followed by the
+    /// record's bytes.  MemoryCollection will create a new file within the path for
+    /// each partition.
+    pub fn sink_framed<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        let target = target_dir(path.into(), None);
+        self.write_parts(target, FileNaming::default(), Encoding::Plain, |record, w| {
+            assert!(record.len() <= u32::MAX as usize, "Record too long to frame");
+            w.write_all(&(record.len() as u32).to_le_bytes())?;
+            w.write_all(record)
+        }).map(|p| p.1)
+    }
+
+}
+
+impl MemoryCollection<Vec<String>> {
+
+    /// Reads a CSV file into a collection of raw records, for exploring data without
+    /// a schema.  See `read_csv` for how the file is split.
+    pub fn read_csv_raw(
+        path: &str, 
+        has_headers: bool, 
+        n_partitions: usize
+    ) -> io::Result<MemoryCollection<Vec<String>>> {
+        read_csv(path, has_headers, n_partitions, |record, _headers| {
+            Ok(record.iter().map(|f| f.to_owned()).collect())
+        })
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + for<'de>Deserialize<'de>> MemoryCollection<A> {
+
+    /// Loads files of bincode records written by `sink_bincode`, or by Disk
+    /// accumulators, from the directory `root`, with one partition per file.  When
+    /// `root` holds a `manifest.json`, partitions follow the order it lists;
+    /// otherwise, files named `tange-*` or `part-*` are loaded in name order.  Files
+    /// are read when the collection is run, and a missing or damaged file fails its
+    /// partition with the file's path.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![(1u32, "a".to_owned())]);
+    ///   col.sink_bincode("/tmp/tange-from-stored-doc").run(&GreedyScheduler::new());
+    ///   let back = MemoryCollection::<(u32, String)>::from_stored("/tmp/tange-from-stored-doc").unwrap();
+    ///   assert_eq!(back.run(&GreedyScheduler::new()), Some(vec![(1, "a".into())]));
+    /// ```
+    pub fn from_stored(root: &str) -> io::Result<MemoryCollection<A>> {
+        let partitions = stored_parts(root)?.iter().map(|part| {
+            part.apply(|store| stream_or_panic(store).into_iter().collect())
+        }).collect();
+        Ok(MemoryCollection::from_defs(partitions).source("from_stored"))
+    }
+
+    /// Reads a CSV file, deserializing each record into `A`.  With `has_headers`, the
+    /// first record names the columns, which are matched to `A`'s fields by name;
+    /// otherwise columns are matched by position.  The file is split into
+    /// `n_partitions` byte ranges aligned to whole records, even when quoted fields
+    /// contain newlines.  Records which can't be deserialized fail the read with an
+    /// error naming the file and offset.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   std::fs::write("/tmp/tange-read-csv-doc", "name,count\na,1\n\"b\nc\",2\n").unwrap();
+    ///   let col = MemoryCollection::<(String, usize)>::read_csv("/tmp/tange-read-csv-doc", true, 2)
+    ///       .unwrap();
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec![("a".into(), 1), ("b\nc".into(), 2)]));
+    /// ```
+    pub fn read_csv(path: &str, has_headers: bool, n_partitions: usize) -> io::Result<MemoryCollection<A>> {
+        read_csv(path, has_headers, n_partitions, csv_deserialize)
+    }
+
+    /// Reads a file of newline-delimited JSON, deserializing each line into `A`.  The
+    /// file is split into `n_partitions` byte ranges and blank lines are ignored.  A
+    /// line which can't be deserialized fails the read with an error giving its line
+    /// number; use TextReader::read_jsonl_skipping to skip and count such lines
+    /// instead.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   std::fs::write("/tmp/tange-read-jsonl-doc", "[\"a\",1]\n\n[\"b\",2]").unwrap();
+    ///   let col = MemoryCollection::<(String, usize)>::read_jsonl("/tmp/tange-read-jsonl-doc", 2)
+    ///       .unwrap();
+    ///   assert_eq!(col.run(&GreedyScheduler::new()), 
+    ///              Some(vec![("a".into(), 1), ("b".into(), 2)]));
+    /// ```
+    pub fn read_jsonl(path: &str, n_partitions: usize) -> io::Result<MemoryCollection<A>> {
+        TextReader::new().read_jsonl(path, n_partitions)
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Serialize> MemoryCollection<A> {
+
+    /// Writes each record as a row of CSV, creating a new file within the path for
+    /// each partition and returning the number of rows in each.  With
+    /// `write_headers`, each file starts with a header row naming the record's
+    /// fields.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![("a, b".to_owned(), 1usize)]);
+    ///   let counts = col.sink_csv("/tmp/tange-sink-csv-doc", false).run(&GreedyScheduler::new());
+    ///   assert_eq!(counts, Some(vec![1]));
+    ///   assert_eq!(std::fs::read_to_string("/tmp/tange-sink-csv-doc/0").unwrap(), "\"a, b\",1\n");
+    /// ```
+    pub fn sink_csv<P: Into<PathBuf>>(&self, path: P, write_headers: bool) -> MemoryCollection<usize> {
+        let headers = if write_headers { CsvHeaders::EachFile } else { CsvHeaders::None };
+        self.sink_csv_with(path, headers)
+    }
+
+    /// Writes each record as a row of CSV, as with `sink_csv`, choosing which files
+    /// get header rows.  Header rows are only written to files with at least one
+    /// row.
+    pub fn sink_csv_with<P: Into<PathBuf>>(&self, path: P, headers: CsvHeaders) -> MemoryCollection<usize> {
+        let target = target_dir(path.into(), None);
+        let naming = FileNaming::default();
+        let n_parts = self.partitions.len();
+        let pats = self.partitions.iter().enumerate().map(|(idx, part)| {
+            let name = naming.name(idx, n_parts);
+            part.join(&target, move |vs, dir| {
+                let (path, out) = PartWriter::create(dir, &name, idx, Encoding::Plain);
+                let has_headers = match headers {
+                    CsvHeaders::None => false,
+                    CsvHeaders::EachFile => true,
+                    CsvHeaders::FirstFile => idx == 0
+                };
+                let mut writer = csv::WriterBuilder::new()
+                    .has_headers(has_headers)
+                    .from_writer(out);
+
+                for record in vs {
+                    if let Err(e) = writer.serialize(record) {
+                        sink_error(&path, idx, &e);
+                    }
+                }
+                writer.into_inner()
+                    .unwrap_or_else(|e| sink_error(&path, idx, e.error()))
+                    .finish();
+
+                vec![(path.to_string_lossy().into_owned(), vs.len())]
+            })
+        }).collect::<Vec<_>>();
+        
+        self.derive("sink", StageKind::Sink, vec![finish_sink(&target, &pats)]).map(|p| p.1)
+    }
+
+    /// Writes each record as a line of compact JSON, creating a new file within the
+    /// path for each partition and returning the number of records in each.  Strings
+    /// containing newlines are escaped, so every record is exactly one line, and
+    /// empty partitions still get an empty file.  The output can be read back with
+    /// `read_jsonl`.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![("a\nb".to_owned(), 1usize)]);
+    ///   let counts = col.sink_jsonl("/tmp/tange-sink-jsonl-doc").run(&GreedyScheduler::new());
+    ///   assert_eq!(counts, Some(vec![1]));
+    ///   assert_eq!(std::fs::read_to_string("/tmp/tange-sink-jsonl-doc/0").unwrap(), "[\"a\\nb\",1]\n");
+    /// ```
+    pub fn sink_jsonl<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<usize> {
+        let target = target_dir(path.into(), None);
+        self.write_parts(target, FileNaming::default(), Encoding::Plain, |record, w| {
+            serde_json::to_writer(&mut *w, record)?;
+            w.write_all(b"\n")
+        }).map(|p| p.1)
+    }
+
+    /// Writes each partition to a file of bincode records within `path`, in the
+    /// same format DiskCollection spills to, followed by a `manifest.json` listing
+    /// the files and their record counts once every partition is written.  Empty
+    /// partitions get empty files.  Returns the absolute path and record count of
+    /// each file, in partition order, within a single partition.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![(1u32, vec![1.5f64])]);
+    ///   let written = col.sink_bincode("/tmp/tange-sink-bincode-doc").run(&GreedyScheduler::new());
+    ///   assert_eq!(written, Some(vec![("/tmp/tange-sink-bincode-doc/0".into(), 1)]));
+    ///   assert!(std::path::Path::new("/tmp/tange-sink-bincode-doc/manifest.json").exists());
+    /// ```
+    pub fn sink_bincode<P: Into<PathBuf>>(&self, path: P) -> MemoryCollection<(String, usize)> {
+        let written = sink_bincode(&self.partitions, path.into());
+        self.derive("sink", StageKind::Sink, vec![written])
+    }
+}
+
+impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> MemoryCollection<A> {
+
+    /// Copies the MemoryCollection to disk, returning a DiskCollection
+    pub fn to_disk(&self, path: String) -> DiskCollection<A> {
+        DiskCollection::from_memory(path, &self.partitions)
+            .with_plan("to_disk", StageKind::ElementWise, vec![self.plan.clone()])
+    }
+
+    /// Runs the collection, writing each partition into a file within `root`, and
+    /// returns a collection with the same data and partitions read back from those
+    /// files.  Work done downstream of the checkpoint starts from the files rather
+    /// than recomputing everything before it, however many times it's run.  The
+    /// files are removed once the returned collection, and anything built from it,
+    /// is dropped.  Returns an error if `root` can't be created or the collection
+    /// fails to run.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1,2,3usize]).map(|x| x * 10);
+    ///   let saved = col.checkpoint("/tmp/tange-checkpoint-doc", &GreedyScheduler::new()).unwrap();
+    ///   assert_eq!(saved.map(|x| x + 1).run(&GreedyScheduler::new()), Some(vec![11, 21, 31]));
+    /// ```
+    pub fn checkpoint<S: Scheduler>(&self, root: &str, s: &S) -> io::Result<MemoryCollection<A>> {
+        let stores = checkpoint(&self.partitions, root, s)?;
+        let partitions = stores.into_iter().map(|store| {
+            Deferred::lift(store, None).apply(|store| stream_or_panic(store).into_iter().collect())
+        }).collect();
+        Ok(MemoryCollection::from_defs(partitions).source("checkpoint"))
+    }
+
+    /// Keeps the partitions of the collection in a cache directory under `key`, so
+    /// later runs, including those of other processes, read them back rather than
+    /// running anything upstream.  Partitions missing from the cache are computed
+    /// and stored when the collection runs.  Nothing checks whether the pipeline
+    /// behind the key has changed: give it a new key, like "clean-v4", to replace
+    /// what's stored.  The cache lives in the directory named by the
+    /// `TANGE_CACHE_DIR` environment variable, or `tange-cache` in the system's
+    /// temporary directory; see `cached_in` to choose another.  Returns an error if
+    /// the cache directory can't be created.
+    /// ```rust
+    ///   extern crate tange;
+    ///   extern crate tange_collection;
+    ///   use tange::scheduler::GreedyScheduler;
+    ///   use tange_collection::collection::memory::MemoryCollection;
+    ///   
+    ///   let col = MemoryCollection::from_vec(vec![1,2,3usize]).map(|x| x * 10);
+    ///   let cached = col.cached_in("/tmp/tange-cached-doc", "times-ten-v1").unwrap();
+    ///   assert_eq!(cached.run(&GreedyScheduler::new()), Some(vec![10, 20, 30]));
+    /// ```
+    pub fn cached(&self, key: &str) -> io::Result<MemoryCollection<A>> {
+        self.cached_in(cache_root(), key)
+    }
+
+    /// Keeps the partitions of the collection under `key` within `root`, as with
+    /// `cached`
+    pub fn cached_in<P: Into<PathBuf>>(&self, root: P, key: &str) -> io::Result<MemoryCollection<A>> {
+        let stores = cached_parts(&self.partitions, &root.into(), key)?;
+        let partitions = stores.iter()
+            .map(|d| d.apply(|store| stream_or_panic(store).into_iter().collect()))
+            .collect();
+        Ok(self.derive("cached", StageKind::ElementWise, partitions))
+    }
+}
+
+#[cfg(test)]
+mod test_lib {
+    extern crate flate2;
+    use super::*;
+    use utils::Utf8Policy;
+    use collection::StageStats;
+    use self::flate2::Compression;
+    use self::flate2::write::GzEncoder;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+    use tange::scheduler::{GreedyScheduler,LeveledScheduler};
+
+    fn write_lines(path: &str, contents: &str) -> String {
+        fs::write(path, contents).unwrap();
+        path.into()
+    }
+
+    #[test]
+    fn test_read_text() {
+        let mut contents = String::new();
+        for i in 0..200_000usize {
+            // Vary line lengths so partition boundaries land all over the place
+            contents.push_str(&format!("{} {}\n", i, "x".repeat(i % 37)));
+        }
+        assert!(contents.len() > 4_000_000);
+        let path = write_lines("/tmp/tange-test-read-text", &contents);
+
+        let col = MemoryCollection::read_text(&path, 7).unwrap();
+        assert_eq!(col.n_partitions(), 7);
+        let results = col.run(&LeveledScheduler).unwrap();
+        let expected = fs::read_to_string(&path).unwrap();
+        let expected: Vec<_> = expected.lines().collect();
+        assert_eq!(results.len(), expected.len());
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_read_text_small() {
+        let path = write_lines("/tmp/tange-test-read-text-small", "a\nbc\r\nd");
+        let col = MemoryCollection::read_text(&path, 16).unwrap();
+        assert!(col.n_partitions() <= 7);
+        let results = col.run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["a".to_owned(), "bc".into(), "d".into()]);
+
+        let path = write_lines("/tmp/tange-test-read-text-empty", "");
+        let col = MemoryCollection::read_text(&path, 4).unwrap();
+        assert_eq!(col.run(&LeveledScheduler).unwrap(), Vec::<String>::new());
+
+        assert!(MemoryCollection::read_text("/tmp/tange-test-missing-file", 4).is_err());
+    }
+
+    #[test]
+    fn test_read_text_long_lines() {
+        // Lines much longer than a partition must still be read exactly once
+        let line = "y".repeat(1000);
+        let contents = format!("{}\n{}\nz\n{}", line, line, line);
+        let path = write_lines("/tmp/tange-test-read-text-long", &contents);
+        let results = MemoryCollection::read_text(&path, 50).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        let expected: Vec<_> = contents.lines().map(|s| s.to_owned()).collect();
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_read_dir() {
+        let dir = "/tmp/tange-test-read-dir";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(format!("{}/nested", dir)).unwrap();
+        write_lines(&format!("{}/b.txt", dir), "b1\nb2\n");
+        write_lines(&format!("{}/a.txt", dir), "a1\n");
+        write_lines(&format!("{}/c.txt", dir), "c1\nc2\nc3");
+        write_lines(&format!("{}/nested/d.txt", dir), "d1\n");
+
+        let col = MemoryCollection::read_dir(dir).unwrap();
+        assert_eq!(col.n_partitions(), 3);
+        let results = col.run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["a1", "b1", "b2", "c1", "c2", "c3"]);
+
+        let empty = format!("{}/nested/empty", dir);
+        fs::create_dir_all(&empty).unwrap();
+        let col = MemoryCollection::read_dir(&empty).unwrap();
+        assert_eq!(col.run(&LeveledScheduler), Some(Vec::new()));
+
+        assert!(MemoryCollection::read_dir("/tmp/tange-test-missing-dir").is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "gone.txt")]
+    fn test_read_dir_unreadable() {
+        let dir = "/tmp/tange-test-read-dir-unreadable";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let path = write_lines(&format!("{}/gone.txt", dir), "a\n");
+        let col = MemoryCollection::read_dir(dir).unwrap();
+        fs::remove_file(&path).unwrap();
+        col.run(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_read_glob() {
+        let dir = "/tmp/tange-test-read-glob";
+        let _ = fs::remove_dir_all(dir);
+        for day in &["2024-01", "2024-02", "2023-12"] {
+            fs::create_dir_all(format!("{}/{}", dir, day)).unwrap();
+            for part in 0..2 {
+                write_lines(&format!("{}/{}/part-{}.txt", dir, day, part), 
+                            &format!("{} {}\n", day, part));
+            }
+            write_lines(&format!("{}/{}/_SUCCESS", dir, day), "");
+        }
+
+        let col = MemoryCollection::read_glob(&format!("{}/2024-*/part-*.txt", dir)).unwrap();
+        assert_eq!(col.n_partitions(), 4);
+        let results = col.run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["2024-01 0", "2024-01 1", "2024-02 0", "2024-02 1"]);
+
+        let col = MemoryCollection::read_glob(&format!("{}/2023-12/part-1.txt", dir)).unwrap();
+        assert_eq!(col.run(&LeveledScheduler).unwrap(), vec!["2023-12 1"]);
+
+        // Directories matched by the pattern are skipped
+        let col = MemoryCollection::read_glob(&format!("{}/**/part-0*", dir)).unwrap();
+        assert_eq!(col.n_partitions(), 3);
+
+        let err = MemoryCollection::read_glob(&format!("{}/2025-*/part-*.txt", dir)).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(err.to_string().contains("2025-*"));
+
+        let err = MemoryCollection::read_glob("/tmp/***").err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    fn write_gzip(path: &str, contents: &str) -> String {
+        let file = fs::File::create(path).unwrap();
+        let mut enc = GzEncoder::new(file, Compression::default());
+        enc.write_all(contents.as_bytes()).unwrap();
+        enc.finish().unwrap();
+        path.into()
+    }
+
+    #[test]
+    fn test_read_gzip() {
+        let contents: String = (0..50_000).map(|i| format!("line {}\n", i)).collect();
+        let path = write_gzip("/tmp/tange-test-read-gzip.txt.gz", &contents);
+        let col = MemoryCollection::read_text(&path, 8).unwrap();
+        assert_eq!(col.n_partitions(), 1);
+        let expected: Vec<_> = contents.lines().map(|l| l.to_owned()).collect();
+        assert_eq!(col.run(&LeveledScheduler).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_read_dir_mixed() {
+        let dir = "/tmp/tange-test-read-dir-mixed";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        write_gzip(&format!("{}/a.gz", dir), "a1\na2\n");
+        write_lines(&format!("{}/b.txt", dir), "b1\n");
+        write_gzip(&format!("{}/c.txt.gz", dir), "c1");
+
+        let results = MemoryCollection::read_dir(dir).unwrap().run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["a1", "a2", "b1", "c1"]);
+
+        let results = MemoryCollection::read_glob(&format!("{}/*.gz", dir)).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["a1", "a2", "c1"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Record at byte 6 of /tmp/tange-test-long-line is longer than 4096 bytes")]
+    fn test_read_text_max_line_len() {
+        let mut contents = "short\n".to_owned();
+        contents.push_str(&"z".repeat(8 << 20));
+        contents.push_str("\nafter\n");
+        let path = write_lines("/tmp/tange-test-long-line", &contents);
+        TextReader::new().max_line_len(4096).read_text(&path, 1).unwrap()
+            .run(&LeveledScheduler);
+    }
+
+    #[test]
+    #[should_panic(expected = "Record at byte 2 of /tmp/tange-test-long-line-gz.gz is longer")]
+    fn test_read_glob_max_line_len() {
+        let mut contents = "a\n".to_owned();
+        contents.push_str(&"z".repeat(1 << 20));
+        let path = write_gzip("/tmp/tange-test-long-line-gz.gz", &contents);
+        TextReader::new().max_line_len(1024).read_glob(&path).unwrap()
+            .run(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_read_text_within_max_line_len() {
+        let path = write_lines("/tmp/tange-test-max-line", "1234\n12345\n123");
+        let results = TextReader::new().max_line_len(5).read_text(&path, 3).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(results, vec!["1234", "12345", "123"]);
+    }
+
+    // Splits the way a single sequential reader would
+    fn split_naive(data: &[u8], delim: &[u8]) -> Vec<Vec<u8>> {
+        let mut out = Vec::new();
+        let mut cur = Vec::new();
+        let mut i = 0;
+        while i < data.len() {
+            if data[i..].starts_with(delim) {
+                out.push(cur.clone());
+                cur.clear();
+                i += delim.len();
+            } else {
+                cur.push(data[i]);
+                i += 1;
+            }
+        }
+        if !cur.is_empty() {
+            out.push(cur);
+        }
+        out
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let input = io::Cursor::new("a\nb\r\nc\nd\ne\nf\ng");
+        let col = MemoryCollection::from_reader(input, 3).unwrap();
+        assert_eq!(col.n_partitions(), 3);
+        let parts: Vec<_> = col.to_defs().iter()
+            .map(|d| d.run(&LeveledScheduler).unwrap().len())
+            .collect();
+        assert_eq!(parts, vec![3, 3, 1]);
+        assert_eq!(col.run(&LeveledScheduler).unwrap(), vec!["a", "b", "c", "d", "e", "f", "g"]);
+
+        let empty = MemoryCollection::from_reader(io::Cursor::new(""), 3).unwrap();
+        assert_eq!(empty.run(&LeveledScheduler), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_from_reader_errors() {
+        let long = format!("ok\n{}\n", "z".repeat(100));
+        let input = io::BufReader::with_capacity(16, io::Cursor::new(long));
+        let err = TextReader::new().max_line_len(10).read_reader(input, 2).err().unwrap();
+        assert_eq!(err.to_string(), "Record at byte 3 of input is longer than 10 bytes");
+
+        let err = MemoryCollection::from_reader(io::Cursor::new(b"ok\n\xff\n".to_vec()), 2)
+            .err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Line at byte 3 of input isn't valid UTF-8");
+    }
+
+    #[test]
+    fn test_read_delimited() {
+        let mut data = Vec::new();
+        for i in 0..300usize {
+            data.extend(format!("record{}", "r".repeat(i % 13)).bytes());
+            data.push(0);
+            if i % 17 == 0 {
+                // Empty records
+                data.push(0);
+            }
+        }
+        data.extend(b"last".iter());
+        let path = "/tmp/tange-test-read-delimited";
+        fs::write(path, &data).unwrap();
+
+        let expected = split_naive(&data, b"\0");
+        // Walk partition counts so boundaries land on every kind of position
+        for n in 1..40 {
+            let results = MemoryCollection::read_delimited(path, b"\0", n).unwrap()
+                .run(&LeveledScheduler).unwrap();
+            assert_eq!(results, expected, "with {} partitions", n);
+        }
+    }
+
+    #[test]
+    fn test_read_delimited_multibyte() {
+        let mut data = Vec::new();
+        for i in 0..200usize {
+            data.extend(format!("{}<>{}", i, "<".repeat(i % 3)).bytes());
+            data.extend(b"<|>".iter());
+        }
+        let path = "/tmp/tange-test-read-delimited-multi";
+        fs::write(path, &data).unwrap();
+
+        let expected = split_naive(&data, b"<|>");
+        assert_eq!(expected.len(), 200);
+        for n in 1..60 {
+            let col = MemoryCollection::read_delimited(path, b"<|>", n).unwrap();
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), expected, "with {} partitions", n);
+        }
+
+        // The delimiter starting right before, exactly at and right after a boundary
+        fs::write(path, b"aaaa<|>bbbb<|>").unwrap();
+        for n in 1..15 {
+            let col = MemoryCollection::read_delimited(path, b"<|>", n).unwrap();
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), 
+                       vec![b"aaaa".to_vec(), b"bbbb".to_vec()], "with {} partitions", n);
+        }
+    }
+
+    #[test]
+    fn test_read_delimited_overlapping() {
+        let data = b"stanza 1\nline\n\n\nstanza 2\n\nstanza 3\n";
+        let path = "/tmp/tange-test-read-delimited-stanzas";
+        fs::write(path, &data[..]).unwrap();
+        let col = MemoryCollection::read_delimited(path, b"\n\n", 4).unwrap();
+        assert_eq!(col.n_partitions(), 1);
+        assert_eq!(col.run(&LeveledScheduler).unwrap(), split_naive(data, b"\n\n"));
+    }
+
+    #[test]
+    fn test_read_delimited_str() {
+        let path = "/tmp/tange-test-read-delimited-str";
+        fs::write(path, b"caf\xc3\xa9\0bad \xff\0ok").unwrap();
+        let reader = TextReader::new();
+        let lossy = reader.read_delimited_str(path, b"\0", 2, Utf8Policy::Lossy).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(lossy, vec!["caf\u{e9}", "bad \u{fffd}", "ok"]);
+
+        let skip = reader.read_delimited_str(path, b"\0", 2, Utf8Policy::Skip).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(skip, vec!["caf\u{e9}", "ok"]);
+
+        assert!(reader.read_delimited(path, b"", 2).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Record at byte 6 of /tmp/tange-test-read-delimited-strict isn't valid UTF-8")]
+    fn test_read_delimited_strict() {
+        let path = "/tmp/tange-test-read-delimited-strict";
+        fs::write(path, b"caf\xc3\xa9\0bad \xff\0ok").unwrap();
+        TextReader::new().read_delimited_str(path, b"\0", 1, Utf8Policy::Strict).unwrap()
+            .run(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_framed_round_trip() {
+        // Deterministic pseudo-random sizes, including empty records
+        let mut state = 12345u64;
+        let records: Vec<Vec<u8>> = (0..500).map(|i| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let len = if i % 11 == 0 { 0 } else { (state >> 33) as usize % 300 };
+            (0..len).map(|j| (i + j) as u8).collect()
+        }).collect();
+
+        let dir = "/tmp/tange-test-framed";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec(records).split(3);
+        let records = col.run(&LeveledScheduler).unwrap();
+        let counts = col.sink_framed(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 500);
+
+        for n in &[1, 2, 7, 50, 1000] {
+            let mut parts = Vec::new();
+            for idx in 0..counts.len() {
+                parts.push(MemoryCollection::read_framed(&format!("{}/{}", dir, idx), *n).unwrap());
+            }
+            assert!(*n < 50 || parts[0].n_partitions() > 1);
+            let all = parts.iter().skip(1).fold(parts[0].clone(), |acc, p| acc.concat(p));
+            assert_eq!(all.run(&LeveledScheduler).unwrap(), records, "with {} partitions", n);
+        }
+    }
+
+    #[test]
+    fn test_framed_corrupt() {
+        let path = "/tmp/tange-test-framed-corrupt";
+        let mut data = Vec::new();
+        data.extend_from_slice(&3u32.to_le_bytes());
+        data.extend_from_slice(b"abc");
+        data.extend_from_slice(&1000u32.to_le_bytes());
+        data.extend_from_slice(b"short");
+        fs::write(path, &data).unwrap();
+        let err = MemoryCollection::read_framed(path, 2).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), format!("Frame at byte 7 of {} has length 1000, which runs \
+                                            past the end of the file at 16", path));
+
+        fs::write(path, &data[..9]).unwrap();
+        let err = MemoryCollection::read_framed(path, 2).err().unwrap();
+        assert!(err.to_string().contains("missing its length"));
+    }
+
+    #[test]
+    fn test_fixed_round_trip() {
+        // 16 byte event structs: a u64 timestamp followed by a u64 value
+        let records: Vec<Vec<u8>> = (0..100u64).map(|i| {
+            let mut r = (i * 1000).to_le_bytes().to_vec();
+            r.extend_from_slice(&(i * i).to_le_bytes());
+            r
+        }).collect();
+
+        let dir = "/tmp/tange-test-fixed";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec(records).split(3);
+        let records = col.run(&LeveledScheduler).unwrap();
+        let counts = col.sink_raw(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 100);
+        for (idx, count) in counts.iter().enumerate() {
+            assert_eq!(fs::metadata(format!("{}/{}", dir, idx)).unwrap().len(), 16 * *count as u64);
+        }
+
+        for n in &[1, 3, 7, 500] {
+            let mut parts = Vec::new();
+            for idx in 0..counts.len() {
+                parts.push(MemoryCollection::read_fixed(&format!("{}/{}", dir, idx), 16, *n).unwrap());
+            }
+            assert_eq!(parts[0].n_partitions(), (*n).min(counts[0]));
+            let all = parts.iter().skip(1).fold(parts[0].clone(), |acc, p| acc.concat(p));
+            assert_eq!(all.run(&LeveledScheduler).unwrap(), records, "with {} partitions", n);
+        }
+    }
+
+    #[test]
+    fn test_fixed_misaligned() {
+        let path = "/tmp/tange-test-fixed-misaligned";
+        fs::write(path, vec![7u8; 40]).unwrap();
+        let err = MemoryCollection::read_fixed(path, 16, 2).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), format!("{} is 40 bytes, which isn't a multiple of the \
+                                            record length 16", path));
+
+        let err = MemoryCollection::read_fixed(path, 0, 2).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+        fs::write(path, b"").unwrap();
+        let empty = MemoryCollection::read_fixed(path, 16, 2).unwrap();
+        assert_eq!(empty.run(&LeveledScheduler), Some(Vec::new()));
+    }
+
+    #[derive(Clone,Debug,PartialEq,Deserialize)]
+    struct Row {
+        name: String,
+        count: Option<u32>,
+        note: Option<String>
+    }
+
+    const CSV: &str = "name,note,count\n\
+        plain,,1\n\
+        \"quoted, with comma\",\"multi\nline\n\"\"note\"\"\",2\n\
+        \"start\n\",trailing,\n\
+        empty,,\n\
+        last,\"a\nb\nc\",5";
+
+    #[test]
+    fn test_read_csv() {
+        let path = write_lines("/tmp/tange-test-read-csv", CSV);
+        let expected = vec![
+            Row { name: "plain".into(), count: Some(1), note: None },
+            Row { name: "quoted, with comma".into(), count: Some(2), 
+                  note: Some("multi\nline\n\"note\"".into()) },
+            Row { name: "start\n".into(), count: None, note: Some("trailing".into()) },
+            Row { name: "empty".into(), count: None, note: None },
+            Row { name: "last".into(), count: Some(5), note: Some("a\nb\nc".into()) },
+        ];
+        // Every partition count puts boundaries inside quoted fields somewhere
+        for n in 1..(CSV.len() + 2) {
+            let col = MemoryCollection::<Row>::read_csv(&path, true, n).unwrap();
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), expected, "with {} partitions", n);
+        }
+    }
+
+    #[test]
+    fn test_read_csv_raw() {
+        let path = write_lines("/tmp/tange-test-read-csv-raw", CSV);
+        let expected: Vec<Vec<String>> = csv_rows(CSV);
+        for n in 1..(CSV.len() + 2) {
+            let with_header = MemoryCollection::read_csv_raw(&path, false, n).unwrap()
+                .run(&LeveledScheduler).unwrap();
+            assert_eq!(with_header, expected, "with {} partitions", n);
+            let without = MemoryCollection::read_csv_raw(&path, true, n).unwrap()
+                .run(&LeveledScheduler).unwrap();
+            assert_eq!(without, &expected[1..], "with {} partitions", n);
+        }
+        assert_eq!(expected[4], vec!["empty", "", ""]);
+    }
+
+    fn csv_rows(contents: &str) -> Vec<Vec<String>> {
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        let mut chars = contents.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' if quoted && chars.peek() == Some(&'"') => { chars.next(); field.push('"'); },
+                '"' => quoted = !quoted,
+                ',' if !quoted => row.push(::std::mem::take(&mut field)),
+                '\n' if !quoted => {
+                    row.push(::std::mem::take(&mut field));
+                    rows.push(::std::mem::take(&mut row));
+                },
+                c => field.push(c)
+            }
+        }
+        row.push(field);
+        rows.push(row);
+        rows
+    }
+
+    #[test]
+    #[should_panic(expected = "Couldn't read /tmp/tange-test-read-csv-bad: Bad record at byte 11")]
+    fn test_read_csv_bad_record() {
+        let path = write_lines("/tmp/tange-test-read-csv-bad", "name,count\nx,notanumber\n");
+        MemoryCollection::<(String, u32)>::read_csv(&path, true, 1).unwrap()
+            .run(&LeveledScheduler);
+    }
+
+    #[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+    struct Tricky {
+        text: String,
+        value: f64,
+        maybe: Option<i32>
+    }
+
+    fn tricky() -> Vec<Tricky> {
+        vec![
+            Tricky { text: "comma, separated".into(), value: 1.5, maybe: Some(-1) },
+            Tricky { text: "\"quoted\" and \"\"doubled\"\"".into(), value: 0.0, maybe: None },
+            Tricky { text: "multi\nline\r\nfield\n".into(), value: -2.25, maybe: Some(7) },
+            Tricky { text: "".into(), value: 1e10, maybe: None },
+            Tricky { text: "plain".into(), value: 3.0, maybe: Some(0) },
+        ]
+    }
+
+    #[test]
+    fn test_sink_csv_round_trip() {
+        let dir = "/tmp/tange-test-sink-csv";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec(tricky()).split(2);
+        let expected = col.run(&LeveledScheduler).unwrap();
+        let counts = col.sink_csv(dir, true).run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 5);
+
+        let mut results = Vec::new();
+        for (idx, count) in counts.iter().enumerate() {
+            let path = format!("{}/{}", dir, idx);
+            assert!(fs::read_to_string(&path).unwrap().starts_with("text,value,maybe\n"));
+            for n in 1..8 {
+                let part = MemoryCollection::<Tricky>::read_csv(&path, true, n).unwrap()
+                    .run(&LeveledScheduler).unwrap();
+                assert_eq!(part.len(), *count);
+                if n == 1 {
+                    results.extend(part);
+                }
+            }
+        }
+        assert_eq!(results, expected);
+    }
+
+    #[test]
+    fn test_sink_csv_headers() {
+        let dir = "/tmp/tange-test-sink-csv-headers";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec(tricky()).split(3);
+        col.sink_csv_with(dir, CsvHeaders::FirstFile).run(&LeveledScheduler).unwrap();
+
+        let mut cat = String::new();
+        for idx in 0..3 {
+            let contents = fs::read_to_string(format!("{}/{}", dir, idx)).unwrap();
+            assert_eq!(contents.starts_with("text,"), idx == 0);
+            cat.push_str(&contents);
+        }
+        let path = write_lines("/tmp/tange-test-sink-csv-cat", &cat);
+        let mut results = MemoryCollection::<Tricky>::read_csv(&path, true, 4).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        let mut expected = tricky();
+        results.sort_by(|a, b| a.text.cmp(&b.text));
+        expected.sort_by(|a, b| a.text.cmp(&b.text));
+        assert_eq!(results, expected);
+
+        let dir = "/tmp/tange-test-sink-csv-no-headers";
+        MemoryCollection::from_vec(vec![(1, "a")]).sink_csv(dir, false).run(&LeveledScheduler);
+        assert_eq!(fs::read_to_string(format!("{}/0", dir)).unwrap(), "1,a\n");
+    }
+
+    #[derive(Clone,Debug,PartialEq,Deserialize)]
+    struct Event {
+        id: u64,
+        user: User,
+        tags: Vec<String>,
+        #[serde(default)]
+        scores: Vec<Vec<f64>>
+    }
+
+    #[derive(Clone,Debug,PartialEq,Deserialize)]
+    struct User {
+        name: String,
+        address: Option<Address>
+    }
+
+    #[derive(Clone,Debug,PartialEq,Deserialize)]
+    struct Address {
+        city: String
+    }
+
+    fn event_lines(n: usize) -> String {
+        let mut contents = String::new();
+        for i in 0..n {
+            let address = if i % 2 == 0 { 
+                format!("{{\"city\": \"Zürich {}\"}}", i) 
+            } else { 
+                "null".into() 
+            };
+            contents.push_str(&format!(
+                "{{\"id\": {}, \"user\": {{\"name\": \"u{}\", \"address\": {}}}, \"tags\": [\"a\", \"b{}\"], \"scores\": [[{}, 0.5], []]}}\n",
+                i, i, address, i, i));
+            if i % 10 == 0 {
+                contents.push_str("\n  \r\n");
+            }
+        }
+        contents
+    }
+
+    #[test]
+    fn test_read_jsonl() {
+        let contents = event_lines(100);
+        let path = write_lines("/tmp/tange-test-read-jsonl", contents.trim_end());
+        assert!(!fs::read_to_string(&path).unwrap().ends_with('\n'));
+        for n in 1..12 {
+            let events = MemoryCollection::<Event>::read_jsonl(&path, n).unwrap()
+                .run(&LeveledScheduler).unwrap();
+            assert_eq!(events.len(), 100);
+            for (i, e) in events.iter().enumerate() {
+                assert_eq!(e.id, i as u64);
+                assert_eq!(e.tags, vec!["a".to_owned(), format!("b{}", i)]);
+                assert_eq!(e.scores, vec![vec![i as f64, 0.5], vec![]]);
+                assert_eq!(e.user.address.as_ref().map(|a| a.city.clone()),
+                           if i % 2 == 0 { Some(format!("Zürich {}", i)) } else { None });
+            }
+        }
+    }
+
+    fn corrupt_events() -> String {
+        let mut contents = event_lines(3);
+        contents.push_str("{\"id\": 3, \"user\": {\"name\": \"truncated\"\n");
+        contents.push_str("{\"id\": \"not a number\", \"user\": {\"name\": \"x\"}, \"tags\": []}\n");
+        contents.push_str(&event_lines(2));
+        write_lines("/tmp/tange-test-read-jsonl-corrupt", &contents)
+    }
+
+    #[test]
+    #[should_panic(expected="Malformed JSON on line 6 of /tmp/tange-test-read-jsonl-corrupt")]
+    fn test_read_jsonl_corrupt() {
+        let path = corrupt_events();
+        MemoryCollection::<Event>::read_jsonl(&path, 3).unwrap().run(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_read_jsonl_skipping() {
+        let path = corrupt_events();
+        for n in 1..6 {
+            let (events, skipped) = TextReader::new().read_jsonl_skipping::<Event>(&path, n)
+                .unwrap();
+            let ids: Vec<_> = events.run(&LeveledScheduler).unwrap()
+                .into_iter().map(|e| e.id).collect();
+            assert_eq!(ids, vec![0, 1, 2, 0, 1]);
+            let skipped = skipped.run(&LeveledScheduler).unwrap();
+            assert_eq!(skipped.len(), events.n_partitions());
+            assert_eq!(skipped.iter().sum::<usize>(), 2);
+        }
+    }
+
+    #[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+    struct Note {
+        author: String,
+        text: String,
+        langs: Vec<String>
+    }
+
+    #[test]
+    fn test_sink_jsonl_round_trip() {
+        let dir = "/tmp/tange-test-sink-jsonl";
+        let _ = fs::remove_dir_all(dir);
+        let notes = vec![
+            Note { author: "Zoë".into(), text: "naïve café\nsecond line".into(), 
+                   langs: vec!["fr".into()] },
+            Note { author: "山田".into(), text: "こんにちは\t\"世界\"".into(), 
+                   langs: vec!["ja".into(), "en".into()] },
+            Note { author: "🦀".into(), text: "\u{2028}\r\n\\".into(), langs: vec![] },
+        ];
+        // The second partition is empty
+        let col = MemoryCollection::from_vec(notes.clone())
+            .partition(3, |idx, _n| if idx == 0 { 0 } else { 2 });
+        assert_eq!(col.n_partitions(), 3);
+        let counts = col.sink_jsonl(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(counts, vec![1, 0, 2]);
+
+        assert_eq!(fs::read_to_string(format!("{}/1", dir)).unwrap(), "");
+        let mut results = Vec::new();
+        for (idx, count) in counts.iter().enumerate() {
+            let path = format!("{}/{}", dir, idx);
+            assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), *count);
+            results.extend(MemoryCollection::<Note>::read_jsonl(&path, 2).unwrap()
+                .run(&LeveledScheduler).unwrap());
+        }
+        assert_eq!(results, notes);
+    }
+
+    #[test]
+    fn test_sink_runtime_path() {
+        let dir = format!("/tmp/tange-test-sink-{}", 12 * 3);
+        let _ = fs::remove_dir_all(&dir);
+        let col = MemoryCollection::from_vec(vec!["one".to_owned(), "two".into(), "three".into()])
+            .split(2);
+        let counts = col.sink(dir.clone()).run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 3);
+        let mut lines = Vec::new();
+        for idx in 0..2 {
+            let contents = fs::read_to_string(format!("{}/{}", dir, idx)).unwrap();
+            lines.extend(contents.lines().map(|l| l.to_owned()));
+        }
+        lines.sort();
+        assert_eq!(lines, vec!["one".to_owned(), "three".into(), "two".into()]);
+
+        // Literals still work, as do borrowed runtime paths
+        col.sink("/tmp/tange-test-sink-literal").run(&LeveledScheduler).unwrap();
+        let path = ::std::path::Path::new(&dir).join("nested");
+        col.sink(&path).run(&LeveledScheduler).unwrap();
+        assert!(path.join("1").exists());
+    }
+
+    #[test]
+    fn test_sink_paths() {
+        let col = MemoryCollection::from_vec((0..100).map(|i| i.to_string()).collect())
+            .split(4);
+        // Relative paths are resolved against the working directory
+        let dir = "target/tange-test-sink-paths";
+        let written = col.sink_paths(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(written.len(), 4);
+        let mut total = 0;
+        for (path, count) in written {
+            let path = ::std::path::Path::new(&path);
+            assert!(path.is_absolute());
+            assert!(path.starts_with(::std::env::current_dir().unwrap()));
+            assert_eq!(fs::read_to_string(path).unwrap().lines().count(), count);
+            total += count;
+        }
+        assert_eq!(total, 100);
+    }
+
+    #[derive(Clone,Debug,PartialEq,Serialize,Deserialize)]
+    struct Matrix {
+        name: String,
+        rows: Vec<Vec<i64>>,
+        labels: Vec<(String, Vec<u8>)>
+    }
+
+    fn read_bincode<A>(path: &str, count: usize) -> Vec<A> 
+            where A: Clone + Send + Sync + for<'de>Deserialize<'de> {
+        let path = ::std::path::Path::new(path);
+        let store = ::std::sync::Arc::new(::store::LocalFs::new(path.parent().unwrap()));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        let fs = ::interfaces::FileStore::<A>::stored(store, name, count).unwrap();
+        ::interfaces::Stream::try_stream(&::std::sync::Arc::new(fs)).unwrap().into_iter().collect()
+    }
+
+    // Stands in for a job which writes its output and exits
+    fn write_stored(dir: &str) -> Vec<(u32, String)> {
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..50u32).map(|i| (i, i.to_string())).collect())
+            .partition(4, |_idx, r| if r.0 < 20 { 3 } else if r.0 < 30 { 0 } else { 1 });
+        col.sink_bincode(dir).run(&LeveledScheduler);
+        col.run(&LeveledScheduler).unwrap()
+    }
+
+    // Stands in for a later job which only knows where the output was written
+    fn load_stored(dir: &str) -> (usize, Vec<(u32, String)>) {
+        let col = MemoryCollection::<(u32, String)>::from_stored(dir).unwrap();
+        (col.n_partitions(), col.run(&LeveledScheduler).unwrap())
+    }
+
+    #[test]
+    fn test_from_stored() {
+        let dir = "/tmp/tange-test-from-stored";
+        let expected = write_stored(dir);
+        assert_eq!(load_stored(dir), (4, expected.clone()));
+
+        // Order follows the manifest rather than file names
+        let path = format!("{}/manifest.json", dir);
+        let file = fs::File::open(&path).unwrap();
+        let mut manifest: super::super::Manifest = serde_json::from_reader(file).unwrap();
+        manifest.files.reverse();
+        fs::write(&path, serde_json::to_vec(&manifest).unwrap()).unwrap();
+        let (_, reversed) = load_stored(dir);
+        assert_eq!(reversed[..20].to_vec(), expected[30..].to_vec());
+        assert_eq!(reversed[20..40].to_vec(), expected[10..30].to_vec());
+        assert_eq!(reversed[40..].to_vec(), expected[..10].to_vec());
+    }
+
+    #[test]
+    fn test_from_stored_listing() {
+        let dir = "/tmp/tange-test-from-stored-listing";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let store = ::interfaces::Store(::std::sync::Arc::new(::store::LocalFs::new(dir)));
+        for (name, vs) in [("part-00001", vec![3, 4]), ("part-00000", vec![1, 2]), ("part-00002", vec![])] {
+            let mut out = store.writer_named::<u64>(name);
+            for v in vs {
+                ::interfaces::ValueWriter::add(&mut out, v);
+            }
+            ::interfaces::ValueWriter::finish(out);
+        }
+        fs::write(format!("{}/notes.txt", dir), "not records").unwrap();
+
+        let col = MemoryCollection::<u64>::from_stored(dir).unwrap();
+        assert_eq!(col.n_partitions(), 3);
+        assert_eq!(col.run(&LeveledScheduler), Some(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    #[should_panic(expected="Records at /tmp/tange-test-from-stored-missing/1 are missing")]
+    fn test_from_stored_missing() {
+        let dir = "/tmp/tange-test-from-stored-missing";
+        write_stored(dir);
+        fs::remove_file(format!("{}/1", dir)).unwrap();
+        load_stored(dir);
+    }
+
+    #[test]
+    #[should_panic(expected="Records at /tmp/tange-test-from-stored-truncated/3 are truncated")]
+    fn test_from_stored_truncated() {
+        let dir = "/tmp/tange-test-from-stored-truncated";
+        write_stored(dir);
+        let path = format!("{}/3", dir);
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        load_stored(dir);
+    }
+
+    #[test]
+    fn test_sink_bincode_round_trip() {
+        let dir = "/tmp/tange-test-sink-bincode";
+        let _ = fs::remove_dir_all(dir);
+        let matrices: Vec<_> = (0..10i64).map(|i| Matrix {
+            name: format!("m{}", i),
+            rows: (0..i).map(|r| vec![r; i as usize]).collect(),
+            labels: vec![(format!("l{}", i), vec![i as u8; 3]), ("".into(), vec![])]
+        }).collect();
+        // The middle partition is empty
+        let col = MemoryCollection::from_vec(matrices.clone())
+            .partition(3, |_idx, m| if m.rows.len() < 5 { 0 } else { 2 });
+        let written = col.sink_bincode(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(written, vec![(format!("{}/0", dir), 5), (format!("{}/1", dir), 0), 
+                                 (format!("{}/2", dir), 5)]);
+        assert_eq!(fs::metadata(format!("{}/1", dir)).unwrap().len(), 0);
+
+        let mut results = Vec::new();
+        for (path, count) in written {
+            let part: Vec<Matrix> = read_bincode(&path, count);
+            assert_eq!(part.len(), count);
+            results.extend(part);
+        }
+        assert_eq!(results, matrices);
+
+        let manifest = fs::read_to_string(format!("{}/manifest.json", dir)).unwrap();
+        let manifest: super::super::Manifest = serde_json::from_str(&manifest).unwrap();
+        let files: Vec<_> = manifest.files.iter().map(|e| (e.path.as_str(), e.records)).collect();
+        assert_eq!(files, vec![("0", 5), ("1", 0), ("2", 5)]);
+    }
+
+    #[derive(Clone)]
+    struct Sale {
+        store: String,
+        units: u32,
+        price: f64
+    }
+
+    impl ::std::fmt::Display for Sale {
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+            write!(f, "{} sold {}", self.store, self.units)
+        }
+    }
+
+    #[test]
+    fn test_sink_with() {
+        let dir = "/tmp/tange-test-sink-with";
+        let _ = fs::remove_dir_all(dir);
+        let sales = vec![
+            Sale { store: "north".into(), units: 3, price: 1.25 },
+            Sale { store: "south".into(), units: 0, price: 10.0 },
+            Sale { store: "east".into(), units: 12, price: 0.5 },
+        ];
+        let col = MemoryCollection::from_vec(sales).split(2);
+        let counts = col.sink_with(dir, |s, w| writeln!(w, "{}\t{}\t{:.2}", s.store, s.units, s.price))
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.iter().sum::<usize>(), 3);
+
+        let mut lines = Vec::new();
+        for idx in 0..counts.len() {
+            let contents = fs::read_to_string(format!("{}/{}", dir, idx)).unwrap();
+            lines.extend(contents.lines().map(|l| l.to_owned()));
+        }
+        lines.sort();
+        assert_eq!(lines, vec!["east\t12\t0.50".to_owned(), "north\t3\t1.25".into(), 
+                               "south\t0\t10.00".into()]);
+
+        let dir = "/tmp/tange-test-sink-display";
+        col.split(1).sink_display(dir).run(&LeveledScheduler).unwrap();
+        let mut lines: Vec<_> = fs::read_to_string(format!("{}/0", dir)).unwrap()
+            .lines().map(|l| l.to_owned()).collect();
+        lines.sort();
+        assert_eq!(lines, vec!["east sold 12".to_owned(), "north sold 3".into(), "south sold 0".into()]);
+    }
+
+    #[test]
+    fn test_sink_named() {
+        let dir = "/tmp/tange-test-sink-named";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..150).map(|i| i.to_string()).collect()).split(150);
+        let written = col.sink_named(dir, "part-", 2, ".txt").run(&LeveledScheduler).unwrap();
+
+        // Names widen to three digits, so listing them sorts in partition order
+        let expected: Vec<_> = (0..150).map(|i| format!("part-{:03}.txt", i)).collect();
+        assert_eq!(file_names(dir)[2..].to_vec(), expected);
+        let paths: Vec<_> = written.into_iter().map(|w| w.0).collect();
+        assert_eq!(paths, expected.iter().map(|n| format!("{}/{}", dir, n)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sink_partitioned() {
+        let dir = "/tmp/tange-test-sink-partitioned";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..20usize).collect()).split(3);
+        let written = col.sink_partitioned(dir, 
+                |x| format!("parity={}", if x % 2 == 0 { "even" } else { "odd" }),
+                |x, w| writeln!(w, "{}", x))
+            .run(&LeveledScheduler).unwrap();
+
+        assert_eq!(file_names(dir), vec!["_SUCCESS", "manifest.json", "parity=even", "parity=odd"]);
+        assert_eq!(written.len(), 6);
+        assert_eq!(written.iter().map(|w| w.1).sum::<usize>(), 20);
+        for parity in &["even", "odd"] {
+            let sub = format!("{}/parity={}", dir, parity);
+            let names = file_names(&sub);
+            assert_eq!(names, vec!["part-00000", "part-00001", "part-00002"]);
+
+            let mut values = Vec::new();
+            for name in names {
+                let path = format!("{}/{}", sub, name);
+                let text = fs::read_to_string(&path).unwrap();
+                let count = written.iter().find(|w| w.0 == path).unwrap().1;
+                assert_eq!(text.lines().count(), count);
+                values.extend(text.lines().map(|l| l.parse::<usize>().unwrap()));
+            }
+            values.sort();
+            let start = if *parity == "even" { 0 } else { 1 };
+            assert_eq!(values, (start..20).step_by(2).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_key_dir() {
+        assert_eq!(key_dir("date=2024-01-01"), "date=2024-01-01");
+        assert_eq!(key_dir("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(key_dir(".."), "__");
+        assert_eq!(key_dir(""), "_");
+        assert_eq!(key_dir("a\\b\nc"), "a_b_c");
+    }
+
+    #[test]
+    fn test_file_naming() {
+        assert_eq!(FileNaming::default().name(12, 150), "12");
+        assert_eq!(FileNaming::new("part-", 5, ".txt").name(12, 150), "part-00012.txt");
+        assert_eq!(FileNaming::new("", 1, "").name(7, 1000), "007");
+        assert_eq!(FileNaming::new("p", 1, "").name(0, 1), "p0");
+    }
+
+    #[test]
+    fn test_sink_gz() {
+        use self::flate2::read::MultiGzDecoder;
+
+        let plain_dir = "/tmp/tange-test-sink-gz-plain";
+        let gz_dir = "/tmp/tange-test-sink-gz";
+        let _ = fs::remove_dir_all(gz_dir);
+        let lines: Vec<String> = (0..20_000).map(|i| format!("line {} {}", i, "z".repeat(i % 50))).collect();
+        let col = MemoryCollection::from_vec(lines).split(3);
+        let plain = col.sink_paths(plain_dir).run(&LeveledScheduler).unwrap();
+
+        for level in &[0, 1, 9] {
+            let written = col.sink_gz(gz_dir, *level).run(&LeveledScheduler).unwrap();
+            assert_eq!(written.len(), 3);
+            for (idx, ((gz_path, gz_count), (plain_path, plain_count))) in written.iter().zip(plain.iter()).enumerate() {
+                assert_eq!(*gz_path, format!("{}/{}.gz", gz_dir, idx));
+                assert_eq!(gz_count, plain_count);
+
+                let mut contents = String::new();
+                MultiGzDecoder::new(fs::File::open(gz_path).unwrap())
+                    .read_to_string(&mut contents).unwrap();
+                let expected = fs::read_to_string(plain_path).unwrap();
+                assert_eq!(contents, expected);
+                if *level > 0 {
+                    assert!(fs::metadata(gz_path).unwrap().len() < expected.len() as u64 / 2);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sink_single() {
+        let dir = "/tmp/tange-test-sink-single";
+        let _ = fs::remove_dir_all(dir);
+        let lines: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        // Partitions 1 and 3 are empty
+        let col = MemoryCollection::from_vec(lines)
+            .partition(5, |idx, _l| [0, 2, 4][idx % 3]);
+        assert_eq!(col.n_partitions(), 5);
+        let expected = col.run(&LeveledScheduler).unwrap();
+
+        let path = format!("{}/nested/out.txt", dir);
+        let total = col.sink_single(path.clone()).run(&LeveledScheduler).unwrap();
+        assert_eq!(total, vec![1000]);
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents.lines().collect::<Vec<_>>(), expected);
+
+        // Temporary files are cleaned up
+        let names: Vec<_> = fs::read_dir(format!("{}/nested", dir)).unwrap()
+            .map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![::std::ffi::OsString::from("out.txt")]);
+
+        // Nothing is left behind when a partition fails, and the existing file is kept
+        let failed = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            col.map(|l| if l == "500" { panic!("bad line") } else { l.clone() })
+                .sink_single(path.clone()).run(&GreedyScheduler::new())
+        }));
+        assert!(failed.is_err());
+        let names: Vec<_> = fs::read_dir(format!("{}/nested", dir)).unwrap()
+            .map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, vec![::std::ffi::OsString::from("out.txt")]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+
+        // Overwrites an existing file, even when empty
+        let empty = MemoryCollection::<String>::from_vec(Vec::new());
+        assert_eq!(empty.sink_single(path.clone()).run(&LeveledScheduler).unwrap(), vec![0]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+    }
+
+    fn file_names(dir: &str) -> Vec<String> {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_sink_to() {
+        let dir = "/tmp/tange-test-sink-to";
+        let _ = fs::remove_dir_all(dir);
+        let four = MemoryCollection::from_vec((0..40).map(|i| i.to_string()).collect()).split(4);
+        let two = four.split(2);
+
+        // Empty or missing directories are fine to write into
+        four.sink_to(dir, OverwritePolicy::ErrorIfExists).unwrap().run(&LeveledScheduler);
+        assert_eq!(file_names(dir), vec!["0", "1", "2", "3", "_SUCCESS", "manifest.json"]);
+        let err = two.sink_to(dir, OverwritePolicy::ErrorIfExists).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(file_names(dir), vec!["0", "1", "2", "3", "_SUCCESS", "manifest.json"]);
+
+        // Nothing remains from the earlier run with more partitions
+        let written = two.sink_to(dir, OverwritePolicy::Overwrite).unwrap()
+            .run(&LeveledScheduler).unwrap();
+        assert_eq!(file_names(dir), vec!["0", "1", "_SUCCESS", "manifest.json"]);
+        assert_eq!(written.iter().map(|w| w.1).sum::<usize>(), 40);
+
+        // Each run gets its own directory
+        for run in 0..2 {
+            let written = two.sink_to(dir, OverwritePolicy::NewRunDir).unwrap()
+                .run(&LeveledScheduler).unwrap();
+            assert_eq!(written[0].0, format!("{}/run-{:05}/0", dir, run));
+        }
+        assert_eq!(file_names(dir), vec!["0", "1", "_SUCCESS", "manifest.json", "run-00000", "run-00001"]);
+        assert_eq!(file_names(&format!("{}/run-00001", dir)), vec!["0", "1", "_SUCCESS", "manifest.json"]);
+    }
+
+    #[test]
+    fn test_sink_without_policy() {
+        let dir = "/tmp/tange-test-sink-no-policy";
+        let _ = fs::remove_dir_all(dir);
+        let four = MemoryCollection::from_vec((0..40).map(|i| i.to_string()).collect()).split(4);
+        four.sink(dir).run(&LeveledScheduler);
+
+        // Rewrites the files of its own partitions, and leaves the rest
+        let written = four.split(2).sink_paths(dir).run(&LeveledScheduler).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(file_names(dir), vec!["0", "1", "2", "3", "_SUCCESS", "manifest.json"]);
+        let manifest = fs::read_to_string(format!("{}/manifest.json", dir)).unwrap();
+        assert!(!manifest.contains("\"2\""), "{}", manifest);
+    }
+
+    #[test]
+    fn test_sink_to_overwrite_keeps_other_files() {
+        let dir = "/tmp/tange-test-sink-to-others";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..30).map(|i| i.to_string()).collect()).split(3);
+        col.sink_to(dir, OverwritePolicy::Overwrite).unwrap().run(&LeveledScheduler);
+        col.sink_named(dir, "other-", 0, ".txt").run(&LeveledScheduler);
+        fs::write(format!("{}/notes", dir), "keep me").unwrap();
+        fs::create_dir(format!("{}/7", dir)).unwrap();
+        // Left behind by a run which failed while writing partition 2
+        fs::write(format!("{}/.tmp-2-67e55044-10b1-426f-9247-bb680e5fe0c8", dir), "").unwrap();
+
+        col.split(2).sink_to(dir, OverwritePolicy::Overwrite).unwrap().run(&LeveledScheduler);
+        assert_eq!(file_names(dir), vec!["0", "1", "7", "_SUCCESS", "manifest.json", "notes",
+                                         "other-0.txt", "other-1.txt", "other-2.txt"]);
+        assert_eq!(fs::read_to_string(format!("{}/notes", dir)).unwrap(), "keep me");
+    }
+
+    #[test]
+    #[should_panic(expected="already exists and isn't empty")]
+    fn test_sink_to_checked_on_run() {
+        let dir = "/tmp/tange-test-sink-to-run";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec(vec!["a".to_owned()]);
+        let sink = col.sink_to(dir, OverwritePolicy::ErrorIfExists).unwrap();
+        sink.run(&LeveledScheduler);
+        sink.run(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_sink_manifest() {
+        let dir = "/tmp/tange-test-sink-manifest";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..25).map(|i| "x".repeat(i)).collect()).split(3);
+        let written = col.sink_paths(dir).run(&LeveledScheduler).unwrap();
+
+        let file = fs::File::open(format!("{}/manifest.json", dir)).unwrap();
+        let manifest: super::super::Manifest = serde_json::from_reader(file).unwrap();
+        assert_eq!(manifest.files.len(), 3);
+        for (entry, (path, records)) in manifest.files.iter().zip(written.iter()) {
+            assert_eq!(format!("{}/{}", dir, entry.path), *path);
+            assert_eq!(entry.records, *records);
+            let text = fs::read_to_string(path).unwrap();
+            assert_eq!(text.lines().count(), entry.records);
+            assert_eq!(text.len() as u64, entry.bytes);
+        }
+        assert_eq!(fs::read(format!("{}/_SUCCESS", dir)).unwrap().len(), 0);
+
+        // A failed rerun into the same directory no longer reads as complete
+        let failed = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            col.sink_with(dir, |x, w| if x.len() == 7 { 
+                Err(io::Error::other("disk on fire")) 
+            } else { 
+                writeln!(w, "{}", x) 
+            }).run(&LeveledScheduler)
+        }));
+        assert!(failed.is_err());
+        let names = file_names(dir);
+        assert!(!names.contains(&"_SUCCESS".to_owned()));
+        assert!(!names.contains(&"manifest.json".to_owned()));
+    }
+
+    #[test]
+    fn test_sink_error_context() {
+        let dir = "/tmp/tange-test-sink-error-context";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..10).collect::<Vec<usize>>()).split(2);
+        let failed = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            col.sink_with(dir, |x, w| if *x == 7 { 
+                Err(io::Error::from_raw_os_error(28)) 
+            } else { 
+                write!(w, "{}", x) 
+            }).run(&LeveledScheduler)
+        }));
+
+        let err = failed.err().unwrap();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.contains("partition 1"), "{}", msg);
+        assert!(msg.contains(&format!("{}/1", dir)), "{}", msg);
+        assert!(msg.contains("No space left on device"), "{}", msg);
+    }
+
+    #[test]
+    fn test_sink_atomic() {
+        let dir = "/tmp/tange-test-sink-atomic";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec((0..10).collect::<Vec<usize>>()).split(2);
+        col.sink_with(dir, |x, w| write!(w, "{}", x)).run(&LeveledScheduler);
+        assert_eq!(file_names(dir), vec!["0", "1", "_SUCCESS", "manifest.json"]);
+
+        // A failed partition leaves neither its temporary nor a partial file
+        let _ = fs::remove_dir_all(dir);
+        let failed = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+            col.sink_with(dir, |x, w| if *x == 7 { 
+                Err(io::Error::other("disk on fire")) 
+            } else { 
+                write!(w, "{}", x) 
+            }).run(&LeveledScheduler)
+        }));
+        assert!(failed.is_err());
+        assert!(file_names(dir).iter().all(|name| name == "0"));
+    }
+
+    #[test]
+    fn test_from_vec_chunked() {
+        for &(len, n) in &[(10, 3), (9, 3), (100, 7), (3, 10), (1, 1), (5, 0)] {
+            let col = MemoryCollection::from_vec_chunked((0..len).collect(), n);
+            let sizes: Vec<_> = col.to_defs().iter()
+                .map(|d| d.run(&LeveledScheduler).unwrap().len())
+                .collect();
+            assert_eq!(sizes.len(), n.min(len).max(1), "{} into {}", len, n);
+            assert!(sizes.iter().all(|s| *s > 0));
+            assert!(sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1);
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), (0..len).collect::<Vec<_>>());
+        }
+
+        let empty = MemoryCollection::<u8>::from_vec_chunked(Vec::new(), 4);
+        assert_eq!(empty.n_partitions(), 1);
+        assert_eq!(empty.run(&LeveledScheduler), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_from_iter_chunked() {
+        for &chunk_size in &[1000, 3000, 1, 10_000, 20_000] {
+            let col = MemoryCollection::from_iter_chunked((0..10_000usize).map(|x| x * 3), chunk_size);
+            let sizes: Vec<_> = col.to_defs().iter()
+                .map(|d| d.run(&LeveledScheduler).unwrap().len())
+                .collect();
+            assert_eq!(sizes.len(), 10_000usize.div_ceil(chunk_size));
+            assert!(sizes[..sizes.len() - 1].iter().all(|s| *s == chunk_size));
+            assert_eq!(*sizes.last().unwrap(), 10_000 - chunk_size * (sizes.len() - 1));
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), (0..10_000).map(|x| x * 3).collect::<Vec<_>>());
+        }
+
+        let empty = MemoryCollection::<u8>::from_iter_chunked(Vec::new(), 4);
+        assert_eq!(empty.n_partitions(), 1);
+        assert_eq!(empty.run(&LeveledScheduler), Some(Vec::new()));
+    }
+
+    #[test]
+    #[should_panic(expected="requires a chunk_size of at least 1")]
+    fn test_from_iter_chunked_zero() {
+        MemoryCollection::from_iter_chunked(0..10, 0);
+    }
+
+    #[test]
+    fn test_from_iter_lazy() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let col = MemoryCollection::from_iter_lazy(move || {
+            c.fetch_add(1, Ordering::SeqCst);
+            0..10_000usize
+        });
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        let total = col.split(4).fold_by(|_| 0, || 0usize, |acc, x| *acc += x, |a, b| *a += b, 1);
+        assert_eq!(total.run(&LeveledScheduler), Some(vec![(0, (0..10_000).sum())]));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_debug() {
+        let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 3)
+            .map(|x| x * 2).named("double")
+            .filter(|x| x % 3 == 0).named("threes");
+        let plan = format!("{:?}", col);
+        assert_eq!(plan, "MemoryCollection { partitions: 3, depth: 3 }\n  threes\n    double\n      Input");
+
+        let folded = col.fold_by(|x| x % 2, || 0, |acc, x| *acc += x, |x, y| *x += y, 2);
+        let plan = format!("{:?}", folded);
+        assert!(plan.starts_with("MemoryCollection { partitions: 2, depth: "), "{}", plan);
+        assert!(plan.contains("\n  fold_by#"), "{}", plan);
+        assert!(plan.contains("threes"), "{}", plan);
+        assert!(plan.contains("\n    Join\n"), "{}", plan);
+
+        let mut deep = col.clone();
+        for _ in 0..20 {
+            deep = deep.map(|x| x + 1);
+        }
+        let plan = format!("{:?}", deep);
+        assert!(plan.starts_with("MemoryCollection { partitions: 3, depth: 23 }"), "{}", plan);
+        // The header, eight steps, and the elision
+        assert_eq!(plan.lines().count(), 10);
+        assert!(plan.ends_with("..."), "{}", plan);
+
+        assert_eq!(format!("{:?}", MemoryCollection::<usize>::empty()),
+                   "MemoryCollection { partitions: 0, depth: 0 }");
+    }
+
+    #[test]
+    fn test_run_with_progress() {
+        use std::sync::Mutex;
+        use tange::scheduler::GreedyScheduler;
+
+        let col = MemoryCollection::from_vec_chunked((0..1000usize).collect(), 8)
+            .map(|x| x % 7)
+            .frequencies(3);
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let progress = Progress::with_callback(move |done, total| log.lock().unwrap().push((done, total)));
+        let out = col.run_with_progress(&GreedyScheduler::new(), &progress).unwrap();
+        assert_eq!(out.len(), 7);
+
+        let seen = seen.lock().unwrap();
+        let total = progress.total();
+        assert_eq!(*seen, (1..total + 1).map(|done| (done, total)).collect::<Vec<_>>());
+
+        let empty = MemoryCollection::<usize>::empty();
+        assert_eq!(empty.run_with_progress(&LeveledScheduler, &progress), Some(Vec::new()));
+        assert_eq!((progress.completed(), progress.total()), (0, 0));
+    }
+
+    #[test]
+    fn test_run_cancellable() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+        use tange::scheduler::GreedyScheduler;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let col = MemoryCollection::from_vec_chunked((0..200usize).collect(), 100)
+            .map(move |x| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                x * 2
+            })
+            .count();
+
+        let mut scheduler = GreedyScheduler::new();
+        scheduler.set_threads(2);
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            canceller.cancel();
+        });
+        let err = col.run_cancellable(&scheduler, &token).unwrap_err();
+        handle.join().unwrap();
+
+        assert!(err.completed < err.total, "{}", err);
+        assert!(calls.load(Ordering::SeqCst) < 100, "{} of 200 records mapped", calls.load(Ordering::SeqCst));
+
+        // The same scheduler runs again once given a fresh token
+        assert_eq!(col.run_cancellable(&scheduler, &CancellationToken::new()), Ok(Some(vec![200])));
+    }
+
+    #[test]
+    fn test_run_timeout() {
+        use std::thread;
+        use tange::scheduler::GreedyScheduler;
+
+        let col = MemoryCollection::from_vec_chunked((0..40usize).collect(), 20)
+            .map(|x| { thread::sleep(Duration::from_millis(50)); *x })
+            .count();
+        let mut scheduler = GreedyScheduler::new();
+        scheduler.set_threads(2);
+
+        let err = col.run_timeout(&scheduler, Duration::from_millis(30)).unwrap_err();
+        assert_eq!(err.timeout, Duration::from_millis(30));
+        assert!(err.completed < err.total, "{}", err);
+        assert!(err.to_string().starts_with("Run timed out after 30ms with "), "{}", err);
+
+        let out = col.run_timeout(&LeveledScheduler, Duration::from_secs(60));
+        assert_eq!(out, Ok(Some(vec![40])));
+    }
+
+    fn check_try_run<S: Scheduler>(s: &S) {
+        let parsed = MemoryCollection::from_vec_chunked((0..12usize).map(|x| x.to_string()).collect(), 3)
+            .map(|s| if s == "9" { panic!("can't parse {}", s) } else { s.parse::<usize>().unwrap() })
+            .named("parse");
+        let err = match parsed.fold_by(|x| x % 2, || 0, |acc, x| *acc += x, |x, y| *x += y, 2).try_run(s) {
+            Err(RunError::Failed(e)) => e,
+            other                    => panic!("expected a failure, got {:?}", other)
+        };
+        assert_eq!(err.stage, Some("parse".into()));
+        assert_eq!(err.partition, Some(2));
+        assert_eq!(err.to_string(), "task 'parse' partition 2 panicked: can't parse 9");
+
+        // The scheduler keeps working for the next run
+        let col = MemoryCollection::from_vec_chunked((0..12usize).collect(), 3).map(|x| x + 1);
+        assert_eq!(col.count().try_run(s), Ok(Some(vec![12])));
+    }
+
+    #[test]
+    fn test_cached() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let root = "/tmp/tange-test-cached";
+        let _ = fs::remove_dir_all(root);
+        let calls = Arc::new(AtomicUsize::new(0));
+        // Builds the pipeline from scratch, as a new process would
+        let pipeline = |key: &str| {
+            let counter = calls.clone();
+            MemoryCollection::from_vec_chunked((0..20usize).collect(), 4)
+                .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); x * 3 })
+                .cached_in(root, key)
+                .unwrap()
+                .filter(|x| x % 2 == 0)
+        };
+
+        let expected: Vec<_> = (0..20usize).map(|x| x * 3).filter(|x| x % 2 == 0).collect();
+        assert_eq!(pipeline("clean-v1").run(&LeveledScheduler), Some(expected.clone()));
+        assert_eq!(calls.load(Ordering::SeqCst), 20);
+
+        let second = pipeline("clean-v1");
+        assert_eq!(second.n_partitions(), 4);
+        assert_eq!(second.run(&LeveledScheduler), Some(expected.clone()));
+        assert_eq!(calls.load(Ordering::SeqCst), 20);
+
+        // A new key starts over
+        assert_eq!(pipeline("clean-v2").run(&LeveledScheduler), Some(expected));
+        assert_eq!(calls.load(Ordering::SeqCst), 40);
+        assert_eq!(fs::read_dir(root).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn test_cache() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use tange::scheduler::GreedyScheduler;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let marker = Arc::new(());
+        let (counter, m) = (calls.clone(), marker.clone());
+        let cleaned = MemoryCollection::from_vec_chunked((0..20usize).collect(), 4)
+            .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); (x * 3, m.clone()) })
+            .cache();
+        let counts = cleaned.count();
+        let evens = cleaned.filter(|p| p.0 % 2 == 0).map(|p| p.0);
+
+        assert_eq!(counts.run(&LeveledScheduler), Some(vec![20]));
+        assert_eq!(calls.load(Ordering::SeqCst), 20);
+        let expected: Vec<_> = (0..20usize).map(|x| x * 3).filter(|x| x % 2 == 0).collect();
+        assert_eq!(evens.run(&GreedyScheduler::new()), Some(expected));
+        assert_eq!(calls.load(Ordering::SeqCst), 20);
+        assert_eq!(cleaned.n_partitions(), 4);
+
+        // The kept partitions go away along with the collections reading them
+        assert!(Arc::strong_count(&marker) > 20);
+        drop((cleaned, counts, evens));
+        assert_eq!(Arc::strong_count(&marker), 1);
+    }
+
+    #[test]
+    fn test_with_max_concurrency() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+        use tange::scheduler::GreedyScheduler;
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let (r, m) = (running.clone(), most.clone());
+        // The limit carries on to the map after it
+        let col = MemoryCollection::from_vec_chunked((0..24usize).collect(), 12)
+            .with_max_concurrency(3)
+            .map(move |x| {
+                let now = r.fetch_add(1, Ordering::SeqCst) + 1;
+                m.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(5));
+                r.fetch_sub(1, Ordering::SeqCst);
+                x * 2
+            });
+        assert!(!col.explain().contains("with_max_concurrency"));
+        let scheduler = GreedyScheduler::builder().workers(8).build();
+        let expected: Vec<_> = (0..24usize).map(|x| x * 2).collect();
+        assert_eq!(col.run(&scheduler), Some(expected));
+        let most = most.load(Ordering::SeqCst);
+        assert!((1..=3).contains(&most), "{} partitions ran at once", most);
+    }
+
+    #[test]
+    fn test_size_hint_bytes() {
+        use std::collections::HashMap;
+        use std::sync::Mutex;
+        use std::thread;
+        use std::time::Duration;
+        use tange::scheduler::GreedyScheduler;
+
+        // Counts the partitions with a record alive, and the most there were at once
+        type Counts = Arc<Mutex<(HashMap<usize, usize>, usize)>>;
+        struct Alive(usize, Counts);
+        impl Alive {
+            fn new(x: usize, counts: &Counts) -> Self {
+                let mut c = counts.lock().unwrap();
+                *c.0.entry(x).or_insert(0) += 1;
+                c.1 = c.1.max(c.0.len());
+                Alive(x, counts.clone())
+            }
+        }
+        impl Clone for Alive {
+            fn clone(&self) -> Self {
+                Alive::new(self.0, &self.1)
+            }
+        }
+        impl Drop for Alive {
+            fn drop(&mut self) {
+                let mut c = self.1.lock().unwrap();
+                let left = c.0.get_mut(&self.0).map(|n| { *n -= 1; *n });
+                if left == Some(0) {
+                    c.0.remove(&self.0);
+                }
+            }
+        }
+
+        // Sixteen partitions of one record each
+        let most_alive = |scheduler: GreedyScheduler| {
+            let counts: Counts = Arc::new(Mutex::new((HashMap::new(), 0)));
+            let c = counts.clone();
+            let col = MemoryCollection::from_vec_chunked((0..16usize).collect(), 16)
+                .map(move |x| {
+                    let alive = Alive::new(*x, &c);
+                    thread::sleep(Duration::from_millis(5));
+                    alive
+                })
+                .size_hint_bytes(1000)
+                .map(|a| a.0);
+            assert_eq!(col.run(&scheduler), Some((0..16).collect()));
+            let most = counts.lock().unwrap().1;
+            most
+        };
+        let wide = most_alive(GreedyScheduler::builder().workers(8).build());
+        assert!(wide > 3, "only {} partitions were alive at once", wide);
+        let most = most_alive(GreedyScheduler::builder().workers(8).max_live_bytes(3000).build());
+        assert!((1..=3).contains(&most), "{} partitions were alive at once", most);
+    }
+
+    #[test]
+    fn test_run_all() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use tange::scheduler::GreedyScheduler;
+        use collection::run_all;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let cleaned = MemoryCollection::from_vec_chunked((0..30usize).collect(), 3)
+            .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); x % 7 });
+        let rows = cleaned.count();
+        let freqs = cleaned.frequencies(2).sort_by(|x| x.0);
+
+        let (n, fs) = run_all(&GreedyScheduler::new(), (&rows, &freqs)).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 30);
+        assert_eq!(Some(n), rows.run(&LeveledScheduler));
+        assert_eq!(Some(fs), freqs.run(&LeveledScheduler));
+
+        calls.store(0, Ordering::SeqCst);
+        let branches = vec![cleaned.filter(|x| x % 2 == 0), cleaned.filter(|x| x % 2 == 1), MemoryCollection::empty()];
+        let all = run_all(&LeveledScheduler, &branches).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 30);
+        let separately: Vec<_> = branches.iter().map(|b| b.run(&LeveledScheduler).unwrap()).collect();
+        assert_eq!(all, separately);
+        assert_eq!(run_all(&LeveledScheduler, &branches[..0]), Some(Vec::new()));
+    }
+
+    #[test]
+    fn test_try_map() {
+        let col = MemoryCollection::from_vec_chunked(vec!["1", "2", "x", "4"], 2);
+        let parsed = col.try_map(|s| s.parse::<u32>().map_err(|_| s.to_string()));
+        assert_eq!(parsed.run(&LeveledScheduler), Some(vec![Ok(1), Ok(2), Err("x".into()), Ok(4)]));
+        assert_eq!(parsed.n_partitions(), 2);
+    }
+
+    #[test]
+    fn test_split_results() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let lines: Vec<String> = (0..30usize)
+            .map(|x| if x % 4 == 0 { format!("bad{}", x) } else { x.to_string() })
+            .collect();
+        let parses = Arc::new(AtomicUsize::new(0));
+        let counter = parses.clone();
+        let parsed = MemoryCollection::from_vec_chunked(lines, 3).try_map(move |s| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            s.parse::<usize>().map_err(|_| s.clone())
+        });
+
+        let (good, bad) = parsed.split_results();
+        assert_eq!((good.n_partitions(), bad.n_partitions()), (3, 3));
+        let dir = "/tmp/tange-test-split-results";
+        let _ = fs::remove_dir_all(dir);
+        let written = good.sink_display(format!("{}/good", dir)).concat(&bad.sink_display(format!("{}/bad", dir)));
+        let counts = written.run(&LeveledScheduler).unwrap();
+        assert_eq!(counts.len(), 6);
+        assert_eq!(counts[..3].iter().sum::<usize>(), 22);
+        assert_eq!(counts[3..].iter().sum::<usize>(), 8);
+        assert_eq!(parses.load(Ordering::SeqCst), 30);
+        assert_eq!(fs::read_to_string(format!("{}/bad/0", dir)).unwrap(), "bad0\nbad4\nbad8\n");
+
+        assert_eq!(parsed.oks().run(&LeveledScheduler), good.run(&LeveledScheduler));
+        assert_eq!(parsed.errs().run(&LeveledScheduler), bad.run(&LeveledScheduler));
+        assert_eq!(parsed.oks().n_partitions(), 3);
+    }
+
+    #[test]
+    fn test_try_map_all() {
+        use tange::scheduler::GreedyScheduler;
+
+        let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 10)
+            .try_map_all(|x| if *x < 100 { Ok(x * 2) } else { Err("too big") })
+            .then(|col| col.count());
+        assert_eq!(col.run(&LeveledScheduler), Ok(Some(vec![100])));
+        assert_eq!(col.run(&GreedyScheduler::new()), Ok(Some(vec![100])));
+
+        let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 10)
+            .try_map_all(|x| if *x != 53 { Ok(*x) } else { Err(format!("bad value {}", x)) });
+        let err = col.run(&LeveledScheduler).unwrap_err();
+        assert_eq!(err, RecordError { partition: 5, index: 3, error: "bad value 53".into() });
+        assert_eq!(err.to_string(), "record 3 of partition 5 failed: bad value 53");
+
+        // Each run starts afresh, so a second run fails in the same way
+        assert_eq!(col.run(&GreedyScheduler::new()).unwrap_err(), err);
+    }
+
+    #[test]
+    fn test_try_map_all_stops_early() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use tange::scheduler::GreedyScheduler;
+
+        fn check<S: Scheduler>(s: &S) {
+            // Enough partitions to keep every thread busy long after the failure
+            let n = 32 * thread::available_parallelism().map(|n| n.get()).unwrap_or(1).max(4);
+            // Whichever record is mapped first fails
+            let mapped = Arc::new(AtomicUsize::new(0));
+            let later = Arc::new(AtomicUsize::new(0));
+            let counter = later.clone();
+            let col = MemoryCollection::from_vec_chunked((0..n).collect(), n)
+                .try_map_all(move |x| if mapped.fetch_add(1, Ordering::SeqCst) == 0 { Err("first record") } else { Ok(*x) })
+                .then(move |col| col.map(move |x| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    *x
+                }));
+
+            let err = col.run(s).unwrap_err();
+            assert!(err.partition < n);
+            assert_eq!((err.index, err.error), (0, "first record"));
+            let ran = later.load(Ordering::SeqCst);
+            assert!(ran < n / 2, "{} of {} records mapped after the failure", ran, n);
+        }
+
+        let mut greedy = GreedyScheduler::new();
+        greedy.set_threads(2);
+        check(&greedy);
+        check(&LeveledScheduler);
+    }
+
+    #[test]
+    fn test_try_run() {
+        use tange::scheduler::GreedyScheduler;
+
+        check_try_run(&LeveledScheduler);
+        check_try_run(&GreedyScheduler::new());
+    }
+
+    #[test]
+    fn test_metrics() {
+        use std::thread::sleep;
+        use std::time::Duration;
+        use tange::scheduler::GreedyScheduler;
+
+        let mut scheduler = GreedyScheduler::new();
+        scheduler.record_metrics(true);
+        let col = MemoryCollection::from_vec_chunked((0..40usize).collect(), 4)
+            .map(|x| x + 1).named("increment")
+            .map(|x| { sleep(Duration::from_millis(10)); x * 2 }).named("slow")
+            .filter(|x| x % 4 == 0).named("multiples");
+        assert_eq!(col.count().run(&scheduler), Some(vec![20]));
+
+        let metrics = scheduler.last_run_metrics().unwrap();
+        let (name, slow) = metrics.stages()[0];
+        assert_eq!(name, "slow");
+        assert_eq!((slow.tasks, slow.elements), (4, Some(40)));
+        assert!(slow.elapsed >= Duration::from_millis(400), "{}", metrics);
+        assert_eq!(metrics.stage("multiples").unwrap().elements, Some(20));
+    }
+
+    #[test]
+    fn test_explain() {
+        let left = MemoryCollection::from_vec_chunked((0..20usize).collect(), 4)
+            .map(|x| (x % 5, *x)).named("pairs");
+        let right = MemoryCollection::from_vec(vec![(1usize, "one"), (2, "two")])
+            .filter(|x| x.0 > 1);
+        let joined = left.join_on(&right, |l| l.0, |r| r.0, |l, r| (l.1, r.1), 2);
+        let totals = joined.concat(&joined)
+            .fold_by(|x| x.0, || 0, |acc, x| *acc += (x.1).0, |x, y| *x += y, 1);
+        assert_eq!(totals.explain(), "\
+            stage  operation         kind          partitions  inputs\n\
+            0      from_vec_chunked  source        4\n\
+            1      map (pairs)       element-wise  4           0\n\
+            2      map               element-wise  4           1\n\
+            3      partition_by_key  shuffle       2           2\n\
+            4      from_vec          source        1\n\
+            5      filter            element-wise  1           4\n\
+            6      map               element-wise  1           5\n\
+            7      partition_by_key  shuffle       2           6\n\
+            8      join_on           shuffle       2           3,7\n\
+            9      concat            union         4           8,8\n\
+            10     fold_by           shuffle       1           9       <- single partition\n");
+        // Stages that already ran in a single partition aren't bottlenecks
+        let counted = totals.count().explain();
+        assert!(counted.ends_with("\n11     count             reduce        1           10\n"), "{}", counted);
+    }
+
+    #[test]
+    fn test_map_with() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let col = MemoryCollection::from_vec_chunked((1..=10usize).collect(), 3);
+        let totals = col.map(move |x| { c.fetch_add(1, Ordering::SeqCst); *x })
+            .fold_by(|_| (), || 0usize, |acc, x| *acc += x, |x, y| *x += y, 1);
+        let normalized = col.map_with(&totals, |x, t| *x as f64 / t[0].1 as f64);
+        let out = normalized.run(&LeveledScheduler).unwrap();
+        // Every partition reads the same total, computed once
+        assert_eq!(calls.load(Ordering::SeqCst), 10);
+
+        let sum = totals.run(&LeveledScheduler).unwrap()[0].1;
+        let expected = col.map(move |x| *x as f64 / sum as f64).run(&LeveledScheduler).unwrap();
+        assert_eq!(out, expected);
+        assert_eq!(normalized.n_partitions(), 3);
+        let stages = normalized.dry_run().stages;
+        assert_eq!(stages.last().map(|s| (s.op.as_str(), s.inputs.len())), Some(("map_with", 2)));
+
+        let empty = col.map_with(&MemoryCollection::<usize>::empty(), |x, vs| x + vs.len());
+        assert_eq!(empty.run(&LeveledScheduler), Some((1..=10).collect()));
+    }
+
+    #[test]
+    fn test_map_broadcast() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use tange::scheduler::GreedyScheduler;
+
+        // Counts its clones
+        struct Table(Vec<usize>, Arc<AtomicUsize>);
+
+        impl Clone for Table {
+            fn clone(&self) -> Self {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                Table(self.0.clone(), self.1.clone())
+            }
+        }
+
+        let clones = Arc::new(AtomicUsize::new(0));
+        let table = Broadcast::new(Table((0..100).map(|x| x * 2).collect(), clones.clone()));
+        let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 16);
+        let looked_up = col.map_broadcast(&table, |x, t| t.0[*x]).map(|x| x + 1);
+        assert_eq!(looked_up.run(&GreedyScheduler::new()), Some((0..100).map(|x| x * 2 + 1).collect()));
+        assert_eq!(looked_up.run(&LeveledScheduler), Some((0..100).map(|x| x * 2 + 1).collect()));
+        assert_eq!(clones.load(Ordering::SeqCst), 0);
+        assert_eq!(looked_up.n_partitions(), 16);
+        assert_eq!(table.value().0.len(), 100);
+    }
+
+    #[test]
+    fn test_dry_run() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let col = MemoryCollection::from_vec_chunked((0..20usize).collect(), 4)
+            .map(move |x| { c.fetch_add(1, Ordering::SeqCst); x % 5 })
+            .named("mod");
+        let stats = col.dry_run();
+        assert_eq!(stats.tasks, 8);
+        assert_eq!(stats.depth, 2);
+        assert_eq!(stats.bottlenecks, 0);
+        assert_eq!(stats.stages, vec![
+            StageStats { op: "from_vec_chunked".into(), name: None, kind: "source", tasks: 4, inputs: vec![], bottleneck: false },
+            StageStats { op: "map".into(), name: Some("mod".into()), kind: "element-wise", tasks: 4, inputs: vec![0], bottleneck: false }
+        ]);
+
+        let totals = col.concat(&col).fold_by(|x| *x, || 0, |acc, _| *acc += 1, |x, y| *x += y, 1).dry_run();
+        let kinds: Vec<_> = totals.stages.iter().map(|s| (s.op.as_str(), s.kind, s.tasks, s.inputs.clone())).collect();
+        assert_eq!(kinds[2..], [("concat", "union", 8, vec![1, 1]), ("fold_by", "shuffle", 1, vec![2])]);
+        assert_eq!(totals.bottlenecks, 1);
+        assert!(totals.stages[3].bottleneck);
+        assert!(totals.tasks > stats.tasks && totals.depth > stats.depth);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn test_stage_names() {
+        let col = MemoryCollection::from_vec(vec![1, 2usize]).map(|x| x + 1);
+        let (mapped, filtered) = (format!("{:?}", col), format!("{:?}", col.filter(|x| *x > 2)));
+        let name = mapped.lines().nth(1).unwrap().trim();
+        assert!(name.starts_with("map#"), "{}", mapped);
+        assert!(name[4..].parse::<usize>().is_ok(), "{}", mapped);
+        let other = filtered.lines().nth(1).unwrap().trim();
+        assert!(other.starts_with("filter#") && other != name, "{}", filtered);
+        assert_eq!(filtered.lines().nth(2).unwrap().trim(), name);
+    }
+
+    #[test]
+    fn test_stage_panic_message() {
+        use std::panic::{catch_unwind,AssertUnwindSafe};
+
+        let col = MemoryCollection::from_vec_chunked(vec!["1", "2", "x", "4"], 4)
+            .map(|s| s.parse::<u32>().expect("not a number"))
+            .named("parse-record")
+            .map(|x| x * 2);
+        let err = catch_unwind(AssertUnwindSafe(|| col.run(&LeveledScheduler))).unwrap_err();
+        let msg = err.downcast_ref::<String>().unwrap();
+        assert!(msg.starts_with("task 'parse-record' partition 2 panicked: not a number"), "{}", msg);
+    }
+
+    #[test]
+    fn test_from_fn() {
+        use std::sync::Mutex;
+
+        let called = Arc::new(Mutex::new(Vec::new()));
+        let c = called.clone();
+        let col = MemoryCollection::from_fn(5, move |idx| {
+            c.lock().unwrap().push(idx);
+            (0..idx).map(|x| (idx, x)).collect()
+        });
+        assert_eq!(col.n_partitions(), 5);
+        assert!(called.lock().unwrap().is_empty());
+
+        let results = col.run(&LeveledScheduler).unwrap();
+        let expected: Vec<_> = (0..5).flat_map(|idx| (0..idx).map(move |x| (idx, x))).collect();
+        assert_eq!(results, expected);
+
+        let mut seen = called.lock().unwrap().clone();
+        seen.sort();
+        assert_eq!(seen, vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_from_range() {
+        for &(start, end, n) in &[(0u64, 10u64, 3usize), (5, 6, 4), (7, 1_007, 7), (0, 4, 4)] {
+            let col = MemoryCollection::from_range(start..end, n);
+            assert_eq!(col.n_partitions(), n.min((end - start) as usize));
+            let parts: Vec<_> = col.to_defs().iter()
+                .map(|d| d.run(&LeveledScheduler).unwrap())
+                .collect();
+            let sizes: Vec<_> = parts.iter().map(|p| p.len()).collect();
+            let (lo, hi) = (sizes.iter().min().unwrap(), sizes.iter().max().unwrap());
+            assert!(hi - lo <= 1, "unbalanced partitions: {:?}", sizes);
+            let all: Vec<u64> = parts.into_iter().flatten().collect();
+            assert_eq!(all, (start..end).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_from_range_empty() {
+        for &(start, end) in &[(0u64, 0u64), (10, 10), (10, 3)] {
+            let col = MemoryCollection::from_range(start..end, 4);
+            assert_eq!(col.n_partitions(), 1);
+            assert_eq!(col.run(&LeveledScheduler).unwrap(), Vec::<u64>::new());
+        }
+    }
+
+    #[test]
+    fn test_collect_and_extend() {
+        let mut col: MemoryCollection<u64> = (0..1000).map(|x| x + 1).collect();
+        assert_eq!(col.n_partitions(), 1);
+
+        col.extend(vec![2000, 3000]);
+        col.extend(Vec::new());
+        col.extend((0..3).map(|x| x * 10_000));
+        assert_eq!(col.n_partitions(), 3);
+
+        let doubled = col.map(|x| x * 2).run(&LeveledScheduler).unwrap();
+        let mut expected: Vec<u64> = (1..1001).chain(vec![2000, 3000]).chain(vec![0, 10_000, 20_000]).collect();
+        for x in expected.iter_mut() {
+            *x *= 2;
+        }
+        assert_eq!(doubled, expected);
+
+        let from: MemoryCollection<_> = vec!["a", "b"].into();
+        assert_eq!(from.n_partitions(), 1);
+        assert_eq!(from.run(&LeveledScheduler), Some(vec!["a", "b"]));
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        use std::sync::Arc;
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let c = calls.clone();
+        let upstream = MemoryCollection::from_vec((0..30usize).collect()).split(3)
+            .map(move |x| { c.fetch_add(1, Ordering::SeqCst); x * 2 });
+        let downstream = |col: &MemoryCollection<usize>| col.filter(|x| x % 3 == 0).map(|x| x + 1);
+        let expected = downstream(&upstream).run(&LeveledScheduler).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 30);
+
+        let dir = "/tmp/tange-test-checkpoint";
+        let _ = fs::remove_dir_all(dir);
+        let saved = upstream.checkpoint(dir, &LeveledScheduler).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 60);
+        assert_eq!(saved.n_partitions(), upstream.n_partitions());
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 3);
+
+        // Downstream variants read from disk without rerunning the upstream map
+        assert_eq!(downstream(&saved).run(&LeveledScheduler).unwrap(), expected);
+        assert_eq!(downstream(&saved).run(&LeveledScheduler).unwrap(), expected);
+        assert_eq!(saved.count().run(&LeveledScheduler), Some(vec![30]));
+        assert_eq!(calls.load(Ordering::SeqCst), 60);
+
+        drop(saved);
+        assert_eq!(fs::read_dir(dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_fold_by() {
+        let col = MemoryCollection::from_vec(vec![1,2,3,1,2usize]);
+        let out = col.fold_by(|x| *x, || 0, |x, _y| *x += 1, |x, y| *x += y, 1);
+        let mut results = out.run(&mut LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, vec![(1, 2), (2, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_fold_by_parts() {
+        let col = MemoryCollection::from_vec(vec![1,2,3,1,2usize]);
+        let out = col.fold_by(|x| *x, || 0, |x, _y| *x += 1, |x, y| *x += y, 2);
+        assert_eq!(out.partitions.len(), 2);
+        let mut results = out.run(&mut LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results, vec![(1, 2), (2, 2), (3, 1)]);
+    }
+
+    #[test]
+    fn test_combine_by_key() {
+        let values: Vec<(usize, u64)> = (0..200u64).map(|x| ((x * 7 % 13) as usize, x * x % 101)).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 5);
+
+        let mut expected: HashMap<usize, Vec<u64>> = HashMap::new();
+        for &(k, v) in values.iter() {
+            expected.entry(k).or_default().push(v);
+        }
+
+        // Average by key, through (sum, count)
+        let averages = col.combine_by_key(|x| x.0, |x| (x.1, 1u64),
+                                          |c, x| { c.0 += x.1; c.1 += 1; },
+                                          |c, o| { c.0 += o.0; c.1 += o.1; }, 3)
+            .map(|x| (x.0, (x.1).0 as f64 / (x.1).1 as f64));
+        let mut results = averages.run(&LeveledScheduler).unwrap();
+        results.sort_by_key(|x| x.0);
+        let mut brute: Vec<_> = expected.iter()
+            .map(|(k, vs)| (*k, vs.iter().sum::<u64>() as f64 / vs.len() as f64))
+            .collect();
+        brute.sort_by_key(|x| x.0);
+        assert_eq!(results, brute);
+
+        // Vec of each key's values, seeded with the first
+        for partitions in &[Partitioning::Single, Partitioning::HashInto(4)] {
+            let grouped = col.combine_by_key(|x| x.0, |x| vec![x.1],
+                                             |c, x| c.push(x.1),
+                                             |c, o| c.extend_from_slice(o), *partitions);
+            let mut results = grouped.run(&LeveledScheduler).unwrap();
+            for r in results.iter_mut() {
+                r.1.sort();
+            }
+            results.sort();
+            let mut brute: Vec<_> = expected.clone().into_iter()
+                .map(|(k, mut vs)| { vs.sort(); (k, vs) })
+                .collect();
+            brute.sort();
+            assert_eq!(results, brute);
+        }
+
+        // Skips the shuffle on values partitioned by the key
+        let key = |x: &(usize, u64)| x.0;
+        let hashed = col.partition_by_key(5, key);
+        let mins = hashed.combine_by_key(key, |x| x.1, |c, x| *c = (*c).min(x.1),
+                                         |c, o| *c = (*c).min(*o), Partitioning::Preserve);
+        assert!(mins.explain().ends_with("combine_by_key    element-wise  5           1\n"), "{}", mins.explain());
+        let mut results = mins.run(&LeveledScheduler).unwrap();
+        results.sort();
+        let mut brute: Vec<_> = expected.iter().map(|(k, vs)| (*k, *vs.iter().min().unwrap())).collect();
+        brute.sort();
+        assert_eq!(results, brute);
+    }
+
+    #[test]
+    fn test_group_by() {
+        let values: Vec<(usize, usize)> = (0..500).map(|x| (x * 7 % 23, x)).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 4);
+        let mut expected: HashMap<usize, Vec<usize>> = HashMap::new();
+        for &(k, v) in values.iter() {
+            expected.entry(k).or_default().push(v);
+        }
+        let mut expected: Vec<_> = expected.into_iter().collect();
+        expected.sort();
+
+        for partitions in &[Partitioning::Single, Partitioning::HashInto(3), Partitioning::Preserve] {
+            let by_key = col.group_by_key(*partitions);
+            let by = col.group_by(|x| x.0, *partitions).map(|g| (g.0, g.1.iter().map(|x| x.1).collect::<Vec<_>>()));
+            assert_eq!(by_key.n_partitions(), partitions.count(4));
+            for grouped in &[by_key, by] {
+                let mut results = grouped.run(&LeveledScheduler).unwrap();
+                for r in results.iter_mut() {
+                    r.1.sort();
+                }
+                results.sort();
+                assert_eq!(results, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_collect_groups() {
+        // Key 0 appears in every partition
+        let values: Vec<(usize, usize)> = (0..300).map(|x| (if x % 10 == 0 { 0 } else { x % 17 + 1 }, x)).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 6);
+        let mut expected: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (k, v) in values.into_iter() {
+            expected.entry(k).or_default().push(v);
+        }
+        // Values were chunked in order, so each group keeps it
+        assert_eq!(col.collect_groups(&LeveledScheduler).unwrap(), expected);
+        assert_eq!(expected[&0].len(), 30);
+
+        let empty = MemoryCollection::<(usize, usize)>::empty();
+        assert_eq!(empty.collect_groups(&LeveledScheduler), Some(HashMap::new()));
+    }
+
+    #[test]
+    fn test_rolling_by_key() {
+        // Keys interleave across partitions, and timestamps repeat within a key
+        let events: Vec<(usize, (u32, usize))> = (0..120).map(|i| (i % 7, ((i / 3 % 5) as u32, i))).collect();
+        let col = MemoryCollection::from_vec_chunked(events.clone(), 4);
+        let seen = col.rolling_by_key(|e| e.0, Vec::new(), |ids: &Vec<usize>, e| {
+            let mut ids = ids.clone();
+            ids.push(e.1);
+            ids
+        }, 3);
+        assert_eq!(seen.n_partitions(), 3);
+        let results = seen.run(&LeveledScheduler).unwrap();
+        assert_eq!(results.len(), events.len());
+
+        // Brute force: each key's events in timestamp order, ties in input order
+        let mut expected: HashMap<usize, Vec<(u32, usize)>> = HashMap::new();
+        for (k, e) in events.into_iter() {
+            expected.entry(k).or_default().push(e);
+        }
+        let mut rolled: HashMap<usize, Vec<Vec<usize>>> = HashMap::new();
+        for (k, ids) in results.into_iter() {
+            rolled.entry(k).or_default().push(ids);
+        }
+        for (k, mut es) in expected.into_iter() {
+            es.sort_by_key(|e| e.0);
+            let ordered: Vec<_> = es.iter().map(|e| e.1).collect();
+            let states: Vec<_> = (1..=ordered.len()).map(|n| ordered[..n].to_vec()).collect();
+            assert_eq!(rolled[&k], states, "key {}", k);
+        }
+    }
+
+    #[test]
+    fn test_cumsum_by_key() {
+        let values: Vec<(usize, f64)> = (0..400).map(|i| (i * 31 % 9, (i * 17 % 23) as f64 / 7.0)).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 5);
+        let totals = col.cumsum_by_key(4);
+        assert_eq!(totals.n_partitions(), 4);
+
+        // Each key's totals come out in order, so compare them key by key
+        let mut results: HashMap<usize, Vec<f64>> = HashMap::new();
+        for (k, total) in totals.run(&LeveledScheduler).unwrap() {
+            results.entry(k).or_default().push(total);
+        }
+        let mut expected: HashMap<usize, Vec<f64>> = HashMap::new();
+        let mut sums: HashMap<usize, f64> = HashMap::new();
+        for (k, v) in values.into_iter() {
+            let sum = sums.entry(k).or_insert(0.0);
+            *sum += v;
+            expected.entry(k).or_default().push(*sum);
+        }
+        assert_eq!(results, expected);
+    }
 
-    /// Writes each record in a collection to disk, newline delimited.
-    /// MemoryCollection will create a new file within the path for each partition.
-    pub fn sink(&self, path: &'static str) -> MemoryCollection<usize> {
-        let pats = batch_apply(&self.partitions, move |idx, vs| {
-            fs::create_dir_all(path)
-                .expect("Welp, something went terribly wrong when creating directory");
+    #[test]
+    fn test_count_distinct_by_key() {
+        // Customer k buys from a range of products growing with k, with repeats
+        let orders: Vec<(usize, usize)> = (0..60_000).map(|i| (i % 6, (i * 7919) % (1000 * (i % 6 + 1)))).collect();
+        let col = MemoryCollection::from_vec_chunked(orders.clone(), 6);
 
-            let file = fs::File::create(&format!("{}/{}", path, idx))
-                .expect("Issues opening file!");
-            let mut bw = BufWriter::new(file);
+        let mut expected: HashMap<usize, HashSet<usize>> = HashMap::new();
+        for (k, v) in orders.into_iter() {
+            expected.entry(k).or_default().insert(v);
+        }
+        let mut expected: Vec<_> = expected.into_iter().map(|(k, vs)| (k, vs.len())).collect();
+        expected.sort();
 
-            let size = vs.len();
-            for line in vs {
-                bw.write(line.as_bytes()).expect("Error writing out line");
-                bw.write(b"\n").expect("Error writing out line");
-            }
+        let mut exact = col.count_distinct_by_key(3).run(&LeveledScheduler).unwrap();
+        exact.sort();
+        assert_eq!(exact, expected);
 
-            vec![size]
-        });
-        
-        MemoryCollection { partitions: pats }
+        let mut approx = col.count_distinct_by_key_approx(12, 3).run(&LeveledScheduler).unwrap();
+        approx.sort();
+        assert_eq!(approx.len(), exact.len());
+        for (a, e) in approx.iter().zip(exact.iter()) {
+            assert_eq!(a.0, e.0);
+            let error = (a.1 as f64 - e.1 as f64).abs() / e.1 as f64;
+            assert!(error < 0.05, "{:?} vs {:?}", a, e);
+        }
     }
-}
 
-impl <A: Any + Send + Sync + Clone + Serialize + for<'de>Deserialize<'de>> MemoryCollection<A> {
+    #[test]
+    fn test_top_k_by_key() {
+        // Key 9 has a single value, fewer than k
+        let values: Vec<(usize, (usize, u64))> = (0..2000usize)
+            .map(|i| if i == 0 { (9, (i, 42)) } else { (i % 7, (i, (i as u64 * 2654435761) % 1000)) })
+            .collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 5);
 
-    /// Copies the MemoryCollection to disk, returning a DiskCollection
-    pub fn to_disk(&self, path: String) -> DiskCollection<A> {
-        DiskCollection::from_memory(path, &self.partitions)
-    }
-}
+        for &k in &[1usize, 3, 10] {
+            let mut top = col.top_k_by_key(k, |v| v.1, 3).run(&LeveledScheduler).unwrap();
+            top.sort();
 
-#[cfg(test)]
-mod test_lib {
-    use super::*;
-    use tange::scheduler::LeveledScheduler;
+            let mut groups: HashMap<usize, Vec<(usize, u64)>> = HashMap::new();
+            for &(key, v) in values.iter() {
+                groups.entry(key).or_default().push(v);
+            }
+            let mut expected: Vec<_> = groups.into_iter().map(|(key, mut vs)| {
+                // Stable, so ties keep their input order
+                vs.sort_by_key(|v| Reverse(v.1));
+                vs.truncate(k);
+                (key, vs)
+            }).collect();
+            expected.sort();
+            assert_eq!(top, expected);
+            assert_eq!(top.iter().find(|t| t.0 == 9).unwrap().1, vec![(0, 42)]);
+        }
+    }
 
     #[test]
-    fn test_fold_by() {
-        let col = MemoryCollection::from_vec(vec![1,2,3,1,2usize]);
-        let out = col.fold_by(|x| *x, || 0, |x, _y| *x += 1, |x, y| *x += y, 1);
-        let mut results = out.run(&mut LeveledScheduler).unwrap();
-        results.sort();
-        assert_eq!(results, vec![(1, 2), (2, 2), (3, 1)]);
+    fn test_sample_per_key() {
+        // Key k has 10 * k values
+        let values: Vec<(usize, usize)> = (1..8).flat_map(|k| (0..10 * k).map(move |i| (k, k * 1000 + i))).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 4).split(5);
+        let all: HashSet<_> = values.iter().cloned().collect();
+
+        let sampled = col.sample_per_key(25, RandomOptions::seeded(11), 3);
+        let results = sampled.run(&LeveledScheduler).unwrap();
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for x in results.iter() {
+            assert!(all.contains(x), "{:?}", x);
+            *counts.entry(x.0).or_default() += 1;
+        }
+        for k in 1..8 {
+            assert_eq!(counts[&k], (10 * k).min(25));
+        }
+        assert_eq!(results.iter().collect::<HashSet<_>>().len(), results.len());
+
+        // Reproducible with a seed, and different with another
+        let sorted = |mut xs: Vec<(usize, usize)>| { xs.sort(); xs };
+        let results = sorted(results);
+        assert_eq!(sorted(sampled.run(&LeveledScheduler).unwrap()), results);
+        let other = col.sample_per_key(25, RandomOptions::seeded(12), 3).run(&LeveledScheduler).unwrap();
+        assert!(sorted(other) != results);
     }
 
     #[test]
-    fn test_fold_by_parts() {
-        let col = MemoryCollection::from_vec(vec![1,2,3,1,2usize]);
-        let out = col.fold_by(|x| *x, || 0, |x, _y| *x += 1, |x, y| *x += y, 2);
-        assert_eq!(out.partitions.len(), 2);
-        let mut results = out.run(&mut LeveledScheduler).unwrap();
-        results.sort();
-        assert_eq!(results, vec![(1, 2), (2, 2), (3, 1)]);
+    fn test_group_by_bounded() {
+        // Counts the values grouped into each partition
+        fn grouped_parts(col: &MemoryCollection<(u32, Vec<u32>)>) -> Vec<usize> {
+            let sizes = col.to_defs().iter()
+                .map(|d| d.apply(|gs| vec![gs.iter().map(|g| g.1.len()).sum::<usize>()]))
+                .collect();
+            MemoryCollection::from_defs(sizes).run(&LeveledScheduler).unwrap()
+        }
+
+        let rows = 2_000_000u32;
+        let col = MemoryCollection::from_iter_chunked((0..rows).map(|x| (x % 10_000, x)), 250_000);
+        for &n in &[4usize, 16] {
+            let sizes = grouped_parts(&col.group_by_key(n));
+            assert_eq!(sizes.len(), n);
+            assert_eq!(sizes.iter().sum::<usize>(), rows as usize);
+            // No partition holds much more than its share
+            let share = rows as usize / n;
+            assert!(sizes.iter().all(|s| *s < share * 3 / 2), "{:?}", sizes);
+        }
     }
 
     #[test]
@@ -545,6 +5322,136 @@ mod test_lib {
         assert_eq!(results, expected);
     }
 
+    #[test]
+    fn test_partitioning() {
+        let col1 = MemoryCollection::from_vec_chunked((0..20usize).collect(), 4);
+        let col2 = MemoryCollection::from_vec((0..10usize).map(|x| (x, x * 2)).collect());
+        let expected: Vec<_> = (0..10).map(|x| (x, (x, x * 2))).collect();
+        for &(p, n) in &[(Partitioning::HashInto(3), 3), (Partitioning::Preserve, 4),
+                         (Partitioning::Single, 1), (0.into(), 1)] {
+            let joined = col1.join_on(&col2, |x| *x, |y| y.0, |x, y| (*x, y.1), p);
+            assert_eq!(joined.n_partitions(), n);
+            let mut results = joined.run(&LeveledScheduler).unwrap();
+            results.sort();
+            assert_eq!(results, expected);
+
+            let folded = col1.fold_by(|x| x % 5, || 0, |x, _y| *x += 1, |x, y| *x += y, p);
+            assert_eq!(folded.n_partitions(), n);
+            let mut results = folded.run(&LeveledScheduler).unwrap();
+            results.sort();
+            assert_eq!(results, (0..5).map(|k| (k, 4)).collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn test_join_with_bloom() {
+        // Sums the elements the shuffles of a run produced
+        fn shuffled<A: Any + Send + Sync + Clone>(col: &MemoryCollection<A>) -> (usize, Vec<A>) {
+            let mut scheduler = GreedyScheduler::new();
+            scheduler.record_metrics(true);
+            let results = col.run(&scheduler).unwrap();
+            let metrics = scheduler.last_run_metrics().unwrap();
+            let elements = metrics.stages().into_iter()
+                .filter(|s| s.0.starts_with("partition_by_key#"))
+                .map(|s| s.1.elements.unwrap())
+                .sum();
+            (elements, results)
+        }
+
+        // One in every hundred visits has a flagged user
+        let visits = MemoryCollection::from_vec_chunked((0..10000usize).collect(), 4);
+        let flagged = MemoryCollection::from_vec_chunked((0..100usize).map(|x| x * 100).collect(), 2);
+        let plain = visits.join_on(&flagged, |x| *x, |y| *y, |x, y| x + y, 4);
+        let bloom = visits.join_with_bloom(&flagged, |x| *x, |y| *y, |x, y| x + y, 4, 10);
+
+        let (plain_elements, mut plain_results) = shuffled(&plain);
+        let (bloom_elements, mut bloom_results) = shuffled(&bloom);
+        plain_results.sort();
+        bloom_results.sort();
+        assert_eq!(plain_results, bloom_results);
+        assert_eq!(bloom_results.len(), 100);
+        assert_eq!(plain_elements, 10100);
+        assert!((200..500).contains(&bloom_elements), "{}", bloom_elements);
+
+        let filters = flagged.to_bloom(|x| *x, 10).run(&LeveledScheduler).unwrap();
+        assert_eq!(filters.len(), 1);
+        assert!((0..100usize).all(|x| filters[0].contains(&(x * 100))));
+        assert_eq!(filters[0].n_bits(), 1024);
+        assert_eq!(MemoryCollection::<usize>::empty().to_bloom(|x| *x, 10).run(&LeveledScheduler).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_co_partitioned() {
+        fn tasks<A: Any + Send + Sync + Clone>(col: &MemoryCollection<A>) -> (usize, Vec<A>) {
+            let started = Arc::new(AtomicUsize::new(0));
+            let counter = started.clone();
+            let scheduler = GreedyScheduler::builder()
+                .on_task_start(move |_, _| { counter.fetch_add(1, Ordering::SeqCst); })
+                .build();
+            let results = col.run(&scheduler).unwrap();
+            (started.load(Ordering::SeqCst), results)
+        }
+
+        let key = |x: &(usize, usize)| x.0;
+        let col1 = MemoryCollection::from_vec_chunked((0..20).map(|x| (x % 5, x)).collect(), 4);
+        let col2 = MemoryCollection::from_vec_chunked((0..5).map(|x| (x, x * 10)).collect(), 2);
+        let hashed1 = col1.partition_by_key(4, key);
+        let hashed2 = col2.partition_by_key(4, key);
+
+        // Preserve keeps both sides where they are, while a key capturing state, even
+        // if it computes the same key, shuffles them again
+        let offset = 0;
+        let captured = move |x: &(usize, usize)| x.0 + offset;
+        let joined = hashed1.join_on(&hashed2, key, key, |x, y| x.1 + y.1, Partitioning::Preserve);
+        let shuffled = hashed1.join_on(&hashed2, captured, captured, |x, y| x.1 + y.1, Partitioning::Preserve);
+        let (co_tasks, mut co_results) = tasks(&joined);
+        let (base_tasks, mut results) = tasks(&shuffled);
+        co_results.sort();
+        results.sort();
+        assert_eq!(co_results, results);
+        assert!(co_tasks < base_tasks, "{} >= {}", co_tasks, base_tasks);
+
+        let (co_tasks, mut co_results) = tasks(&hashed1.fold_by(key, || 0, |x, _y| *x += 1, |x, y| *x += y, Partitioning::Preserve));
+        let (base_tasks, mut results) = tasks(&hashed1.fold_by(captured, || 0, |x, _y| *x += 1, |x, y| *x += y, Partitioning::Preserve));
+        co_results.sort();
+        results.sort();
+        assert_eq!(co_results, results);
+        assert!(co_tasks < base_tasks, "{} >= {}", co_tasks, base_tasks);
+        assert!(hashed1.fold_by(key, || 0, |x, _y| *x += 1, |x, y| *x += y, Partitioning::Preserve)
+            .explain().contains("fold_by           element-wise"));
+
+        // As does another count
+        let explain = hashed1.fold_by(key, || 0, |x, _y| *x += 1, |x, y| *x += y, 3).explain();
+        assert!(explain.contains("fold_by           shuffle"));
+    }
+
+    #[test]
+    fn test_join_where() {
+        // Twenty overlapping intervals of 25, starting every 10
+        let events = MemoryCollection::from_vec_chunked((0..1000u32).collect(), 4);
+        let intervals = MemoryCollection::from_vec_chunked((0..20u32).map(|k| (10 * k, 10 * k + 25)).collect(), 3);
+        let matched = events.join_where(&intervals, |t, i| i.0 <= *t && *t < i.1);
+        assert_eq!(matched.n_partitions(), 4);
+
+        let mut results = matched.run(&LeveledScheduler).unwrap();
+        results.sort();
+        assert_eq!(results.len(), 20 * 25);
+        let mut expected = Vec::new();
+        for t in 0..1000u32 {
+            for k in 0..20u32 {
+                if 10 * k <= t && t < 10 * k + 25 {
+                    expected.push((t, (10 * k, 10 * k + 25)));
+                }
+            }
+        }
+        assert_eq!(results, expected);
+        assert_eq!(results.iter().filter(|x| x.0 == 15).count(), 2);
+        assert_eq!(results.iter().filter(|x| x.0 == 20).count(), 3);
+
+        let none = events.join_where(&MemoryCollection::<u32>::empty(), |_, _| true);
+        assert_eq!(none.run(&LeveledScheduler), Some(vec![]));
+    }
+
     #[test]
     fn test_emit() {
         let results = MemoryCollection::from_vec(vec![1,2,3usize])
@@ -569,3 +5476,733 @@ mod test_lib {
     }
 
 }
+
+#[cfg(test)]
+mod test_empty {
+    use super::*;
+    use tange::scheduler::LeveledScheduler;
+
+    fn run<A: Any + Send + Sync + Clone>(col: &MemoryCollection<A>) -> Vec<A> {
+        col.run(&LeveledScheduler).expect("empty collections should still run")
+    }
+
+    #[test]
+    fn test_empty_transforms() {
+        let col = MemoryCollection::<usize>::empty();
+        assert_eq!(col.n_partitions(), 0);
+        assert_eq!(run(&col), Vec::<usize>::new());
+        assert_eq!(run(&col.map(|x| x + 1)), Vec::<usize>::new());
+        assert_eq!(run(&col.filter(|x| *x > 1)), Vec::<usize>::new());
+        assert_eq!(run(&col.emit(|x, emitter| emitter(*x))), Vec::<usize>::new());
+        assert_eq!(run(&col.sort_by(|x| *x)), Vec::<usize>::new());
+        assert_eq!(run(&col.split(4)), Vec::<usize>::new());
+        assert_eq!(run(&col.partition(3, |idx, _x| idx)), Vec::<usize>::new());
+        assert_eq!(run(&col.partition_by_key(3, |x| *x)), Vec::<usize>::new());
+        assert_eq!(run(&col.map(|x| vec![*x]).flatten()), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_empty_aggregates() {
+        let col = MemoryCollection::<usize>::empty();
+        assert_eq!(run(&col.count()), vec![0]);
+        assert_eq!(run(&col.frequencies(2)), Vec::<(usize, usize)>::new());
+        let folded = col.fold_by(|x| *x, || 0usize, |acc, x| *acc += x, |x, y| *x += y, 2);
+        assert_eq!(run(&folded), Vec::<(usize, usize)>::new());
+        assert_eq!(run(&folded.count()), vec![0]);
+    }
+
+    #[test]
+    fn test_empty_concat_and_join() {
+        let empty = MemoryCollection::<usize>::empty();
+        let full = MemoryCollection::from_vec_chunked(vec![1, 2, 3usize], 2);
+        assert_eq!(empty.concat(&full).n_partitions(), 2);
+        assert_eq!(run(&empty.concat(&full)), vec![1, 2, 3]);
+        assert_eq!(run(&full.concat(&empty)), vec![1, 2, 3]);
+        assert_eq!(run(&empty.concat(&empty)), Vec::<usize>::new());
+
+        let left = empty.join_on(&full, |x| *x, |x| *x, |x, y| x + y, 2);
+        assert_eq!(run(&left), Vec::<(usize, usize)>::new());
+        let right = full.join_on(&empty, |x| *x, |x| *x, |x, y| x + y, 2);
+        assert_eq!(run(&right), Vec::<(usize, usize)>::new());
+    }
+
+    #[test]
+    fn test_concat_all() {
+        assert_eq!(MemoryCollection::<usize>::concat_all(&[]).n_partitions(), 0);
+        assert_eq!(run(&MemoryCollection::<usize>::concat_all(&[])), Vec::<usize>::new());
+
+        let one = MemoryCollection::from_vec_chunked(vec![1, 2, 3usize], 2);
+        let single = MemoryCollection::concat_all(::std::slice::from_ref(&one));
+        assert_eq!(single.n_partitions(), 2);
+        assert_eq!(format!("{:?}", single), format!("{:?}", one));
+        assert_eq!(run(&single), vec![1, 2, 3]);
+
+        let days: Vec<_> = (0..30usize)
+            .map(|d| MemoryCollection::from_vec_chunked((d * 10..d * 10 + 10).collect(), 1 + d % 3))
+            .collect();
+        let all = MemoryCollection::concat_all(&days);
+        assert_eq!(all.n_partitions(), days.iter().map(|d| d.n_partitions()).sum::<usize>());
+        assert_eq!(run(&all), (0..300).collect::<Vec<_>>());
+        assert_eq!(run(&days.iter().fold(MemoryCollection::empty(), |acc, d| acc.concat(d))), run(&all));
+
+        let mixed = MemoryCollection::concat_all(&[MemoryCollection::empty(), one.clone(), MemoryCollection::empty()]);
+        assert_eq!(mixed.n_partitions(), 2);
+        assert_eq!(run(&mixed), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_concat_rebalanced() {
+        let many = MemoryCollection::from_vec_chunked((0..2000usize).collect(), 1000);
+        let one = MemoryCollection::from_vec((2000..5000usize).collect());
+        let cat = many.concat_rebalanced(&one, 7);
+        assert_eq!(cat.n_partitions(), 7);
+        let sizes: Vec<_> = cat.to_defs().iter()
+            .map(|d| d.run(&LeveledScheduler).unwrap().len())
+            .collect();
+        let (lo, hi) = (sizes.iter().min().unwrap(), sizes.iter().max().unwrap());
+        assert!(hi - lo <= 1, "unbalanced partitions: {:?}", sizes);
+
+        let mut plain = run(&many.concat(&one));
+        let results = run(&cat);
+        assert_eq!(results, plain);
+        plain.sort();
+        assert_eq!(plain, (0..5000).collect::<Vec<_>>());
+
+        let tiny = MemoryCollection::from_vec(vec![1, 2usize]).concat_rebalanced(&MemoryCollection::empty(), 4);
+        assert_eq!(tiny.n_partitions(), 4);
+        assert_eq!(run(&tiny), vec![1, 2]);
+        assert_eq!(run(&MemoryCollection::<usize>::empty().concat_rebalanced(&MemoryCollection::empty(), 3)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_sizes() {
+        // Most of the values share a key, so land in one partition
+        let col = MemoryCollection::from_vec_chunked((0..1000usize).map(|i| if i < 600 { 0 } else { i }).collect(), 4);
+        assert_eq!(col.sizes(&LeveledScheduler), Some(vec![250; 4]));
+        let skewed = col.partition_by_key(4, |x| *x);
+        let sizes = skewed.sizes(&LeveledScheduler).unwrap();
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(sizes.iter().sum::<usize>(), 1000);
+        assert!(*sizes.iter().max().unwrap() >= 600, "{:?}", sizes);
+        assert_eq!(MemoryCollection::<usize>::empty().sizes(&LeveledScheduler), Some(vec![]));
+    }
+
+    #[test]
+    fn test_rebalance_by_key_sample() {
+        // Zipf-like counts, with the first key holding close to 40% of the pairs
+        let pairs: Vec<_> = (1..1000usize)
+            .flat_map(|r| (0..(40_000.0 / (r as f64).powf(1.5)) as usize).map(move |i| (r, i)))
+            .collect();
+        let total = pairs.len();
+        let col = MemoryCollection::from_vec_chunked(pairs, 8);
+
+        let report = run(&col.key_skew_report(2000, RandomOptions::seeded(5)));
+        assert_eq!(report[0].0, 1);
+        assert!((report[0].1 - 40_000.0 / total as f64).abs() < 0.05, "{:?}", &report[..3]);
+        assert!(report.windows(2).all(|w| w[0].1 >= w[1].1));
+
+        let plain = col.partition_by_key(16, |x| x.0).sizes(&LeveledScheduler).unwrap();
+        let spread = col.rebalance_by_key_sample(16, 2000, RandomOptions::seeded(5));
+        let sizes = spread.sizes(&LeveledScheduler).unwrap();
+        assert_eq!(sizes.len(), 16);
+        let (plain_max, max) = (*plain.iter().max().unwrap(), *sizes.iter().max().unwrap());
+        assert!(plain_max >= 40_000);
+        assert!(max * 3 < plain_max, "{:?} against {:?}", sizes, plain);
+
+        let mut results = run(&spread);
+        results.sort();
+        let mut expected = run(&col);
+        expected.sort();
+        assert_eq!(results, expected);
+
+        let empty = MemoryCollection::<(usize, usize)>::empty();
+        assert_eq!(run(&empty.key_skew_report(10, RandomOptions::seeded(1))), vec![]);
+        assert_eq!(run(&empty.rebalance_by_key_sample(3, 10, RandomOptions::seeded(1))), vec![]);
+    }
+
+    #[test]
+    fn test_fold_by_salted() {
+        use std::collections::BTreeSet;
+        use std::sync::Mutex;
+
+        // Zipf-like keys, with each row remembering its partition
+        let col = MemoryCollection::from_fn(16, |idx| {
+            (1..200usize).flat_map(|r| (0..(400.0 / (r as f64).powf(1.5)) as usize).map(move |_| (r, idx))).collect()
+        });
+
+        // Records which partitions' folds each merge of the first key joins
+        type Fold = (usize, usize, BTreeSet<usize>);
+        let merges = Arc::new(Mutex::new(Vec::new()));
+        let run_fold = |salts: Option<usize>| {
+            let m = merges.clone();
+            let default = || (0usize, 0usize, BTreeSet::new());
+            let binop = |acc: &mut Fold, x: &(usize, usize)| { acc.0 = x.0; acc.1 += 1; acc.2.insert(x.1); };
+            let reduce = move |acc: &mut Fold, other: &Fold| {
+                if acc.0 == 1 {
+                    m.lock().unwrap().push((acc.2.clone(), other.2.clone()));
+                }
+                acc.1 += other.1;
+                acc.2.extend(other.2.iter().cloned());
+            };
+            let folded = match salts {
+                Some(salts) => col.fold_by_salted(salts, |x| x.0, default, binop, reduce, 8),
+                None        => col.fold_by(|x| x.0, default, binop, reduce, 8)
+            };
+            let mut results: Vec<_> = run(&folded).into_iter().map(|(k, b)| (k, b.1)).collect();
+            results.sort();
+            results
+        };
+
+        let plain = run_fold(None);
+        let plain_merges = merges.lock().unwrap().drain(..).collect::<Vec<_>>();
+        let salted = run_fold(Some(4));
+        let salted_merges = merges.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert_eq!(salted, plain);
+        assert_eq!(plain[0], (1, 16 * 400));
+
+        // Every partition's fold of the first key is merged once either way, but
+        // salted, all but three merges join folds of partitions sharing a salt
+        assert_eq!(plain_merges.len(), 15);
+        assert_eq!(salted_merges.len(), 15);
+        let across = |merges: &[(BTreeSet<usize>, BTreeSet<usize>)]| merges.iter()
+            .filter(|(a, b)| a.iter().chain(b.iter()).any(|p| p % 4 != a.iter().next().unwrap() % 4))
+            .count();
+        assert_eq!(across(&salted_merges), 3);
+        assert!(across(&plain_merges) > 3);
+    }
+
+    #[test]
+    fn test_is_sorted_by() {
+        let sorted = MemoryCollection::from_vec_chunked((0..100usize).collect(), 4);
+        assert_eq!(sorted.is_sorted_by(|x| *x, &LeveledScheduler), Some(true));
+        assert_eq!(sorted.is_sorted_by(|x| Reverse(*x), &LeveledScheduler), Some(false));
+
+        // Sorted within each partition, but the ranges overlap
+        let overlapping = MemoryCollection::from_vec_chunked((0..100usize).collect(), 4).split(3).sort_by(|x| *x);
+        assert_eq!(overlapping.is_sorted_by(|x| *x, &LeveledScheduler), Some(false));
+
+        let unsorted = MemoryCollection::from_vec_chunked(vec![1, 2, 5, 4, 6usize], 1);
+        assert_eq!(unsorted.is_sorted_by(|x| *x, &LeveledScheduler), Some(false));
+
+        // Empty and single-value partitions are sorted, and don't break runs
+        let gaps = MemoryCollection::concat_all(&[
+            MemoryCollection::from_vec(vec![1usize]),
+            MemoryCollection::from_vec(vec![]),
+            MemoryCollection::from_vec(vec![1, 2]),
+            MemoryCollection::from_vec(vec![3])
+        ]);
+        assert_eq!(gaps.is_sorted_by(|x| *x, &LeveledScheduler), Some(true));
+        assert_eq!(gaps.is_sorted_by(|x| 3 - *x, &LeveledScheduler), Some(false));
+        assert_eq!(MemoryCollection::<usize>::empty().is_sorted_by(|x| *x, &LeveledScheduler), Some(true));
+    }
+
+    #[test]
+    fn test_validate() {
+        let check = |x: &usize| if x % 40 != 7 { Ok(()) } else { Err(format!("{} ends in 7", x)) };
+        let clean = MemoryCollection::from_vec_chunked((0..1000usize).map(|x| x * 2).collect(), 4);
+        let validated = clean.validate(check);
+        assert_eq!(validated.n_partitions(), 4);
+        assert_eq!(validated.try_run(&LeveledScheduler), Ok(Some(run(&clean))));
+
+        // Every partition is checked, and the report keeps the first ten rejected
+        let dirty = MemoryCollection::from_vec_chunked((0..1000usize).collect(), 4);
+        let message = match dirty.validate(check).named("checked").map(|x| x + 1).try_run(&LeveledScheduler) {
+            Err(RunError::Failed(e)) => e.message,
+            other                    => panic!("expected a failure, got {:?}", other)
+        };
+        assert!(message.starts_with("25 records failed validation: record 7 of partition 0 failed: 7 ends in 7; "), "{}", message);
+        assert_eq!(message.matches("ends in 7").count(), 10);
+        assert!(message.ends_with("record 117 of partition 1 failed: 367 ends in 7"), "{}", message);
+
+        let warned = dirty.validate_warn(check);
+        assert_eq!(run(&warned), (0..1000).collect::<Vec<_>>());
+        assert_eq!(run(&MemoryCollection::<usize>::empty().validate(check)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_dedup_sorted() {
+        // Runs of a key spread over several partitions, and an empty one between
+        let words: Vec<_> = vec!["a", "a", "a", "b", "b", "c", "c", "c", "c", "d"];
+        let col = MemoryCollection::concat_all(&[
+            MemoryCollection::from_vec_chunked(words[..3].to_vec(), 2),
+            MemoryCollection::from_vec(vec![]),
+            MemoryCollection::from_vec_chunked(words[3..].to_vec(), 3)
+        ]);
+        let deduped = col.dedup_sorted(|x| *x);
+        assert_eq!(deduped.n_partitions(), col.n_partitions());
+        assert_eq!(run(&deduped), vec!["a", "b", "c", "d"]);
+        let mut distinct: Vec<_> = run(&col.frequencies(2)).into_iter().map(|x| x.0).collect();
+        distinct.sort();
+        assert_eq!(run(&deduped), distinct);
+
+        // Only adjacent duplicates are dropped from unsorted values
+        let unsorted = MemoryCollection::from_vec_chunked(vec![3, 3, 1, 1, 3, 2, 2usize], 2);
+        assert_eq!(run(&unsorted.dedup_sorted(|x| *x)), vec![3, 1, 3, 2]);
+        assert_eq!(run(&unsorted.dedup_sorted(|x| *x % 2)), vec![3, 2]);
+        assert_eq!(run(&MemoryCollection::<usize>::empty().dedup_sorted(|x| *x)), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_word_count() {
+        let corpus = vec![
+            "It was the best of times,\nit was the worst of times,".to_string(),
+            "it was the age of wisdom,\n\nit was the age of foolishness".into()
+        ];
+        let counts = MemoryCollection::from_vec_chunked(corpus, 2)
+            .lines()
+            .split_on(',')
+            .split_whitespace()
+            .map(|w| w.to_lowercase())
+            .frequencies(3)
+            .sort_by(|x| (Reverse(x.1), x.0.clone()));
+        let expected: Vec<(String, usize)> = vec![
+            ("it", 4), ("of", 4), ("the", 4), ("was", 4), ("age", 2), ("times", 2),
+            ("best", 1), ("foolishness", 1), ("wisdom", 1), ("worst", 1)
+        ].into_iter().map(|(w, c)| (w.to_string(), c)).collect();
+        let mut results = run(&counts);
+        results.sort_by_key(|x| (Reverse(x.1), x.0.clone()));
+        assert_eq!(results, expected);
+
+        let lines = MemoryCollection::from_vec(vec!["a\n\nb".to_string(), "".into()]);
+        assert_eq!(run(&lines.lines()), vec!["a", "b"]);
+        assert_eq!(run(&lines.lines_with(true)), vec!["a", "", "b"]);
+        let fields = MemoryCollection::from_vec(vec![";x;;y".to_string()]);
+        assert_eq!(run(&fields.split_on(';')), vec!["x", "y"]);
+        assert_eq!(run(&fields.split_on_with(';', true)), vec!["", "x", "", "y"]);
+    }
+
+    #[test]
+    fn test_parse() {
+        let dir = "/tmp/tange-test-parse";
+        let _ = fs::remove_dir_all(dir);
+        fs::create_dir_all(dir).unwrap();
+        let long = "9".repeat(40) + &"x".repeat(40);
+        let rows: Vec<String> = (0..100usize).map(|i| match i {
+            13 => "thirteen".into(),
+            42 => "".into(),
+            77 => long.clone(),
+            _  => format!("{}.5", i)
+        }).collect();
+        fs::write(format!("{}/values.txt", dir), rows.join("\n")).unwrap();
+
+        let lines = MemoryCollection::read_text(&format!("{}/values.txt", dir), 4).unwrap();
+        let (values, errors) = lines.parse_lossy::<f64>();
+        let values = run(&values);
+        assert_eq!(values.len(), 97);
+        assert_eq!(values.iter().sum::<f64>(), (0..100usize).filter(|i| ![13, 42, 77].contains(i))
+            .map(|i| i as f64 + 0.5).sum::<f64>());
+
+        let errors = run(&errors);
+        assert_eq!(errors.iter().map(|e| e.input.as_str()).collect::<Vec<_>>(), 
+                   vec!["thirteen", "", &format!("{}...", &long[..64])]);
+        assert_eq!(errors[0].message, "invalid float literal");
+        let start: usize = lines.sizes(&LeveledScheduler).unwrap()[..errors[2].partition].iter().sum();
+        assert_eq!(start + errors[2].index, 77);
+
+        let parsed = run(&lines.parse::<f64>());
+        assert_eq!(parsed.len(), 100);
+        assert_eq!(parsed.iter().filter(|r| r.is_err()).count(), 3);
+        assert_eq!(parsed[1], Ok(1.5));
+    }
+
+    #[test]
+    fn test_join_strings() {
+        let parts: Vec<Vec<String>> = vec![vec!["a".into(), "b".into()], vec![], vec![], vec!["c".into()], vec!["".into(), "d".into()], vec![]];
+        let col = MemoryCollection::from_defs(parts.iter().map(|p| Deferred::lift(p.clone(), None)).collect());
+        let all: Vec<String> = parts.concat();
+        assert_eq!(col.join_strings(", ", &LeveledScheduler), Some(all.join(", ")));
+        assert_eq!(col.join_strings(", ", &LeveledScheduler).unwrap(), "a, b, c, , d");
+        assert_eq!(col.join_strings("", &LeveledScheduler).unwrap(), "abcd");
+
+        let leading_empty = MemoryCollection::from_defs(vec![Deferred::lift(Vec::new(), None), Deferred::lift(vec!["x".to_string()], None)]);
+        assert_eq!(leading_empty.join_strings("-", &LeveledScheduler).unwrap(), "x");
+        assert_eq!(leading_empty.filter(|_| false).join_strings("-", &LeveledScheduler).unwrap(), "");
+        assert_eq!(MemoryCollection::<String>::empty().join_strings("-", &LeveledScheduler).unwrap(), "");
+    }
+
+    #[test]
+    fn test_unzip() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use collection::run_all;
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let pairs = MemoryCollection::from_vec_chunked((0..10usize).collect(), 3)
+            .map(move |x| { counter.fetch_add(1, Ordering::SeqCst); (*x, x.to_string()) });
+        let (nums, names) = pairs.unzip();
+        assert_eq!((nums.n_partitions(), names.n_partitions()), (3, 3));
+
+        let (ns, ss) = run_all(&LeveledScheduler, (&nums, &names)).unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 10);
+        assert_eq!(ns, (0..10usize).collect::<Vec<_>>());
+        assert_eq!(ss, (0..10usize).map(|x| x.to_string()).collect::<Vec<_>>());
+
+        let zipped = nums.zip(&names);
+        assert_eq!(zipped.sizes(&LeveledScheduler), pairs.sizes(&LeveledScheduler));
+        assert_eq!(run(&zipped), run(&pairs));
+
+        let short = MemoryCollection::from_vec_chunked(vec![1, 2, 3usize], 3);
+        let long = MemoryCollection::from_vec_chunked(vec!['a', 'b', 'c', 'd', 'e'], 3);
+        assert_eq!(run(&short.zip(&long)), vec![(1, 'a'), (2, 'c'), (3, 'e')]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't zip collections with different numbers of partitions")]
+    fn test_zip_partitions() {
+        let one = MemoryCollection::from_vec(vec![1usize]);
+        one.zip(&one.split(2));
+    }
+
+    #[test]
+    fn test_swap() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        // Counts how many times it's cloned
+        struct Counted(usize, Arc<AtomicUsize>);
+
+        impl Clone for Counted {
+            fn clone(&self) -> Counted {
+                self.1.fetch_add(1, Ordering::SeqCst);
+                Counted(self.0, self.1.clone())
+            }
+        }
+
+        let (kc, vc) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let pairs: Vec<(Counted, Counted)> = (0..6usize)
+            .map(|i| (Counted(i, kc.clone()), Counted(i * 10, vc.clone())))
+            .collect();
+        let col = MemoryCollection::from_vec_chunked(pairs, 2);
+
+        // Running the collection clones each half for every stage; swapping should
+        // clone no more than an identity map does, and a projection only the half
+        // it keeps
+        let counts = || (kc.swap(0, Ordering::SeqCst), vc.swap(0, Ordering::SeqCst));
+        run(&col.map(|x| x.clone()));
+        let (k0, v0) = counts();
+        let swapped = run(&col.swap());
+        assert_eq!(counts(), (k0, v0));
+        assert_eq!(swapped.iter().map(|x| (x.0 .0, x.1 .0)).collect::<Vec<_>>(),
+                   (0..6usize).map(|i| (i * 10, i)).collect::<Vec<_>>());
+
+        let keys = run(&col.map_keys(|k| k.0 + 1));
+        let (k1, v1) = counts();
+        assert!(k1 < k0 && v1 == v0, "{} {}", k1, v1);
+        assert_eq!(keys.iter().map(|x| x.0).collect::<Vec<_>>(), (1..7usize).collect::<Vec<_>>());
+        let values = run(&col.map_values(|v| v.0 + 1));
+        assert_eq!(counts(), (v1, k1));
+        assert_eq!(values.iter().map(|x| x.1).collect::<Vec<_>>(), (0..6usize).map(|i| i * 10 + 1).collect::<Vec<_>>());
+
+        let nums = MemoryCollection::from_vec_chunked((0..5usize).map(|x| (x, x * x)).collect(), 2);
+        assert_eq!(run(&nums.swap().swap()), run(&nums));
+    }
+
+    #[test]
+    fn test_swap_right_join() {
+        // There's no right join; swapping turns the values into keys to join on, and
+        // back again afterwards
+        let orders = MemoryCollection::from_vec_chunked(
+            vec![(1, "tea"), (2, "jam"), (3, "tea"), (4, "oil")], 2);
+        let prices = MemoryCollection::from_vec(vec![("tea", 3.5), ("jam", 2.0), ("salt", 1.0)]);
+        let priced = orders.swap()
+            .join_on(&prices, |o| o.0, |p| p.0, |o, p| (o.1, p.1), 3)
+            .map_values(|x| x.1)
+            .swap()
+            .sort_by(|x| x.1.to_string());
+        assert_eq!(run(&priced), vec![(2.0, "jam"), (3.5, "tea"), (3.5, "tea")]);
+
+        let by_product = orders.swap().group_by_key(2).map_values(|ids| ids.len()).sort_by(|x| x.0);
+        assert_eq!(run(&by_product), vec![("jam", 1), ("oil", 1), ("tea", 2)]);
+    }
+
+    #[test]
+    fn test_all_any() {
+        let s = &LeveledScheduler;
+        let empty = MemoryCollection::<usize>::empty();
+        assert_eq!((empty.all(|_| false, s), empty.any(|_| true, s)), (Some(true), Some(false)));
+        let emptied = MemoryCollection::from_vec_chunked(vec![1, 2, 3usize], 3).filter(|_| false);
+        assert_eq!((emptied.all(|_| false, s), emptied.any(|_| true, s)), (Some(true), Some(false)));
+
+        let evens = MemoryCollection::from_vec_chunked((0..20usize).map(|x| x * 2).collect(), 4);
+        assert_eq!((evens.all(|x| x % 2 == 0, s), evens.any(|x| x % 2 == 0, s)), (Some(true), Some(true)));
+        assert_eq!((evens.all(|x| x % 2 == 1, s), evens.any(|x| x % 2 == 1, s)), (Some(false), Some(false)));
+
+        // A single match in the last partition, with empty partitions around it
+        let mixed = MemoryCollection::from_vec_chunked((0..20usize).collect(), 5).filter(|x| *x < 4 || *x == 19);
+        assert_eq!(mixed.sizes(s), Some(vec![4, 0, 0, 0, 1]));
+        assert_eq!((mixed.all(|x| *x < 4, s), mixed.any(|x| *x == 19, s)), (Some(false), Some(true)));
+        assert_eq!((mixed.all(|x| *x < 20, s), mixed.any(|x| *x > 19, s)), (Some(true), Some(false)));
+    }
+
+    #[test]
+    fn test_products() {
+        let floats = MemoryCollection::from_vec_chunked(vec![0.5, 4.0, 1.25, 2.0, 3.0], 3);
+        assert_eq!(run(&floats.product()), vec![15.0]);
+        assert_eq!(run(&floats.sum()), vec![10.75]);
+        assert_eq!(run(&floats.filter(|x| *x > 2.5).product()), vec![12.0]);
+
+        // Identities come through for empty partitions and empty collections
+        let none = floats.filter(|_| false);
+        assert_eq!((run(&none.product()), run(&none.sum())), (vec![1.0], vec![0.0]));
+        assert_eq!(run(&MemoryCollection::<u64>::empty().checked_product()), vec![Some(1)]);
+
+        let big = MemoryCollection::from_range(1..30, 4);
+        assert_eq!(run(&big.filter(|x| *x <= 20).checked_product()), vec![Some(2432902008176640000)]);
+        assert_eq!(run(&big.checked_product()), vec![None]);
+        assert_eq!(run(&big.wrapping_product()), vec![(1..30u64).fold(1u64, |x, y| x.wrapping_mul(y))]);
+        assert_eq!(run(&big.sum()), vec![435]);
+
+        let joined = MemoryCollection::from_vec_chunked(vec![vec![1], vec![2, 3], vec![], vec![4]], 3)
+            .fold_monoid(Vec::new(), |x, y| x.iter().chain(y.iter()).cloned().collect::<Vec<usize>>());
+        assert_eq!(run(&joined), vec![vec![1, 2, 3, 4]]);
+    }
+
+    #[test]
+    fn test_sample_with_replacement() {
+        // Partitions of 7, 10, 7 and 0 values
+        let col = MemoryCollection::from_vec_chunked((0..30usize).collect(), 3)
+            .filter(|x| *x < 7 || (*x >= 10 && *x < 27));
+        let col = col.concat(&MemoryCollection::from_vec(Vec::new()));
+        let sizes = col.sizes(&LeveledScheduler).unwrap();
+        assert_eq!(sizes, vec![7, 10, 7, 0]);
+
+        let resampled = col.sample_with_replacement(24000, RandomOptions::seeded(5));
+        let drawn = run(&resampled);
+        assert_eq!(drawn.len(), 24000);
+        let mut counts: HashMap<usize, usize> = HashMap::new();
+        for x in drawn.iter() {
+            *counts.entry(*x).or_default() += 1;
+        }
+        // Every one of the 24 values is expected 1000 times, give or take about 31
+        assert_eq!(counts.len(), 24);
+        assert!(counts.values().all(|c| *c > 850 && *c < 1150), "{:?}", counts);
+
+        // Reproducible with a seed, and different with another
+        assert_eq!(run(&resampled), drawn);
+        assert!(run(&col.sample_with_replacement(24000, RandomOptions::seeded(6))) != drawn);
+
+        assert_eq!(run(&col.sample_with_replacement(1, RandomOptions::new())).len(), 1);
+        assert_eq!(run(&col.sample_with_replacement(0, RandomOptions::new())), Vec::<usize>::new());
+        let none = col.filter(|_| false).sample_with_replacement(10, RandomOptions::seeded(5));
+        assert_eq!(run(&none), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn test_kth() {
+        let s = &LeveledScheduler;
+        // Deterministic pseudo-random values with many duplicates, so pivots often
+        // land on runs of equal values
+        let mut state = 54321u64;
+        let values: Vec<u64> = (0..20000).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 33) % 5000
+        }).collect();
+        let col = MemoryCollection::from_vec_chunked(values.clone(), 7);
+        let mut sorted = values.clone();
+        sorted.sort();
+        for k in [0, 1, 777, 9999, 10000, 15000, 19998, 19999].iter() {
+            assert_eq!(col.kth(*k, s), Some(sorted[*k]), "{}", k);
+        }
+        assert_eq!(col.kth(20000, s), None);
+        assert_eq!(col.median(s), Some(sorted[9999]));
+
+        let strings = MemoryCollection::from_vec_chunked(vec!["pear", "fig", "apple", "kiwi"], 3)
+            .map(|x| x.to_string());
+        assert_eq!(strings.kth(1, s), Some("fig".into()));
+        assert_eq!(strings.median(s), Some("fig".into()));
+        let same = MemoryCollection::from_vec_chunked(vec![7usize; 9000], 3);
+        assert_eq!((same.kth(0, s), same.kth(8999, s)), (Some(7), Some(7)));
+
+        assert_eq!(MemoryCollection::<usize>::empty().kth(0, s), None);
+        assert_eq!(MemoryCollection::<usize>::empty().median(s), None);
+        assert_eq!(col.filter(|_| false).median(s), None);
+    }
+
+    #[test]
+    fn test_heavy_hitters() {
+        // Zipf counts over 2000 keys, key r appearing 20000 / r times
+        let mut values: Vec<usize> = (1..2000usize).flat_map(|r| vec![r; 20000 / r]).collect();
+        let total = values.len();
+        let mut exact: HashMap<usize, usize> = HashMap::new();
+        for v in values.iter() {
+            *exact.entry(*v).or_default() += 1;
+        }
+        let heavy: HashSet<usize> = exact.iter().filter(|x| *x.1 as f64 >= 0.01 * total as f64).map(|x| *x.0).collect();
+        assert_eq!(heavy.len(), 12);
+
+        let check = |col: &MemoryCollection<usize>| {
+            let hitters = run(&col.heavy_hitters(0.01, 2000, 5));
+            let found: HashSet<usize> = hitters.iter().map(|x| x.0).collect();
+            assert!(heavy.is_subset(&found), "{:?}", hitters);
+            for (v, est) in hitters.iter() {
+                assert!(*est >= exact[v], "{} estimated {} of {}", v, est, exact[v]);
+                assert!(*est - exact[v] <= 3 * total / 2000, "{} estimated {} of {}", v, est, exact[v]);
+            }
+            assert!(hitters.windows(2).all(|w| w[0].1 >= w[1].1));
+            hitters
+        };
+        // Each key's values all in one partition, and then spread over all of them
+        let grouped = check(&MemoryCollection::from_vec_chunked(values.clone(), 6));
+        let mut state = 777u64;
+        for i in (1..values.len()).rev() {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            values.swap(i, (state >> 33) as usize % (i + 1));
+        }
+        let spread = check(&MemoryCollection::from_vec_chunked(values, 6));
+        assert_eq!(grouped[0], spread[0]);
+
+        assert_eq!(run(&MemoryCollection::<usize>::empty().heavy_hitters(0.01, 100, 3)), vec![]);
+        let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 3);
+        assert_eq!(run(&col.filter(|_| false).heavy_hitters(0.01, 100, 3)), vec![]);
+        assert_eq!(run(&col.heavy_hitters(0.5, 100, 3)), vec![]);
+    }
+
+    #[test]
+    fn test_empty_sinks() {
+        let root = "/tmp/tange-test-empty-sinks";
+        let _ = fs::remove_dir_all(root);
+        let col = MemoryCollection::<String>::empty();
+
+        assert_eq!(run(&col.sink(format!("{}/text", root))), Vec::<usize>::new());
+        assert!(Path::new(&format!("{}/text/_SUCCESS", root)).exists());
+        assert_eq!(run(&col.sink_jsonl(format!("{}/jsonl", root))), Vec::<usize>::new());
+        assert_eq!(run(&col.sink_bincode(format!("{}/bincode", root))), Vec::new());
+        assert_eq!(run(&col.sink_single(format!("{}/single", root))), vec![0]);
+        assert_eq!(fs::read_to_string(format!("{}/single", root)).unwrap(), "");
+
+        let loaded = MemoryCollection::<String>::from_stored(&format!("{}/bincode", root)).unwrap();
+        assert_eq!(run(&loaded), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_empty_disk_round_trip() {
+        let col = MemoryCollection::<usize>::empty();
+        let disk = col.to_disk("/tmp".into());
+        assert_eq!(disk.n_partitions(), 0);
+        assert_eq!(disk.run(&LeveledScheduler), Some(Vec::new()));
+        assert_eq!(disk.count().run(&LeveledScheduler), Some(vec![0]));
+        assert_eq!(run(&disk.to_memory()), Vec::<usize>::new());
+        assert_eq!(run(&col.emit_to_disk("/tmp".into(), |x, emitter| emitter(*x)).to_memory()),
+                   Vec::<usize>::new());
+
+        let saved = col.checkpoint("/tmp/tange-test-empty-checkpoint", &LeveledScheduler).unwrap();
+        assert_eq!(saved.n_partitions(), 0);
+        assert_eq!(run(&saved), Vec::<usize>::new());
+    }
+}
+
+#[cfg(all(test, feature = "log"))]
+mod test_log {
+    use super::*;
+    use std::fs;
+    use std::sync::Mutex;
+    use log::{self,Log,Level,LevelFilter,Metadata,Record};
+    use tange::scheduler::LeveledScheduler;
+
+    struct Capture(Mutex<Vec<(Level, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _metadata: &Metadata) -> bool { true }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    #[test]
+    fn test_sink_logging() {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Debug);
+
+        let dir = "/tmp/tange-test-sink-logging";
+        let _ = fs::remove_dir_all(dir);
+        let col = MemoryCollection::from_vec_chunked((0..10usize).collect(), 2)
+            .map(|x| x.to_string()).named("log-lines");
+        assert_eq!(col.sink(dir).run(&LeveledScheduler), Some(vec![5, 5]));
+
+        // Each partition is written as soon as it's computed, so only the first and
+        // last records are ordered
+        let logged: Vec<_> = CAPTURE.0.lock().unwrap().iter()
+            .filter(|r| r.1.contains("log-lines") || r.1.starts_with("Wrote") || r.1.starts_with("Sink"))
+            .filter(|r| r.1.contains("log-lines") || r.1.contains(dir))
+            .map(|r| (r.0, r.1.split(" in ").next().unwrap().replace(dir, "<dir>")))
+            .collect();
+        assert!(logged.iter().all(|r| r.0 == Level::Debug));
+        let mut middle: Vec<_> = logged[1..4].iter().map(|r| r.1.as_str()).collect();
+        middle.sort();
+        assert_eq!((logged.len(), logged[0].1.as_str(), middle, logged[4].1.as_str()), (
+            5,
+            "Stage 'log-lines' started: 2 tasks",
+            vec!["Stage 'log-lines' finished: 2 tasks", "Wrote partition 0 to <dir>/0", "Wrote partition 1 to <dir>/1"],
+            "Sink to <dir> finished: 2 files, 10 records"
+        ));
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_validate_warn_logging() {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Debug);
+
+        let col = MemoryCollection::from_vec_chunked((0..6usize).collect(), 2)
+            .validate_warn(|x| if *x != 4 { Ok(()) } else { Err("four is unlucky".into()) });
+        assert_eq!(col.run(&LeveledScheduler), Some((0..6).collect()));
+        let logged: Vec<_> = CAPTURE.0.lock().unwrap().iter()
+            .filter(|r| r.1.contains("failed validation"))
+            .cloned()
+            .collect();
+        assert_eq!(logged, vec![(Level::Warn,
+            "Partition 1: 1 records failed validation: record 1 of partition 1 failed: four is unlucky".into())]);
+    }
+}
+
+#[cfg(all(test, feature = "regex"))]
+mod test_regex {
+    use super::*;
+    use tange::scheduler::LeveledScheduler;
+
+    fn log_lines() -> MemoryCollection<String> {
+        MemoryCollection::from_vec_chunked(vec![
+            "2024-01-02 ERROR db: connection refused".to_string(),
+            "2024-01-02 INFO web: started".into(),
+            "2024-01-03 WARN db: slow query".into(),
+            "not a log line".into(),
+            "2024-01-04 ERROR web: timeout".into()
+        ], 2)
+    }
+
+    #[test]
+    fn test_grep() {
+        let errors = log_lines().grep(r"\bERROR\b").unwrap();
+        assert_eq!(errors.run(&LeveledScheduler), Some(vec![
+            "2024-01-02 ERROR db: connection refused".into(),
+            "2024-01-04 ERROR web: timeout".into()]));
+
+        let none = log_lines().grep("^FATAL").unwrap();
+        assert_eq!(none.run(&LeveledScheduler), Some(Vec::new()));
+        assert!(log_lines().grep("[unclosed").is_err());
+    }
+
+    #[test]
+    fn test_extract() {
+        let re = r"^(\d{4}-\d{2}-\d{2}) (?P<level>[A-Z]+) (?P<service>\w+): (.*)$";
+        let parsed = log_lines().extract(re).unwrap();
+        assert_eq!(parsed.run(&LeveledScheduler).unwrap(), vec![
+            vec!["2024-01-02", "ERROR", "db", "connection refused"],
+            vec!["2024-01-02", "INFO", "web", "started"],
+            vec!["2024-01-03", "WARN", "db", "slow query"],
+            vec!["2024-01-04", "ERROR", "web", "timeout"]]);
+
+        // Groups which don't take part in the match come through empty
+        let optional = log_lines().extract(r"(ERROR)|(WARN)").unwrap();
+        assert_eq!(optional.run(&LeveledScheduler).unwrap(), vec![
+            vec!["ERROR", ""], vec!["", "WARN"], vec!["ERROR", ""]]);
+
+        let none = log_lines().extract(r"^(\d+)$").unwrap();
+        assert_eq!(none.run(&LeveledScheduler), Some(Vec::new()));
+        assert!(log_lines().extract("(?P<bad").is_err());
+    }
+}
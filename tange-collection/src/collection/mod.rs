@@ -6,10 +6,640 @@ pub mod memory;
 /// Defines DiskCollection and assorted functions
 pub mod disk;
 
-use std::any::Any;
+/// Defines Fallible, the collection returned by fallible maps
+pub mod fallible;
 
-use tange::deferred::{Deferred, batch_apply};
-use interfaces::{Accumulator,ValueWriter,Stream};
+/// Defines Broadcast, a value shared by every partition of a collection
+pub mod broadcast;
+
+/// Defines BloomFilter, a compact set of keys used to drop values before a join
+pub mod bloom;
+
+/// Defines HyperLogLog, an estimate of the number of distinct values in a set
+pub mod hyperloglog;
+
+/// Defines CountMinSketch, an estimate of how many times each key was seen
+pub mod countmin;
+
+extern crate serde;
+extern crate serde_json;
+extern crate flate2;
+extern crate uuid;
+extern crate rand;
+
+use std::any::{Any,TypeId};
+use std::cmp::{self,Reverse};
+use std::collections::{HashMap,HashSet};
+use std::env;
+use std::fmt::{self,Display};
+use std::fs;
+use std::hash::Hash;
+use std::io::{self,BufWriter,Write};
+use std::mem;
+use std::path::{Path,PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize,Ordering};
+
+use self::serde::{Deserialize,Serialize};
+use self::flate2::Compression;
+use self::flate2::write::GzEncoder;
+use self::uuid::Uuid;
+use self::rand::Rng;
+
+use tange::deferred::{Deferred, Node, batch_apply, gather, tree_reduce};
+use tange::scheduler::{Scheduler,Cancelled,RunError};
+use interfaces::{Accumulator,Disk,FileStore,ValueWriter,Stream,StreamError,Store,stream_or_panic};
+use store::{LocalFs,ObjectStore};
+use random::partition_rng;
+use self::bloom::{BloomFilter,hash_key};
+use self::countmin::CountMinSketch;
+use self::fallible::{ParseError,RecordError};
+
+/// Name of the file listing the files written by a sink
+const MANIFEST: &str = "manifest.json";
+
+/// Name of the empty file marking a sink's directory as complete
+const SUCCESS: &str = "_SUCCESS";
+
+/// How many steps deep a collection's Debug output goes before eliding the rest
+const PLAN_DEPTH: usize = 8;
+
+/// How many of the records failing validation are reported
+const MAX_VIOLATIONS: usize = 10;
+
+/// How many values `kth` samples each round to choose its pivot
+const KTH_SAMPLE: usize = 256;
+
+/// How few candidates `kth` gathers and sorts rather than narrowing them further
+const KTH_GATHER: usize = 4096;
+
+/// How many rounds `kth` narrows its candidates for before gathering them anyway
+const KTH_ROUNDS: u64 = 16;
+
+/// Number of stages created so far, which keeps generated stage names distinct
+static STAGES: AtomicUsize = AtomicUsize::new(0);
+
+// Lists the files written by a sink, in partition order
+#[derive(Serialize,Deserialize,Debug,PartialEq)]
+struct Manifest {
+    files: Vec<ManifestEntry>
+}
+
+#[derive(Serialize,Deserialize,Clone,Debug,PartialEq)]
+struct ManifestEntry {
+    /// File name within the sink's directory
+    path: String,
+
+    /// Number of records in the file
+    records: usize,
+
+    /// Size of the file in bytes
+    #[serde(default)]
+    bytes: u64
+}
+
+/// What a sink does when its output directory already holds files
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum OverwritePolicy {
+    /// Fail if the directory exists and isn't empty
+    #[default]
+    ErrorIfExists,
+
+    /// Remove the files an earlier run of the sink left in the directory before
+    /// writing: part files named as this sink names them, its manifest and success
+    /// marker, and any it didn't finish writing.  Other files are left alone.
+    Overwrite,
+
+    /// Write into a new numbered subdirectory, `run-00000`, `run-00001` and so on,
+    /// leaving earlier runs untouched
+    NewRunDir
+}
+
+impl OverwritePolicy {
+    // Applies the policy to `dir` for a sink naming its files with `naming` and
+    // `encoding`, returning the directory to write into
+    fn prepare(self, dir: &Path, naming: &FileNaming, encoding: Encoding) -> io::Result<PathBuf> {
+        match self {
+            OverwritePolicy::ErrorIfExists => {
+                if is_occupied(dir)? {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, 
+                        format!("Output directory {} already exists and isn't empty", dir.display())));
+                }
+            },
+            OverwritePolicy::Overwrite => if dir.exists() {
+                remove_sink_files(dir, naming, encoding)?;
+            },
+            OverwritePolicy::NewRunDir => {
+                let mut next = 0;
+                if dir.exists() {
+                    for entry in fs::read_dir(dir)? {
+                        let name = entry?.file_name();
+                        let run = name.to_str()
+                            .and_then(|n| n.strip_prefix("run-"))
+                            .and_then(|n| n.parse::<usize>().ok());
+                        if let Some(n) = run {
+                            next = next.max(n + 1);
+                        }
+                    }
+                }
+                return Ok(dir.join(format!("run-{:05}", next)));
+            }
+        }
+        Ok(dir.to_path_buf())
+    }
+}
+
+// Whether a path exists as anything other than an empty directory
+fn is_occupied(dir: &Path) -> io::Result<bool> {
+    match fs::read_dir(dir) {
+        Ok(mut entries) => Ok(entries.next().is_some()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e)
+    }
+}
+
+// Removes the files a sink named by `naming` and `encoding` leaves in `dir`.  Other
+// sinks may share the directory under other prefixes, so only files this sink
+// would name are removed: its parts, its markers, and its temporary files.
+fn remove_sink_files(dir: &Path, naming: &FileNaming, encoding: Encoding) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = match name.to_str() {
+            Some(n) => n,
+            None => continue
+        };
+        let ours = match temp_tag(name) {
+            Some(tag) => tag == MANIFEST || tag == SUCCESS || naming.is_part(tag, ""),
+            None => name == MANIFEST || name == SUCCESS || naming.is_part(name, encoding.extension())
+        };
+        if ours && entry.file_type()?.is_file() {
+            fs::remove_file(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+// Resolves to the directory a sink writes into, applying the policy when the
+// collection is run.  Any marker left by an earlier run is removed first, so the
+// directory only reads as complete once this run has finished.
+fn target_dir(dir: PathBuf, policy: Option<(OverwritePolicy, FileNaming, Encoding)>) -> Deferred<PathBuf> {
+    let name = format!("Sink: {}", dir.display());
+    Deferred::lift(dir, Some(&name)).apply(move |d| {
+        let dir = match policy {
+            Some((policy, ref naming, encoding)) => policy.prepare(d, naming, encoding)
+                .unwrap_or_else(|e| panic!("{}", e)),
+            None => d.clone()
+        };
+        for marker in &[SUCCESS, MANIFEST] {
+            match fs::remove_file(dir.join(marker)) {
+                Err(ref e) if e.kind() != io::ErrorKind::NotFound => {
+                    panic!("Unable to remove {}: {}", dir.join(marker).display(), e)
+                },
+                _ => ()
+            }
+        }
+        dir
+    })
+}
+
+// Waits on every partition a sink writes, then lists the files written in the
+// manifest and marks the directory complete.  Runs only once every partition has
+// succeeded, returning the absolute paths and record counts in partition order.
+fn finish_sink(
+    target: &Deferred<PathBuf>, 
+    parts: &[Deferred<Vec<(String, usize)>>]
+) -> Deferred<Vec<(String, usize)>> {
+    let all = tree_reduce(parts, |x, y| {
+        x.iter().chain(y.iter()).cloned().collect()
+    }).unwrap_or_else(|| Deferred::lift(Vec::new(), None));
+
+    all.join(target, |files, dir| {
+        fs::create_dir_all(dir)
+            .expect("Welp, something went terribly wrong when creating directory");
+        let dir = fs::canonicalize(dir)
+            .expect("Welp, something went terribly wrong when creating directory");
+        write_manifest(&dir, files).unwrap_or_else(|e| {
+            panic!("Error writing out manifest for {}: {}", dir.display(), e)
+        });
+        log_at!(debug, "Sink to {} finished: {} files, {} records", dir.display(), files.len(),
+                files.iter().map(|f| f.1).sum::<usize>());
+        files.clone()
+    })
+}
+
+// Writes the manifest, then the success marker, each under a temporary name first
+fn write_manifest(dir: &Path, files: &[(String, usize)]) -> io::Result<()> {
+    let mut entries = Vec::with_capacity(files.len());
+    for &(ref path, records) in files {
+        let path = Path::new(path);
+        let name = path.strip_prefix(dir).unwrap_or(path);
+        entries.push(ManifestEntry {
+            path: name.to_string_lossy().into_owned(),
+            records,
+            bytes: fs::metadata(path)?.len()
+        });
+    }
+
+    let manifest = serde_json::to_vec_pretty(&Manifest { files: entries }).map_err(io::Error::from)?;
+    write_atomic(dir, MANIFEST, &manifest)?;
+    write_atomic(dir, SUCCESS, b"")
+}
+
+// Writes a whole file within `dir` under a temporary name, then moves it into place
+fn write_atomic(dir: &Path, name: &str, bytes: &[u8]) -> io::Result<()> {
+    let tmp = temp_path(dir, name);
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, dir.join(name))
+}
+
+// Turns a key into a single directory name which stays within its parent: path
+// separators and control characters become underscores, as do empty names and
+// names made only of dots
+pub(crate) fn key_dir(key: &str) -> String {
+    let name: String = key.chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    if name.chars().all(|c| c == '.') {
+        "_".repeat(name.len().max(1))
+    } else {
+        name
+    }
+}
+
+// Returns a unique path within `dir` for a file which is still being written, hidden
+// from most tools by a leading dot
+fn temp_path(dir: &Path, tag: &str) -> PathBuf {
+    dir.join(format!(".tmp-{}-{}", tag, Uuid::new_v4()))
+}
+
+// A temporary file which is removed once dropped, including while unwinding from a
+// failed write.  Files moved into place are no longer there to remove.
+pub(crate) struct TempFile(pub(crate) PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Returns the tag of a file name made by `temp_path`, or None if it isn't one
+fn temp_tag(name: &str) -> Option<&str> {
+    let rest = name.strip_prefix(".tmp-")?;
+    let uuid = rest.len().checked_sub(37)?;
+    if rest.is_char_boundary(uuid) && rest[uuid..].starts_with('-') && Uuid::parse_str(&rest[uuid + 1..]).is_ok() {
+        Some(&rest[..uuid])
+    } else {
+        None
+    }
+}
+
+/// How a sink names the file written for each partition: a prefix, the partition
+/// index zero-padded to a width, and a suffix.  The default names files by their
+/// bare index, `0`, `1`, and so on.
+#[derive(Clone,Debug,Default,PartialEq,Eq)]
+pub struct FileNaming {
+    prefix: String,
+    width: usize,
+    suffix: String
+}
+
+impl FileNaming {
+    /// Creates a new naming scheme.  A width of zero leaves indexes unpadded; any
+    /// other width is widened as needed to fit the largest index, so file names
+    /// always sort in partition order.
+    pub fn new(prefix: &str, width: usize, suffix: &str) -> Self {
+        FileNaming { prefix: prefix.into(), width, suffix: suffix.into() }
+    }
+
+    /// Returns the file name for partition `idx` out of `partitions`
+    pub fn name(&self, idx: usize, partitions: usize) -> String {
+        let width = if self.width == 0 {
+            0
+        } else {
+            self.width.max(partitions.saturating_sub(1).to_string().len())
+        };
+        format!("{}{:0width$}{}", self.prefix, idx, self.suffix, width=width)
+    }
+
+    // Whether `name` is a partition's file under this naming, followed by `extension`
+    fn is_part(&self, name: &str, extension: &str) -> bool {
+        name.strip_suffix(extension)
+            .and_then(|n| n.strip_prefix(self.prefix.as_str()))
+            .and_then(|n| n.strip_suffix(self.suffix.as_str()))
+            .is_some_and(|idx| !idx.is_empty() && idx.bytes().all(|b| b.is_ascii_digit()))
+    }
+}
+
+// How a sink encodes the files it writes
+#[derive(Clone,Copy,Debug)]
+enum Encoding {
+    Plain,
+    // Gzip at the given compression level, from 0 to 9
+    Gzip(u32)
+}
+
+impl Encoding {
+    // Extension appended to file names, after any suffix
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Plain => "",
+            Encoding::Gzip(_) => ".gz"
+        }
+    }
+}
+
+enum Encoder {
+    Plain(BufWriter<fs::File>),
+    Gzip(GzEncoder<BufWriter<fs::File>>)
+}
+
+impl Write for Encoder {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Encoder::Plain(ref mut w) => w.write(buf),
+            Encoder::Gzip(ref mut w) => w.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Encoder::Plain(ref mut w) => w.flush(),
+            Encoder::Gzip(ref mut w) => w.flush()
+        }
+    }
+}
+
+// Panics with everything needed to diagnose a failed write, such as a full disk:
+// the partition, the file, and the underlying error
+fn sink_error(path: &Path, idx: usize, e: &dyn Display) -> ! {
+    log_at!(error, "Error writing out partition {} to {}: {}", idx, path.display(), e);
+    panic!("Error writing out partition {} to {}: {}", idx, path.display(), e)
+}
+
+// A buffered writer for a partition's file, compressing if requested.  Bytes go to
+// a temporary file which is renamed into place once finished, so a partially
+// written file is never mistaken for a complete one.
+struct PartWriter {
+    out: Option<Encoder>,
+    idx: usize,
+    tmp: PathBuf,
+    path: PathBuf
+}
+
+impl PartWriter {
+    // Creates the file `name` within `dir` for partition `idx`, with the encoding's
+    // extension added, returning its absolute path and a writer for it
+    fn create(dir: &Path, name: &str, idx: usize, encoding: Encoding) -> (PathBuf, PartWriter) {
+        let dir = fs::create_dir_all(dir)
+            .and_then(|_| fs::canonicalize(dir))
+            .unwrap_or_else(|e| sink_error(dir, idx, &e));
+
+        let tmp = temp_path(&dir, name);
+        let file = fs::File::create(&tmp).unwrap_or_else(|e| sink_error(&tmp, idx, &e));
+        let file = BufWriter::new(file);
+        let out = match encoding {
+            Encoding::Plain => Encoder::Plain(file),
+            Encoding::Gzip(level) => Encoder::Gzip(GzEncoder::new(file, Compression::new(level)))
+        };
+        let path = dir.join(format!("{}{}", name, encoding.extension()));
+        (path.clone(), PartWriter { out: Some(out), idx, tmp, path })
+    }
+
+    // Unwraps the result of a write, panicking with the partition and file on error
+    fn check<T>(&self, result: io::Result<T>) -> T {
+        result.unwrap_or_else(|e| sink_error(&self.path, self.idx, &e))
+    }
+
+    // Writes out any buffered bytes, along with the gzip trailer, then moves the
+    // file into place
+    fn finish(mut self) {
+        let flushed = match self.out.take() {
+            Some(Encoder::Plain(mut bw)) => bw.flush(),
+            Some(Encoder::Gzip(gz)) => gz.finish().and_then(|mut bw| bw.flush()),
+            None => Ok(())
+        };
+        let renamed = flushed.and_then(|_| fs::rename(&self.tmp, &self.path));
+        if let Err(e) = renamed {
+            let _ = fs::remove_file(&self.tmp);
+            sink_error(&self.path, self.idx, &e)
+        }
+        log_at!(debug, "Wrote partition {} to {}", self.idx, self.path.display());
+    }
+}
+
+impl Write for PartWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.out.as_mut().expect("Writer already finished").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.as_mut().expect("Writer already finished").flush()
+    }
+}
+
+// Removes the temporary file of a writer which was never finished
+impl Drop for PartWriter {
+    fn drop(&mut self) {
+        if self.out.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+// Writes each partition to a file of bincode records within `dir`, in the same
+// format as the Disk accumulator, then writes a manifest once every partition has
+// been written.  Resolves to the absolute path and record count of each file.
+fn sink_bincode<
+    A: Any + Send + Sync + Clone + Serialize,
+    Col: Any + Send + Sync + Clone + Stream<A>
+>(defs: &[Deferred<Col>], dir: PathBuf) -> Deferred<Vec<(String, usize)>> {
+    let target = target_dir(dir, None);
+    let written: Vec<_> = defs.iter().enumerate().map(|(idx, part)| {
+        part.join(&target, move |vs, dir| {
+            fs::create_dir_all(dir)
+                .expect("Welp, something went terribly wrong when creating directory");
+            let store = Store(Arc::new(LocalFs::new(dir.clone())));
+            let name = idx.to_string();
+            let mut out = store.writer_named(&name);
+            for v in stream_or_panic(vs) {
+                out.add(v);
+            }
+            let records = out.finish().len();
+            let path = fs::canonicalize(dir.join(&name)).expect("Error writing out partition");
+            log_at!(debug, "Wrote partition {} to {}: {} records", idx, path.display(), records);
+            vec![(path.to_string_lossy().into_owned(), records)]
+        })
+    }).collect();
+
+    finish_sink(&target, &written)
+}
+
+// Opens the files of bincode records within `root`, one partition per file.  With a
+// manifest, files are opened in the order it lists them and checked against the
+// sizes it records; otherwise, files named `tange-*` or `part-*` are opened in name
+// order.  Files are only opened when their partition runs, failing it with the
+// file's path if missing or damaged.
+fn stored_parts<A: Any + Send + Sync + Clone + for<'de> Deserialize<'de>>(
+    root: &str
+) -> io::Result<Vec<Deferred<Arc<FileStore<A>>>>> {
+    let dir = Path::new(root);
+    let entries = match fs::File::open(dir.join(MANIFEST)) {
+        Ok(file) => {
+            let manifest: Manifest = serde_json::from_reader(io::BufReader::new(file))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, 
+                    format!("Couldn't read {}: {}", dir.join(MANIFEST).display(), e)))?;
+            manifest.files
+        },
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            let mut names = Vec::new();
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let is_part = name.starts_with("tange-") || name.starts_with("part-");
+                if is_part && entry.file_type()?.is_file() {
+                    names.push(name);
+                }
+            }
+            names.sort();
+            names.into_iter().map(|path| ManifestEntry { path, records: 0, bytes: 0 }).collect()
+        },
+        Err(e) => return Err(e)
+    };
+
+    let store: Arc<dyn ObjectStore> = Arc::new(LocalFs::new(dir));
+    Ok(entries.into_iter().map(|entry| {
+        let store = store.clone();
+        let name = format!("File: {}", dir.join(&entry.path).display());
+        Deferred::lift(entry, Some(&name)).apply(move |entry| {
+            let path = store.local_path(&entry.path)
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| entry.path.clone());
+            let fs = FileStore::stored(store.clone(), &entry.path, entry.records)
+                .unwrap_or_else(|cause| panic!("{}", StreamError::Missing { path: path.clone(), cause }));
+            let found = store.size(&entry.path).unwrap_or(0);
+            if entry.bytes > 0 && found != entry.bytes {
+                panic!("{}", StreamError::Truncated { path, expected: entry.bytes, found });
+            }
+            Arc::new(fs)
+        })
+    }).collect())
+}
+
+// Runs every partition, writing each into a new file within `root` through the
+// Disk accumulator, and returns handles to the files in partition order.  The files
+// are removed once the last handle to them is dropped.
+fn checkpoint<
+    A: Any + Send + Sync + Clone + Serialize,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    S: Scheduler
+>(defs: &[Deferred<Col>], root: &str, s: &S) -> io::Result<Vec<Arc<FileStore<A>>>> {
+    fs::create_dir_all(root)?;
+    let disk = Disk::from_str(root);
+    let stores: Vec<_> = batch_apply(defs, move |_idx, vs| {
+        let mut out = disk.writer();
+        for v in stream_or_panic(vs) {
+            out.add(v);
+        }
+        vec![out.finish()]
+    });
+    match tree_reduce(&stores, |x, y| x.iter().chain(y.iter()).cloned().collect()) {
+        Some(all) => all.run(s).ok_or_else(|| {
+            io::Error::other(format!("Unable to checkpoint into {}", root))
+        }),
+        None => Ok(Vec::new())
+    }
+}
+
+// Reads each partition from its file within the cache directory for `key` under
+// `root` if an earlier run stored it, and otherwise runs the partition and stores
+// it there.  Files are written under a temporary name and moved into place once
+// complete, so a failed run never leaves part of a partition behind.  Each file's
+// record count and size are kept alongside it, in `part-NNNNN.json`, and a file
+// only counts as stored once they're written and its size still matches.
+fn cached_parts<
+    A: Any + Send + Sync + Clone + Serialize + for<'de> Deserialize<'de>,
+    Col: Any + Send + Sync + Clone + Stream<A>
+>(defs: &[Deferred<Col>], root: &Path, key: &str) -> io::Result<Vec<Deferred<Arc<FileStore<A>>>>> {
+    let dir = root.join(cache_name(key));
+    fs::create_dir_all(&dir)?;
+    let store: Arc<dyn ObjectStore> = Arc::new(LocalFs::new(dir.clone()));
+    Ok(defs.iter().enumerate().map(|(idx, d)| {
+        let name = format!("part-{:05}", idx);
+        let store = store.clone();
+        let path = dir.join(&name);
+        if let Some(entry) = cached_entry(&dir, &name) {
+            let label = format!("Cached: {}", path.display());
+            Deferred::lift(entry, Some(&label)).apply(move |entry| {
+                let fs = FileStore::stored(store.clone(), &entry.path, entry.records).unwrap_or_else(|cause| {
+                    panic!("{}", StreamError::Missing { path: path.display().to_string(), cause })
+                });
+                Arc::new(fs)
+            })
+        } else {
+            let dir = dir.clone();
+            d.apply(move |vs| {
+                let entry_name = format!("{}.json", name);
+                match fs::remove_file(dir.join(&entry_name)) {
+                    Err(ref e) if e.kind() != io::ErrorKind::NotFound => sink_error(&path, idx, e),
+                    _ => ()
+                }
+                let tmp = TempFile(temp_path(&dir, &name));
+                let tmp_name = tmp.0.file_name().unwrap().to_string_lossy().into_owned();
+                let mut out = Store(store.clone()).writer_named(&tmp_name);
+                let mut count = 0;
+                for v in stream_or_panic(vs) {
+                    out.add(v);
+                    count += 1;
+                }
+                if let Err(e) = out.flush() {
+                    sink_error(&path, idx, &e);
+                }
+                out.finish();
+                if let Err(e) = fs::rename(&tmp.0, &path) {
+                    sink_error(&path, idx, &e);
+                }
+                let fs = FileStore::stored(store.clone(), &name, count)
+                    .unwrap_or_else(|e| sink_error(&path, idx, &e));
+                let written = fs::metadata(&path)
+                    .map(|m| ManifestEntry { path: name.clone(), records: count, bytes: m.len() })
+                    .and_then(|entry| serde_json::to_vec(&entry).map_err(io::Error::from))
+                    .and_then(|bytes| write_atomic(&dir, &entry_name, &bytes));
+                if let Err(e) = written {
+                    sink_error(&dir.join(&entry_name), idx, &e);
+                }
+                Arc::new(fs)
+            })
+        }
+    }).collect())
+}
+
+// Returns the entry kept for a cached partition's file, or None if either is
+// missing or the file's size no longer matches
+fn cached_entry(dir: &Path, name: &str) -> Option<ManifestEntry> {
+    let bytes = fs::read(dir.join(format!("{}.json", name))).ok()?;
+    let entry: ManifestEntry = serde_json::from_slice(&bytes).ok()?;
+    let size = fs::metadata(dir.join(&entry.path)).ok()?.len();
+    if entry.path == name && size == entry.bytes { Some(entry) } else { None }
+}
+
+// Names the cache directory for a key after its FNV-1a hash, which stays the same
+// from one process to the next
+fn cache_name(key: &str) -> String {
+    let hash = key.bytes().fold(0xcbf2_9ce4_8422_2325u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x100_0000_01b3)
+    });
+    format!("cache-{:016x}", hash)
+}
+
+// Directory holding cached collections unless another is given: TANGE_CACHE_DIR
+// if set, otherwise `tange-cache` in the system's temporary directory
+fn cache_root() -> PathBuf {
+    env::var_os("TANGE_CACHE_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| env::temp_dir().join("tange-cache"))
+}
 
 fn emit<
     A,
@@ -21,10 +651,1187 @@ fn emit<
 
     batch_apply(&defs, move |_idx, vs| {
         let mut out = acc.writer();
-        for v in vs.stream().into_iter() {
+        for v in stream_or_panic(vs).into_iter() {
             f(&v, &mut |r| out.add(r));
         }
         out.finish()
     })
 }
 
+// Keeps each value with a chance of `fraction`, drawing from the partition's stream
+// of the "sample" op
+fn sample<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    Acc: 'static + Accumulator<A>
+>(defs: &[Deferred<Col>], acc: Acc, fraction: f64, seed: u64) -> Written<Acc, A> {
+    batch_apply(defs, move |idx, vs| {
+        let mut rng = partition_rng(seed, "sample", idx);
+        let mut out = acc.writer();
+        for v in stream_or_panic(vs).into_iter() {
+            if rng.next_f64() < fraction {
+                out.add(v);
+            }
+        }
+        out.finish()
+    })
+}
+
+// Maps each value along with a value computed once for every partition, like the
+// records of another collection, which each task reads alongside its partition
+fn map_with<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    S: Any + Send + Sync,
+    B: Any + Send + Sync + Clone,
+    F: 'static + Sync + Send + Clone + Fn(&A, &S) -> B,
+    Acc: 'static + Accumulator<B>
+>(defs: &[Deferred<Col>], side: &Deferred<S>, acc: Acc, f: F) -> Written<Acc, B> {
+    defs.iter().map(|d| {
+        let (acc, f) = (acc.clone(), f.clone());
+        d.join(side, move |vs, s| {
+            let mut out = acc.writer();
+            for v in stream_or_panic(vs).into_iter() {
+                out.add(f(&v, s));
+            }
+            out.finish()
+        })
+    }).collect()
+}
+
+// Keeps the values for which `f` holds, given a value computed once for every
+// partition
+fn filter_with<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    S: Any + Send + Sync,
+    F: 'static + Sync + Send + Clone + Fn(&A, &S) -> bool,
+    Acc: 'static + Accumulator<A>
+>(defs: &[Deferred<Col>], side: &Deferred<S>, acc: Acc, f: F) -> Written<Acc, A> {
+    defs.iter().map(|d| {
+        let (acc, f) = (acc.clone(), f.clone());
+        d.join(side, move |vs, s| {
+            let mut out = acc.writer();
+            for v in stream_or_panic(vs).into_iter() {
+                if f(&v, s) {
+                    out.add(v);
+                }
+            }
+            out.finish()
+        })
+    }).collect()
+}
+
+// Builds a BloomFilter of the keys of every value.  Each partition hashes its keys
+// first, so every partition's filter can be sized for the total, letting them merge.
+fn to_bloom<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    K: Hash,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K
+>(defs: &[Deferred<Col>], key: F, bits_per_key: usize) -> Deferred<BloomFilter> {
+    let hashes = batch_apply(defs, move |_idx, vs| {
+        let mut hashes: Vec<_> = stream_or_panic(vs).into_iter()
+            .map(|v| hash_key(&key(&v)))
+            .collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        hashes
+    });
+    let counts: Vec<_> = hashes.iter().map(|hs| hs.apply(|hs| hs.len())).collect();
+    let total = tree_reduce(&counts, |x, y| x + y)
+        .unwrap_or_else(|| Deferred::lift(0, None));
+    let filters: Vec<_> = hashes.iter().map(|hs| {
+        hs.join(&total, move |hs, n| {
+            let mut filter = BloomFilter::new(*n, bits_per_key);
+            for h in hs.iter() {
+                filter.insert_hash(*h);
+            }
+            filter
+        })
+    }).collect();
+    tree_reduce(&filters, |x, y| {
+        let mut merged = x.clone();
+        merged.union(y);
+        merged
+    }).unwrap_or_else(|| Deferred::lift(BloomFilter::new(0, bits_per_key), None))
+}
+
+// Groups the values of each key into one map, grouping within each partition first
+// and merging the maps in partition order
+fn collect_groups<
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<(K, V)>
+>(defs: &[Deferred<Col>]) -> Deferred<HashMap<K, Vec<V>>> {
+    let groups = batch_apply(defs, |_idx, vs| {
+        let mut groups: HashMap<K, Vec<V>> = HashMap::new();
+        for (k, v) in stream_or_panic(vs).into_iter() {
+            groups.entry(k).or_default().push(v);
+        }
+        groups
+    });
+    tree_reduce(&groups, |left, right| {
+        let mut merged = left.clone();
+        for (k, vs) in right.iter() {
+            merged.entry(k.clone()).or_default().extend_from_slice(vs);
+        }
+        merged
+    }).unwrap_or_else(|| Deferred::lift(HashMap::new(), None))
+}
+
+// Tags each pair's value with its partition and its place within the partition, so
+// the order of the collection can be restored after a shuffle
+fn tag_order<
+    K: Any + Send + Sync + Clone,
+    V: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<(K, V)>,
+    Acc: 'static + Accumulator<(K, Ordered<V>)>
+>(defs: &[Deferred<Col>], acc: Acc) -> Written<Acc, (K, Ordered<V>)> {
+    batch_apply(defs, move |idx, vs| {
+        let mut out = acc.writer();
+        for (i, (k, v)) in stream_or_panic(vs).into_iter().enumerate() {
+            out.add((k, ((idx, i), v)));
+        }
+        out.finish()
+    })
+}
+
+// Adds a value to `top`, holding at most the `k` highest scoring values seen, highest
+// first.  Values scoring the same as one already held go after it.
+fn keep_top<V, S: Ord, F: Fn(&V) -> S>(top: &mut Vec<V>, v: V, k: usize, score: &F) {
+    let s = score(&v);
+    let idx = top.partition_point(|t| score(t) >= s);
+    if idx < k {
+        top.insert(idx, v);
+        top.truncate(k);
+    }
+}
+
+// Finds the values making up at least `threshold` of the collection, with their
+// estimated counts, most frequent first.  Each partition counts its values in a
+// CountMinSketch and keeps as candidates those estimated at `threshold` of the
+// partition so far.  A value that often overall is that often in some partition,
+// and as estimates never fall short, it's kept as a candidate there, at the
+// latest by its last occurrence.  The sketches are summed and the candidates
+// pooled, then checked against the total.
+fn heavy_hitters<
+    A: Any + Send + Sync + Clone + Hash + Eq,
+    Col: Any + Send + Sync + Clone + Stream<A>
+>(defs: &[Deferred<Col>], threshold: f64, width: usize, depth: usize) -> Deferred<Vec<(A, usize)>> {
+    let parts = batch_apply(defs, move |_idx, vs| {
+        let mut sketch = CountMinSketch::new(width, depth);
+        let mut candidates = HashSet::new();
+        let mut limit = (2.0 / threshold).ceil().min(1e6) as usize;
+        for v in stream_or_panic(vs).into_iter() {
+            sketch.insert(&v, 1);
+            if sketch.estimate(&v) as f64 >= threshold * sketch.total() as f64 {
+                candidates.insert(v);
+            }
+            if candidates.len() > limit {
+                let cutoff = threshold * sketch.total() as f64;
+                candidates.retain(|c| sketch.estimate(c) as f64 >= cutoff);
+                limit = limit.max(2 * candidates.len());
+            }
+        }
+        let cutoff = threshold * sketch.total() as f64;
+        candidates.retain(|c| sketch.estimate(c) as f64 >= cutoff);
+        (sketch, candidates)
+    });
+    let all = tree_reduce(&parts, |x, y| {
+        let mut sketch = x.0.clone();
+        sketch.merge(&y.0);
+        (sketch, x.1.union(&y.1).cloned().collect())
+    });
+    match all {
+        None      => Deferred::lift(Vec::new(), None),
+        Some(all) => all.apply(move |(sketch, candidates)| {
+            let cutoff = threshold * sketch.total() as f64;
+            let mut hitters: Vec<(A, usize)> = candidates.iter()
+                .map(|c| (c.clone(), sketch.estimate(c) as usize))
+                .filter(|c| c.1 as f64 >= cutoff)
+                .collect();
+            hitters.sort_by_key(|c| Reverse(c.1));
+            hitters
+        })
+    }
+}
+
+// Folds the values of each partition into `identity` with `op`, then combines the
+// partitions' folds in a tree.  With no partitions the fold is `identity`.
+fn fold_monoid<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    F: 'static + Sync + Send + Clone + Fn(&A, &A) -> A
+>(defs: &[Deferred<Col>], identity: A, op: F) -> Deferred<A> {
+    let (id, part_op) = (identity.clone(), op.clone());
+    let parts = batch_apply(defs, move |_idx, vs| {
+        stream_or_panic(vs).into_iter().fold(id.clone(), |acc, v| part_op(&acc, &v))
+    });
+    tree_reduce(&parts, op).unwrap_or_else(|| Deferred::lift(identity, None))
+}
+
+// Checks whether every value satisfies `f`, checking each partition on its own
+// and combining the answers, with no partitions at all satisfying it vacuously
+fn all<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> bool
+>(defs: &[Deferred<Col>], f: F) -> Deferred<bool> {
+    let parts = batch_apply(defs, move |_idx, vs| stream_or_panic(vs).into_iter().all(|v| f(&v)));
+    tree_reduce(&parts, |x, y| *x && *y).unwrap_or_else(|| Deferred::lift(true, None))
+}
+
+// Lower and upper bounds, both exclusive, on the values `kth` is choosing between
+type Bounds<A> = (Option<A>, Option<A>);
+
+fn within<A: Ord>(bounds: &Bounds<A>, v: &A) -> bool {
+    bounds.0.as_ref().is_none_or(|lo| v > lo) && bounds.1.as_ref().is_none_or(|hi| v < hi)
+}
+
+// Finds the value at index `k` of the sorted values by quickselect across the
+// partitions.  Each round counts and samples the candidates, the values within
+// the bounds, to choose a pivot, then counts the candidates below and equal to
+// the pivot to narrow the bounds.  Once few enough candidates are left, or the
+// rounds run out, they're gathered and sorted.  Returns None if there are no
+// more than `k` values.
+fn kth<
+    A: Any + Send + Sync + Clone + Ord,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    S: Scheduler
+>(defs: &[Deferred<Col>], mut k: usize, s: &S) -> Option<A> {
+    let mut bounds: Bounds<A> = (None, None);
+    let smallest = |x: &(u64, A)| Reverse(x.0);
+    for round in 0..KTH_ROUNDS {
+        let b = bounds.clone();
+        let samples = batch_apply(defs, move |idx, vs| {
+            let mut rng = partition_rng(round, "kth", idx);
+            let (mut n, mut kept) = (0, Vec::new());
+            for v in stream_or_panic(vs).into_iter().filter(|v| within(&b, v)) {
+                n += 1;
+                keep_top(&mut kept, (rng.next_u64(), v), KTH_SAMPLE, &smallest);
+            }
+            (n, kept)
+        });
+        let samples = gather(&samples).run(s)?;
+        let n: usize = samples.iter().map(|x| x.0).sum();
+        if k >= n {
+            return None;
+        } else if n <= KTH_GATHER {
+            break;
+        }
+
+        let mut sample: Vec<(u64, A)> = samples.into_iter().flat_map(|x| x.1).collect();
+        sample.sort_by_key(|x| x.0);
+        let mut sample: Vec<A> = sample.into_iter().take(KTH_SAMPLE).map(|x| x.1).collect();
+        sample.sort();
+        let pivot = sample[k * sample.len() / n].clone();
+
+        let (b, p) = (bounds.clone(), pivot.clone());
+        let counts = batch_apply(defs, move |_idx, vs| {
+            let (mut below, mut equal) = (0, 0);
+            for v in stream_or_panic(vs).into_iter().filter(|v| within(&b, v)) {
+                match v.cmp(&p) {
+                    cmp::Ordering::Less    => below += 1,
+                    cmp::Ordering::Equal   => equal += 1,
+                    cmp::Ordering::Greater => ()
+                }
+            }
+            (below, equal)
+        });
+        let (below, equal) = tree_reduce(&counts, |x, y| (x.0 + y.0, x.1 + y.1))?.run(s)?;
+        if k < below {
+            bounds.1 = Some(pivot);
+        } else if k < below + equal {
+            return Some(pivot);
+        } else {
+            k -= below + equal;
+            bounds.0 = Some(pivot);
+        }
+    }
+
+    let candidates = batch_apply(defs, move |_idx, vs| {
+        stream_or_panic(vs).into_iter().filter(|v| within(&bounds, v)).collect::<Vec<A>>()
+    });
+    let mut candidates = gather(&candidates).run(s)?.concat();
+    candidates.sort();
+    candidates.into_iter().nth(k)
+}
+
+// Checks every partition is sorted by `key` and ends with a key no greater than
+// the one the next nonempty partition starts with.  Partitions report only their
+// first and last keys and whether they're sorted.
+fn is_sorted_by<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    K: Any + Send + Sync + Clone + Ord,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K
+>(defs: &[Deferred<Col>], key: F) -> Deferred<bool> {
+    let bounds = batch_apply(defs, move |_idx, vs| {
+        let mut bounds: Option<(K, K)> = None;
+        for v in stream_or_panic(vs).into_iter() {
+            let k = key(&v);
+            bounds = match bounds {
+                None => Some((k.clone(), k)),
+                Some((first, last)) => if last <= k { Some((first, k)) } else { return (false, None) }
+            };
+        }
+        (true, bounds)
+    });
+    gather(&bounds).apply(|bounds| {
+        let mut last: Option<&K> = None;
+        for (sorted, b) in bounds.iter() {
+            if !sorted {
+                return false;
+            }
+            if let Some((first, end)) = b {
+                if last.is_some_and(|l| l > first) {
+                    return false;
+                }
+                last = Some(end);
+            }
+        }
+        true
+    })
+}
+
+// Drops every value with the same key as the value before it, looking back across
+// partitions to the last key of the nearest nonempty partition before each
+fn dedup_sorted<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    K: Any + Send + Sync + Clone + PartialEq,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K,
+    Acc: 'static + Accumulator<A>
+>(defs: &[Deferred<Col>], acc: Acc, key: F) -> Written<Acc, A> {
+    let k2 = key.clone();
+    let lasts = gather(&batch_apply(defs, move |_idx, vs| {
+        stream_or_panic(vs).into_iter().last().map(|v| k2(&v))
+    }));
+    defs.iter().enumerate().map(|(i, d)| {
+        let (acc, key) = (acc.clone(), key.clone());
+        d.join(&lasts, move |vs, lasts| {
+            let mut prev = lasts[..i].iter().rev().flatten().next().cloned();
+            let mut out = acc.writer();
+            for v in stream_or_panic(vs).into_iter() {
+                let k = key(&v);
+                if prev.as_ref() != Some(&k) {
+                    out.add(v);
+                    prev = Some(k);
+                }
+            }
+            out.finish()
+        })
+    }).collect()
+}
+
+// Records failing validation: how many there were, along with the first few
+#[derive(Clone,Debug,Default)]
+struct Violations {
+    count: usize,
+    examples: Vec<RecordError<String>>
+}
+
+impl Violations {
+    fn merge(&mut self, other: &Violations) {
+        self.count += other.count;
+        let room = (MAX_VIOLATIONS - self.examples.len()).min(other.examples.len());
+        self.examples.extend_from_slice(&other.examples[..room]);
+    }
+}
+
+impl Display for Violations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} records failed validation", self.count)?;
+        for (i, e) in self.examples.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { ":" } else { ";" }, e)?;
+        }
+        Ok(())
+    }
+}
+
+// Checks every value of a partition with `f`
+fn violations<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+>(defs: &[Deferred<Col>], f: F) -> Vec<Deferred<Violations>> {
+    batch_apply(defs, move |idx, vs| {
+        let mut found = Violations::default();
+        for (i, v) in stream_or_panic(vs).into_iter().enumerate() {
+            if let Err(error) = f(&v) {
+                found.count += 1;
+                if found.examples.len() < MAX_VIOLATIONS {
+                    found.examples.push(RecordError { partition: idx, index: i, error });
+                }
+            }
+        }
+        found
+    })
+}
+
+// Passes every partition on once all of them are checked, failing the run if any
+// value was rejected
+fn validate<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+>(defs: &[Deferred<Col>], f: F) -> Vec<Deferred<Col>> {
+    let found = violations(defs, f);
+    let all = gather(&found).apply(|found| {
+        let mut all = Violations::default();
+        for v in found.iter() {
+            all.merge(v);
+        }
+        all
+    });
+    defs.iter().map(|d| {
+        d.join(&all, |vs, all| {
+            if all.count > 0 {
+                panic!("{}", all);
+            }
+            vs.clone()
+        })
+    }).collect()
+}
+
+// Passes each partition on as soon as it's checked, logging a warning if any of its
+// values were rejected
+fn validate_warn<
+    A,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> Result<(), String>
+>(defs: &[Deferred<Col>], f: F) -> Vec<Deferred<Col>> {
+    let found = violations(defs, f);
+    defs.iter().zip(found.iter()).enumerate().map(|(idx, (d, v))| {
+        d.join(v, move |vs, v| {
+            if v.count > 0 {
+                log_at!(warn, "Partition {}: {}", idx, v);
+            }
+            vs.clone()
+        })
+    }).collect()
+}
+
+// Joins all the strings with `sep` between them, in partition order.  Empty
+// partitions join to None, so they add no separator of their own.
+fn join_strings<Col: Any + Send + Sync + Clone + Stream<String>>(defs: &[Deferred<Col>], sep: &str) -> Deferred<String> {
+    let part_sep = sep.to_owned();
+    let joined = batch_apply(defs, move |_idx, vs| {
+        let mut out: Option<String> = None;
+        for v in stream_or_panic(vs).into_iter() {
+            match out {
+                None => out = Some(v),
+                Some(ref mut s) => { s.push_str(&part_sep); s.push_str(&v); }
+            }
+        }
+        out
+    });
+    let sep = sep.to_owned();
+    tree_reduce(&joined, move |x, y| match (x, y) {
+        (Some(x), Some(y)) => Some([x.as_str(), y.as_str()].join(&sep)),
+        (Some(s), None) | (None, Some(s)) => Some(s.clone()),
+        (None, None) => None
+    }).map(|all| all.apply(|all| all.clone().unwrap_or_default()))
+      .unwrap_or_else(|| Deferred::lift(String::new(), None))
+}
+
+// Parses every string with `FromStr`, noting where each failure was found
+fn parse<
+    T: Any + Send + Sync + Clone + FromStr,
+    Col: Any + Send + Sync + Clone + Stream<String>,
+    Acc: 'static + Accumulator<Result<T, ParseError>>
+>(defs: &[Deferred<Col>], acc: Acc) -> Written<Acc, Result<T, ParseError>> 
+    where T::Err: Display
+{
+    batch_apply(defs, move |idx, vs| {
+        let mut out = acc.writer();
+        for (i, v) in stream_or_panic(vs).into_iter().enumerate() {
+            out.add(v.parse().map_err(|e| ParseError::new(idx, i, &v, e)));
+        }
+        out.finish()
+    })
+}
+
+// Draws `n` values uniformly, with replacement.  The partitions are counted first,
+// and `n` draws from all their values divided between them, so each partition
+// only has to draw its share from its own values.  A partition's values are
+// emitted in order, each once for every time it was drawn.
+fn sample_with_replacement<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    Acc: 'static + Accumulator<A>
+>(defs: &[Deferred<Col>], acc: Acc, n: usize, seed: u64) -> Written<Acc, A> {
+    let sizes = gather(&batch_apply(defs, |_idx, vs| stream_or_panic(vs).into_iter().count()));
+    let quotas = sizes.apply(move |sizes| {
+        let mut ends = Vec::with_capacity(sizes.len());
+        let mut total = 0;
+        for size in sizes.iter() {
+            total += size;
+            ends.push(total);
+        }
+        let mut quotas: Vec<(usize, usize)> = sizes.iter().map(|size| (*size, 0)).collect();
+        if total > 0 {
+            let mut rng = partition_rng(seed, "sample_with_replacement_quotas", 0);
+            for _ in 0..n {
+                let i = rng.gen_range(0, total);
+                quotas[ends.partition_point(|end| *end <= i)].1 += 1;
+            }
+        }
+        quotas
+    });
+    defs.iter().enumerate().map(|(idx, d)| {
+        let acc = acc.clone();
+        d.join(&quotas, move |vs, quotas| {
+            let (size, quota) = quotas[idx];
+            let mut out = acc.writer();
+            if quota > 0 {
+                let mut rng = partition_rng(seed, "sample_with_replacement", idx);
+                let mut drawn: Vec<usize> = (0..quota).map(|_| rng.gen_range(0, size)).collect();
+                drawn.sort_unstable();
+                let mut next = drawn.iter().peekable();
+                for (i, v) in stream_or_panic(vs).into_iter().enumerate() {
+                    while next.next_if(|d| **d == i).is_some() {
+                        out.add(v.clone());
+                    }
+                }
+            }
+            out.finish()
+        })
+    }).collect()
+}
+
+// Pairs every value with a salt, shared by the values of a partition, cycling
+// through `salts` salts across the partitions
+fn salt<
+    A: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<A>,
+    Acc: 'static + Accumulator<(usize, A)>
+>(defs: &[Deferred<Col>], acc: Acc, salts: usize) -> Written<Acc, (usize, A)> {
+    batch_apply(defs, move |idx, vs| {
+        let mut out = acc.writer();
+        for v in stream_or_panic(vs).into_iter() {
+            out.add((idx % salts, v));
+        }
+        out.finish()
+    })
+}
+
+// Tags each pair's value with a random number, drawn from the partition's own
+// generator so the tags depend only on the seed and the partitioning
+fn tag_random<
+    K: Any + Send + Sync + Clone,
+    V: Any + Send + Sync + Clone,
+    Col: Any + Send + Sync + Clone + Stream<(K, V)>,
+    Acc: 'static + Accumulator<(K, (u64, V))>
+>(defs: &[Deferred<Col>], acc: Acc, op_id: &'static str, seed: u64) -> Written<Acc, (K, (u64, V))> {
+    batch_apply(defs, move |idx, vs| {
+        let mut rng = partition_rng(seed, op_id, idx);
+        let mut out = acc.writer();
+        for (k, v) in stream_or_panic(vs).into_iter() {
+            out.add((k, (rng.next_u64(), v)));
+        }
+        out.finish()
+    })
+}
+
+// Estimates the share of the pairs held by each key from a uniform sample of
+// `sample_size` pairs, most common first.  Each partition keeps the keys drawing the
+// smallest random numbers, and merging the partitions' samples keeps the smallest
+// of all.
+fn key_shares<
+    K: Any + Send + Sync + Clone + Hash + Eq,
+    V,
+    Col: Any + Send + Sync + Clone + Stream<(K, V)>
+>(defs: &[Deferred<Col>], sample_size: usize, seed: u64) -> Deferred<Vec<(K, f64)>> {
+    let smallest = |x: &(u64, K)| Reverse(x.0);
+    let samples = batch_apply(defs, move |idx, vs| {
+        let mut rng = partition_rng(seed, "key_skew_report", idx);
+        let mut kept = Vec::new();
+        for (k, _) in stream_or_panic(vs).into_iter() {
+            keep_top(&mut kept, (rng.next_u64(), k), sample_size, &smallest);
+        }
+        kept
+    });
+    let sample = tree_reduce(&samples, move |left, right| {
+        let mut merged = left.clone();
+        for x in right.iter() {
+            keep_top(&mut merged, x.clone(), sample_size, &smallest);
+        }
+        merged
+    }).unwrap_or_else(|| Deferred::lift(Vec::new(), None));
+
+    sample.apply(|sample| {
+        let mut counts: HashMap<K, usize> = HashMap::new();
+        for (_, k) in sample.iter() {
+            *counts.entry(k.clone()).or_insert(0) += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by_key(|x| Reverse(x.1));
+        counts.into_iter().map(|(k, c)| (k, c as f64 / sample.len() as f64)).collect()
+    })
+}
+
+// Where `rebalance_by_key_sample` sends each key.  A key holding more than a
+// partition's share of the pairs gets partitions of its own, as many as its share
+// fills, and its pairs are dealt across them.  The remaining keys are hashed over
+// the partitions left, of which there is always at least one.
+struct KeyRoutes<K> {
+    heavy: HashMap<K, (usize, usize)>,
+    light: (usize, usize)
+}
+
+impl <K: Clone + Hash + Eq> KeyRoutes<K> {
+    fn new(shares: &[(K, f64)], partitions: usize) -> Self {
+        let mut heavy = HashMap::new();
+        let mut next = 0;
+        for (k, share) in shares.iter() {
+            if *share * partitions as f64 <= 1.0 {
+                break;
+            }
+            let n = ((*share * partitions as f64).round() as usize).min(partitions - 1 - next);
+            if n == 0 {
+                break;
+            }
+            heavy.insert(k.clone(), (next, n));
+            next += n;
+        }
+        KeyRoutes { heavy, light: (next, partitions - next) }
+    }
+
+    // Returns the partition of the `idx`th pair of partition `part`
+    fn route(&self, key: &K, part: usize, idx: usize) -> usize {
+        match self.heavy.get(key) {
+            Some(&(start, n)) => start + hash_key(&(part, idx)) as usize % n,
+            None              => self.light.0 + hash_key(key) as usize % self.light.1
+        }
+    }
+}
+
+// Pairs the values of each partition with those of the matching partition of
+// `right`, stopping at the end of the shorter
+fn zip<
+    A, B,
+    ColA: Any + Send + Sync + Clone + Stream<A>,
+    ColB: Any + Send + Sync + Clone + Stream<B>,
+    Acc: 'static + Accumulator<(A, B)>
+>(left: &[Deferred<ColA>], right: &[Deferred<ColB>], acc: Acc) -> Written<Acc, (A, B)> 
+    where <Acc::VW as ValueWriter<(A, B)>>::Out: Any
+{
+    left.iter().zip(right.iter()).map(|(l, r)| {
+        let acc = acc.clone();
+        l.join(r, move |ls, rs| {
+            let mut out = acc.writer();
+            for pair in stream_or_panic(ls).into_iter().zip(stream_or_panic(rs)) {
+                out.add(pair);
+            }
+            out.finish()
+        })
+    }).collect()
+}
+
+// Splits the pairs in each partition into their first and second halves, reading
+// every pair once.  The halves are returned as two sets of partitions, in the
+// order of the inputs.
+fn unzip<
+    A, B,
+    Col: Any + Send + Sync + Clone + Stream<(A, B)>,
+    AccA: 'static + Accumulator<A>,
+    AccB: 'static + Accumulator<B>
+>(
+    defs: &[Deferred<Col>], 
+    firsts: AccA, 
+    seconds: AccB
+) -> (Written<AccA, A>, Written<AccB, B>) 
+    where <AccA::VW as ValueWriter<A>>::Out: Any,
+          <AccB::VW as ValueWriter<B>>::Out: Any
+{
+    let both = batch_apply(defs, move |_idx, vs| {
+        let (mut a_out, mut b_out) = (firsts.writer(), seconds.writer());
+        for (a, b) in stream_or_panic(vs).into_iter() {
+            a_out.add(a);
+            b_out.add(b);
+        }
+        (a_out.finish(), b_out.finish())
+    });
+    let a_parts = both.iter().map(|d| d.apply(|p| p.0.clone())).collect();
+    let b_parts = both.iter().map(|d| d.apply(|p| p.1.clone())).collect();
+    (a_parts, b_parts)
+}
+
+// A value along with its partition and its place within the partition
+type Ordered<V> = ((usize, usize), V);
+
+// Partitions written by an Accumulator
+pub(crate) type Written<Acc, A> = Vec<Deferred<<<Acc as Accumulator<A>>::VW as ValueWriter<A>>::Out>>;
+
+// Routes the Ok values in each partition to one Accumulator and the Err values to
+// another, reading every value once.  The pieces are returned as two sets of
+// partitions, in the order of the inputs.
+fn split_results<
+    T, E,
+    Col: Any + Send + Sync + Clone + Stream<Result<T, E>>,
+    AccT: 'static + Accumulator<T>,
+    AccE: 'static + Accumulator<E>
+>(
+    defs: &[Deferred<Col>], 
+    oks: AccT, 
+    errs: AccE
+) -> (Written<AccT, T>, Written<AccE, E>) 
+    where <AccT::VW as ValueWriter<T>>::Out: Any,
+          <AccE::VW as ValueWriter<E>>::Out: Any
+{
+    let both = batch_apply(defs, move |_idx, vs| {
+        let (mut ok_out, mut err_out) = (oks.writer(), errs.writer());
+        for v in stream_or_panic(vs).into_iter() {
+            match v {
+                Ok(t)  => ok_out.add(t),
+                Err(e) => err_out.add(e)
+            }
+        }
+        (ok_out.finish(), err_out.finish())
+    });
+    let ok_parts = both.iter().map(|d| d.apply(|p| p.0.clone())).collect();
+    let err_parts = both.iter().map(|d| d.apply(|p| p.1.clone())).collect();
+    (ok_parts, err_parts)
+}
+
+
+/// How keyed operations, like `join_on` and `fold_by`, spread their output over
+/// partitions.  Counts convert into `HashInto`, so passing `4` hashes keys into
+/// four partitions.  The default is `Preserve`.
+///
+/// Collections partitioned with `partition_by_key` remember the key they were
+/// partitioned by, as long as it captures nothing: a fn, or a closure kept in a
+/// variable and passed to each operation.  Keyed operations given the same key, and
+/// hashing into the same number of partitions, find every record where the shuffle
+/// would send it, and skip the shuffle.  Operations changing the records, like
+/// `map` or `filter`, forget the key.
+/// ```rust
+///   extern crate tange;
+///   extern crate tange_collection;
+///   use tange::scheduler::GreedyScheduler;
+///   use tange_collection::collection::Partitioning;
+///   use tange_collection::collection::memory::MemoryCollection;
+///   
+///   let col = MemoryCollection::from_vec_chunked((0..100usize).collect(), 4);
+///   let key = |x: &usize| x % 10;
+///   let folded = col.fold_by(key, || 0, |acc, _| *acc += 1, |x, y| *x += y, Partitioning::Preserve);
+///   assert_eq!(folded.n_partitions(), 4);
+///   assert_eq!(col.fold_by(key, || 0, |acc, _| *acc += 1, |x, y| *x += y, 3).n_partitions(), 3);
+///
+///   // Records are already where the fold would send them
+///   let hashed = col.partition_by_key(4, key);
+///   let folded = hashed.fold_by(key, || 0, |acc, _| *acc += 1, |x, y| *x += y, Partitioning::Preserve);
+///   assert!(folded.explain().ends_with("fold_by           element-wise  4           1\n"));
+///   assert_eq!(folded.count().run(&GreedyScheduler::new()), Some(vec![10]));
+/// ```
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub enum Partitioning {
+    /// Hashes keys into this many partitions, or one if given zero
+    HashInto(usize),
+
+    /// Hashes keys into as many partitions as the input has, or for joins, as
+    /// many as the left input has
+    #[default]
+    Preserve,
+
+    /// Gathers every key into a single partition
+    Single
+}
+
+impl Partitioning {
+    /// Returns the number of partitions to hash into, given the number the input has
+    pub fn count(self, input: usize) -> usize {
+        match self {
+            Partitioning::HashInto(n) => n.max(1),
+            Partitioning::Preserve    => input.max(1),
+            Partitioning::Single      => 1
+        }
+    }
+}
+
+impl From<usize> for Partitioning {
+    fn from(n: usize) -> Self {
+        Partitioning::HashInto(n)
+    }
+}
+
+// Identifies a key function by its type, if it captures nothing, since calling two
+// such functions of the same type always gives the same key
+fn key_id<F: 'static>(_key: &F) -> Option<TypeId> {
+    if mem::size_of::<F>() == 0 {
+        Some(TypeId::of::<F>())
+    } else {
+        None
+    }
+}
+
+/// How a stage moves records between partitions
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub(crate) enum StageKind {
+    /// Creates records, reading nothing from other stages
+    Source,
+
+    /// Transforms each partition independently of the others
+    ElementWise,
+
+    /// Moves records between partitions, so every output partition may depend on
+    /// every input partition
+    Shuffle,
+
+    /// Lays the partitions of several collections side by side
+    Union,
+
+    /// Combines every partition into a single value
+    Reduce,
+
+    /// Writes records out of the collection
+    Sink
+}
+
+impl StageKind {
+    fn label(self) -> &'static str {
+        match self {
+            StageKind::Source      => "source",
+            StageKind::ElementWise => "element-wise",
+            StageKind::Shuffle     => "shuffle",
+            StageKind::Union       => "union",
+            StageKind::Reduce      => "reduce",
+            StageKind::Sink        => "sink"
+        }
+    }
+}
+
+// Describes the stage that produced a collection, and through its inputs, the
+// stages before it.  Collections carry one alongside their partitions so they can
+// explain themselves without walking the Deferred graph.
+#[derive(Clone,Debug)]
+pub(crate) struct PlanNode {
+    op: String,
+    name: Option<String>,
+    kind: StageKind,
+    partitions: usize,
+    inputs: Vec<Arc<PlanNode>>,
+    // Key function the stage's output was hashed by, if one was recognized
+    hashed_by: Option<TypeId>
+}
+
+impl PlanNode {
+    fn new(op: &str, kind: StageKind, partitions: usize, inputs: Vec<Arc<PlanNode>>) -> Arc<PlanNode> {
+        Arc::new(PlanNode { op: op.into(), name: None, kind, partitions, inputs, hashed_by: None })
+    }
+
+    // Copies the node, noting that its records were hashed by `key` into its partitions
+    fn hashed(&self, key: Option<TypeId>) -> Arc<PlanNode> {
+        Arc::new(PlanNode { hashed_by: key, ..self.clone() })
+    }
+
+    // Whether hashing the records by `key` into `partitions` would leave each where it is
+    fn is_hashed(&self, key: Option<TypeId>, partitions: usize) -> bool {
+        key.is_some() && self.hashed_by == key && self.partitions == partitions
+    }
+
+    // Copies the node under a different operation, dropping any name it was given
+    fn relabeled(&self, op: &str) -> Arc<PlanNode> {
+        Arc::new(PlanNode { op: op.into(), name: None, ..self.clone() })
+    }
+
+    fn renamed(&self, name: &str) -> Arc<PlanNode> {
+        Arc::new(PlanNode { name: Some(name.into()), ..self.clone() })
+    }
+
+    // Lists this stage and every stage before it, inputs first, in a table with one
+    // row per stage.
+    fn explain(&self) -> String {
+        let (stages, index) = self.stages();
+
+        let header: Vec<String> = ["stage", "operation", "kind", "partitions", "inputs"]
+            .iter().map(|h| h.to_string()).collect();
+        let rows: Vec<Vec<String>> = stages.iter().enumerate().map(|(i, stage)| {
+            let op = match stage.name {
+                Some(ref name) => format!("{} ({})", stage.op, name),
+                None           => stage.op.clone()
+            };
+            let inputs: Vec<_> = stage.inputs.iter()
+                .map(|n| index[&(&**n as *const PlanNode)].to_string())
+                .collect();
+            vec![i.to_string(), op, stage.kind.label().into(), stage.partitions.to_string(), inputs.join(",")]
+        }).collect();
+
+        let mut widths: Vec<_> = header.iter().map(|h| h.len()).collect();
+        for row in rows.iter() {
+            for (w, cell) in widths.iter_mut().zip(row.iter()) {
+                *w = (*w).max(cell.len());
+            }
+        }
+
+        let mut out = String::new();
+        write_row(&mut out, &widths, &header, "");
+        for (row, stage) in rows.iter().zip(stages.iter()) {
+            let note = if stage.is_bottleneck() { "<- single partition" } else { "" };
+            write_row(&mut out, &widths, row, note);
+        }
+        out
+    }
+
+    // Measures the plan, along with the steps leading to the collection's partitions
+    fn stats(&self, nodes: &[Node]) -> PlanStats {
+        let (stages, index) = self.stages();
+        let stages: Vec<_> = stages.into_iter().map(|stage| StageStats {
+            op: stage.op.clone(),
+            name: stage.name.clone(),
+            kind: stage.kind.label(),
+            tasks: stage.partitions,
+            inputs: stage.inputs.iter().map(|n| index[&(&**n as *const PlanNode)]).collect(),
+            bottleneck: stage.is_bottleneck()
+        }).collect();
+
+        let mut depths = HashMap::new();
+        PlanStats {
+            tasks: count_steps(nodes),
+            depth: nodes.iter().map(|n| plan_depth(n, &mut depths)).max().unwrap_or(0),
+            bottlenecks: stages.iter().filter(|s| s.bottleneck).count(),
+            stages
+        }
+    }
+
+    // Returns this stage and every stage before it, inputs first, with the position
+    // of each
+    fn stages(&self) -> (Vec<&PlanNode>, HashMap<*const PlanNode, usize>) {
+        let mut stages = Vec::new();
+        let mut index = HashMap::new();
+        self.collect_stages(&mut stages, &mut index);
+        (stages, index)
+    }
+
+    fn collect_stages<'a>(&'a self, stages: &mut Vec<&'a PlanNode>, index: &mut HashMap<*const PlanNode, usize>) {
+        if index.contains_key(&(self as *const PlanNode)) {
+            return;
+        }
+        for input in self.inputs.iter() {
+            input.collect_stages(stages, index);
+        }
+        index.insert(self as *const PlanNode, stages.len());
+        stages.push(self);
+    }
+
+    // A stage funneling several partitions into one runs without parallelism
+    fn is_bottleneck(&self) -> bool {
+        self.partitions == 1 && self.inputs.iter().map(|n| n.partitions).sum::<usize>() > 1
+    }
+}
+
+/// The shape of the plan behind a collection, as returned by `dry_run`, found
+/// without running any of it.
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct PlanStats {
+    /// Number of distinct Deferred steps leading to the collection's partitions,
+    /// each of which runs as a task
+    pub tasks: usize,
+
+    /// Number of steps in the longest chain leading to any partition
+    pub depth: usize,
+
+    /// Every stage the collection is built from, inputs first, as listed by
+    /// `explain`
+    pub stages: Vec<StageStats>,
+
+    /// Number of stages funneling several partitions into one
+    pub bottlenecks: usize
+}
+
+/// One stage of a collection's plan, as listed in `PlanStats`
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct StageStats {
+    /// Operation that created the stage, like "map" or "fold_by"
+    pub op: String,
+
+    /// Name given to the stage with `named`, if any
+    pub name: Option<String>,
+
+    /// How the stage moves records between partitions: "source", "element-wise",
+    /// "shuffle", "union", "reduce" or "sink"
+    pub kind: &'static str,
+
+    /// Number of tasks producing the stage's output, one per partition
+    pub tasks: usize,
+
+    /// Positions in `PlanStats::stages` of the stages this one reads from
+    pub inputs: Vec<usize>,
+
+    /// Whether the stage funnels several partitions into one, and so runs without
+    /// any parallelism
+    pub bottleneck: bool
+}
+
+// Counts the distinct steps leading to any of the nodes, including the nodes
+fn count_steps(nodes: &[Node]) -> usize {
+    let mut seen = HashSet::new();
+    let mut stack = nodes.to_vec();
+    while let Some(node) = stack.pop() {
+        if seen.insert(node.id()) {
+            stack.append(&mut node.inputs());
+        }
+    }
+    seen.len()
+}
+
+fn write_row(out: &mut String, widths: &[usize], cells: &[String], note: &str) {
+    let mut line = String::new();
+    for (w, cell) in widths.iter().zip(cells.iter()) {
+        line.push_str(&format!("{:width$}  ", cell, width = *w));
+    }
+    line.push_str(note);
+    out.push_str(line.trim_end());
+    out.push('\n');
+}
+
+/// A collection which `run_all` can evaluate alongside others
+pub trait Evaluate {
+    /// Type of the collection's elements
+    type Item: Any + Send + Sync + Clone;
+
+    /// Returns a Deferred holding every element of the collection, in order
+    fn gathered(&self) -> Deferred<Vec<Self::Item>>;
+}
+
+/// Collections `run_all` evaluates together: a pair of collections, each of any
+/// type, or a slice of collections of the same type.
+pub trait RunAll {
+    /// What running the collections returns
+    type Output: Any + Send + Sync + Clone;
+
+    /// Returns a Deferred holding the elements of every collection
+    fn combined(self) -> Deferred<Self::Output>;
+}
+
+impl <'a, X: Evaluate, Y: Evaluate> RunAll for (&'a X, &'a Y) {
+    type Output = (Vec<X::Item>, Vec<Y::Item>);
+
+    fn combined(self) -> Deferred<Self::Output> {
+        self.0.gathered().join(&self.1.gathered(), |x, y| (x.clone(), y.clone()))
+    }
+}
+
+impl <X: Evaluate> RunAll for &[X] {
+    type Output = Vec<Vec<X::Item>>;
+
+    fn combined(self) -> Deferred<Self::Output> {
+        let defs: Vec<_> = self.iter().map(|c| c.gathered()).collect();
+        gather(&defs)
+    }
+}
+
+impl <X: Evaluate> RunAll for &Vec<X> {
+    type Output = Vec<Vec<X::Item>>;
+
+    fn combined(self) -> Deferred<Self::Output> {
+        self.as_slice().combined()
+    }
+}
+
+/// Executes several collections in a single pass, returning all of their results
+/// together.  Stages the collections share are computed only once, rather than
+/// once for each collection as running them separately would.
+/// ```rust
+///   extern crate tange;
+///   extern crate tange_collection;
+///   use tange::scheduler::GreedyScheduler;
+///   use tange_collection::collection::run_all;
+///   use tange_collection::collection::memory::MemoryCollection;
+///   
+///   let words = MemoryCollection::from_vec(vec!["a", "b", "a"]).map(|w| w.to_string());
+///   let counts = words.frequencies(1);
+///   let (total, freqs) = run_all(&GreedyScheduler::new(), (&words.count(), &counts)).unwrap();
+///   assert_eq!(total, vec![3]);
+///   assert_eq!(freqs.len(), 2);
+///
+///   let lengths = vec![words.count(), words.filter(|w| w == "a").count()];
+///   assert_eq!(run_all(&GreedyScheduler::new(), &lengths), Some(vec![vec![3], vec![2]]));
+/// ```
+pub fn run_all<S: Scheduler, R: RunAll>(s: &S, collections: R) -> Option<R::Output> {
+    collections.combined().run(s)
+}
+
+/// Integers whose products may overflow, multiplied by `checked_product` and
+/// `wrapping_product`
+pub trait Integer: Copy {
+    /// The multiplicative identity
+    const ONE: Self;
+
+    /// Multiplies, returning None on overflow
+    fn checked_mul(self, other: Self) -> Option<Self>;
+
+    /// Multiplies, wrapping around on overflow
+    fn wrapping_mul(self, other: Self) -> Self;
+}
+
+macro_rules! impl_integer {
+    ($($t:ty),*) => {$(
+        impl Integer for $t {
+            const ONE: $t = 1;
+            fn checked_mul(self, other: $t) -> Option<$t> { <$t>::checked_mul(self, other) }
+            fn wrapping_mul(self, other: $t) -> $t { <$t>::wrapping_mul(self, other) }
+        }
+    )*}
+}
+
+impl_integer!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize);
+
+// Reads the cancellation out of a failed run, raising a failed task's panic again
+// for the callers which don't return task errors.
+fn cancelled_or_panic(e: RunError) -> Cancelled {
+    match e {
+        RunError::Cancelled(c) => c,
+        RunError::Failed(e)    => panic!("{}", e)
+    }
+}
+
+// Generates the name of a stage that wasn't given one, like "map#3"
+fn stage_name(op: &str) -> String {
+    format!("{}#{}", op, STAGES.fetch_add(1, Ordering::SeqCst))
+}
+
+// Writes out the shape of the plan behind a collection: its number of partitions,
+// the longest chain of steps leading to any of them, and the steps leading to the
+// first partition as an indented tree.
+fn fmt_plan(f: &mut fmt::Formatter, kind: &str, nodes: &[Node]) -> fmt::Result {
+    let mut depths = HashMap::new();
+    let depth = nodes.iter().map(|n| plan_depth(n, &mut depths)).max().unwrap_or(0);
+    write!(f, "{} {{ partitions: {}, depth: {} }}", kind, nodes.len(), depth)?;
+    match nodes.first() {
+        Some(node) => fmt_node(f, node, 1, 1),
+        None       => Ok(())
+    }
+}
+
+fn plan_depth(node: &Node, depths: &mut HashMap<usize, usize>) -> usize {
+    if let Some(depth) = depths.get(&node.id()) {
+        return *depth;
+    }
+    let depth = 1 + node.inputs().iter().map(|n| plan_depth(n, depths)).max().unwrap_or(0);
+    depths.insert(node.id(), depth);
+    depth
+}
+
+fn fmt_node(f: &mut fmt::Formatter, node: &Node, count: usize, level: usize) -> fmt::Result {
+    write!(f, "\n{:indent$}{}", "", node.name(), indent = level * 2)?;
+    if count > 1 {
+        write!(f, " x{}", count)?;
+    }
+    let inputs = node.inputs();
+    if !inputs.is_empty() && level == PLAN_DEPTH {
+        return write!(f, "\n{:indent$}...", "", indent = (level + 1) * 2);
+    }
+
+    // Runs of inputs with the same name, like both sides of a reduction, are
+    // written once
+    let mut i = 0;
+    while i < inputs.len() {
+        let run = inputs[i..].iter().take_while(|n| n.name() == inputs[i].name()).count();
+        fmt_node(f, &inputs[i], run, level + 1)?;
+        i += run;
+    }
+    Ok(())
+}
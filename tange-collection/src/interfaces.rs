@@ -3,11 +3,17 @@ extern crate serde;
 extern crate bincode;
 extern crate uuid;
 extern crate snap;
+#[cfg(feature = "memmap")]
+extern crate memmap;
 
-use std::any::Any;
-use std::fs::{File,remove_file,create_dir_all};
-use std::io::{BufReader,BufWriter};
+use std::any::{Any,type_name};
+use std::error;
+use std::fmt;
+use std::fs::{File,create_dir_all,rename};
+use std::io::{self,BufRead,BufReader,BufWriter,Cursor,Read,Write};
+use std::iter::{Empty,empty};
 use std::marker::PhantomData;
+use std::mem;
 use std::sync::Arc;
 
 use self::snap::{Writer,Reader};
@@ -15,6 +21,9 @@ use self::serde::{Serialize,Deserialize};
 use self::bincode::{serialize_into, deserialize_from,ErrorKind};
 use self::uuid::Uuid;
 
+use collection::key_dir;
+use store::{ObjectStore,LocalFs};
+
 /// Accumulators are object which can create 'Writers', using effectively the Builder
 /// pattern
 pub trait Accumulator<A>: Send + Sync + Clone  {
@@ -51,10 +60,66 @@ pub trait ValueWriter<A>: Sized {
         }
     }
 
+    /// Add an element to the ValueWriter, returning an error rather than panicking
+    /// if it can't be written.  Infallible writers can rely on the default, which
+    /// calls `add`.
+    fn try_add(&mut self, item: A) -> Result<(), WriteError> {
+        self.add(item);
+        Ok(())
+    }
+
+    /// Pushes any buffered values to the underlying storage
+    fn flush(&mut self) -> Result<(), WriteError> {
+        Ok(())
+    }
+
+    /// Number of bytes handed to the underlying storage so far, if the writer
+    /// tracks it.  Writers may buffer, so this can lag behind what has been added
+    /// until `flush` is called.
+    fn bytes_written(&self) -> Option<u64> {
+        None
+    }
+
     /// Close the ValueWriter, returning the store
     fn finish(self) -> Self::Out;
 }
 
+/// Errors surfaced by fallible ValueWriter methods
+#[derive(Debug)]
+pub enum WriteError {
+    /// The underlying storage failed
+    Io(io::Error),
+
+    /// A value couldn't be serialized
+    Serialize(String)
+}
+
+impl fmt::Display for WriteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WriteError::Io(e) => write!(f, "Failed writing records: {}", e),
+            WriteError::Serialize(e) => write!(f, "Failed serializing record: {}", e)
+        }
+    }
+}
+
+impl error::Error for WriteError {}
+
+impl From<io::Error> for WriteError {
+    fn from(e: io::Error) -> Self {
+        WriteError::Io(e)
+    }
+}
+
+impl From<bincode::Error> for WriteError {
+    fn from(e: bincode::Error) -> Self {
+        match *e {
+            ErrorKind::Io(e) => WriteError::Io(e),
+            e => WriteError::Serialize(e.to_string())
+        }
+    }
+}
+
 /// Defines an Accumulator that writes values in memory, using Vec as the store.
 #[derive(Clone)]
 pub struct Memory;
@@ -96,10 +161,80 @@ pub trait Stream<A> {
     /// Returns an iterator with owned values.
     fn stream(&self) -> Self::Iter;
 
+    /// Returns an iterator with owned values, or an error if the store can't be
+    /// read.  Infallible stores can rely on the default, which calls `stream`.
+    fn try_stream(&self) -> Result<Self::Iter, StreamError> {
+        Ok(self.stream())
+    }
+
     /// Returns a copy of the store.
     fn copy(&self) -> Self;
 }
 
+/// Errors surfaced when a Store can't be read
+#[derive(Debug)]
+pub enum StreamError {
+    /// The underlying object no longer exists
+    Missing {
+        /// Location of the object
+        path: String,
+        /// Error returned when opening it
+        cause: io::Error
+    },
+
+    /// The underlying object is shorter than what was written
+    Truncated {
+        /// Location of the object
+        path: String,
+        /// Number of bytes written
+        expected: u64,
+        /// Number of bytes found
+        found: u64
+    },
+
+    /// The records couldn't be decoded as the requested type, such as when they
+    /// were written by a different version of the job
+    WrongType {
+        /// Location of the object
+        path: String,
+        /// Type the records were decoded as
+        type_name: &'static str,
+        /// Description of the decoding failure
+        cause: String
+    },
+
+    /// The underlying object can't be read, or its contents are damaged
+    Corrupt {
+        /// Location of the object
+        path: String,
+        /// Description of the failure
+        cause: String
+    }
+}
+
+impl fmt::Display for StreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StreamError::Missing { path, cause } => 
+                write!(f, "Records at {} are missing: {}", path, cause),
+            StreamError::Truncated { path, expected, found } => 
+                write!(f, "Records at {} are truncated: expected {} bytes, found {}", 
+                       path, expected, found),
+            StreamError::WrongType { path, type_name, cause } =>
+                write!(f, "Records at {} can't be read as {}: {}", path, type_name, cause),
+            StreamError::Corrupt { path, cause } =>
+                write!(f, "Records at {} are corrupt: {}", path, cause)
+        }
+    }
+}
+
+impl error::Error for StreamError {}
+
+// Streams a store from within a task, where there's no way yet to return the error
+pub(crate) fn stream_or_panic<A, S: Stream<A>>(s: &S) -> S::Iter {
+    s.try_stream().unwrap_or_else(|e| panic!("{}", e))
+}
+
 impl <A: Clone> Stream<A> for Vec<A> {
     type Iter = Vec<A>;
 
@@ -112,6 +247,184 @@ impl <A: Clone> Stream<A> for Vec<A> {
     }
 }
 
+/// Stores which can be concatenated directly, without streaming each value through
+/// a new ValueWriter.
+pub trait Merge: Sized {
+    /// Returns a store containing the values of `self` followed by those of `other`
+    fn merge(&self, other: &Self) -> Self;
+}
+
+impl <A: Clone> Merge for Vec<A> {
+    fn merge(&self, other: &Self) -> Self {
+        let mut out = Vec::with_capacity(self.len() + other.len());
+        out.extend_from_slice(self);
+        out.extend_from_slice(other);
+        out
+    }
+}
+
+/// Accumulator which writes every value to two Accumulators at once, such as to
+/// memory for the next stage and to disk as a checkpoint.  The output is a Tee of
+/// the two outputs, which streams from the first.
+#[derive(Clone)]
+pub struct Tee<A1, A2>(pub A1, pub A2);
+
+/// ValueWriter for Tee, fanning each value out to both underlying writers
+pub struct TeeWriter<W1, W2>(W1, W2);
+
+impl <A: Clone, A1: Accumulator<A>, A2: Accumulator<A>> Accumulator<A> for Tee<A1, A2> {
+    type VW = TeeWriter<A1::VW, A2::VW>;
+
+    fn writer(&self) -> Self::VW {
+        TeeWriter(self.0.writer(), self.1.writer())
+    }
+}
+
+impl <A: Clone, W1: ValueWriter<A>, W2: ValueWriter<A>> ValueWriter<A> for TeeWriter<W1, W2> {
+    type Out = Tee<W1::Out, W2::Out>;
+
+    fn add(&mut self, item: A) {
+        self.1.add(item.clone());
+        self.0.add(item);
+    }
+
+    fn try_add(&mut self, item: A) -> Result<(), WriteError> {
+        self.1.try_add(item.clone())?;
+        self.0.try_add(item)
+    }
+
+    fn flush(&mut self) -> Result<(), WriteError> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        match (self.0.bytes_written(), self.1.bytes_written()) {
+            (None, None) => None,
+            (l, r) => Some(l.unwrap_or(0) + r.unwrap_or(0))
+        }
+    }
+
+    fn finish(self) -> Self::Out {
+        Tee(self.0.finish(), self.1.finish())
+    }
+}
+
+impl <A, S1: Stream<A>, S2: Stream<A>> Stream<A> for Tee<S1, S2> {
+    type Iter = S1::Iter;
+
+    fn stream(&self) -> Self::Iter {
+        self.0.stream()
+    }
+
+    fn try_stream(&self) -> Result<Self::Iter, StreamError> {
+        self.0.try_stream()
+    }
+
+    fn copy(&self) -> Self {
+        Tee(self.0.copy(), self.1.copy())
+    }
+}
+
+type Sizer<A> = Option<Arc<dyn Fn(&A) -> usize + Send + Sync>>;
+
+/// Accumulator which discards values, only keeping track of how many were written
+/// and, optionally, how many bytes they hold.  Useful for validating a pipeline
+/// without paying to store its output.
+pub struct Counting<A>(Sizer<A>);
+
+impl <A> Counting<A> {
+    /// Creates a Counting accumulator which only counts items
+    pub fn new() -> Self {
+        Counting(None)
+    }
+
+    /// Creates a Counting accumulator which uses `f` to measure the size of each item
+    pub fn with_size<F: 'static + Send + Sync + Fn(&A) -> usize>(f: F) -> Self {
+        Counting(Some(Arc::new(f)))
+    }
+}
+
+impl <A: AsRef<[u8]>> Counting<A> {
+    /// Creates a Counting accumulator which tracks the number of bytes in each item
+    pub fn bytes() -> Self {
+        Counting::with_size(|a: &A| a.as_ref().len())
+    }
+}
+
+impl <A> Default for Counting<A> {
+    fn default() -> Self {
+        Counting::new()
+    }
+}
+
+impl <A> Clone for Counting<A> {
+    fn clone(&self) -> Self {
+        Counting(self.0.clone())
+    }
+}
+
+/// Tally of the values written through a Counting accumulator.  Streaming a Counts
+/// yields nothing.
+pub struct Counts<A> {
+    /// Number of items written
+    pub items: usize,
+
+    /// Total size of the items written, or 0 if no size function was provided
+    pub bytes: usize,
+
+    sizer: Sizer<A>
+}
+
+impl <A> Clone for Counts<A> {
+    fn clone(&self) -> Self {
+        Counts { items: self.items, bytes: self.bytes, sizer: self.sizer.clone() }
+    }
+}
+
+impl <A: Send + Sync> Accumulator<A> for Counting<A> {
+    type VW = Counts<A>;
+
+    fn writer(&self) -> Self::VW {
+        Counts { items: 0, bytes: 0, sizer: self.0.clone() }
+    }
+}
+
+impl <A: Send + Sync> Accumulator<A> for Counts<A> {
+    type VW = Counts<A>;
+
+    fn writer(&self) -> Self::VW {
+        Counts { items: 0, bytes: 0, sizer: self.sizer.clone() }
+    }
+}
+
+impl <A: Send + Sync> ValueWriter<A> for Counts<A> {
+    type Out = Counts<A>;
+
+    fn add(&mut self, item: A) {
+        self.items += 1;
+        if let Some(ref f) = self.sizer {
+            self.bytes += f(&item);
+        }
+    }
+
+    fn finish(self) -> Self::Out {
+        self
+    }
+}
+
+impl <A> Stream<A> for Counts<A> {
+    type Iter = Empty<A>;
+
+    fn stream(&self) -> Self::Iter {
+        empty()
+    }
+
+    fn copy(&self) -> Self {
+        self.clone()
+    }
+}
+
 /// Writes values to a directory
 #[derive(Clone)]
 pub struct Disk(pub Arc<String>);
@@ -123,38 +436,270 @@ impl Disk {
     }
 }
 
+impl Disk {
+    /// Returns an Accumulator which names files after their content, sharing them
+    /// across runs of the same job.
+    pub fn content_addressed(&self) -> ContentAddressed {
+        let p: &str = &self.0;
+        ContentAddressed(Arc::new(LocalFs::new(p)))
+    }
+}
+
+/// Writes values into an arbitrary ObjectStore, such as a MemoryStore or a user
+/// provided wrapper around a cloud bucket.
+#[derive(Clone)]
+pub struct Store(pub Arc<dyn ObjectStore>);
+
+impl Store {
+    /// Creates a writer for an object with exactly the given name, replacing any
+    /// existing object.  The object is created even if no records are added, and
+    /// is kept when its FileStore is dropped, making it suitable for final output.
+    pub fn writer_named<A>(&self, name: &str) -> DiskBuffer<A> {
+        DiskBuffer::with_naming(self.0.clone(), Naming::Exact(name.into()))
+    }
+}
+
+/// Writes values into an ObjectStore under a name derived from a hash of their
+/// serialized bytes.  If an object with the same name and length already exists,
+/// it's reused instead of stored again, allowing identical intermediate results
+/// to be shared between runs.  Objects written this way are kept when their
+/// FileStore is dropped.
+#[derive(Clone)]
+pub struct ContentAddressed(pub Arc<dyn ObjectStore>);
+
+impl ContentAddressed {
+    /// Creates a writer whose object is named after `key` rather than its content.
+    /// If a complete object for the key already exists, values added to the writer
+    /// are counted but not written, so the key must uniquely identify the data.
+    /// Objects are only complete once moved into place and their length recorded,
+    /// so one left behind by a failed run is written again.  Path separators in the
+    /// key are replaced, keeping the object within the store.
+    pub fn writer_with_key<A>(&self, key: &str) -> DiskBuffer<A> {
+        DiskBuffer::with_naming(self.0.clone(), Naming::Key(key.into()))
+    }
+}
+
+// How a DiskBuffer names the object it writes
+enum Naming {
+    Random,
+    Content,
+    Key(String),
+    Exact(String)
+}
+
+// Where a DiskBuffer sends its bytes.  Stores backed by local files are streamed to
+// directly; everything else is buffered and handed over in a single put.
+enum Sink {
+    File(BufWriter<File>),
+    Buffer(Vec<u8>)
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::File(ref mut f) => f.write(buf),
+            Sink::Buffer(ref mut b) => b.write(buf)
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::File(ref mut f) => f.flush(),
+            Sink::Buffer(_) => Ok(())
+        }
+    }
+}
+
+impl Sink {
+    fn open(store: &dyn ObjectStore, name: &str) -> io::Result<Sink> {
+        if let Some(path) = store.local_path(name) {
+            if let Some(dir) = path.parent() {
+                create_dir_all(dir)?;
+            }
+            Ok(Sink::File(BufWriter::new(File::create(&path)?)))
+        } else {
+            Ok(Sink::Buffer(Vec::new()))
+        }
+    }
+
+    fn close(self, store: &dyn ObjectStore, name: &str) -> io::Result<()> {
+        match self {
+            Sink::File(mut f) => f.flush(),
+            Sink::Buffer(b) => store.put(name, &b)
+        }
+    }
+}
+
+// Keeps track of the number and FNV-1a hash of the bytes passing through a Write
+struct Tally<W> {
+    inner: W,
+    bytes: u64,
+    hash: u64
+}
+
+impl <W> Tally<W> {
+    fn new(inner: W) -> Self {
+        Tally { inner, bytes: 0, hash: 0xcbf2_9ce4_8422_2325 }
+    }
+}
+
+impl <W: Write> Write for Tally<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.bytes += n as u64;
+        for b in &buf[..n] {
+            self.hash = (self.hash ^ u64::from(*b)).wrapping_mul(0x100_0000_01b3);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Describes where an object lives in error messages: its file, for local stores
+fn object_path(store: &dyn ObjectStore, name: &str) -> String {
+    store.local_path(name)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|| name.to_owned())
+}
+
+// Classifies a failure to decode a record as `A`: damaged bytes fail within the
+// decompressor, anything else means the bytes hold some other type
+fn decode_error<A>(path: String, e: &ErrorKind) -> StreamError {
+    match *e {
+        ErrorKind::Io(ref e) => StreamError::Corrupt { path, cause: e.to_string() },
+        ref e => StreamError::WrongType { path, type_name: type_name::<A>(), cause: e.to_string() }
+    }
+}
+
+// Opens the raw bytes of an object for reading
+fn open_reader(store: &dyn ObjectStore, name: &str) -> io::Result<Box<dyn Read + Send>> {
+    if let Some(path) = store.local_path(name) {
+        Ok(Box::new(BufReader::new(File::open(path)?)))
+    } else {
+        Ok(Box::new(Cursor::new(store.get(name)?)))
+    }
+}
+
+fn new_name() -> String {
+    format!("tange-{}", Uuid::new_v4())
+}
+
+fn key_name(key: &str) -> String {
+    format!("tange-key-{}", key_dir(key))
+}
+
+// Names the object recording the length of a complete keyed object
+fn key_length_name(key: &str) -> String {
+    format!("tange-keylen-{}", key_dir(key))
+}
+
+// Returns the length of the object stored under a key, or None if it's missing or
+// doesn't match the length recorded when it was written
+fn key_length(store: &dyn ObjectStore, key: &str) -> Option<u64> {
+    let recorded = store.get(&key_length_name(key)).ok()?;
+    let recorded: u64 = String::from_utf8(recorded).ok()?.parse().ok()?;
+    store.size(&key_name(key)).ok().filter(|size| *size == recorded)
+}
+
 /// An open buffer for writing records to disk
 pub struct DiskBuffer<A> {
-    root_path: Arc<String>, 
+    store: Arc<dyn ObjectStore>,
     name: String,
+    naming: Naming,
+    count: usize,
     pd: PhantomData<A>,
-    out: Writer<BufWriter<File>>
+    out: Output
+}
+
+// Objects aren't created until the first record arrives, so empty writers leave
+// nothing behind in the store
+enum Output {
+    Pending,
+    Open(Box<Writer<Tally<Sink>>>),
+    // The object already exists under its key
+    Cached
 }
 
 impl <A> DiskBuffer<A> {
-    fn new(path: Arc<String>) -> Self {
-        let name = format!("{}/tange-{}", &path, Uuid::new_v4());
-        {
-            let p: &str = &path;
-            create_dir_all(p).expect("Unable to create directory!");
-        }
-        let fd = File::create(&name).expect("Can't create file!");
-        let bw = BufWriter::new(fd);
-        let encoder = Writer::new(bw);
+    fn new(store: Arc<dyn ObjectStore>) -> Self {
+        DiskBuffer::with_naming(store, Naming::Random)
+    }
+
+    fn with_naming(store: Arc<dyn ObjectStore>, naming: Naming) -> Self {
+        let (name, exists) = match naming {
+            // Keyed objects are written under a temporary name until complete
+            Naming::Key(ref k) => match key_length(&*store, k) {
+                Some(_) => (key_name(k), true),
+                None => (new_name(), false)
+            },
+            Naming::Exact(ref n) => (n.clone(), false),
+            _ => (new_name(), false)
+        };
+        let out = if exists { Output::Cached } else { Output::Pending };
         DiskBuffer { 
-            root_path: path, 
-            name: name, 
+            store, 
+            name, 
+            naming,
+            count: 0,
             pd: PhantomData,
-            out: encoder
+            out
+        }
+    }
+
+    // Moves the written object under its content derived name, or discards it if
+    // an identical object is already stored there
+    fn store_by_content(&self, tally: Tally<Sink>) -> io::Result<String> {
+        let name = format!("tange-{:016x}-{}", tally.hash, tally.bytes);
+        let exists = self.store.size(&name).map(|s| s == tally.bytes).unwrap_or(false);
+        match tally.inner {
+            Sink::File(mut f) => {
+                f.flush()?;
+                drop(f);
+                if exists {
+                    self.store.delete(&self.name)?;
+                } else if let (Some(from), Some(to)) = (self.store.local_path(&self.name), self.store.local_path(&name)) {
+                    rename(from, to)?;
+                }
+            },
+            Sink::Buffer(b) => if !exists {
+                self.store.put(&name, &b)?;
+            }
+        }
+        Ok(name)
+    }
+
+    // Moves the written object under its key, then records its length to mark it
+    // complete
+    fn store_by_key(&self, key: &str, tally: Tally<Sink>) -> io::Result<String> {
+        let name = key_name(key);
+        match tally.inner {
+            Sink::File(mut f) => {
+                f.flush()?;
+                drop(f);
+                if let (Some(from), Some(to)) = (self.store.local_path(&self.name), self.store.local_path(&name)) {
+                    rename(from, to)?;
+                }
+            },
+            Sink::Buffer(b) => self.store.put(&name, &b)?
         }
+        self.store.put(&key_length_name(key), tally.bytes.to_string().as_bytes())?;
+        Ok(name)
     }
 }
 
-/// Contains a root path for storing temporary files
+/// Handle to a set of records written into an ObjectStore.  The records are removed
+/// from the store when the FileStore is dropped, unless they were written by name
+/// through ContentAddressed.
 #[derive(Clone)]
 pub struct FileStore<A: Clone + Send + Sync> {
-    root_path: Arc<String>, 
+    store: Arc<dyn ObjectStore>,
     name: Option<String>,
+    count: usize,
+    bytes: u64,
+    persistent: bool,
     pd: PhantomData<A>
 }
 
@@ -162,20 +707,109 @@ impl <A: Clone + Send + Sync> FileStore<A> {
 
     /// Create an empty FileStore at the given path
     pub fn empty(path: Arc<String>) -> Self {
+        let p: &str = &path;
+        FileStore::empty_in(Arc::new(LocalFs::new(p)))
+    }
+
+    /// Create an empty FileStore backed by the given ObjectStore
+    pub fn empty_in(store: Arc<dyn ObjectStore>) -> Self {
         FileStore {
-            root_path: path,
+            store,
             name: None,
+            count: 0,
+            bytes: 0,
+            persistent: false,
+            pd: PhantomData
+        }
+    }
+
+    /// Opens `count` records previously written to `name` within a store, such as
+    /// by `Store::writer_named` or a bincode sink.  The object is kept when the
+    /// FileStore is dropped.  Empty objects hold no records.
+    pub fn stored(store: Arc<dyn ObjectStore>, name: &str, count: usize) -> io::Result<Self> {
+        let bytes = store.size(name)?;
+        Ok(FileStore {
+            store,
+            name: if bytes > 0 { Some(name.into()) } else { None },
+            count,
+            bytes,
+            persistent: true,
+            pd: PhantomData
+        })
+    }
+
+    /// Returns the number of records held by the FileStore
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    /// Returns true if the FileStore holds no records
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Concatenates the records of two FileStores into a new FileStore.  Records are
+    /// copied as serialized bytes, making the merge IO-bound with no deserialization.
+    /// If either side is empty, a handle to the other side is returned instead.
+    pub fn merge(this: &Arc<Self>, other: &Arc<Self>) -> io::Result<Arc<Self>> {
+        if other.name.is_none() {
+            return Ok(this.clone());
+        } else if this.name.is_none() {
+            return Ok(other.clone());
+        }
+
+        let name = new_name();
+        let mut sink = Sink::open(&*this.store, &name)?;
+        let mut bytes = 0;
+        for fs in &[this, other] {
+            if let Some(ref n) = fs.name {
+                bytes += io::copy(&mut open_reader(&*fs.store, n)?, &mut sink)?;
+            }
+        }
+        sink.close(&*this.store, &name)?;
+        Ok(Arc::new(FileStore {
+            store: this.store.clone(),
+            name: Some(name),
+            count: this.count + other.count,
+            bytes,
+            persistent: false,
             pd: PhantomData
+        }))
+    }
+}
+
+#[cfg(feature = "memmap")]
+impl <A: Clone + Send + Sync + for<'de> Deserialize<'de>> FileStore<A> {
+
+    /// Streams records by memory mapping the underlying file instead of reading it
+    /// through a buffer.  Records are still decoded lazily, but pages are shared
+    /// between all concurrent readers of the same FileStore.  Falls back to the
+    /// buffered reader when the store isn't backed by a local file or the
+    /// mapping fails.
+    pub fn stream_mmap(&self) -> RecordStreamer<A> {
+        if let Some(ref name) = self.name {
+            if let Some(path) = self.store.local_path(name) {
+                // Spill files are private to tange and never modified once written
+                let mapped = File::open(&path).and_then(|f| unsafe { memmap::Mmap::map(&f) });
+                if let Ok(m) = mapped {
+                    let reader: Box<dyn Read + Send> = Box::new(Cursor::new(m));
+                    return RecordStreamer::new(reader, path.display().to_string());
+                }
+            }
         }
+        RecordFile(self.store.clone(), self.name.clone(), PhantomData).into_iter()
     }
 }
 
 // Delete the temporary file on disk when dropped
 impl <A: Clone + Send + Sync> Drop for FileStore<A> {
     fn drop(&mut self) {
+        if self.persistent {
+            return;
+        }
         if let Some(ref name) = self.name {
-            if let Err(e) = remove_file(name) {
-                eprintln!("Error Deleting {}: {:?}J", name, e);
+            if let Err(e) = self.store.delete(name) {
+                eprintln!("Error Deleting {}: {:?}", name, e);
             }
         }
     }
@@ -184,16 +818,33 @@ impl <A: Clone + Send + Sync> Drop for FileStore<A> {
 impl <A: Serialize + Clone + Send + Sync> Accumulator<A> for Disk {
     type VW = DiskBuffer<A>;
 
+    fn writer(&self) -> Self::VW {
+        let p: &str = &self.0;
+        DiskBuffer::new(Arc::new(LocalFs::new(p)))
+    }
+}
+
+impl <A: Serialize + Clone + Send + Sync> Accumulator<A> for Store {
+    type VW = DiskBuffer<A>;
+
     fn writer(&self) -> Self::VW {
         DiskBuffer::new(self.0.clone())
     }
 }
 
+impl <A: Serialize + Clone + Send + Sync> Accumulator<A> for ContentAddressed {
+    type VW = DiskBuffer<A>;
+
+    fn writer(&self) -> Self::VW {
+        DiskBuffer::with_naming(self.0.clone(), Naming::Content)
+    }
+}
+
 impl <A: Serialize + Clone + Send + Sync> Accumulator<A> for Arc<FileStore<A>> {
     type VW = DiskBuffer<A>;
 
     fn writer(&self) -> Self::VW {
-        DiskBuffer::new(self.root_path.clone())
+        DiskBuffer::new(self.store.clone())
     }
 }
 
@@ -201,72 +852,620 @@ impl <A: Serialize + Clone + Send + Sync> ValueWriter<A> for DiskBuffer<A> {
     type Out = Arc<FileStore<A>>;
 
     fn add(&mut self, item: A) -> () {
-        serialize_into(&mut self.out, &item).expect("Couldn't write record!");
+        self.try_add(item).unwrap_or_else(|e| panic!("Couldn't write record: {}", e));
     }
 
-    fn finish(self) -> Self::Out {
+    fn try_add(&mut self, item: A) -> Result<(), WriteError> {
+        if let Output::Pending = self.out {
+            let sink = Sink::open(&*self.store, &self.name)?;
+            self.out = Output::Open(Box::new(Writer::new(Tally::new(sink))));
+        }
+        if let Output::Open(ref mut out) = self.out {
+            serialize_into(out, &item)?;
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<(), WriteError> {
+        if let Output::Open(ref mut out) = self.out {
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    fn bytes_written(&self) -> Option<u64> {
+        match self.out {
+            Output::Open(ref out) => Some(out.get_ref().bytes),
+            _ => Some(0)
+        }
+    }
+
+    fn finish(mut self) -> Self::Out {
+        let (name, bytes) = match mem::replace(&mut self.out, Output::Pending) {
+            Output::Pending => {
+                if let Naming::Exact(_) = self.naming {
+                    // Named objects always exist, even without records
+                    self.store.put(&self.name, &[]).expect("Couldn't store records!");
+                }
+                (None, 0)
+            },
+            Output::Cached => (Some(self.name.clone()), self.store.size(&self.name).unwrap_or(0)),
+            Output::Open(out) => {
+                let tally = out.into_inner()
+                    .unwrap_or_else(|e| panic!("Couldn't flush records: {}", e));
+                let bytes = tally.bytes;
+                let stored = match self.naming {
+                    Naming::Content => self.store_by_content(tally),
+                    Naming::Key(ref k) => self.store_by_key(k, tally),
+                    _ => tally.inner.close(&*self.store, &self.name).map(|_| self.name.clone())
+                };
+                (Some(stored.expect("Couldn't store records!")), bytes)
+            }
+        };
+        let persistent = !matches!(self.naming, Naming::Random);
         Arc::new(FileStore { 
-            root_path: self.root_path.clone(), 
-            name: Some(self.name), 
+            store: self.store, 
+            name, 
+            count: self.count,
+            bytes,
+            persistent,
             pd: PhantomData
         })
     }
 }
 
 
+impl <A: Clone + Send + Sync> Merge for Arc<FileStore<A>> {
+    fn merge(&self, other: &Self) -> Self {
+        FileStore::merge(self, other).expect("Couldn't merge FileStores!")
+    }
+}
+
 impl <A: Clone + Send + Sync + for<'de> Deserialize<'de>> Stream<A> for Arc<FileStore<A>> {
     type Iter = RecordFile<A>;
 
     fn stream(&self) -> Self::Iter {
-        RecordFile(self.name.clone(), PhantomData)
+        RecordFile(self.store.clone(), self.name.clone(), PhantomData)
+    }
+
+    /// Checks that the records exist, have the length they were written with, and
+    /// that the first record decodes as `A` before returning the stream.  Records
+    /// after the first are checked as they're streamed, panicking with the same
+    /// errors if they can't be decoded.
+    fn try_stream(&self) -> Result<Self::Iter, StreamError> {
+        if let Some(ref name) = self.name {
+            let path = object_path(&*self.store, name);
+
+            let found = self.store.size(name)
+                .map_err(|cause| StreamError::Missing { path: path.clone(), cause })?;
+            if found < self.bytes {
+                return Err(StreamError::Truncated { path, expected: self.bytes, found });
+            } else if found > self.bytes {
+                let cause = format!("expected {} bytes, found {}", self.bytes, found);
+                return Err(StreamError::Corrupt { path, cause });
+            }
+
+            let reader = open_reader(&*self.store, name)
+                .map_err(|cause| StreamError::Missing { path: path.clone(), cause })?;
+            if let Err(e) = deserialize_from::<_, A>(Reader::new(reader)) {
+                return Err(decode_error::<A>(path, &e));
+            }
+        }
+        Ok(self.stream())
     }
 
     fn copy(&self) -> Self { self.clone() }
 }
 
 /// Streams records from an optional File.  If the file is none, returns the Empty iterator
-pub struct RecordFile<A>(Option<String>, PhantomData<A>);
+pub struct RecordFile<A>(Arc<dyn ObjectStore>, Option<String>, PhantomData<A>);
 
 impl <A: Clone + Send + Sync + for<'de> Deserialize<'de>> IntoIterator for RecordFile<A> {
     type Item = A;
     type IntoIter = RecordStreamer<A>;
 
     fn into_iter(self) -> Self::IntoIter {
-        if let Some(ref n) = self.0 {
-            let fd = File::open(n).expect("File didn't exist on open!");
-            let brfd = BufReader::new(fd);
-            let decoder = Reader::new(brfd);
-            RecordStreamer(Some(decoder), PhantomData)
+        if let Some(ref n) = self.1 {
+            let reader = open_reader(&*self.0, n).expect("File didn't exist on open!");
+            RecordStreamer::new(reader, object_path(&*self.0, n))
         } else {
-            RecordStreamer(None, PhantomData)
+            RecordStreamer { reader: None, path: String::new(), read: 0, pd: PhantomData }
         }
     }
 }
 
-/// Stream Records from an open file
-pub struct RecordStreamer<A>(Option<Reader<BufReader<File>>>, PhantomData<A>);
+/// Stream Records from an open file.  The stream ends once every record has been
+/// read; a record which can't be decoded, such as one cut short or damaged, panics
+/// with a StreamError rather than quietly ending the stream early.
+pub struct RecordStreamer<A> {
+    reader: Option<BufReader<Reader<Box<dyn Read + Send>>>>,
+    path: String,
+    read: usize,
+    pd: PhantomData<A>
+}
+
+impl <A> RecordStreamer<A> {
+    fn new(reader: Box<dyn Read + Send>, path: String) -> Self {
+        RecordStreamer { reader: Some(BufReader::new(Reader::new(reader))), path, read: 0, pd: PhantomData }
+    }
+
+    // Panics with the error, noting how many records were read before it
+    fn fail(&self, e: StreamError) -> ! {
+        panic!("{} (after {} records)", e, self.read)
+    }
+}
 
 impl <A: Clone + Send + Sync + for<'de> Deserialize<'de>> Iterator for RecordStreamer<A> {
     type Item = A;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(ref mut bw) = self.0 {
-            //deserialize_from(bw).expect("Failure on deserialization!")
-            match deserialize_from(bw) {
-                Ok(record) => Some(record),
-                Err(e) => {
-                    let ek: &ErrorKind = &e;
-                    match ek {
-                        &ErrorKind::DeserializeAnyNotSupported => {
-                            eprintln!("Bincode doesn't work with certain types!");
-                            panic!();
-                        },
-                        _ => None
-                    }
-                }
+        let decoded = {
+            let bw = self.reader.as_mut()?;
+            // Only a stream with no bytes left has ended cleanly
+            match bw.fill_buf() {
+                Ok([]) => None,
+                Ok(_) => Some(deserialize_from(bw).map_err(|e| *e)),
+                Err(e) => Some(Err(ErrorKind::Io(e)))
             }
-        } else {
-            None
+        };
+        match decoded {
+            None => {
+                self.reader = None;
+                None
+            },
+            Some(Ok(record)) => {
+                self.read += 1;
+                Some(record)
+            },
+            Some(Err(ErrorKind::DeserializeAnyNotSupported)) => {
+                eprintln!("Bincode doesn't work with certain types!");
+                panic!();
+            },
+            Some(Err(e)) => self.fail(decode_error::<A>(self.path.clone(), &e))
         }
     }
 }
+
+#[cfg(test)]
+mod test_interfaces {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+    use store::MemoryStore;
+
+    fn read<A: Clone + Send + Sync + for<'de> Deserialize<'de>>(fs: &Arc<FileStore<A>>) -> Vec<A> {
+        fs.stream().into_iter().collect()
+    }
+
+    #[test]
+    fn test_merge() {
+        let disk = Disk::from_str("/tmp");
+        let left = disk.write_vec((0..1000usize).map(|i| format!("left {}", i)).collect());
+        let right = disk.write_vec((0..10usize).map(|i| format!("right {}", i)).collect());
+        let merged = left.merge(&right);
+
+        let mut expected = read(&left);
+        expected.extend_from_slice(&read(&right));
+        assert_eq!(read(&merged), expected);
+        assert_eq!(merged.len(), 1010);
+
+        // Merges nest like any other store
+        let twice = merged.merge(&left);
+        assert_eq!(read(&twice).len(), 2010);
+        assert_eq!(read(&twice)[1010], "left 0");
+    }
+
+    #[test]
+    fn test_merge_empty() {
+        let store: Arc<dyn ObjectStore> = Arc::new(MemoryStore::new());
+        let empty: Arc<FileStore<usize>> = Arc::new(FileStore::empty_in(store.clone()));
+        let full = Store(store).write_vec(vec![1usize, 2, 3]);
+
+        assert!(Arc::ptr_eq(&empty.merge(&full), &full));
+        assert!(Arc::ptr_eq(&full.merge(&empty), &full));
+        assert!(empty.merge(&empty).is_empty());
+    }
+
+    #[test]
+    fn test_merge_object_store() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = Store(ms.clone());
+        let left = acc.write_vec(vec![(1u8, "a".to_owned())]);
+        let right = acc.write_vec(vec![(2u8, "b".to_owned()), (3, "c".into())]);
+        let merged = left.merge(&right);
+        assert_eq!(ms.keys().len(), 3);
+        assert_eq!(read(&merged), vec![(1, "a".into()), (2, "b".into()), (3, "c".into())]);
+    }
+
+    static CLONES: AtomicUsize = AtomicUsize::new(0);
+
+    // Counts how often it's cloned
+    #[derive(Debug,PartialEq)]
+    struct Counted(u64);
+
+    impl Clone for Counted {
+        fn clone(&self) -> Self {
+            CLONES.fetch_add(1, Ordering::SeqCst);
+            Counted(self.0)
+        }
+    }
+
+    impl Serialize for Counted {
+        fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_u64(self.0)
+        }
+    }
+
+    impl <'de> Deserialize<'de> for Counted {
+        fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            u64::deserialize(d).map(Counted)
+        }
+    }
+
+    #[test]
+    fn test_tee() {
+        let acc = Tee(Memory, Disk::from_str("/tmp"));
+        let mut writer = acc.writer();
+        for i in 0..100 {
+            writer.add(Counted(i));
+        }
+        let Tee(mem, disk): Tee<Vec<Counted>, Arc<FileStore<Counted>>> = writer.finish();
+        assert_eq!(CLONES.load(Ordering::SeqCst), 100);
+
+        let expected: Vec<_> = (0..100).map(Counted).collect();
+        assert_eq!(mem, expected);
+        assert_eq!(read(&disk), expected);
+        assert_eq!(Tee(mem, disk).stream().len(), 100);
+    }
+
+    #[test]
+    fn test_counting() {
+        let counts = Counting::new().write_vec(vec![1, 2, 3]);
+        assert_eq!((counts.items, counts.bytes), (3, 0));
+        assert_eq!(counts.stream().count(), 0);
+
+        let counts = Counting::bytes().write_vec(vec!["ab".to_owned(), "cde".into()]);
+        assert_eq!((counts.items, counts.bytes), (2, 5));
+
+        let mut writer = Counting::with_size(|x: &Vec<u8>| x.len() * 2).writer();
+        writer.add(vec![1, 2]);
+        writer.extend(&mut (0..10).map(|i| vec![i]));
+        let counts = writer.finish();
+        assert_eq!((counts.items, counts.bytes), (11, 24));
+
+        // Writers created from the output keep measuring sizes
+        let again = counts.write_vec(vec![vec![0; 4]]);
+        assert_eq!((again.items, again.bytes), (1, 8));
+    }
+
+    // Spills to /dev/full, which fails every write
+    struct FullDisk;
+
+    impl ObjectStore for FullDisk {
+        fn put(&self, _key: &str, _bytes: &[u8]) -> io::Result<()> { Ok(()) }
+        fn get(&self, _key: &str) -> io::Result<Vec<u8>> { Ok(Vec::new()) }
+        fn delete(&self, _key: &str) -> io::Result<()> { Ok(()) }
+        fn local_path(&self, _key: &str) -> Option<::std::path::PathBuf> {
+            Some("/dev/full".into())
+        }
+    }
+
+    // Fails once a number of records have been written
+    struct FailAfter<W>(W, usize);
+
+    impl <A, W: ValueWriter<A>> ValueWriter<A> for FailAfter<W> {
+        type Out = W::Out;
+
+        fn add(&mut self, item: A) {
+            self.try_add(item).unwrap()
+        }
+
+        fn try_add(&mut self, item: A) -> Result<(), WriteError> {
+            if self.1 == 0 {
+                return Err(WriteError::Io(io::Error::other("injected")));
+            }
+            self.1 -= 1;
+            self.0.try_add(item)
+        }
+
+        fn finish(self) -> Self::Out {
+            self.0.finish()
+        }
+    }
+
+    #[test]
+    fn test_fallible_writer() {
+        let mut vw: Vec<u32> = Vec::new();
+        assert!(vw.try_add(1).is_ok());
+        assert!(vw.flush().is_ok());
+        assert_eq!(vw.bytes_written(), None);
+
+        let mut db = Disk::from_str("/tmp").writer();
+        assert_eq!(db.bytes_written(), Some(0));
+        for i in 0..1000usize {
+            db.try_add(i).unwrap();
+        }
+        db.flush().unwrap();
+        let written = db.bytes_written().unwrap();
+        assert!(written > 0);
+        let fs: Arc<FileStore<usize>> = db.finish();
+        assert_eq!(fs.len(), 1000);
+
+        // Failures from the inner writer surface through a Tee
+        let mut tee = TeeWriter(FailAfter(Vec::new(), 2), Vec::new());
+        assert!(tee.try_add(1).is_ok());
+        assert!(tee.try_add(2).is_ok());
+        match tee.try_add(3) {
+            Err(WriteError::Io(ref e)) if e.to_string() == "injected" => (),
+            _ => panic!("Expected injected failure")
+        }
+        let Tee(left, right) = tee.finish();
+        assert_eq!((left, right), (vec![1, 2], vec![1, 2, 3]));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_disk_buffer_io_error() {
+        let mut db = Store(Arc::new(FullDisk)).writer();
+        let mut failed = false;
+        // Records are buffered, so the error surfaces on a later add or the flush
+        for i in 0..100_000u64 {
+            if db.try_add(i).is_err() {
+                failed = true;
+                break;
+            }
+        }
+        failed = failed || db.flush().is_err();
+        assert!(failed);
+    }
+
+    #[test]
+    fn test_content_addressed() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = ContentAddressed(ms.clone());
+        let data: Vec<_> = (0..100usize).collect();
+        let first = acc.write_vec(data.clone());
+        let second = acc.write_vec(data.clone());
+        assert_eq!(ms.keys().len(), 1);
+        assert_eq!(read(&second), data);
+
+        let other = acc.write_vec(vec![1usize, 2, 3]);
+        assert_eq!(ms.keys().len(), 2);
+        assert_eq!(read(&other), vec![1, 2, 3]);
+
+        // Objects outlive their handles so later runs can reuse them
+        drop((first, second, other));
+        assert_eq!(ms.keys().len(), 2);
+    }
+
+    #[test]
+    fn test_content_addressed_disk() {
+        let dir = "/tmp/tange-test-content-addressed";
+        let _ = ::std::fs::remove_dir_all(dir);
+        let acc = Disk::from_str(dir).content_addressed();
+        let first = acc.write_vec(vec!["a".to_owned(); 10]);
+        let second = acc.write_vec(vec!["a".to_owned(); 10]);
+        let third = acc.write_vec(vec!["b".to_owned(); 10]);
+        assert_eq!(::std::fs::read_dir(dir).unwrap().count(), 2);
+        assert_eq!(read(&first), read(&second));
+        assert_eq!(read(&third), vec!["b".to_owned(); 10]);
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = ContentAddressed(ms.clone());
+        let first = acc.writer_with_key("stage-1");
+        assert_eq!(first.bytes_written(), Some(0));
+        let first: Arc<FileStore<u32>> = {
+            let mut w = first;
+            w.extend(&mut (0..10));
+            w.finish()
+        };
+        assert_eq!(ms.keys(), vec!["tange-key-stage-1".to_owned(), "tange-keylen-stage-1".to_owned()]);
+
+        // A second writer with the same key skips writing entirely
+        let mut w = acc.writer_with_key("stage-1");
+        w.extend(&mut (0..10));
+        w.flush().unwrap();
+        assert_eq!(w.bytes_written(), Some(0));
+        let second: Arc<FileStore<u32>> = w.finish();
+        assert_eq!(second.len(), 10);
+        assert_eq!(read(&second), read(&first));
+    }
+
+    #[test]
+    fn test_cache_key_incomplete() {
+        let dir = "/tmp/tange-test-cache-key-incomplete";
+        let _ = ::std::fs::remove_dir_all(dir);
+        let acc = Disk::from_str(dir).content_addressed();
+        let write = |vs: Vec<u32>| {
+            let mut w = acc.writer_with_key("stage/../1");
+            w.extend(&mut vs.into_iter());
+            let fs: Arc<FileStore<u32>> = w.finish();
+            read(&fs)
+        };
+        assert_eq!(write((0..100).collect()), (0..100).collect::<Vec<_>>());
+        let path = ::std::path::Path::new(dir).join("tange-key-stage_.._1");
+        let mut files: Vec<_> = ::std::fs::read_dir(dir).unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["tange-key-stage_.._1", "tange-keylen-stage_.._1"]);
+
+        // Truncated by a crash part way through writing, so written again
+        let bytes = ::std::fs::read(&path).unwrap();
+        ::std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert_eq!(write((0..100).collect()), (0..100).collect::<Vec<_>>());
+
+        // Written but never marked complete
+        ::std::fs::remove_file(::std::path::Path::new(dir).join("tange-keylen-stage_.._1")).unwrap();
+        assert_eq!(write(vec![7]), vec![7]);
+
+        // Complete, so reused
+        assert_eq!(write(vec![8]), vec![7]);
+    }
+
+    #[test]
+    fn test_writer_named() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = Store(ms.clone());
+        {
+            let mut w = acc.writer_named::<u32>("out");
+            w.extend(&mut vec![1, 2, 3].into_iter());
+            let fs = w.finish();
+            assert_eq!(read(&fs), vec![1, 2, 3]);
+
+            // Replaces the existing object
+            let mut w = acc.writer_named::<u32>("out");
+            w.add(4);
+            assert_eq!(read(&w.finish()), vec![4]);
+
+            // Empty writers still create their object
+            let fs = acc.writer_named::<u32>("empty").finish();
+            assert!(fs.is_empty());
+            assert!(read(&fs).is_empty());
+        }
+        // Named objects outlive their FileStores
+        assert_eq!(ms.keys(), vec!["empty".to_owned(), "out".to_owned()]);
+        assert_eq!(ms.size("empty").unwrap(), 0);
+        let fs = Arc::new(FileStore::<u32>::stored(ms.clone(), "out", 1).unwrap());
+        assert_eq!(fs.try_stream().unwrap().into_iter().collect::<Vec<_>>(), vec![4]);
+        let fs = Arc::new(FileStore::<u32>::stored(ms.clone(), "empty", 0).unwrap());
+        assert!(fs.try_stream().unwrap().into_iter().next().is_none());
+    }
+
+    #[test]
+    fn test_empty_writer() {
+        let dir = "/tmp/tange-test-empty-writer";
+        let _ = ::std::fs::remove_dir_all(dir);
+        let fs: Arc<FileStore<u32>> = Disk::from_str(dir).write_vec(Vec::new());
+        assert!(fs.is_empty());
+        assert_eq!(read(&fs), Vec::<u32>::new());
+        assert!(!::std::path::Path::new(dir).exists());
+
+        let ms = Arc::new(MemoryStore::new());
+        let fs: Arc<FileStore<u32>> = Store(ms.clone()).writer().finish();
+        assert_eq!(fs.stream().into_iter().count(), 0);
+        assert!(ms.keys().is_empty());
+    }
+
+    #[test]
+    fn test_try_stream() {
+        let dir = "/tmp/tange-test-try-stream";
+        let acc = Disk::from_str(dir);
+        let fs = acc.write_vec((0..1000u64).collect());
+        assert_eq!(fs.try_stream().unwrap().into_iter().count(), 1000);
+        assert_eq!(stream_or_panic(&fs).into_iter().count(), 1000);
+        let empty: Arc<FileStore<u64>> = acc.write_vec(Vec::new());
+        assert!(empty.try_stream().is_ok());
+
+        // Deleted out from under us
+        let path = fs.store.local_path(fs.name.as_ref().unwrap()).unwrap();
+        ::std::fs::remove_file(&path).unwrap();
+        match fs.try_stream() {
+            Err(ref e @ StreamError::Missing { .. }) => 
+                assert!(e.to_string().contains(&path.display().to_string())),
+            _ => panic!("Expected Missing")
+        }
+        ::std::fs::write(&path, b"").unwrap();
+
+        // Truncated after it was written
+        let fs = acc.write_vec((0..1000u64).collect());
+        let path = fs.store.local_path(fs.name.as_ref().unwrap()).unwrap();
+        let bytes = ::std::fs::read(&path).unwrap();
+        ::std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        match fs.try_stream() {
+            Err(StreamError::Truncated { expected, found, .. }) => {
+                assert_eq!(expected, bytes.len() as u64);
+                assert_eq!(found, bytes.len() as u64 / 2);
+            },
+            _ => panic!("Expected Truncated")
+        }
+
+        // Damaged in place
+        let mut damaged = bytes.clone();
+        let l = damaged.len();
+        damaged[l - 1] ^= 0xff;
+        damaged[20] ^= 0xff;
+        ::std::fs::write(&path, &damaged).unwrap();
+        match fs.try_stream() {
+            Err(StreamError::Corrupt { .. }) => (),
+            _ => panic!("Expected Corrupt")
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "are corrupt")]
+    fn test_stream_damaged_mid_stream() {
+        let acc = Disk::from_str("/tmp/tange-test-stream-damaged");
+        let fs = acc.write_vec((0..200_000u64).map(|i| i.wrapping_mul(0x9e37_79b9_7f4a_7c15)).collect());
+        let path = fs.store.local_path(fs.name.as_ref().unwrap()).unwrap();
+        let mut bytes = ::std::fs::read(&path).unwrap();
+        let l = bytes.len();
+        bytes[l - 10] ^= 0xff;
+        ::std::fs::write(&path, &bytes).unwrap();
+
+        // The damage is past the first record, so it's only found while streaming
+        let records = fs.try_stream().unwrap().into_iter();
+        records.count();
+    }
+
+    #[test]
+    fn test_try_stream_wrong_type() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = ContentAddressed(ms.clone());
+        let mut w = acc.writer_with_key("strings");
+        w.add("abc".to_owned());
+        let _: Arc<FileStore<String>> = w.finish();
+
+        // A later version of the job reads the same key as a different type
+        let mut w = acc.writer_with_key("strings");
+        w.add(true);
+        let fs: Arc<FileStore<bool>> = w.finish();
+        match fs.try_stream() {
+            Err(ref e @ StreamError::WrongType { .. }) => {
+                let msg = e.to_string();
+                assert!(msg.contains("tange-key-strings") && msg.contains("bool"), "{}", msg);
+            },
+            _ => panic!("Expected WrongType")
+        }
+    }
+
+    #[test]
+    fn test_vec_merge() {
+        assert_eq!(vec![1, 2].merge(&vec![3]), vec![1, 2, 3]);
+    }
+}
+
+#[cfg(all(test, feature = "memmap"))]
+mod test_mmap {
+    use super::*;
+    use std::thread;
+    use store::MemoryStore;
+
+    #[test]
+    fn test_stream_mmap() {
+        let expected: Vec<_> = (0..50_000usize).map(|i| (i, format!("record {}", i))).collect();
+        let fs = Disk::from_str("/tmp").write_vec(expected.clone());
+        let buffered: Vec<_> = fs.stream().into_iter().collect();
+        assert_eq!(buffered, expected);
+
+        let handles: Vec<_> = (0..4).map(|_| {
+            let fs = fs.clone();
+            thread::spawn(move || fs.stream_mmap().collect::<Vec<_>>())
+        }).collect();
+        for h in handles {
+            assert_eq!(h.join().unwrap(), expected);
+        }
+    }
+
+    #[test]
+    fn test_stream_mmap_fallback() {
+        let fs = Store(Arc::new(MemoryStore::new())).write_vec(vec![1u8, 2, 3]);
+        assert_eq!(fs.stream_mmap().collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        let empty: FileStore<u8> = FileStore::empty(Arc::new("/tmp".into()));
+        assert_eq!(empty.stream_mmap().count(), 0);
+    }
+}
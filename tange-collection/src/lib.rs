@@ -92,14 +92,42 @@
 
 extern crate tange;
 
+#[macro_use]
+extern crate serde_derive;
+
+#[cfg(feature = "log")]
+#[macro_use]
+extern crate log;
+
+// Logs through the `log` crate when the "log" feature is enabled, and compiles to
+// nothing otherwise
+#[cfg(feature = "log")]
+macro_rules! log_at {
+    ($level:ident, $($arg:tt)*) => { $level!($($arg)*) }
+}
+
+#[cfg(not(feature = "log"))]
+macro_rules! log_at {
+    ($level:ident, $($arg:tt)*) => { if false { let _ = format_args!($($arg)*); } }
+}
+
 /// Defines useful utilities, such as reading files
 pub mod utils;
 
 /// Describes basic interfaces for storing and consuming data
 pub mod interfaces;
 
+/// Defines the byte storage backends used by disk-backed collections
+pub mod store;
+
 /// Defines the two major primitives: MemoryColleciton and DiskCollection
 pub mod collection;
 
+/// Re-exports the collections, traits and schedulers most pipelines need
+pub mod prelude;
+
+/// Defines RandomOptions, which seed randomized operations
+pub mod random;
+
 mod partitioned;
 
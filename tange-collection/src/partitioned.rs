@@ -4,10 +4,12 @@ use std::any::Any;
 use std::hash::{Hasher,Hash};
 use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::collections::hash_map::Entry;
 use std::sync::Arc;
 
-use tange::deferred::{Deferred, batch_apply, tree_reduce};
+use tange::deferred::{Deferred, batch_apply, gather, tree_reduce};
 use interfaces::*;
+use collection::Written;
 
 pub fn block_reduce<
     A,
@@ -28,7 +30,7 @@ pub fn block_reduce<
 ) -> Vec<Deferred<C>> {
     batch_apply(defs, move |_idx, vs| {
         let mut reducer = HashMap::new();
-        for v in vs.stream().into_iter() {
+        for v in stream_or_panic(vs).into_iter() {
             let k = key(&v);
             let e = reducer.entry(k).or_insert_with(&default);
             binop(e, &v);
@@ -51,7 +53,7 @@ pub fn split_by_key<
     // Group into buckets 
     let stage1 = batch_apply(&defs, move |_idx, vs| {
         let mut parts: Vec<_> = (0..partitions).map(|_| vs.writer()).collect();
-        for (idx, x) in vs.stream().into_iter().enumerate() {
+        for (idx, x) in stream_or_panic(vs).into_iter().enumerate() {
             let p = hash_function(idx, &x) % partitions;
             parts[p].add(x.clone());
         }
@@ -73,7 +75,7 @@ pub fn split_by_key<
 }
 
 pub fn partition<
-    Col: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
+    Col: Any + Sync + Send + Clone + Accumulator<A> + Stream<A> + Merge,
     A: Any + Send + Sync + Clone,
     F: 'static + Sync + Send + Clone + Fn(usize, &A) -> usize
 >(
@@ -94,6 +96,62 @@ pub fn partition<
     new_chunks
 }
 
+// Splits each partition into `partitions` pieces as `split_by_key` does, given a
+// value computed once for every partition.  `f` is passed that value, the index of
+// the partition and the index of the value within it.
+pub fn split_with<
+    Col: Any + Sync + Send + Clone + Accumulator<A> + Stream<A> + Merge,
+    A: Any + Send + Sync + Clone,
+    S: Any + Send + Sync,
+    F: 'static + Sync + Send + Clone + Fn(&S, usize, usize, &A) -> usize
+>(
+    defs: &[Deferred<Col>], 
+    side: &Deferred<S>,
+    partitions: usize, 
+    f: F
+) -> Vec<Deferred<Col>>
+        where Col::VW: ValueWriter<A,Out=Col> {
+
+    let partitions = partitions.max(1);
+    let stage1: Vec<_> = defs.iter().enumerate().map(|(i, d)| {
+        let f = f.clone();
+        d.join(side, move |vs, s| {
+            let mut parts: Vec<_> = (0..partitions).map(|_| vs.writer()).collect();
+            for (idx, x) in stream_or_panic(vs).into_iter().enumerate() {
+                let p = f(s, i, idx, &x) % partitions;
+                parts[p].add(x);
+            }
+            parts.into_iter().map(|x| x.finish()).collect::<Vec<_>>()
+        })
+    }).collect();
+
+    (0..partitions).filter_map(|idx| {
+        let group: Vec<_> = stage1.iter().map(|s| s.apply(move |parts| parts[idx].copy())).collect();
+        concat(&group)
+    }).collect()
+}
+
+// Redistributes values into `partitions` partitions of nearly equal size, sending
+// each contiguous run of values, in order, to the next partition.  Counts every
+// partition first, so each knows where its values start.
+pub fn rebalance<
+    Col: Any + Sync + Send + Clone + Accumulator<A> + Stream<A> + Merge,
+    A: Any + Send + Sync + Clone
+>(
+    defs: &[Deferred<Col>], 
+    partitions: usize
+) -> Vec<Deferred<Col>>
+        where Col::VW: ValueWriter<A,Out=Col> {
+
+    let partitions = partitions.max(1);
+    let counts = gather(&batch_apply(defs, |_idx, vs| stream_or_panic(vs).into_iter().count()));
+    split_with(defs, &counts, partitions, move |counts: &Vec<usize>, i, idx, _x| {
+        let offset: usize = counts[..i].iter().sum();
+        let total: usize = counts.iter().sum();
+        (offset + idx) * partitions / total
+    })
+}
+
 pub fn fold_by<
     A: Clone,
     C1: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
@@ -122,9 +180,88 @@ pub fn fold_by<
         out.extend(&mut x.into_iter());
         out.finish()
     });
+    reduce_by_key(&stage1, reduce, acc2, partitions)
+}
+
+// Combines values within each partition, creating each key's combiner from the
+// first of its values rather than from a default
+pub fn block_combine<
+    A,
+    B,
+    Col: Any + Sync + Send + Clone + Stream<A>,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    C: Any + Sync + Send + Clone,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+    I: 'static + Sync + Send + Clone + Fn(&A) -> B, 
+    O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+    M: 'static + Sync + Send + Clone + Fn(HashMap<K,B>) -> C,
+>(
+    defs: &[Deferred<Col>], 
+    key: F, 
+    create: I, 
+    merge_value: O,
+    map: M
+) -> Vec<Deferred<C>> {
+    batch_apply(defs, move |_idx, vs| {
+        let mut reducer = HashMap::new();
+        for v in stream_or_panic(vs).into_iter() {
+            match reducer.entry(key(&v)) {
+                Entry::Occupied(mut e) => merge_value(e.get_mut(), &v),
+                Entry::Vacant(e)       => { e.insert(create(&v)); }
+            }
+        }
+        map(reducer)
+    })
+}
+
+pub fn combine_by_key<
+    A: Clone,
+    C1: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
+    B: Any + Sync + Send + Clone,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+    I: 'static + Sync + Send + Clone + Fn(&A) -> B, 
+    O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+    R: 'static + Sync + Send + Clone + Fn(&mut B, &B),
+    Acc: 'static + Accumulator<(K, B)> + Stream<(K,B)>
+>(
+    defs: &[Deferred<C1>],
+    key: F, 
+    create: I, 
+    merge_value: O, 
+    merge_combiners: R, 
+    acc: Acc,
+    partitions: usize
+) -> Written<Acc, (K, B)>
+        where Acc::VW: ValueWriter<(K, B),Out=Acc> {
+
+    let acc2 = Arc::new(acc);
+    let am = acc2.clone();
+    let stage1 = block_combine(defs, key, create, merge_value, move |x| {
+        let mut out = am.writer();
+        out.extend(&mut x.into_iter());
+        out.finish()
+    });
+    reduce_by_key(&stage1, merge_combiners, acc2, partitions)
+}
+
+// Shuffles partitions holding at most one value per key, hashing the keys into
+// `partitions`, and merges the values of each key with `reduce`
+fn reduce_by_key<
+    B: Any + Sync + Send + Clone,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    R: 'static + Sync + Send + Clone + Fn(&mut B, &B),
+    Acc: 'static + Accumulator<(K, B)> + Stream<(K,B)>
+>(
+    stage1: &[Deferred<Acc>],
+    reduce: R,
+    acc2: Arc<Acc>,
+    partitions: usize
+) -> Written<Acc, (K, B)>
+        where Acc::VW: ValueWriter<(K, B),Out=Acc> {
 
     // Split into chunks
-    let chunks = partition_by_key::<Acc,_,_,_>(&stage1, partitions, |x| x.0.clone());
+    let chunks = partition_by_key::<Acc,_,_,_>(stage1, partitions, |x| x.0.clone());
 
     // partition reduce
     let am = acc2.clone();
@@ -132,7 +269,7 @@ pub fn fold_by<
         let am = am.clone();
         batch_apply(&chunk, move |_idx, vs| {
             let mut hm = HashMap::new();
-            for (k, v) in vs.stream() {
+            for (k, v) in stream_or_panic(vs) {
                 hm.insert(k, v);
             }
             let mut out = am.writer();
@@ -149,10 +286,10 @@ pub fn fold_by<
 
         let out = tree_reduce(&group, move |left, right| {
             let mut nl = HashMap::new();
-            for (k, v) in left.stream() {
+            for (k, v) in stream_or_panic(left) {
                 nl.insert(k, v);
             }
-            for (k, v) in right.stream() {
+            for (k, v) in stream_or_panic(right) {
                 if !nl.contains_key(&k) {
                     nl.insert(k, v);
                 } else {
@@ -168,11 +305,127 @@ pub fn fold_by<
             }
             out.finish()
         });
-        reduction.push(out.unwrap());
+        // Groups are only empty when there were no partitions to fold
+        if let Some(out) = out {
+            reduction.push(out);
+        }
     }
     reduction
 }
 
+// Folds partitions already hashed by the key, so that every value of a key is in
+// the same partition, without moving any between partitions
+pub fn fold_local<
+    A: Clone,
+    C1: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
+    B: Any + Sync + Send + Clone,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    D: 'static + Sync + Send + Clone + Fn() -> B, 
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+    O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+    Acc: 'static + Accumulator<(K, B)>
+>(
+    defs: &[Deferred<C1>],
+    key: F, 
+    default: D, 
+    binop: O, 
+    acc: Acc
+) -> Written<Acc, (K, B)> {
+    block_reduce(defs, key, default, binop, move |x| {
+        let mut out = acc.writer();
+        out.extend(&mut x.into_iter());
+        out.finish()
+    })
+}
+
+// Combines partitions already hashed by the key, as with `fold_local`
+pub fn combine_local<
+    A: Clone,
+    C1: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
+    B: Any + Sync + Send + Clone,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+    I: 'static + Sync + Send + Clone + Fn(&A) -> B, 
+    O: 'static + Sync + Send + Clone + Fn(&mut B, &A),
+    Acc: 'static + Accumulator<(K, B)>
+>(
+    defs: &[Deferred<C1>],
+    key: F, 
+    create: I, 
+    merge_value: O, 
+    acc: Acc
+) -> Written<Acc, (K, B)> {
+    block_combine(defs, key, create, merge_value, move |x| {
+        let mut out = acc.writer();
+        out.extend(&mut x.into_iter());
+        out.finish()
+    })
+}
+
+// Groups the values of each key within each partition, as one Vec per key, for
+// partitions already hashed by the key so that no key is split between them
+pub fn group_local<
+    A,
+    Col: Any + Sync + Send + Clone + Stream<A>,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    V: Any + Sync + Send + Clone,
+    F: 'static + Sync + Send + Clone + Fn(&A) -> K, 
+    G: 'static + Sync + Send + Clone + Fn(A) -> V, 
+    Acc: 'static + Accumulator<(K, Vec<V>)>
+>(
+    defs: &[Deferred<Col>],
+    key: F, 
+    value: G, 
+    acc: Acc
+) -> Written<Acc, (K, Vec<V>)> {
+    batch_apply(defs, move |_idx, vs| {
+        let mut groups: HashMap<K, Vec<V>> = HashMap::new();
+        for v in stream_or_panic(vs).into_iter() {
+            groups.entry(key(&v)).or_default().push(value(v));
+        }
+        let mut out = acc.writer();
+        out.extend(&mut groups.into_iter());
+        out.finish()
+    })
+}
+
+// Folds the values of each key in order of `sort_key`, emitting the state after
+// each value, for partitions already hashed by the key.  Values with equal sort keys
+// keep the order they arrived in.
+pub fn rolling_local<
+    Col: Any + Sync + Send + Clone + Stream<(K, V)>,
+    K: Any + Sync + Send + Clone + Hash + Eq,
+    V: Any + Sync + Send + Clone,
+    S: Ord,
+    B: Any + Sync + Send + Clone,
+    FS: 'static + Sync + Send + Clone + Fn(&V) -> S, 
+    FB: 'static + Sync + Send + Clone + Fn(&B, &V) -> B, 
+    Acc: 'static + Accumulator<(K, B)>
+>(
+    defs: &[Deferred<Col>],
+    sort_key: FS, 
+    init: B, 
+    step: FB, 
+    acc: Acc
+) -> Written<Acc, (K, B)> {
+    batch_apply(defs, move |_idx, vs| {
+        let mut groups: HashMap<K, Vec<V>> = HashMap::new();
+        for (k, v) in stream_or_panic(vs).into_iter() {
+            groups.entry(k).or_default().push(v);
+        }
+        let mut out = acc.writer();
+        for (k, mut vs) in groups.into_iter() {
+            vs.sort_by_key(|v| sort_key(v));
+            let mut state = init.clone();
+            for v in vs.iter() {
+                state = step(&state, v);
+                out.add((k.clone(), state.clone()));
+            }
+        }
+        out.finish()
+    })
+}
+
 pub fn partition_by_key<
     C: Any + Sync + Send + Clone + Accumulator<A> + Stream<A>,
     A: Clone,
@@ -193,23 +446,46 @@ pub fn partition_by_key<
 }
 
 pub fn concat<
-    Col: Any + Sync + Send + Accumulator<A> + Stream<A>,
-    A: Clone,
+    Col: Any + Sync + Send + Clone + Merge,
 >(
     defs: &[Deferred<Col>]
-) -> Option<Deferred<Col>>
-        where  Col::VW: ValueWriter<A,Out=Col> {
+) -> Option<Deferred<Col>> {
+    tree_reduce(&defs, |x, y| x.merge(y))
+}
 
-    tree_reduce(&defs, |x, y| {
-        let mut out = x.writer();
-        for xi in x.stream() {
-            out.add(xi);
-        }
-        for yi in y.stream() {
-            out.add(yi);
-        }
-        out.finish()
-    })
+// Pairs the values of each left partition with those of every right partition in a
+// task of its own, keeping only the pairs `f` accepts.  Gives the pieces crossed
+// from each left partition, in order.
+pub fn cross_where<
+    A: Any + Send + Sync + Clone,
+    B: Any + Send + Sync + Clone,
+    Col1: Any + Sync + Send + Clone + Stream<A>,
+    Col2: Any + Sync + Send + Clone + Stream<B>,
+    F: 'static + Sync + Send + Clone + Fn(&A, &B) -> bool,
+    Acc: 'static + Accumulator<(A, B)>
+>(
+    left: &[Deferred<Col1>], 
+    right: &[Deferred<Col2>], 
+    acc: Acc,
+    f: F
+) -> Vec<Written<Acc, (A, B)>> {
+    left.iter().map(|l| {
+        right.iter().map(|r| {
+            let (acc, f) = (acc.clone(), f.clone());
+            l.join(r, move |ls, rs| {
+                let rs: Vec<B> = stream_or_panic(rs).into_iter().collect();
+                let mut out = acc.writer();
+                for a in stream_or_panic(ls).into_iter() {
+                    for b in rs.iter() {
+                        if f(&a, b) {
+                            out.add((a.clone(), b.clone()));
+                        }
+                    }
+                }
+                out.finish()
+            })
+        }).collect()
+    }).collect()
 }
 
 pub fn join_on_key<
@@ -231,12 +507,12 @@ pub fn join_on_key<
     d1.join(d2, move |left, right| {
         // Slurp up left into a hashmap
         let mut hm = HashMap::new();
-        for (k, lv) in left.stream() {
+        for (k, lv) in stream_or_panic(left) {
             let e = hm.entry(k).or_insert_with(|| Vec::with_capacity(1)); 
             e.push(lv);
         }
         let mut ret = acc.writer();
-        for (k, rv) in right.stream() {
+        for (k, rv) in stream_or_panic(right) {
             if let Some(lvs) = hm.get(&k) {
                 for lv in lvs.iter() {
                     ret.add((k.clone(), joiner(&lv, &rv)))
@@ -0,0 +1,33 @@
+//! Re-exports what most pipelines need, so a single glob import is enough to
+//! build and run a collection:
+//!
+//! ```rust
+//! extern crate tange_collection;
+//! use tange_collection::prelude::*;
+//!
+//! fn main() {
+//!     let evens = MemoryCollection::from_range(0..100, 4)
+//!         .filter(|x| x % 2 == 0)
+//!         .to_disk("/tmp".into())
+//!         .fold_by(|x| x % 10, || 0u64, |acc, x| *acc += x, |a, b| *a += b, 2)
+//!         .sort_by(|x| x.0);
+//!
+//!     let sums = evens.run(&LeveledScheduler).expect("pipeline failed");
+//!     assert_eq!(sums.len(), 5);
+//!     assert_eq!(sums.into_iter().map(|x| x.1).sum::<u64>(), 2450);
+//! }
+//! ```
+//!
+//! The traits are exported by name so their methods are in scope, for code that
+//! works with `to_defs()` or writes its own Accumulators.  Only types and traits
+//! meant to keep their names are added here.  Operations on collections of
+//! pairs, like `group_by_key`, are methods of the collections themselves, so they
+//! need no import of their own.
+
+pub use collection::memory::MemoryCollection;
+pub use collection::disk::DiskCollection;
+pub use interfaces::{Accumulator,Stream,ValueWriter};
+pub use collection::{run_all,Partitioning};
+pub use random::RandomOptions;
+pub use tange::deferred::Deferred;
+pub use tange::scheduler::{Scheduler,GreedyScheduler,LeveledScheduler,SerialScheduler,Progress,CancellationToken,RunError};
@@ -0,0 +1,127 @@
+//! Seeds the randomness of randomized operations, like sampling, so each partition
+//! of each operation draws from its own stream.
+//!
+//! An operation's streams are derived from a master seed, the operation's id, and
+//! the index of the partition, by hashing all three together.  Given the same
+//! master seed, an operation produces the same output for the same input split
+//! into the same partitions, whichever scheduler runs it and in whatever order its
+//! tasks run.  Repartitioning the input, or changing the order of the records within
+//! a partition, changes the output.  Operations with different ids draw unrelated
+//! streams from the same master seed, as do the partitions of one operation.
+extern crate rand;
+
+use self::rand::{ChaChaRng,Rng,SeedableRng};
+
+/// How a randomized operation seeds its randomness
+#[derive(Clone,Copy,Debug,Default,PartialEq,Eq)]
+pub struct RandomOptions {
+    /// Master seed the operation's randomness is derived from.  With None, a seed
+    /// is drawn from the system's entropy as the operation is added, so the output
+    /// differs from one pipeline to the next.
+    pub seed: Option<u64>
+}
+
+impl RandomOptions {
+    /// Options drawing a fresh seed for each operation
+    pub fn new() -> Self {
+        RandomOptions::default()
+    }
+
+    /// Options seeding each operation from `seed`, for reproducible output
+    pub fn seeded(seed: u64) -> Self {
+        RandomOptions { seed: Some(seed) }
+    }
+
+    /// Returns the seed, drawing one if none was given.  Operations call this once,
+    /// as they're added, so every partition shares the seed and a task run again
+    /// draws the same values.
+    pub(crate) fn master_seed(&self) -> u64 {
+        self.seed.unwrap_or_else(rand::random)
+    }
+}
+
+/// Returns the random number generator for a partition of an operation, derived
+/// from the master seed, the operation's id and the partition's index
+pub(crate) fn partition_rng(master_seed: u64, op_id: &str, partition: usize) -> impl Rng {
+    let seed = mix(mix(mix(master_seed) ^ fnv(op_id)) ^ partition as u64);
+    let (a, b) = (mix(seed), mix(seed ^ SECOND_STREAM));
+    let words = [a as u32, (a >> 32) as u32, b as u32, (b >> 32) as u32];
+    ChaChaRng::from_seed(&words[..])
+}
+
+/// Distinguishes the second half of a generator's seed from the first
+const SECOND_STREAM: u64 = 0x6A09_E667_F3BC_C909;
+
+// The finalizer of SplitMix64, which spreads every bit of its input over the output
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+// FNV-1a, which unlike the standard library's hasher is fixed across releases, so
+// seeds derived from an op's id stay the same
+fn fnv(s: &str) -> u64 {
+    s.bytes().fold(0xCBF2_9CE4_8422_2325, |h, b| (h ^ u64::from(b)).wrapping_mul(0x0100_0000_01B3))
+}
+
+#[cfg(test)]
+mod test_random {
+    use super::*;
+    use tange::deferred::batch_apply;
+    use tange::scheduler::{GreedyScheduler,LeveledScheduler};
+    use collection::memory::MemoryCollection;
+
+    // Keeps each record with a chance of one in four, as `sample` does, drawing
+    // from the streams of `op`
+    fn sampled(col: &MemoryCollection<usize>, seed: u64, op: &'static str) -> MemoryCollection<usize> {
+        let parts = batch_apply(col.to_defs(), move |idx, vs| {
+            let mut rng = partition_rng(seed, op, idx);
+            vs.iter().filter(|_| rng.next_f64() < 0.25).cloned().collect()
+        });
+        MemoryCollection::from_defs(parts)
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let col = MemoryCollection::from_vec_chunked((0..1000usize).collect(), 4);
+        let once = col.sample(0.25, RandomOptions::seeded(42)).run(&GreedyScheduler::new()).unwrap();
+        assert_eq!(col.sample(0.25, RandomOptions::seeded(42)).run(&LeveledScheduler), Some(once.clone()));
+        assert_eq!(sampled(&col, 42, "sample").run(&LeveledScheduler), Some(once.clone()));
+        assert!(once.len() > 150 && once.len() < 350, "kept {} of 1000", once.len());
+        assert_ne!(col.sample(0.25, RandomOptions::seeded(43)).run(&LeveledScheduler), Some(once.clone()));
+        // Unseeded samples differ from run to run of the pipeline
+        assert_ne!(col.sample(0.25, RandomOptions::new()).run(&LeveledScheduler), Some(once));
+
+        let draws: Vec<u64> = partition_rng(7, "op", 3).gen_iter().take(4).collect();
+        assert_eq!(partition_rng(7, "op", 3).gen_iter().take(4).collect::<Vec<u64>>(), draws);
+    }
+
+    #[test]
+    fn test_independent() {
+        // Two ops sampling in one pipeline, with the same master seed
+        let col = MemoryCollection::from_vec_chunked((0..1000usize).collect(), 4);
+        let sample = col.sample(0.25, RandomOptions::seeded(42));
+        let both = sample.concat(&sampled(&col, 42, "shuffle"));
+        let out = both.run(&GreedyScheduler::new()).unwrap();
+        let first = sample.run(&LeveledScheduler).unwrap();
+        let (a, b) = out.split_at(first.len());
+        assert_eq!(a, &first[..]);
+        assert_ne!(a, b);
+        let shared = a.iter().filter(|x| b.contains(x)).count();
+        assert!(shared < a.len() / 2, "{} of {} records were picked by both", shared, a.len());
+
+        // Partitions of one op draw different streams too
+        let p0: Vec<u64> = partition_rng(42, "sample", 0).gen_iter().take(8).collect();
+        let p1: Vec<u64> = partition_rng(42, "sample", 1).gen_iter().take(8).collect();
+        assert_ne!(p0, p1);
+    }
+
+    #[test]
+    fn test_options() {
+        assert_eq!(RandomOptions::seeded(5).master_seed(), 5);
+        assert_eq!(RandomOptions::new().seed, None);
+        assert_ne!(RandomOptions::new().master_seed(), RandomOptions::new().master_seed());
+    }
+}
@@ -0,0 +1,171 @@
+//! Byte storage backends used when spilling data out of memory.
+use std::collections::HashMap;
+use std::fs::{self, remove_file};
+use std::io::{Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::sync::RwLock;
+
+/// Minimal interface for storing opaque blobs of bytes by key.  Disk-backed
+/// Accumulators write their records through an ObjectStore, which makes it possible
+/// to spill to something other than the local file system, such as a cloud bucket.
+pub trait ObjectStore: Send + Sync {
+
+    /// Stores the bytes under the given key, replacing any existing object
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()>;
+
+    /// Reads back all the bytes stored under the given key
+    fn get(&self, key: &str) -> Result<Vec<u8>>;
+
+    /// Removes the object stored under the given key
+    fn delete(&self, key: &str) -> Result<()>;
+
+    /// Returns the size in bytes of the object stored under the given key, or an
+    /// error if it doesn't exist.  Stores should override this if they can answer
+    /// without reading the object.
+    fn size(&self, key: &str) -> Result<u64> {
+        self.get(key).map(|b| b.len() as u64)
+    }
+
+    /// If objects are backed by files on the local machine, returns the path of the
+    /// file for a given key.  This allows readers and writers to stream directly from
+    /// and to the file rather than buffering the entire object in memory.
+    fn local_path(&self, _key: &str) -> Option<PathBuf> {
+        None
+    }
+}
+
+/// Stores objects as files within a directory on the local file system
+#[derive(Clone,Debug)]
+pub struct LocalFs {
+    root: PathBuf
+}
+
+impl LocalFs {
+    /// Creates a new LocalFs which writes objects under `root`.  The directory is
+    /// created when the first object is written.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalFs { root: root.into() }
+    }
+}
+
+impl ObjectStore for LocalFs {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        fs::create_dir_all(&self.root)?;
+        fs::write(self.root.join(key), bytes)
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>> {
+        fs::read(self.root.join(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        remove_file(self.root.join(key))
+    }
+
+    fn size(&self, key: &str) -> Result<u64> {
+        fs::metadata(self.root.join(key)).map(|m| m.len())
+    }
+
+    fn local_path(&self, key: &str) -> Option<PathBuf> {
+        Some(self.root.join(key))
+    }
+}
+
+/// Keeps all objects in memory.  This is useful for tests and for small, ephemeral
+/// jobs where the convenience of DiskCollection is desired without touching disk.
+#[derive(Debug,Default)]
+pub struct MemoryStore {
+    objects: RwLock<HashMap<String, Vec<u8>>>
+}
+
+impl MemoryStore {
+    /// Creates an empty MemoryStore
+    pub fn new() -> Self {
+        MemoryStore::default()
+    }
+
+    /// Returns the keys of all objects currently stored
+    pub fn keys(&self) -> Vec<String> {
+        let objects = self.objects.read().unwrap();
+        let mut keys: Vec<_> = objects.keys().cloned().collect();
+        keys.sort();
+        keys
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn put(&self, key: &str, bytes: &[u8]) -> Result<()> {
+        let mut objects = self.objects.write().unwrap();
+        objects.insert(key.into(), bytes.to_vec());
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let objects = self.objects.read().unwrap();
+        objects.get(key).cloned().ok_or_else(|| not_found(key))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut objects = self.objects.write().unwrap();
+        objects.remove(key).map(|_| ()).ok_or_else(|| not_found(key))
+    }
+
+    fn size(&self, key: &str) -> Result<u64> {
+        let objects = self.objects.read().unwrap();
+        objects.get(key).map(|b| b.len() as u64).ok_or_else(|| not_found(key))
+    }
+}
+
+fn not_found(key: &str) -> Error {
+    Error::new(ErrorKind::NotFound, format!("No object stored under {}", key))
+}
+
+#[cfg(test)]
+mod test_store {
+    use super::*;
+    use std::sync::Arc;
+    use interfaces::{Accumulator,FileStore,Store,Stream};
+
+    #[test]
+    fn test_local_fs() {
+        let fs = LocalFs::new("/tmp/tange-test-local-fs");
+        fs.put("object", b"some bytes").unwrap();
+        assert_eq!(fs.get("object").unwrap(), b"some bytes".to_vec());
+        assert!(fs.local_path("object").unwrap().exists());
+        assert_eq!(fs.size("object").unwrap(), 10);
+        fs.delete("object").unwrap();
+        assert!(fs.get("object").is_err());
+    }
+
+    #[test]
+    fn test_memory_store() {
+        let ms = MemoryStore::new();
+        ms.put("a", b"1").unwrap();
+        ms.put("b", b"2").unwrap();
+        assert_eq!(ms.keys(), vec!["a".to_owned(), "b".to_owned()]);
+        assert_eq!(ms.get("a").unwrap(), b"1".to_vec());
+        assert_eq!(ms.size("b").unwrap(), 1);
+        ms.delete("a").unwrap();
+        assert_eq!(ms.get("a").unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(ms.delete("a").is_err());
+    }
+
+    #[test]
+    fn test_file_store_in_memory() {
+        let ms = Arc::new(MemoryStore::new());
+        let acc = Store(ms.clone());
+        {
+            let fs: Arc<FileStore<String>> = acc.write_vec(vec!["a".into(), "b".into()]);
+            assert_eq!(ms.keys().len(), 1);
+            let back: Vec<_> = fs.stream().into_iter().collect();
+            assert_eq!(back, vec!["a".to_owned(), "b".to_owned()]);
+
+            // New writers from the FileStore land in the same store
+            let copy = fs.write_vec(vec!["c".into()]);
+            assert_eq!(copy.stream().into_iter().collect::<Vec<_>>(), vec!["c".to_owned()]);
+            assert_eq!(ms.keys().len(), 2);
+        }
+        // Dropping the FileStores cleans up their objects
+        assert!(ms.keys().is_empty());
+    }
+}
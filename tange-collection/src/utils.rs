@@ -1,17 +1,810 @@
 //! Utilities for creating collections
+extern crate glob;
+extern crate flate2;
+extern crate csv;
+extern crate serde;
+extern crate serde_json;
+
+use std::any::Any;
 use std::io::prelude::*;
-use std::io::{SeekFrom,BufReader,Error};
-use std::fs::{File,metadata};
+use std::io::{SeekFrom,BufReader,Error,ErrorKind,stdin};
+use std::fs::{File,metadata,read_dir};
+use std::path::{Path,PathBuf};
+
+use self::flate2::read::MultiGzDecoder;
+use self::serde::Deserialize;
 
 use tange::deferred::{Deferred, batch_apply};
 
 use collection::memory::MemoryCollection;
 
+/// Lines longer than this many bytes are treated as corrupt input by default
+pub const DEFAULT_MAX_LINE_LEN: usize = 64 * 1024 * 1024;
+
 #[derive(Clone)]
 struct Chunk { path: String, start: u64, end: u64 }
 
 /// Reads a new-line delimited text file, creating a new partition every `chunk_size`
 pub fn read_text(path: &str, chunk_size: u64) -> Result<MemoryCollection<String>,Error> {
+    let dfs = chunks(path, chunk_size)?;
+    Ok(MemoryCollection::from_defs(batch_apply(&dfs, read)).source("read_text"))
+}
+
+/// Reads new-line delimited text files into collections of lines, without their
+/// trailing newlines.  Files are streamed a line at a time when the collection is
+/// run, and any line longer than `max_line_len` fails the read with an error naming
+/// the file and offset, rather than exhausting memory.
+///
+/// Files ending in `.gz` are decompressed as they're read.  Since compressed
+/// files can't be split, each is always read into a single partition; read
+/// directories or globs of many compressed files to read them in parallel.
+/// ```rust
+///   extern crate tange;
+///   extern crate tange_collection;
+///   use tange::scheduler::GreedyScheduler;
+///   use tange_collection::utils::TextReader;
+///   
+///   std::fs::write("/tmp/tange-text-reader-doc", "one\ntwo\n").unwrap();
+///   let col = TextReader::new()
+///       .max_line_len(1024)
+///       .read_text("/tmp/tange-text-reader-doc", 2)
+///       .unwrap();
+///   assert_eq!(col.run(&GreedyScheduler::new()), Some(vec!["one".into(), "two".into()]));
+/// ```
+#[derive(Clone,Debug)]
+pub struct TextReader {
+    max_line_len: usize
+}
+
+impl Default for TextReader {
+    fn default() -> Self {
+        TextReader { max_line_len: DEFAULT_MAX_LINE_LEN }
+    }
+}
+
+impl TextReader {
+    /// Creates a TextReader which allows lines up to DEFAULT_MAX_LINE_LEN bytes
+    pub fn new() -> Self {
+        TextReader::default()
+    }
+
+    /// Sets the length, in bytes, past which a line is considered corrupt
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = max_line_len;
+        self
+    }
+
+    /// Reads a file, split into `n_partitions` byte ranges which are read in
+    /// parallel.  Files with fewer bytes than partitions get fewer partitions.
+    pub fn read_text(&self, path: &str, n_partitions: usize) -> Result<MemoryCollection<String>,Error> {
+        self.split(path, n_partitions, b"\n", |bytes, path, offset| Some(to_line(bytes, path, offset)))
+    }
+
+    /// Reads each regular file within a directory into its own partition, ordered by
+    /// file name.  Subdirectories are skipped, and an empty directory yields an
+    /// empty collection.
+    pub fn read_dir(&self, path: &str) -> Result<MemoryCollection<String>,Error> {
+        let files = list_files(path)?;
+        if files.is_empty() {
+            return Ok(MemoryCollection::from_vec(Vec::new()));
+        }
+        Ok(self.read_files(files, b"\n", |bytes, path, offset| Some(to_line(bytes, path, offset))))
+    }
+
+    /// Reads each regular file matching a glob pattern, such as
+    /// `logs/2024-*/part-*.txt`, into its own partition, ordered by path.  The
+    /// pattern is expanded immediately, returning a `NotFound` error if it matches
+    /// nothing.
+    pub fn read_glob(&self, pattern: &str) -> Result<MemoryCollection<String>,Error> {
+        let files = glob_files(pattern)?;
+        Ok(self.read_files(files, b"\n", |bytes, path, offset| Some(to_line(bytes, path, offset))))
+    }
+
+    /// Reads a file of records separated by `delimiter`, such as the NUL separated
+    /// output of `find -print0`, split into `n_partitions` byte ranges.  The
+    /// delimiter isn't included in the records, and a final record without a
+    /// trailing delimiter is kept.  Records are limited to `max_line_len` bytes.
+    ///
+    /// Delimiters which can overlap themselves, like a blank line ("\n\n"), can't
+    /// be found reliably from the middle of a file, so such files are read into a
+    /// single partition.
+    pub fn read_delimited(
+        &self, 
+        path: &str, 
+        delimiter: &[u8], 
+        n_partitions: usize
+    ) -> Result<MemoryCollection<Vec<u8>>,Error> {
+        self.split(path, n_partitions, delimiter, |bytes, _path, _offset| Some(bytes))
+    }
+
+    /// Reads delimited records as with `read_delimited`, decoding each as UTF-8
+    /// according to `policy`.
+    pub fn read_delimited_str(
+        &self, 
+        path: &str, 
+        delimiter: &[u8], 
+        n_partitions: usize,
+        policy: Utf8Policy
+    ) -> Result<MemoryCollection<String>,Error> {
+        self.split(path, n_partitions, delimiter, move |bytes, path, offset| {
+            policy.decode(bytes, path, offset)
+        })
+    }
+
+    /// Reads a file of newline-delimited JSON, deserializing each line into `T`,
+    /// split into `n_partitions` byte ranges.  Blank lines are ignored, and a line
+    /// which can't be deserialized fails the read with an error giving its line
+    /// number and the start of the line.
+    pub fn read_jsonl<T: Any + Send + Sync + Clone + for<'de> Deserialize<'de>>(
+        &self, 
+        path: &str, 
+        n_partitions: usize
+    ) -> Result<MemoryCollection<T>,Error> {
+        self.split(path, n_partitions, b"\n", |bytes, path, offset| {
+            parse_json(&bytes).map(|r| r.unwrap_or_else(|e| {
+                panic!("{}", json_error(e, &bytes, path, offset))
+            }))
+        })
+    }
+
+    /// Reads newline-delimited JSON as with `read_jsonl`, skipping lines which
+    /// can't be deserialized.  Alongside the records, returns a collection with the
+    /// number of lines skipped in each partition.
+    pub fn read_jsonl_skipping<T: Any + Send + Sync + Clone + for<'de> Deserialize<'de>>(
+        &self, 
+        path: &str, 
+        n_partitions: usize
+    ) -> Result<(MemoryCollection<T>, MemoryCollection<usize>),Error> {
+        let parsed = self.split(path, n_partitions, b"\n", |bytes, _path, _offset| {
+            parse_json(&bytes).map(|r| r.ok())
+        })?;
+        let records = batch_apply(parsed.to_defs(), |_idx, vs| {
+            vs.iter().filter_map(|v: &Option<T>| v.clone()).collect()
+        });
+        let skipped = batch_apply(parsed.to_defs(), |_idx, vs| {
+            vec![vs.iter().filter(|v| v.is_none()).count()]
+        });
+        Ok((MemoryCollection::from_defs(records).source("read_jsonl"), 
+            MemoryCollection::from_defs(skipped).source("read_jsonl")))
+    }
+
+    /// Reads lines from `reader` into partitions of up to `chunk_size` lines each.
+    /// Unlike files, a reader can't be read again when the collection is run, so
+    /// it's read to the end immediately and its lines are held in memory.  An
+    /// empty reader yields an empty collection.
+    pub fn read_reader<R: BufRead>(
+        &self, 
+        mut reader: R, 
+        chunk_size: usize
+    ) -> Result<MemoryCollection<String>,Error> {
+        let chunk_size = chunk_size.max(1);
+        let mut parts = Vec::new();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        let mut buf = Vec::new();
+        let mut offset = 0u64;
+        loop {
+            let used = read_record(&mut reader, b"\n", self.max_line_len, &mut buf)
+                .map_err(|e| record_error(e, "input", offset, self.max_line_len))?;
+            if used == 0 {
+                break;
+            }
+            if buf.ends_with(b"\r") {
+                buf.pop();
+            }
+            let line = String::from_utf8(buf.clone()).map_err(|_| {
+                Error::new(ErrorKind::InvalidData, 
+                           format!("Line at byte {} of input isn't valid UTF-8", offset))
+            })?;
+            chunk.push(line);
+            if chunk.len() == chunk_size {
+                parts.push(Deferred::lift(chunk, None));
+                chunk = Vec::with_capacity(chunk_size);
+            }
+            offset += used as u64;
+        }
+        if !chunk.is_empty() {
+            parts.push(Deferred::lift(chunk, None));
+        }
+        if parts.is_empty() {
+            return Ok(MemoryCollection::from_vec(Vec::new()));
+        }
+        Ok(MemoryCollection::from_defs(parts).source("read_reader"))
+    }
+
+    /// Reads lines from standard input as with `read_reader`, reading all of it
+    /// immediately.
+    pub fn read_stdin(&self, chunk_size: usize) -> Result<MemoryCollection<String>,Error> {
+        let stdin = stdin();
+        let lock = stdin.lock();
+        self.read_reader(lock, chunk_size)
+    }
+
+    // Splits a file into byte ranges, converting each record in a range with `conv`
+    fn split<
+        T: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(Vec<u8>, &str, u64) -> Option<T>
+    >(
+        &self, 
+        path: &str, 
+        n_partitions: usize, 
+        delimiter: &[u8], 
+        conv: F
+    ) -> Result<MemoryCollection<T>,Error> {
+        if delimiter.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidInput, "Delimiter can't be empty"));
+        }
+        let file_size = metadata(path)?.len();
+        if is_gzip(Path::new(path)) {
+            // Compressed files can't be split by byte range
+            return Ok(self.read_files(vec![path.into()], delimiter, conv));
+        }
+        let n = if self_overlapping(delimiter) { 1 } else { n_partitions.max(1) as u64 };
+        let chunk_size = file_size.div_ceil(n).max(1);
+        let mut dfs = chunks(path, chunk_size)?;
+        if dfs.is_empty() {
+            // Empty files still get a partition
+            dfs.push(Deferred::lift(Chunk { path: path.into(), start: 0, end: 0 }, 
+                                    Some(&format!("File: {}, start: 0", path))));
+        }
+        let max = self.max_line_len;
+        let delim = delimiter.to_vec();
+        Ok(MemoryCollection::from_defs(batch_apply(&dfs, move |_idx, chunk| {
+            let mut records = Vec::new();
+            scan(chunk, &delim, max, |bytes, offset, _| {
+                if let Some(r) = conv(bytes, &chunk.path, offset) {
+                    records.push(r);
+                }
+            }).unwrap_or_else(|e| panic!("{}", e));
+            records.shrink_to_fit();
+            records
+        })).source("read_file"))
+    }
+
+    // Creates a partition for each file.  Files which can't be read fail their task
+    // with an error naming them.
+    fn read_files<
+        T: Any + Send + Sync + Clone,
+        F: 'static + Sync + Send + Clone + Fn(Vec<u8>, &str, u64) -> Option<T>
+    >(
+        &self, 
+        paths: Vec<PathBuf>, 
+        delimiter: &[u8], 
+        conv: F
+    ) -> MemoryCollection<T> {
+        let dfs: Vec<_> = paths.into_iter().map(|p| {
+            let name = format!("File: {}", p.display());
+            Deferred::lift(p, Some(&name))
+        }).collect();
+        let max = self.max_line_len;
+        let delim = delimiter.to_vec();
+        MemoryCollection::from_defs(batch_apply(&dfs, move |_idx, path| {
+            let name = path.display().to_string();
+            let mut reader = open_text(path)
+                .unwrap_or_else(|e| panic!("Couldn't open {}: {}", name, e));
+            let mut records = Vec::new();
+            let mut offset = 0;
+            let mut buf = Vec::new();
+            loop {
+                match read_record(&mut reader, &delim, max, &mut buf) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        if let Some(r) = conv(buf.clone(), &name, offset) {
+                            records.push(r);
+                        }
+                        offset += n as u64;
+                    },
+                    Err(e) => panic!("{}", record_error(e, &name, offset, max))
+                }
+            }
+            records.shrink_to_fit();
+            records
+        })).source("read_files")
+    }
+}
+
+/// Splits a file of length-prefixed frames, each a u32 little-endian length followed
+/// by that many bytes, into about `n_partitions` partitions of whole frames.  The
+/// frame lengths are walked up front to find the partition boundaries, so a corrupt
+/// length is reported immediately, while the frames themselves are read when the
+/// collection is run.
+pub(crate) fn read_framed(path: &str, n_partitions: usize) -> Result<MemoryCollection<Vec<u8>>,Error> {
+    let file_size = metadata(path)?.len();
+    let target = file_size.div_ceil(n_partitions.max(1) as u64).max(1);
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut dfs = Vec::new();
+    let mut start = 0u64;
+    let mut offset = 0u64;
+    while offset < file_size {
+        if file_size - offset < 4 {
+            return Err(Error::new(ErrorKind::InvalidData, 
+                format!("Frame at byte {} of {} is missing its length", offset, path)));
+        }
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let len = u64::from(u32::from_le_bytes(len));
+        if offset + 4 + len > file_size {
+            return Err(Error::new(ErrorKind::InvalidData, 
+                format!("Frame at byte {} of {} has length {}, which runs past the end of the file at {}", 
+                        offset, path, len, file_size)));
+        }
+        reader.seek_relative(len as i64)?;
+        offset += 4 + len;
+        if offset - start >= target || offset == file_size {
+            dfs.push(Deferred::lift(Chunk { path: path.into(), start, end: offset },
+                                    Some(&format!("File: {}, start: {}", path, start))));
+            start = offset;
+        }
+    }
+    if dfs.is_empty() {
+        dfs.push(Deferred::lift(Chunk { path: path.into(), start: 0, end: 0 }, 
+                                Some(&format!("File: {}, start: 0", path))));
+    }
+
+    Ok(MemoryCollection::from_defs(batch_apply(&dfs, |_idx, chunk| {
+        read_frames(chunk).unwrap_or_else(|e| panic!("Couldn't read frames from {}: {}", chunk.path, e))
+    })).source("read_framed"))
+}
+
+// Reads the frames within a chunk, which must start and end on frame boundaries
+fn read_frames(chunk: &Chunk) -> Result<Vec<Vec<u8>>,Error> {
+    let mut reader = BufReader::new(File::open(&chunk.path)?);
+    reader.seek(SeekFrom::Start(chunk.start))?;
+    let mut frames = Vec::new();
+    let mut offset = chunk.start;
+    while offset < chunk.end {
+        let mut len = [0u8; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_le_bytes(len) as usize;
+        let mut frame = vec![0u8; len];
+        reader.read_exact(&mut frame)?;
+        frames.push(frame);
+        offset += 4 + len as u64;
+    }
+    Ok(frames)
+}
+
+/// Splits a file of fixed-size binary records, each `record_len` bytes, into about
+/// `n_partitions` byte ranges of whole records, read when the collection is run.
+/// Returns an `InvalidData` error if the file's length isn't a multiple of
+/// `record_len`.
+pub(crate) fn read_fixed(
+    path: &str, 
+    record_len: usize, 
+    n_partitions: usize
+) -> Result<MemoryCollection<Vec<u8>>,Error> {
+    if record_len == 0 {
+        return Err(Error::new(ErrorKind::InvalidInput, "Record length can't be zero"));
+    }
+    let file_size = metadata(path)?.len();
+    let record_len = record_len as u64;
+    if file_size % record_len != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, 
+            format!("{} is {} bytes, which isn't a multiple of the record length {}", 
+                    path, file_size, record_len)));
+    }
+    let n_records = file_size / record_len;
+    let per_part = n_records.div_ceil(n_partitions.max(1) as u64).max(1);
+
+    let mut dfs = Vec::new();
+    let mut start = 0u64;
+    while start < file_size {
+        let end = (start + per_part * record_len).min(file_size);
+        dfs.push(Deferred::lift(Chunk { path: path.into(), start, end },
+                                Some(&format!("File: {}, start: {}", path, start))));
+        start = end;
+    }
+    if dfs.is_empty() {
+        dfs.push(Deferred::lift(Chunk { path: path.into(), start: 0, end: 0 }, 
+                                Some(&format!("File: {}, start: 0", path))));
+    }
+
+    Ok(MemoryCollection::from_defs(batch_apply(&dfs, move |_idx, chunk| {
+        read_records(chunk, record_len as usize)
+            .unwrap_or_else(|e| panic!("Couldn't read records from {}: {}", chunk.path, e))
+    })).source("read_fixed"))
+}
+
+// Reads the fixed-size records within a chunk, which must start and end on record
+// boundaries
+fn read_records(chunk: &Chunk, record_len: usize) -> Result<Vec<Vec<u8>>,Error> {
+    let mut reader = BufReader::new(File::open(&chunk.path)?);
+    reader.seek(SeekFrom::Start(chunk.start))?;
+    let n = ((chunk.end - chunk.start) / record_len as u64) as usize;
+    let mut records = Vec::with_capacity(n);
+    for _ in 0..n {
+        let mut record = vec![0u8; record_len];
+        reader.read_exact(&mut record)?;
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Splits a CSV file into about `n_partitions` byte ranges aligned to whole records,
+/// including quoted fields containing newlines, converting each record with `conv`.
+/// Partitions count the quotes within their ranges in parallel, which tells each
+/// partition whether its range begins inside a quoted field.  With `has_headers`,
+/// the first record is skipped and its names passed to `conv`.
+pub(crate) fn read_csv<
+    T: Any + Send + Sync + Clone,
+    F: 'static + Sync + Send + Clone + Fn(&csv::StringRecord, Option<&csv::StringRecord>) -> Result<T,csv::Error>
+>(
+    path: &str, 
+    has_headers: bool, 
+    n_partitions: usize, 
+    conv: F
+) -> Result<MemoryCollection<T>,Error> {
+    let file_size = metadata(path)?.len();
+    let chunk_size = file_size.div_ceil(n_partitions.max(1) as u64).max(1);
+    let mut dfs = chunks(path, chunk_size)?;
+    if dfs.is_empty() {
+        dfs.push(Deferred::lift(Chunk { path: path.into(), start: 0, end: 0 }, 
+                                Some(&format!("File: {}, start: 0", path))));
+    }
+
+    let quotes = batch_apply(&dfs, |_idx, chunk| {
+        count_quotes(chunk).unwrap_or_else(|e| panic!("Couldn't read {}: {}", chunk.path, e))
+    });
+
+    // Number of quotes preceding each range
+    let mut preceding = Vec::with_capacity(dfs.len());
+    let mut total = Deferred::lift(0u64, None);
+    for q in quotes.iter() {
+        preceding.push(total.clone());
+        total = total.join(q, |a, b| a + b);
+    }
+
+    let parts = dfs.iter().zip(preceding.iter()).map(|(chunk, quotes)| {
+        let conv = conv.clone();
+        chunk.join(quotes, move |chunk, quotes| {
+            scan_csv(chunk, quotes % 2 == 1, has_headers, &conv)
+                .unwrap_or_else(|e| panic!("Couldn't read {}: {}", chunk.path, e))
+        })
+    }).collect();
+    Ok(MemoryCollection::from_defs(parts).source("read_csv"))
+}
+
+fn count_quotes(chunk: &Chunk) -> Result<u64,Error> {
+    let mut reader = BufReader::new(File::open(&chunk.path)?);
+    reader.seek(SeekFrom::Start(chunk.start))?;
+    let mut reader = reader.take(chunk.end - chunk.start);
+    let mut count = 0u64;
+    loop {
+        let n = {
+            let buf = reader.fill_buf()?;
+            count += buf.iter().filter(|b| **b == b'"').count() as u64;
+            buf.len()
+        };
+        if n == 0 {
+            return Ok(count);
+        }
+        reader.consume(n);
+    }
+}
+
+// Reads the records starting within a chunk, which owns the records starting after
+// its first byte up to and including the record starting at its end
+fn scan_csv<T, F: Fn(&csv::StringRecord, Option<&csv::StringRecord>) -> Result<T,csv::Error>>(
+    chunk: &Chunk, 
+    in_quotes: bool, 
+    has_headers: bool,
+    conv: &F
+) -> Result<Vec<T>,Error> {
+    let builder = {
+        let mut b = csv::ReaderBuilder::new();
+        b.has_headers(false).flexible(true);
+        b
+    };
+
+    let headers = if has_headers {
+        let mut rdr = builder.from_reader(BufReader::new(File::open(&chunk.path)?));
+        let mut h = csv::StringRecord::new();
+        rdr.read_record(&mut h).map_err(|e| csv_error(e, &chunk.path))?;
+        Some((h, rdr.position().byte()))
+    } else {
+        None
+    };
+
+    let mut reader = BufReader::new(File::open(&chunk.path)?);
+    let start = if chunk.start > 0 {
+        // Find the first newline outside of a quoted field
+        reader.seek(SeekFrom::Start(chunk.start))?;
+        let mut quoted = in_quotes;
+        let mut offset = chunk.start;
+        loop {
+            let (used, found) = {
+                let buf = reader.fill_buf()?;
+                if buf.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let mut found = None;
+                for (i, b) in buf.iter().enumerate() {
+                    if *b == b'"' {
+                        quoted = !quoted;
+                    } else if *b == b'\n' && !quoted {
+                        found = Some(i + 1);
+                        break;
+                    }
+                }
+                (found.unwrap_or(buf.len()), found.is_some())
+            };
+            reader.consume(used);
+            offset += used as u64;
+            if found {
+                break offset;
+            }
+        }
+    } else {
+        0
+    };
+
+    let mut rdr = builder.from_reader(reader);
+    let mut records = Vec::new();
+    let mut record = csv::StringRecord::new();
+    loop {
+        let pos = start + rdr.position().byte();
+        if pos > chunk.end || !rdr.read_record(&mut record).map_err(|e| csv_error(e, &chunk.path))? {
+            break;
+        }
+        if let Some((_, header_end)) = headers {
+            if pos < header_end {
+                // The first partition skips the header itself
+                continue;
+            }
+        }
+        let r = conv(&record, headers.as_ref().map(|h| &h.0))
+            .map_err(|e| Error::new(ErrorKind::InvalidData, 
+                                    format!("Bad record at byte {}: {}", pos, e)))?;
+        records.push(r);
+    }
+    Ok(records)
+}
+
+fn csv_error(e: csv::Error, path: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("Bad CSV in {}: {}", path, e))
+}
+
+/// Deserializes a CSV record, by header name if available
+pub(crate) fn csv_deserialize<T: for<'de> Deserialize<'de>>(
+    record: &csv::StringRecord, 
+    headers: Option<&csv::StringRecord>
+) -> Result<T,csv::Error> {
+    record.deserialize(headers)
+}
+
+/// Which files written by a CSV sink start with a header row
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum CsvHeaders {
+    /// No file has a header
+    None,
+
+    /// Every file has a header
+    EachFile,
+
+    /// Only the first partition's file has a header, so the files can be
+    /// concatenated into a single valid CSV
+    FirstFile
+}
+
+/// How records which aren't valid UTF-8 are handled when decoding them as Strings
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub enum Utf8Policy {
+    /// Fail the read with an error naming the file and offset
+    Strict,
+
+    /// Replace invalid sequences with U+FFFD
+    Lossy,
+
+    /// Drop invalid records
+    Skip
+}
+
+impl Utf8Policy {
+    fn decode(self, bytes: Vec<u8>, path: &str, offset: u64) -> Option<String> {
+        match String::from_utf8(bytes) {
+            Ok(s) => Some(s),
+            Err(e) => match self {
+                Utf8Policy::Strict => 
+                    panic!("Record at byte {} of {} isn't valid UTF-8", offset, path),
+                Utf8Policy::Lossy => Some(String::from_utf8_lossy(e.as_bytes()).into_owned()),
+                Utf8Policy::Skip => None
+            }
+        }
+    }
+}
+
+// Parses a line of JSON, returning None for blank lines
+fn parse_json<T: for<'de> Deserialize<'de>>(line: &[u8]) -> Option<Result<T,serde_json::Error>> {
+    if line.iter().all(|b| b.is_ascii_whitespace()) {
+        None
+    } else {
+        Some(serde_json::from_slice(line))
+    }
+}
+
+// Describes a malformed line of JSON by its line number, which is only counted once
+// a bad line is found
+fn json_error(e: serde_json::Error, line: &[u8], path: &str, offset: u64) -> Error {
+    let mut snippet = String::from_utf8_lossy(&line[..line.len().min(64)]).into_owned();
+    if line.len() > 64 {
+        snippet.push_str("...");
+    }
+    let line_no = match line_number(Path::new(path), offset) {
+        Ok(n) => n.to_string(),
+        Err(_) => format!("at byte {}", offset)
+    };
+    Error::new(ErrorKind::InvalidData, 
+               format!("Malformed JSON on line {} of {}: {}: {}", line_no, path, e, snippet))
+}
+
+// Finds the 1-based line number of the line starting at `offset`
+fn line_number(path: &Path, offset: u64) -> Result<u64,Error> {
+    let mut reader = open_text(path)?.take(offset);
+    let mut lines = 1;
+    loop {
+        let n = {
+            let buf = reader.fill_buf()?;
+            lines += buf.iter().filter(|b| **b == b'\n').count() as u64;
+            buf.len()
+        };
+        if n == 0 {
+            return Ok(lines);
+        }
+        reader.consume(n);
+    }
+}
+
+// Whether a proper prefix of the delimiter is also a suffix, allowing matches to
+// overlap
+fn self_overlapping(delim: &[u8]) -> bool {
+    (1..delim.len()).any(|k| delim[..k] == delim[delim.len() - k..])
+}
+
+// Lists the regular files within a directory, sorted by name
+fn list_files(path: &str) -> Result<Vec<PathBuf>,Error> {
+    let mut files = Vec::new();
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Expands a glob pattern into the regular files it matches, sorted by path.
+// Matching no files is an error, as it's almost always a typo in the pattern.
+fn glob_files(pattern: &str) -> Result<Vec<PathBuf>,Error> {
+    let paths = glob::glob(pattern)
+        .map_err(|e| Error::new(ErrorKind::InvalidInput, format!("Bad pattern {}: {}", pattern, e)))?;
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(Error::from)?;
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(Error::new(ErrorKind::NotFound, format!("No files match {}", pattern)));
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_gzip(path: &Path) -> bool {
+    path.extension().map(|e| e == "gz").unwrap_or(false)
+}
+
+// Opens a text file for reading, decompressing it on the fly if gzipped
+fn open_text(path: &Path) -> Result<Box<dyn BufRead>,Error> {
+    let file = File::open(path)?;
+    if is_gzip(path) {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+// Converts a line without its newline into a String
+fn to_line(mut bytes: Vec<u8>, path: &str, offset: u64) -> String {
+    if bytes.ends_with(b"\r") {
+        bytes.pop();
+    }
+    let mut line = String::from_utf8(bytes)
+        .unwrap_or_else(|_| panic!("Line at byte {} of {} isn't valid UTF-8", offset, path));
+    line.shrink_to_fit();
+    line
+}
+
+fn record_error(e: Error, path: &str, offset: u64, max: usize) -> Error {
+    if e.kind() == ErrorKind::InvalidData {
+        Error::new(e.kind(), format!("Record at byte {} of {} is longer than {} bytes", 
+                                     offset, path, max))
+    } else {
+        Error::new(e.kind(), format!("Error reading {} at byte {}: {}", path, offset, e))
+    }
+}
+
+// Reads a record terminated by `delim` into `buf`, without the delimiter, returning
+// the number of bytes consumed or 0 at the end of the stream.  The final record
+// need not be terminated.  Records longer than `max` bytes fail with InvalidData
+// before they're fully buffered.
+fn read_record<R: BufRead + ?Sized>(
+    reader: &mut R, 
+    delim: &[u8], 
+    max: usize, 
+    buf: &mut Vec<u8>
+) -> Result<usize,Error> {
+    buf.clear();
+    let last = delim[delim.len() - 1];
+    let mut consumed = 0;
+    loop {
+        let (used, found) = {
+            let avail = match reader.fill_buf() {
+                Ok(avail) => avail,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e)
+            };
+            if avail.is_empty() {
+                return Ok(consumed);
+            }
+            match avail.iter().position(|b| *b == last) {
+                Some(i) => {
+                    buf.extend_from_slice(&avail[..=i]);
+                    (i + 1, buf.ends_with(delim))
+                },
+                None => {
+                    buf.extend_from_slice(avail);
+                    (avail.len(), false)
+                }
+            }
+        };
+        reader.consume(used);
+        consumed += used;
+        if found {
+            let l = buf.len() - delim.len();
+            buf.truncate(l);
+            return Ok(consumed);
+        } else if buf.len() > max + delim.len() {
+            return Err(Error::new(ErrorKind::InvalidData, "record too long"));
+        }
+    }
+}
+
+// Consumes bytes up to and including the next delimiter, returning how many were
+// consumed.  Nothing is buffered beyond what's needed to match the delimiter.
+fn skip_record<R: BufRead + ?Sized>(reader: &mut R, delim: &[u8]) -> Result<u64,Error> {
+    let mut tail: Vec<u8> = Vec::with_capacity(delim.len() * 2);
+    let mut consumed = 0u64;
+    loop {
+        let (used, found) = {
+            let avail = reader.fill_buf()?;
+            if avail.is_empty() {
+                return Ok(consumed);
+            }
+            let mut result = (avail.len(), false);
+            for (i, b) in avail.iter().enumerate() {
+                tail.push(*b);
+                if tail.ends_with(delim) {
+                    result = (i + 1, true);
+                    break;
+                }
+                if tail.len() >= delim.len() * 2 {
+                    tail.drain(..delim.len());
+                }
+            }
+            result
+        };
+        reader.consume(used);
+        consumed += used as u64;
+        if found {
+            return Ok(consumed);
+        }
+    }
+}
+
+fn chunks(path: &str, chunk_size: u64) -> Result<Vec<Deferred<Chunk>>,Error> {
     // Read the file size
     let file_size = metadata(path)?.len();
     let mut dfs = Vec::new();
@@ -26,42 +819,59 @@ pub fn read_text(path: &str, chunk_size: u64) -> Result<MemoryCollection<String>
                                 Some(&format!("File: {}, start: {}", path, cur_offset))));
         cur_offset += chunk_size;
     }
-
-    Ok(MemoryCollection::from_defs(batch_apply(&dfs, read)))
+    Ok(dfs)
 }
 
 fn read(_idx: usize, chunk: &Chunk) -> Vec<String> {
-    let f = File::open(&chunk.path)
-        .expect("Error when opening file");
-    let mut reader = BufReader::new(f);
-    reader.seek(SeekFrom::Start(chunk.start))
-        .expect("Error when reading file!");
+    let mut lines = Vec::new();
+    scan(chunk, b"\n", DEFAULT_MAX_LINE_LEN, |mut bytes, offset, terminated| {
+        if terminated {
+            bytes.push(b'\n');
+        }
+        let mut s = String::from_utf8(bytes)
+            .unwrap_or_else(|_| panic!("Line at byte {} of {} isn't valid UTF-8", offset, chunk.path));
+        s.shrink_to_fit();
+        lines.push(s);
+    }).unwrap_or_else(|e| panic!("{}", e));
+    lines.shrink_to_fit();
+    lines
+}
+
+// Passes each record starting within the chunk to `f`, along with its offset and
+// whether it ended in a delimiter.  A chunk owns the records which start after its
+// first byte, up to and including the record starting at its end, so adjacent chunks
+// never share or drop a record.
+fn scan<F: FnMut(Vec<u8>, u64, bool)>(
+    chunk: &Chunk, 
+    delim: &[u8], 
+    max: usize, 
+    mut f: F
+) -> Result<(),Error> {
+    let file = File::open(&chunk.path)
+        .map_err(|e| Error::new(e.kind(), format!("Couldn't open {}: {}", chunk.path, e)))?;
+    let mut reader = BufReader::new(file);
 
     let mut start = if chunk.start > 0 { 
-        // Skip first line, which is likely a partial line
-        let mut s = Vec::new();
-        let size = reader.read_until(b'\n', &mut s)
-            .expect("Error reading line from file!");
-        chunk.start + size as u64
+        // Skip the first record, which is likely partial.  Back up far enough to
+        // catch a delimiter straddling the start of the chunk.
+        let from = chunk.start.saturating_sub(delim.len() as u64 - 1);
+        reader.seek(SeekFrom::Start(from))?;
+        from + skip_record(&mut reader, delim)?
     } else {
         0
     };
 
-    let total = chunk.end;
-    let mut lines = Vec::new();
-    loop {
-        let mut s = String::new();
-        match reader.read_line(&mut s) {
+    let mut buf = Vec::new();
+    while start <= chunk.end {
+        match read_record(&mut reader, delim, max, &mut buf) {
             Ok(0) => break,
-            Ok(size) => {
-                start += size as u64;
-                s.shrink_to_fit();
-                lines.push(s);
+            Ok(n) => {
+                let terminated = n > buf.len();
+                f(buf.clone(), start, terminated);
+                start += n as u64;
             },
-            _ => break
-        };
-        if start > total { break; }
+            Err(e) => return Err(record_error(e, &chunk.path, start, max))
+        }
     }
-    lines.shrink_to_fit();
-    lines
+    Ok(())
 }
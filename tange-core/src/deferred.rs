@@ -4,9 +4,18 @@ use std::marker::PhantomData;
 use std::sync::Arc;
 use std::any::Any;
 
-use task::{DynFn,DynFn2,BASS};
+use task::{DynFn,DynFn2,BASS,SizeFn};
 use graph::*;
-use scheduler::Scheduler;
+use scheduler::{Scheduler,Progress,CancellationToken,Cancelled,ConcurrencyLimit,RunError};
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use futures::FutureExt;
+#[cfg(feature = "tokio")]
+use task::AsyncFn;
+#[cfg(feature = "tokio")]
+use scheduler::AsyncScheduler;
 
 struct Lift<A>(A);
 
@@ -16,6 +25,52 @@ impl <A: Any + Send + Sync + Clone> Input for Lift<A> {
     }
 }
 
+/// A read-only view of one step in a `Deferred`'s dependency graph, for inspecting
+/// how a computation was put together before running it.
+/// ```
+/// use tange::deferred::Deferred;
+///
+/// let a = Deferred::lift(1usize, "a".into());
+/// let b = a.apply(|x| x + 1).named("Increment");
+/// let c = a.join(&b, |x, y| x + y);
+/// let node = c.node();
+/// assert_eq!(node.name(), "Join");
+/// let inputs: Vec<_> = node.inputs().iter().map(|n| n.name().to_owned()).collect();
+/// assert_eq!(inputs, vec!["a", "Increment"]);
+/// assert_eq!(node.inputs()[1].inputs()[0].id(), a.node().id());
+/// ```
+#[derive(Clone)]
+pub struct Node(Arc<Graph>);
+
+impl Node {
+    /// Name of the step.  Unless renamed with `Deferred::named`, this is "Apply" or 
+    /// "Join" for functions, and the name given to `lift` for inputs.
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Which of a set of partitions the step produces, if set with
+    /// `Deferred::named_part`.
+    pub fn part(&self) -> Option<usize> {
+        self.0.part
+    }
+
+    /// Unique id of the step.  Steps shared between Deferreds have the same id.
+    pub fn id(&self) -> usize {
+        self.0.handle.id()
+    }
+
+    /// Steps this one consumes: none for an input, one for an apply, and two for
+    /// a join.
+    pub fn inputs(&self) -> Vec<Node> {
+        match self.0.args {
+            None                            => Vec::new(),
+            Some(FnArgs::Single(ref g))     => vec![Node(g.clone())],
+            Some(FnArgs::Join(ref l, ref r)) => vec![Node(l.clone()), Node(r.clone())]
+        }
+    }
+}
+
 /// A `Deferred` is the core struct defining how computations are composed
 /// The type parameter indicates the type of data contained within the `Deferred`
 #[derive(Clone)]
@@ -78,6 +133,79 @@ impl <A: Any + Send + Sync> Deferred<A> {
         }
 
     }
+
+    /// Applies a function returning a future to a Deferred, returning a new Deferred
+    /// holding the future's output.  `AsyncScheduler` runs the future on its
+    /// runtime without tying up a thread while it waits; other schedulers wait for
+    /// it on the task's thread, so it mustn't need a runtime of its own there.
+    ///
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::AsyncScheduler;
+    ///
+    /// let def = Deferred::lift(4usize, None).apply_async(|x| std::future::ready(x * 2));
+    /// assert_eq!(def.run(&AsyncScheduler::new(4).unwrap()), Some(8));
+    /// ```
+    #[cfg(feature = "tokio")]
+    pub fn apply_async<
+        B: Any + Send + Sync,
+        Fut: 'static + Send + Future<Output = B>,
+        F: Send + Sync + 'static + Fn(&A) -> Fut
+    >(&self, f: F) -> Deferred<B> {
+        let ng = Graph::create_async_task(
+            FnArgs::Single(self.graph.clone()), AsyncFn::new(f), "ApplyAsync");
+        Deferred {
+            graph: ng,
+            items: PhantomData
+        }
+    }
+
+    /// Renames the step producing this Deferred's value, which otherwise takes a
+    /// generic name like "Apply".  Names show up in the Deferred's `Node`, in
+    /// scheduler logs, and in the message of a panic raised by the step.  The renamed Deferred computes the same value, but as a
+    /// separate step, so it's best called on a Deferred that was just created
+    /// rather than one that's already used elsewhere.
+    ///
+    /// ```
+    /// use tange::deferred::Deferred;
+    ///
+    /// let def = Deferred::lift(vec![1u8, 2, 3], None).apply(|v| v.len()).named("Length");
+    /// assert_eq!(def.node().name(), "Length");
+    /// ```
+    pub fn named(self, name: &str) -> Deferred<A> {
+        let part = self.graph.part;
+        Deferred {
+            graph: self.graph.rename(name, part),
+            items: PhantomData
+        }
+    }
+
+    /// Renames the step producing this Deferred's value, as with `named`, and records
+    /// which of a set of partitions it produces.  If the step panics, the panic 
+    /// message names both.
+    ///
+    /// ```should_panic
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::LeveledScheduler;
+    ///
+    /// // Panics with "task 'Parse' partition 3 panicked: invalid digit found in string"
+    /// let def = Deferred::lift("x".to_owned(), None)
+    ///     .apply(|s| s.parse::<u32>().unwrap())
+    ///     .named_part("Parse", 3);
+    /// def.run(&LeveledScheduler);
+    /// ```
+    pub fn named_part(self, name: &str, part: usize) -> Deferred<A> {
+        Deferred {
+            graph: self.graph.rename(name, Some(part)),
+            items: PhantomData
+        }
+    }
+
+    /// Returns the step producing this Deferred's value, from which the rest of
+    /// its graph can be walked.
+    pub fn node(&self) -> Node {
+        Node(self.graph.clone())
+    }
 }
 
 impl <A: Any + Send + Sync + Clone> Deferred<A> {
@@ -98,8 +226,83 @@ impl <A: Any + Send + Sync + Clone> Deferred<A> {
         }
     }
 
-    /// Evaluates the Deferred object and dependency graph, returning the result 
-    /// of the computation.  
+    /// Counts the elements of this Deferred's value with `f` when a scheduler records
+    /// run metrics, reporting them alongside the time spent in the step that
+    /// produces it.  As with `named`, the sized Deferred is a separate step.
+    ///
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let mut scheduler = GreedyScheduler::new();
+    /// scheduler.record_metrics(true);
+    /// let words = Deferred::lift("a b c".to_owned(), None)
+    ///     .apply(|s| s.split(' ').map(|w| w.to_owned()).collect::<Vec<_>>())
+    ///     .named("Split")
+    ///     .sized(|ws| ws.len());
+    /// assert_eq!(words.run(&scheduler).map(|ws| ws.len()), Some(3));
+    /// let metrics = scheduler.last_run_metrics().unwrap();
+    /// assert_eq!(metrics.stage("Split").unwrap().elements, Some(3));
+    /// ```
+    pub fn sized<F: 'static + Send + Sync + Fn(&A) -> usize>(self, f: F) -> Deferred<A> {
+        let size: SizeFn = Arc::new(move |out: &BASS| out.downcast_ref::<A>().map(&f));
+        Deferred {
+            graph: self.graph.sized(size),
+            items: PhantomData
+        }
+    }
+
+    /// Keeps the value once a run computes it, so later runs of this Deferred or
+    /// anything built from it read the kept value rather than computing it and its
+    /// dependencies again.  The value is released when the cached Deferred and
+    /// everything built from it are dropped.
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    /// use std::sync::atomic::{AtomicUsize,Ordering};
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let calls = Arc::new(AtomicUsize::new(0));
+    /// let c = calls.clone();
+    /// let data = Deferred::lift(3usize, None)
+    ///     .apply(move |x| { c.fetch_add(1, Ordering::SeqCst); x * 2 })
+    ///     .cache();
+    /// assert_eq!(data.apply(|x| x + 1).run(&GreedyScheduler::new()), Some(7));
+    /// assert_eq!(data.apply(|x| x * 10).run(&GreedyScheduler::new()), Some(60));
+    /// assert_eq!(calls.load(Ordering::SeqCst), 1);
+    /// ```
+    pub fn cache(&self) -> Deferred<A> {
+        Deferred {
+            graph: self.graph.cached(),
+            items: PhantomData
+        }
+    }
+
+    /// Runs the step producing this Deferred's value under `limit`, so no more
+    /// steps sharing the limit run at once than it allows.  Steps applied to the
+    /// value, such as by `apply`, run under the limit too; those joining it with
+    /// another value don't.
+    pub fn with_limit(&self, limit: &ConcurrencyLimit) -> Deferred<A> {
+        Deferred {
+            graph: self.graph.limited(limit),
+            items: PhantomData
+        }
+    }
+
+    /// Estimates the bytes this Deferred's value takes in memory, for schedulers
+    /// keeping the values computed at once within a budget, such as a
+    /// GreedyScheduler given `max_live_bytes`.  Values without an estimate aren't
+    /// counted.
+    pub fn size_hint_bytes(&self, bytes: usize) -> Deferred<A> {
+        Deferred {
+            graph: self.graph.hinted(bytes),
+            items: PhantomData
+        }
+    }
+
+    /// Evaluates the Deferred object and dependency graph, returning the result
+    /// of the computation.
     /// 
     /// ```
     /// use tange::deferred::Deferred;
@@ -113,12 +316,106 @@ impl <A: Any + Send + Sync + Clone> Deferred<A> {
     /// ```
 
     pub fn run<S: Scheduler>(&self, s: &S) -> Option<A> {
-        s.compute(self.graph.clone()).and_then(|v| { 
-            Arc::try_unwrap(v).ok().and_then(|ab| {
-                ab.downcast_ref::<A>().map(|x| x.clone())
-            })
-        })
+        downcast(s.compute(self.graph.clone()))
+    }
+
+    /// Executes the Computation, as with `run`, reporting to `progress` as tasks
+    /// finish.
+    /// 
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::{GreedyScheduler,Progress};
+    ///
+    /// let a = Deferred::lift(1usize, "a".into());
+    /// let b = Deferred::lift(2usize, "b".into());
+    /// let c = a.join(&b, |x, y| x + y);
+    /// let progress = Progress::new();
+    /// assert_eq!(c.run_with_progress(&GreedyScheduler::new(), &progress), Some(3usize));
+    /// assert_eq!(progress.completed(), progress.total());
+    /// ```
+    pub fn run_with_progress<S: Scheduler>(&self, s: &S, progress: &Progress) -> Option<A> {
+        downcast(s.compute_with_progress(self.graph.clone(), progress))
     }
+
+    /// Executes the Computation, as with `run_with_progress`, until `token` is
+    /// cancelled.  Once it is, no new tasks start, though those already running
+    /// finish, and the number of tasks completed is returned in the error.
+    /// 
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::{GreedyScheduler,Progress,CancellationToken};
+    ///
+    /// let a = Deferred::lift(1usize, "a".into()).apply(|x| x + 1);
+    /// let token = CancellationToken::new();
+    /// let progress = Progress::new();
+    /// assert_eq!(a.run_cancellable(&GreedyScheduler::new(), &progress, &token), Ok(Some(2)));
+    /// token.cancel();
+    /// assert!(a.run_cancellable(&GreedyScheduler::new(), &progress, &token).is_err());
+    /// ```
+    pub fn run_cancellable<S: Scheduler>(
+        &self, 
+        s: &S, 
+        progress: &Progress, 
+        token: &CancellationToken
+    ) -> Result<Option<A>, Cancelled> {
+        match self.try_run_until(s, progress, token) {
+            Ok(out)                     => Ok(out),
+            Err(RunError::Cancelled(c)) => Err(c),
+            Err(RunError::Failed(e))    => panic!("{}", e)
+        }
+    }
+
+    /// Executes the Computation, as with `run`, returning an error naming the
+    /// failed task rather than panicking if any task panics.  Tasks not yet
+    /// started when one fails are never run.
+    /// 
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::{GreedyScheduler,RunError};
+    ///
+    /// let def = Deferred::lift(0usize, None)
+    ///     .apply(|x| if *x == 0 { panic!("zero!") } else { 10 / x })
+    ///     .named("Divide");
+    /// match def.try_run(&GreedyScheduler::new()) {
+    ///     Err(RunError::Failed(e)) => assert_eq!(e.to_string(), "task 'Divide' panicked: zero!"),
+    ///     _                        => panic!("expected a failure")
+    /// }
+    /// ```
+    pub fn try_run<S: Scheduler>(&self, s: &S) -> Result<Option<A>, RunError> {
+        self.try_run_until(s, &Progress::new(), &CancellationToken::new())
+    }
+
+    /// Executes the Computation as with `try_run`, reporting to `progress` as each
+    /// task finishes and giving up once `token` is cancelled.
+    pub fn try_run_until<S: Scheduler>(
+        &self, 
+        s: &S, 
+        progress: &Progress, 
+        token: &CancellationToken
+    ) -> Result<Option<A>, RunError> {
+        s.try_compute(self.graph.clone(), progress, token).map(downcast)
+    }
+
+    /// Executes the Computation on an AsyncScheduler, as with `run`, returning a
+    /// future of the result for async code to wait on rather than blocking.
+    #[cfg(feature = "tokio")]
+    pub fn run_async(&self, s: &AsyncScheduler) -> impl Future<Output = Option<A>> + Send {
+        self.try_run_async(s).map(|out| out.unwrap_or_else(|e| panic!("{}", e)))
+    }
+
+    /// Executes the Computation on an AsyncScheduler as with `try_run`, returning
+    /// a future of the result.
+    #[cfg(feature = "tokio")]
+    pub fn try_run_async(&self, s: &AsyncScheduler) -> impl Future<Output = Result<Option<A>, RunError>> + Send {
+        s.compute_async(self.graph.clone(), Progress::new(), CancellationToken::new())
+            .map(|out| out.map(downcast))
+    }
+}
+
+// Reads the value a scheduler computed, if it's of the expected type.  The value
+// may still be shared, such as with a scheduler's cache, so it's always copied.
+fn downcast<A: Any + Clone>(out: Option<Arc<BASS>>) -> Option<A> {
+    out.and_then(|v| v.downcast_ref::<A>().cloned())
 }
 
 /// `batch_apply` is a convenience method that takes a set of homogenous `Deferred`s
@@ -212,10 +509,37 @@ pub fn tree_reduce_until<A: Any + Send + Sync + Clone,
     }
 }
 
+/// `gather` combines several Deferred into one holding all of their values, in
+/// order.  Running it evaluates every one of them in a single pass, so the steps
+/// they share are computed only once.
+/// ```
+/// use std::sync::Arc;
+/// use std::sync::atomic::{AtomicUsize,Ordering};
+/// use tange::deferred::{Deferred, gather};
+/// use tange::scheduler::GreedyScheduler;
+///
+/// let calls = Arc::new(AtomicUsize::new(0));
+/// let c = calls.clone();
+/// let shared = Deferred::lift(4usize, None).apply(move |x| { c.fetch_add(1, Ordering::SeqCst); x * 2 });
+/// let roots = vec![shared.apply(|x| x + 1), shared.apply(|x| x * 10)];
+/// assert_eq!(gather(&roots).run(&GreedyScheduler::new()), Some(vec![9, 80]));
+/// assert_eq!(calls.load(Ordering::SeqCst), 1);
+/// ```
+pub fn gather<A: Any + Send + Sync + Clone>(defs: &[Deferred<A>]) -> Deferred<Vec<A>> {
+    let wrapped: Vec<_> = defs.iter().map(|d| d.apply(|x| vec![x.clone()])).collect();
+    let all = tree_reduce(&wrapped, |x, y| {
+        let mut v1 = x.clone();
+        v1.extend(y.iter().cloned());
+        v1
+    });
+    all.unwrap_or_else(|| Deferred::lift(Vec::new(), None))
+}
+
 #[cfg(test)]
-mod def_test {
+pub(crate) mod def_test {
     use super::*;
-    use scheduler::{LeveledScheduler,GreedyScheduler};
+    use std::sync::atomic::{AtomicUsize,Ordering};
+    use scheduler::{LeveledScheduler,GreedyScheduler,SerialScheduler,TaskError};
 
     #[test]
     fn test_tree_reduce() {
@@ -231,6 +555,459 @@ mod def_test {
         assert_eq!(results, Some(res));
     }
 
+    #[test]
+    fn test_named() {
+        let input = Deferred::lift(2usize, "two".into());
+        let doubled = input.apply(|x| x * 2).named("Double");
+        let summed = tree_reduce(&[doubled.clone(), input.clone(), doubled.clone()], |x, y| x + y)
+            .unwrap()
+            .named("Sum");
+
+        let node = summed.node();
+        assert_eq!(node.name(), "Sum");
+        assert_eq!(node.inputs().len(), 2);
+        assert_eq!(node.inputs()[0].name(), "Join");
+        assert_eq!(node.inputs()[1].name(), "Double");
+        assert_eq!(node.inputs()[1].inputs()[0].name(), "two");
+        assert!(input.node().inputs().is_empty());
+        assert_eq!(summed.run(&LeveledScheduler), Some(10));
+        assert_eq!(summed.run(&GreedyScheduler::new()), Some(10));
+    }
+
+    fn failing() -> Deferred<usize> {
+        let v: Vec<_> = (0..8usize)
+            .map(|x| Deferred::lift(x, None).apply(move |x| {
+                if *x == 5 { panic!("bad input: {}", x) }
+                *x
+            }))
+            .collect();
+        tree_reduce(&v, |x, y| x + y).unwrap()
+    }
+
+    #[test]
+    fn test_panic_names_task() {
+        use std::panic::{catch_unwind,AssertUnwindSafe};
+
+        let failing = Deferred::lift(5usize, None)
+            .apply(|x| -> usize { panic!("bad input: {}", x) });
+        let unnamed = failing.clone().named("Check");
+        let named = failing.named_part("Check", 12).apply(|x| x + 1);
+
+        let err = catch_unwind(AssertUnwindSafe(|| named.run(&LeveledScheduler))).unwrap_err();
+        assert_eq!(err.downcast_ref::<String>().unwrap(),
+                   "task 'Check' partition 12 panicked: bad input: 5");
+        let err = catch_unwind(AssertUnwindSafe(|| unnamed.run(&GreedyScheduler::new()))).unwrap_err();
+        assert_eq!(err.downcast_ref::<String>().unwrap(), "task 'Check' panicked: bad input: 5");
+    }
+
+    #[test]
+    #[should_panic(expected="bad input: 5")]
+    fn test_panic_leveled() {
+        failing().run(&LeveledScheduler);
+    }
+
+    #[test]
+    #[should_panic(expected="bad input: 5")]
+    fn test_panic_greedy() {
+        failing().run(&GreedyScheduler::new());
+    }
+
+    fn check_try_run<S: Scheduler>(s: &S) {
+        let failing = Deferred::lift(5usize, None)
+            .apply(|x| -> usize { panic!("bad input: {}", x) })
+            .named_part("Check", 2);
+        let err = match failing.apply(|x| x + 1).try_run(s) {
+            Err(RunError::Failed(e)) => e,
+            other                    => panic!("expected a failure, got {:?}", other)
+        };
+        assert_eq!(err, TaskError {
+            stage: Some("Check".into()),
+            partition: Some(2),
+            message: "bad input: 5".into()
+        });
+
+        // The scheduler is still usable afterwards
+        let v: Vec<_> = (0..8usize).map(|x| Deferred::lift(x, None)).collect();
+        assert_eq!(tree_reduce(&v, |x, y| x + y).unwrap().try_run(s), Ok(Some(28)));
+    }
+
+    #[test]
+    fn test_try_run() {
+        check_try_run(&LeveledScheduler);
+        check_try_run(&GreedyScheduler::new());
+        check_try_run(&SerialScheduler::new());
+    }
+
+    #[test]
+    fn test_retry() {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::time::Duration;
+        use scheduler::RetryPolicy;
+
+        // Fails the first two times it runs, counted by `calls`
+        fn flaky(upstream: &Arc<AtomicUsize>, calls: &Arc<AtomicUsize>) -> Deferred<usize> {
+            let (upstream, calls) = (upstream.clone(), calls.clone());
+            Deferred::lift(20usize, None)
+                .apply(move |x| { upstream.fetch_add(1, Ordering::SeqCst); x + 1 })
+                .named("Read")
+                .apply(move |x| {
+                    if calls.fetch_add(1, Ordering::SeqCst) < 2 { panic!("connection reset") }
+                    x * 2
+                })
+                .named_part("Fetch", 0)
+        }
+        let upstream = Arc::new(AtomicUsize::new(0));
+        let calls = Arc::new(AtomicUsize::new(0));
+
+        let mut scheduler = GreedyScheduler::new();
+        scheduler.record_metrics(true);
+        match flaky(&upstream, &calls).try_run(&scheduler) {
+            Err(RunError::Failed(e)) => assert_eq!(e.message, "connection reset"),
+            other                    => panic!("expected a failure, got {:?}", other)
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        calls.store(0, Ordering::SeqCst);
+        upstream.store(0, Ordering::SeqCst);
+        scheduler.set_retry_policy(RetryPolicy::new(3).with_backoff(Duration::from_millis(1)));
+        assert_eq!(flaky(&upstream, &calls).try_run(&scheduler), Ok(Some(42)));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(upstream.load(Ordering::SeqCst), 1);
+        let metrics = scheduler.last_run_metrics().unwrap();
+        assert_eq!(metrics.stage("Fetch").unwrap().retries, 2);
+        assert_eq!(metrics.stage("Read").unwrap().retries, 0);
+
+        // Errors the predicate rejects aren't retried
+        calls.store(0, Ordering::SeqCst);
+        scheduler.set_retry_policy(RetryPolicy::new(3).retry_if(|e| e.message.contains("timed out")));
+        assert!(flaky(&upstream, &calls).try_run(&scheduler).is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    fn check_progress<S: Scheduler>(s: &S) {
+        use std::sync::Mutex;
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let progress = Progress::with_callback(move |done, total| {
+            log.lock().unwrap().push((done, total));
+        });
+        let v: Vec<_> = (0..50usize).map(|x| Deferred::lift(x, None).apply(|x| x * 2)).collect();
+        let agg = tree_reduce(&v, |x, y| x + y).unwrap();
+        assert_eq!(agg.run_with_progress(s, &progress), Some(2450));
+
+        let seen = seen.lock().unwrap();
+        let total = progress.total();
+        assert!(total > 0);
+        assert_eq!(progress.completed(), total);
+        assert_eq!(*seen, (1..total + 1).map(|done| (done, total)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_progress() {
+        check_progress(&LeveledScheduler);
+        check_progress(&GreedyScheduler::new());
+    }
+
+    #[test]
+    fn test_metrics() {
+        use std::thread::sleep;
+        use std::time::Duration;
+
+        let v: Vec<_> = (0..4usize).map(|x| {
+            Deferred::lift(x, None)
+                .apply(|x| { sleep(Duration::from_millis(50)); vec![*x; 3] })
+                .named_part("Slow", x)
+                .sized(|xs| xs.len())
+                .apply(|xs| xs.iter().sum::<usize>())
+                .named_part("Fast", x)
+        }).collect();
+        let total = tree_reduce(&v, |x, y| x + y).unwrap();
+
+        let mut scheduler = GreedyScheduler::new();
+        assert_eq!(total.run(&scheduler), Some(18));
+        assert!(scheduler.last_run_metrics().is_none());
+
+        scheduler.record_metrics(true);
+        assert_eq!(total.run(&scheduler), Some(18));
+        let metrics = scheduler.last_run_metrics().unwrap();
+        let stages = metrics.stages();
+        assert_eq!(stages[0].0, "Slow");
+        assert_eq!(stages[0].1.tasks, 4);
+        assert_eq!(stages[0].1.elements, Some(12));
+        assert!(stages[0].1.elapsed >= Duration::from_millis(200));
+        assert_eq!(metrics.stage("Fast").unwrap().elements, None);
+        assert!(metrics.to_string().lines().nth(1).unwrap().starts_with("Slow "));
+    }
+
+    fn check_cancel<S: Scheduler>(s: &S) {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        // Enough tasks to keep every thread busy for well past the cancellation
+        let n = 32 * thread::available_parallelism().map(|n| n.get()).unwrap_or(1).max(4);
+        let ran = Arc::new(AtomicUsize::new(0));
+        let v: Vec<_> = (0..n).map(|x| {
+            let ran = ran.clone();
+            Deferred::lift(x, None).apply(move |x| {
+                ran.fetch_add(1, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(20));
+                *x
+            })
+        }).collect();
+        let total = tree_reduce(&v, |x, y| x + y).unwrap();
+
+        let token = CancellationToken::new();
+        let canceller = token.clone();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            canceller.cancel();
+        });
+        let progress = Progress::new();
+        let res = total.run_cancellable(s, &progress, &token);
+        handle.join().unwrap();
+
+        let err = res.unwrap_err();
+        assert_eq!(err.total, progress.total());
+        assert!(err.completed < err.total);
+        assert!(ran.load(Ordering::SeqCst) < n / 2, "{} of {} tasks ran", ran.load(Ordering::SeqCst), n);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut greedy = GreedyScheduler::new();
+        greedy.set_threads(4);
+        check_cancel(&greedy);
+        check_cancel(&LeveledScheduler);
+        check_cancel(&SerialScheduler::new());
+    }
+
+    fn check_cache<S: Scheduler>(s: &S) {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+
+        let calls = Arc::new(AtomicUsize::new(0));
+        let v: Vec<_> = (0..8usize).map(|x| {
+            let calls = calls.clone();
+            Deferred::lift(x, None).apply(move |x| { calls.fetch_add(1, Ordering::SeqCst); x * 2 })
+        }).collect();
+        let total = tree_reduce(&v, |x, y| x + y).unwrap().cache();
+        assert_eq!(total.apply(|x| x + 1).run(s), Some(57));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+
+        // Other branches, and renamed copies, read the kept value
+        assert_eq!(total.apply(|x| x * 10).run(s), Some(560));
+        assert_eq!(total.named("Total").run(s), Some(56));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+
+        // The uncached parts are still computed each run
+        assert_eq!(v[3].run(s), Some(6));
+        assert_eq!(calls.load(Ordering::SeqCst), 9);
+    }
+
+    #[test]
+    fn test_cache() {
+        check_cache(&LeveledScheduler);
+        check_cache(&GreedyScheduler::new());
+        check_cache(&SerialScheduler::new());
+    }
+
+    // Counts the values alive at once
+    struct Tracked(usize, Arc<(AtomicUsize, AtomicUsize)>);
+
+    impl Tracked {
+        fn new(x: usize, live: &Arc<(AtomicUsize, AtomicUsize)>) -> Self {
+            let now = live.0.fetch_add(1, Ordering::SeqCst) + 1;
+            live.1.fetch_max(now, Ordering::SeqCst);
+            Tracked(x, live.clone())
+        }
+    }
+
+    impl Clone for Tracked {
+        fn clone(&self) -> Self {
+            Tracked::new(self.0, &self.1)
+        }
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            (self.1).0.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+
+    // Computes sixteen partitions of a kilobyte each on eight workers, summing them
+    // in pairs, and returns the most partitions alive at once.  The first of each
+    // pair finishes well before the second, so without a budget several wait at once.
+    fn live_partitions(scheduler: GreedyScheduler) -> usize {
+        use std::thread;
+        use std::time::Duration;
+
+        let live = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let parts: Vec<_> = (0..16usize).map(|x| {
+            let l = live.clone();
+            Deferred::lift(x, None).apply(move |x| {
+                thread::sleep(Duration::from_millis(if x % 2 == 0 { 5 } else { 30 }));
+                Tracked::new(*x, &l)
+            }).size_hint_bytes(1000)
+        }).collect();
+        let sums: Vec<_> = parts.chunks(2).map(|p| p[0].join(&p[1], |x, y| x.0 + y.0)).collect();
+        assert_eq!(tree_reduce(&sums, |x, y| x + y).unwrap().run(&scheduler), Some(120));
+        assert_eq!(live.0.load(Ordering::SeqCst), 0);
+        live.1.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_max_live_bytes() {
+        let wide = live_partitions(GreedyScheduler::builder().workers(8).build());
+        assert!(wide > 3, "only {} partitions were alive at once", wide);
+
+        let most = live_partitions(GreedyScheduler::builder().workers(8).max_live_bytes(3000).build());
+        assert!((1..=3).contains(&most), "{} partitions were alive at once", most);
+    }
+
+    // Runs twelve sleeping tasks under a limit of two alongside twelve unlimited
+    // ones, returning the most unlimited tasks seen at once
+    pub(crate) fn check_limit<S: Scheduler>(s: &S) -> usize {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let counter = || (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let sleeper = |(running, most): (Arc<AtomicUsize>, Arc<AtomicUsize>)| move |x: &usize| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(10));
+            running.fetch_sub(1, Ordering::SeqCst);
+            *x
+        };
+        let (limited, wide) = (counter(), counter());
+        let limit = ConcurrencyLimit::new(2);
+
+        // The inputs are tagged, and the steps applied to them run under the limit
+        let mut v: Vec<_> = (0..12usize)
+            .map(|x| Deferred::lift(x, None).with_limit(&limit).apply(sleeper(limited.clone())))
+            .collect();
+        v.extend((0..12usize).map(|x| Deferred::lift(x, None).apply(sleeper(wide.clone()))));
+        assert_eq!(tree_reduce(&v, |x, y| x + y).unwrap().run(s), Some(132));
+        let most_limited = limited.1.load(Ordering::SeqCst);
+        assert!((1..=2).contains(&most_limited), "{} limited tasks ran at once", most_limited);
+        wide.1.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_limit() {
+        check_limit(&LeveledScheduler);
+        check_limit(&SerialScheduler::new());
+
+        // Other tasks fill the workers the limited ones can't use
+        let most = check_limit(&GreedyScheduler::builder().workers(6).build());
+        assert!(most >= 3, "only {} unlimited tasks ran at once", most);
+    }
+
+    // Runs 12 sleeping tasks on `workers` threads, returning the most seen at once
+    fn max_concurrency(workers: usize) -> usize {
+        use std::sync::atomic::{AtomicUsize,Ordering};
+        use std::thread;
+        use std::time::Duration;
+
+        let running = Arc::new(AtomicUsize::new(0));
+        let most = Arc::new(AtomicUsize::new(0));
+        let v: Vec<_> = (0..12usize).map(|x| {
+            let (running, most) = (running.clone(), most.clone());
+            Deferred::lift(x, None).apply(move |x| {
+                let name = thread::current().name().map(|n| n.to_owned());
+                assert!(name.map(|n| n.starts_with("tange-worker-")).unwrap_or(false));
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                most.fetch_max(now, Ordering::SeqCst);
+                thread::sleep(Duration::from_millis(10));
+                running.fetch_sub(1, Ordering::SeqCst);
+                *x
+            })
+        }).collect();
+        let scheduler = GreedyScheduler::builder().workers(workers).build();
+        assert_eq!(scheduler.workers(), workers);
+        assert_eq!(tree_reduce(&v, |x, y| x + y).unwrap().run(&scheduler), Some(66));
+        most.load(Ordering::SeqCst)
+    }
+
+    #[test]
+    fn test_workers() {
+        assert_eq!(max_concurrency(1), 1);
+        let most = max_concurrency(3);
+        assert!((1..=3).contains(&most), "{} tasks ran at once", most);
+    }
+
+    #[test]
+    fn test_stragglers() {
+        use std::thread;
+        use std::time::{Duration,Instant};
+
+        // One slow partition and fifteen fast ones, whose reduction is done by the
+        // time the slow one finishes rather than waiting to start until it has
+        let sleepy = |ms| move |x: &u64| {
+            thread::sleep(Duration::from_millis(ms));
+            *x
+        };
+        let slow = Deferred::lift(0u64, None).apply(sleepy(300));
+        let fast: Vec<_> = (1..16u64).map(|x| Deferred::lift(x, None).apply(sleepy(10))).collect();
+        let fast = tree_reduce(&fast, |x, y| {
+            thread::sleep(Duration::from_millis(50));
+            x + y
+        }).unwrap();
+        let total = slow.join(&fast, |x, y| {
+            thread::sleep(Duration::from_millis(10));
+            x + y
+        });
+
+        let scheduler = GreedyScheduler::builder().workers(8).build();
+        let start = Instant::now();
+        assert_eq!(total.run(&scheduler), Some(120));
+        let elapsed = start.elapsed();
+
+        // Waiting on the slow partition before reducing would take 510ms
+        assert!(elapsed >= Duration::from_millis(310), "took {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(420), "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_serial() {
+        use std::sync::Mutex;
+
+        // Builds a graph mixing joins, shared steps and partitions
+        let v: Vec<_> = (0..10usize)
+            .map(|x| Deferred::lift(x, None).apply(|x| x * 3).named_part("Triple", x))
+            .collect();
+        let shared = tree_reduce(&v, |x, y| x + y).unwrap().named("Sum");
+        let out = shared.join(&v[4], |x, y| x - y).join(&shared, |x, y| x * y);
+
+        let visited = Arc::new(Mutex::new(Vec::new()));
+        let v2 = visited.clone();
+        let serial = SerialScheduler::new().on_task(move |name, part| v2.lock().unwrap().push((name.to_owned(), part)));
+        let visits = || {
+            let res = out.run(&serial);
+            (res, visited.lock().unwrap().drain(..).collect::<Vec<_>>())
+        };
+        let (first, order) = visits();
+        let (second, again) = visits();
+
+        assert_eq!(first, Some((135 - 12) * 135));
+        assert_eq!(first, second);
+        assert_eq!(first, out.run(&GreedyScheduler::new()));
+        assert_eq!(order, again);
+        assert_eq!(order[0], ("Input".to_owned(), None));
+        assert_eq!(order[1], ("Triple".to_owned(), Some(0)));
+        assert_eq!(order.last().unwrap().0, "Join");
+        assert_eq!(order.iter().filter(|n| n.0 == "Sum").count(), 1);
+    }
+
+    #[test]
+    fn test_gather() {
+        let v: Vec<_> = (0..5usize).map(|x| Deferred::lift(x, None).apply(|x| x * 2)).collect();
+        assert_eq!(gather(&v).run(&LeveledScheduler), Some(vec![0, 2, 4, 6, 8]));
+        assert_eq!(gather(&v[..1]).run(&GreedyScheduler::new()), Some(vec![0]));
+        assert_eq!(gather::<usize>(&[]).run(&LeveledScheduler), Some(vec![]));
+    }
+
     #[test]
     fn test_tree_reduce_greedy() {
         let v: Vec<_> = (0..2usize).into_iter()
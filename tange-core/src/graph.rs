@@ -2,10 +2,13 @@
 //! Graph definition libraries.  These are typically not used directly, instead accessed
 //! via Deferred objects.
 //!
-use std::sync::Arc;
+use std::sync::{Arc,OnceLock};
 use std::sync::atomic::{AtomicUsize, ATOMIC_USIZE_INIT, Ordering};
 
-use task::{BASS,DynRun};
+use task::{BASS,DynRun,SizeFn,TapFn,ResultCell};
+#[cfg(feature = "tokio")]
+use task::DynAsync;
+use scheduler::ConcurrencyLimit;
 
 static GLOBAL_HANDLE_COUNT: AtomicUsize = ATOMIC_USIZE_INIT;
 
@@ -24,6 +27,11 @@ impl Handle {
     fn new(name: String) -> Self {
         Handle(name, GLOBAL_HANDLE_COUNT.fetch_add(1, Ordering::SeqCst))
     }
+
+    /// Returns the unique id of the handle
+    pub fn id(&self) -> usize {
+        self.1
+    }
 }
 
 /// ADT for handling either Tasks or reading data into the graph
@@ -33,7 +41,14 @@ pub enum Task {
     Function(Box<DynRun>),
     
     /// Node which generates data
-    Input(Box<Input>)
+    Input(Box<Input>),
+
+    /// Node holding the output of an earlier run
+    Stored(Arc<BASS>),
+
+    /// Node which consumes down stream data to produce a future of new data
+    #[cfg(feature = "tokio")]
+    Async(Box<dyn DynAsync>)
 }
 
 /// Holds references to the number of arguments to pass into a Task
@@ -59,7 +74,28 @@ pub struct Graph {
     pub task: Arc<Task>,
 
     /// Arguments consumed by defined Task
-    pub args: Option<FnArgs>
+    pub args: Option<FnArgs>,
+
+    /// Name of the computation, as given when it was created
+    pub name: String,
+
+    /// Which of a set of partitions the computation produces, if it's one of many
+    pub part: Option<usize>,
+
+    /// Counts the elements in the computation's output, for run metrics
+    pub size: Option<SizeFn>,
+
+    /// Receives the computation's output once it's computed
+    pub tap: Option<TapFn>,
+
+    /// Keeps the computation's output for later runs, which then skip computing it
+    pub cell: Option<ResultCell>,
+
+    /// Caps how many computations sharing it run at once
+    pub limit: Option<ConcurrencyLimit>,
+
+    /// Estimates how many bytes the computation's output takes in memory
+    pub bytes: Option<usize>
 
 }
 
@@ -73,7 +109,14 @@ impl Graph {
         Arc::new(Graph {
             handle: handle,
             task: inp,
-            args: None
+            args: None,
+            name: name.into(),
+            part: None,
+            size: None,
+            tap: None,
+            cell: None,
+            limit: None,
+            bytes: None
         })
     }
 
@@ -84,12 +127,114 @@ impl Graph {
         let h_name = format!("Task<name={}>", name);
         let handle = Arc::new(Handle::new(h_name));
         let task = Arc::new(Task::Function(Box::new(t)));
+        let limit = inherited_limit(&inputs);
         Arc::new(Graph {
             handle: handle,
             task: task,
-            args: Some(inputs)
+            args: Some(inputs),
+            name: name.into(),
+            part: None,
+            size: None,
+            tap: None,
+            cell: None,
+            limit,
+            bytes: None
         })
     }
 
+    /// Adds a task to the dataset with the given inputs, as with `create_task`, whose
+    /// output is computed by a future.
+    #[cfg(feature = "tokio")]
+    pub fn create_async_task<D: 'static + DynAsync>(inputs: FnArgs, t: D, name: &str) -> Arc<Graph> {
+        let handle = Arc::new(Handle::new(format!("Task<name={}>", name)));
+        let limit = inherited_limit(&inputs);
+        Arc::new(Graph {
+            handle,
+            task: Arc::new(Task::Async(Box::new(t))),
+            args: Some(inputs),
+            name: name.into(),
+            part: None,
+            size: None,
+            tap: None,
+            cell: None,
+            limit,
+            bytes: None
+        })
+    }
+
+    /// Creates a copy of the Graph under a new name, partition, and handle, sharing
+    /// its task, arguments, and any output kept by `cached`.
+    pub fn rename(&self, name: &str, part: Option<usize>) -> Arc<Graph> {
+        let h_name = match self.args {
+            Some(_) => format!("Task<name={}>", name),
+            None    => format!("Input<name={}>", name)
+        };
+        Arc::new(Graph {
+            handle: Arc::new(Handle::new(h_name)),
+            task: self.task.clone(),
+            args: self.args.clone(),
+            name: name.into(),
+            part,
+            size: self.size.clone(),
+            tap: None,
+            cell: self.cell.clone(),
+            limit: self.limit.clone(),
+            bytes: self.bytes
+        })
+    }
+
+    /// Creates a copy of the Graph under a new handle, as with `rename`, which
+    /// counts the elements of its output with `size`.
+    pub fn sized(&self, size: SizeFn) -> Arc<Graph> {
+        let renamed = self.rename(&self.name, self.part);
+        Arc::new(Graph { size: Some(size), ..(*renamed).clone() })
+    }
+
+    /// Creates a copy of the Graph under the same handle whose task is replaced by
+    /// `value`, the output of an earlier run, so none of its arguments are needed.
+    pub fn stored(&self, value: Arc<BASS>) -> Arc<Graph> {
+        Arc::new(Graph { task: Arc::new(Task::Stored(value)), args: None, tap: None, ..self.clone() })
+    }
+
+    /// Creates a copy of the Graph under the same handle, reading its arguments
+    /// from `args` and handing its output to `tap`.
+    pub fn tapped(&self, args: Option<FnArgs>, tap: TapFn) -> Arc<Graph> {
+        Arc::new(Graph { args, tap: Some(tap), ..self.clone() })
+    }
+
+    /// Creates a copy of the Graph under a new handle, as with `rename`, whose
+    /// output is kept by the first run computing it and read back by later runs.
+    pub fn cached(&self) -> Arc<Graph> {
+        let renamed = self.rename(&self.name, self.part);
+        Arc::new(Graph { cell: Some(Arc::new(OnceLock::new())), ..(*renamed).clone() })
+    }
+
+    /// Creates a copy of the Graph under a new handle, as with `rename`, which runs
+    /// under `limit`.  Tasks created from it alone keep the limit, while those
+    /// joining it with another don't.
+    pub fn limited(&self, limit: &ConcurrencyLimit) -> Arc<Graph> {
+        let renamed = self.rename(&self.name, self.part);
+        Arc::new(Graph { limit: Some(limit.clone()), ..(*renamed).clone() })
+    }
+
+    /// Creates a copy of the Graph under a new handle, as with `rename`, whose
+    /// output is estimated to take `bytes` bytes
+    pub fn hinted(&self, bytes: usize) -> Arc<Graph> {
+        let renamed = self.rename(&self.name, self.part);
+        Arc::new(Graph { bytes: Some(bytes), ..(*renamed).clone() })
+    }
+
+    /// Returns the output kept by `cached`, if a run has computed it
+    pub fn cached_value(&self) -> Option<Arc<BASS>> {
+        self.cell.as_ref().and_then(|c| c.get()).cloned()
+    }
+
 }
 
+// Tasks computed from a single input run under its limit, if it has one
+fn inherited_limit(inputs: &FnArgs) -> Option<ConcurrencyLimit> {
+    match inputs {
+        FnArgs::Single(g) => g.limit.clone(),
+        FnArgs::Join(_, _) => None
+    }
+}
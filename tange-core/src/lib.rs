@@ -42,15 +42,30 @@
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "tokio")]
+extern crate tokio;
+
+#[cfg(feature = "tokio")]
+extern crate futures;
+
 /// Contains Deferred primitive and function definitions
 pub mod deferred;
 
 /// Contains Scheduler trait definition and implementations
 pub mod scheduler;
 
+/// Contains the timings recorded by schedulers
+pub mod metrics;
+
+/// Contains a scheduler reusing results across runs
+pub mod memo;
+
 /// Internal Graph implementation
 mod graph;
 
 /// Internal task definitions
 mod task;
 
+/// Internal thread pool
+mod pool;
+
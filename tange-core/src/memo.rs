@@ -0,0 +1,205 @@
+//! Defines a scheduler which remembers the results of earlier runs.
+//!
+use std::collections::HashMap;
+use std::sync::{Arc,Mutex};
+
+use task::{BASS,TapFn};
+use graph::{Graph,FnArgs};
+use scheduler::{Scheduler,Progress,CancellationToken,RunError};
+
+// Outputs of earlier runs by handle id, along with when each was last used
+struct ResultCache {
+    entries: HashMap<usize, (Arc<BASS>, u64)>,
+    capacity: Option<usize>,
+    clock: u64
+}
+
+impl ResultCache {
+    fn get(&mut self, id: usize) -> Option<Arc<BASS>> {
+        self.clock += 1;
+        let clock = self.clock;
+        self.entries.get_mut(&id).map(|e| {
+            e.1 = clock;
+            e.0.clone()
+        })
+    }
+
+    // Adds an output, evicting the least recently used ones past the capacity
+    fn insert(&mut self, id: usize, value: Arc<BASS>) {
+        self.clock += 1;
+        self.entries.insert(id, (value, self.clock));
+        if let Some(cap) = self.capacity {
+            while self.entries.len() > cap {
+                let oldest = self.entries.iter().min_by_key(|(_, e)| e.1).map(|(id, _)| *id);
+                match oldest {
+                    Some(id) => self.entries.remove(&id),
+                    None     => break
+                };
+            }
+        }
+    }
+}
+
+/// Wraps a Scheduler, keeping the output of every task it runs so that later runs
+/// sharing part of the same graph, such as two aggregations over one Deferred,
+/// compute the shared part only once.  Outputs are kept for as long as the
+/// scheduler lives, unless bounded with `with_capacity` or dropped with `clear`.
+/// ```
+/// use tange::deferred::Deferred;
+/// use tange::memo::MemoizingScheduler;
+/// use tange::scheduler::GreedyScheduler;
+///
+/// let scheduler = MemoizingScheduler::new(GreedyScheduler::new());
+/// let data = Deferred::lift(vec![1usize, 2, 3], None).apply(|v| v.iter().map(|x| x * 2).collect::<Vec<_>>());
+/// assert_eq!(data.apply(|v| v.len()).run(&scheduler), Some(3));
+/// // `data` is read from the cache
+/// assert_eq!(data.apply(|v| v.iter().sum::<usize>()).run(&scheduler), Some(12));
+/// ```
+pub struct MemoizingScheduler<S> {
+    inner: S,
+    cache: Arc<Mutex<ResultCache>>
+}
+
+impl <S: Scheduler> MemoizingScheduler<S> {
+
+    /// Creates a MemoizingScheduler running tasks with `inner` and keeping every
+    /// output
+    pub fn new(inner: S) -> Self {
+        MemoizingScheduler::build(inner, None)
+    }
+
+    /// Creates a MemoizingScheduler keeping at most `max_entries` outputs, evicting
+    /// the least recently used first
+    pub fn with_capacity(inner: S, max_entries: usize) -> Self {
+        MemoizingScheduler::build(inner, Some(max_entries))
+    }
+
+    fn build(inner: S, capacity: Option<usize>) -> Self {
+        let cache = ResultCache { entries: HashMap::new(), capacity, clock: 0 };
+        MemoizingScheduler { inner, cache: Arc::new(Mutex::new(cache)) }
+    }
+
+    /// Returns the wrapped scheduler
+    pub fn inner(&self) -> &S {
+        &self.inner
+    }
+
+    /// Returns the number of outputs kept
+    pub fn len(&self) -> usize {
+        self.cache.lock().unwrap().entries.len()
+    }
+
+    /// Returns whether no outputs are kept
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drops every output kept so far
+    pub fn clear(&self) {
+        self.cache.lock().unwrap().entries.clear();
+    }
+
+    // Rewrites the graph so steps computed by an earlier run read their stored
+    // output, and the rest hand theirs to the cache.
+    fn prepare(&self, graph: &Arc<Graph>, seen: &mut HashMap<usize, Arc<Graph>>) -> Arc<Graph> {
+        let id = graph.handle.id();
+        if let Some(g) = seen.get(&id) {
+            return g.clone();
+        }
+        let stored = self.cache.lock().unwrap().get(id);
+        let g = match stored {
+            Some(value) => graph.stored(value),
+            None        => {
+                let args = match graph.args {
+                    None                             => None,
+                    Some(FnArgs::Single(ref g))      => Some(FnArgs::Single(self.prepare(g, seen))),
+                    Some(FnArgs::Join(ref l, ref r)) => {
+                        Some(FnArgs::Join(self.prepare(l, seen), self.prepare(r, seen)))
+                    }
+                };
+                let cache = self.cache.clone();
+                let tap: TapFn = Arc::new(move |value| cache.lock().unwrap().insert(id, value.clone()));
+                graph.tapped(args, tap)
+            }
+        };
+        seen.insert(id, g.clone());
+        g
+    }
+}
+
+impl <S: Scheduler> Scheduler for MemoizingScheduler<S> {
+    fn compute(&self, graph: Arc<Graph>) -> Option<Arc<BASS>> {
+        self.inner.compute(self.prepare(&graph, &mut HashMap::new()))
+    }
+
+    fn compute_with_progress(&self, graph: Arc<Graph>, progress: &Progress) -> Option<Arc<BASS>> {
+        self.inner.compute_with_progress(self.prepare(&graph, &mut HashMap::new()), progress)
+    }
+
+    fn try_compute(
+        &self,
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        self.inner.try_compute(self.prepare(&graph, &mut HashMap::new()), progress, token)
+    }
+}
+
+#[cfg(test)]
+mod memo_test {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize,Ordering};
+    use deferred::{Deferred,tree_reduce};
+    use scheduler::{GreedyScheduler,LeveledScheduler};
+
+    // Doubles each of 0..8 and sums them, counting how often a number is doubled
+    fn doubled(calls: &Arc<AtomicUsize>) -> (Vec<Deferred<usize>>, Deferred<usize>) {
+        let v: Vec<_> = (0..8usize).map(|x| {
+            let calls = calls.clone();
+            Deferred::lift(x, None).apply(move |x| { calls.fetch_add(1, Ordering::SeqCst); x * 2 })
+        }).collect();
+        let total = tree_reduce(&v, |x, y| x + y).unwrap();
+        (v, total)
+    }
+
+    fn check_reuse<S: Scheduler>(s: S) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (_, total) = doubled(&calls);
+        let scheduler = MemoizingScheduler::new(s);
+        assert_eq!(total.apply(|x| x + 1).run(&scheduler), Some(57));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+        assert_eq!(total.apply(|x| x * 10).run(&scheduler), Some(560));
+        assert_eq!(total.run(&scheduler), Some(56));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+
+        scheduler.clear();
+        assert!(scheduler.is_empty());
+        assert_eq!(total.run(&scheduler), Some(56));
+        assert_eq!(calls.load(Ordering::SeqCst), 16);
+    }
+
+    #[test]
+    fn test_reuse() {
+        check_reuse(LeveledScheduler);
+        check_reuse(GreedyScheduler::new());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (parts, total) = doubled(&calls);
+        let scheduler = MemoizingScheduler::with_capacity(LeveledScheduler, 4);
+        assert_eq!(total.run(&scheduler), Some(56));
+        assert_eq!(scheduler.len(), 4);
+
+        // The sum was computed last, so it's still kept
+        assert_eq!(total.apply(|x| x + 1).run(&scheduler), Some(57));
+        assert_eq!(calls.load(Ordering::SeqCst), 8);
+
+        // But the doubled values were evicted, so they're computed again
+        assert_eq!(parts[3].run(&scheduler), Some(6));
+        assert_eq!(calls.load(Ordering::SeqCst), 9);
+        assert_eq!(scheduler.len(), 4);
+    }
+}
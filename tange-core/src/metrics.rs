@@ -0,0 +1,122 @@
+//! Defines the timings schedulers record while running a Graph.
+//!
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// Time spent running the tasks of a single stage, that is all the steps sharing a
+/// name, and the number of elements they produced.
+#[derive(Clone,Debug,Default,PartialEq)]
+pub struct StageMetrics {
+    /// Number of tasks run
+    pub tasks: usize,
+
+    /// Wall-clock time spent running them, summed over every task
+    pub elapsed: Duration,
+
+    /// Number of elements produced, if the stage's steps were given a size with
+    /// `Deferred::sized`
+    pub elements: Option<usize>,
+
+    /// Number of times a failed task was run again, as allowed by the scheduler's
+    /// `RetryPolicy`
+    pub retries: usize
+}
+
+/// Timings of each stage of a run, retrieved from a scheduler after the run finishes.
+/// Displays as a table with the slowest stages first.
+#[derive(Clone,Debug,Default)]
+pub struct RunMetrics {
+    stages: HashMap<String, StageMetrics>
+}
+
+impl RunMetrics {
+
+    /// Returns the metrics for the stage `name`, if any of its tasks ran
+    pub fn stage(&self, name: &str) -> Option<&StageMetrics> {
+        self.stages.get(name)
+    }
+
+    /// Returns every stage along with its metrics, slowest first
+    pub fn stages(&self) -> Vec<(&str, &StageMetrics)> {
+        let mut stages: Vec<_> = self.stages.iter()
+            .map(|(name, m)| (name.as_str(), m))
+            .collect();
+        stages.sort_by(|a, b| b.1.elapsed.cmp(&a.1.elapsed).then(a.0.cmp(b.0)));
+        stages
+    }
+
+    /// Returns the time spent running tasks, summed over every stage
+    pub fn total(&self) -> Duration {
+        self.stages.values().map(|m| m.elapsed).sum()
+    }
+
+    // Adds a finished task to its stage
+    pub(crate) fn record(&mut self, name: &str, elapsed: Duration, elements: Option<usize>) {
+        let m = self.stages.entry(name.into()).or_default();
+        m.tasks += 1;
+        m.elapsed += elapsed;
+        if let Some(n) = elements {
+            m.elements = Some(m.elements.unwrap_or(0) + n);
+        }
+    }
+
+    // Counts a retry of one of a stage's tasks
+    pub(crate) fn record_retry(&mut self, name: &str) {
+        self.stages.entry(name.into()).or_default().retries += 1;
+    }
+}
+
+impl fmt::Display for RunMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows: Vec<_> = self.stages().into_iter().map(|(name, m)| {
+            let elements = m.elements.map(|n| n.to_string()).unwrap_or_else(|| "-".into());
+            [name.to_owned(), m.tasks.to_string(), format_secs(m.elapsed), elements]
+        }).collect();
+
+        let header = ["stage", "tasks", "elapsed", "elements"];
+        let mut widths: Vec<_> = header.iter().map(|h| h.len()).collect();
+        for row in rows.iter() {
+            for (w, cell) in widths.iter_mut().zip(row.iter()) {
+                *w = (*w).max(cell.len());
+            }
+        }
+
+        writeln!(f, "{:w0$}  {:>w1$}  {:>w2$}  {:>w3$}", header[0], header[1], header[2], header[3],
+                 w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3])?;
+        for row in rows.iter() {
+            writeln!(f, "{:w0$}  {:>w1$}  {:>w2$}  {:>w3$}", row[0], row[1], row[2], row[3],
+                     w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3])?;
+        }
+        Ok(())
+    }
+}
+
+fn format_secs(d: Duration) -> String {
+    format!("{:.3}s", d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9)
+}
+
+#[cfg(test)]
+mod metrics_test {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let mut metrics = RunMetrics::default();
+        metrics.record("parse", Duration::from_millis(250), Some(10));
+        metrics.record("parse", Duration::from_millis(1250), Some(5));
+        metrics.record("Input", Duration::from_millis(2), None);
+
+        assert_eq!(metrics.stage("parse"), Some(&StageMetrics {
+            tasks: 2,
+            elapsed: Duration::from_millis(1500),
+            elements: Some(15),
+            retries: 0
+        }));
+        assert_eq!(metrics.total(), Duration::from_millis(1502));
+        assert_eq!(metrics.to_string(), "\
+            stage  tasks  elapsed  elements\n\
+            parse      2   1.500s        15\n\
+            Input      1   0.002s         -\n");
+    }
+}
@@ -0,0 +1,57 @@
+//! A fixed-size pool of named worker threads, on which schedulers run tasks.
+//!
+use std::sync::{Arc,Mutex,mpsc};
+use std::thread::{self,JoinHandle};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+pub(crate) struct WorkerPool {
+    tx: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>
+}
+
+impl WorkerPool {
+
+    /// Starts `size` threads, named `tange-worker-0`, `tange-worker-1` and so on.
+    /// Pools always have at least one thread.
+    pub(crate) fn new(size: usize) -> Self {
+        let (tx, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        let workers = (0..size.max(1)).map(|id| {
+            let rx = rx.clone();
+            thread::Builder::new()
+                .name(format!("tange-worker-{}", id))
+                .spawn(move || loop {
+                    // The lock is released as soon as a job is received
+                    let job = rx.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_)  => break
+                    }
+                })
+                .expect("Unable to start worker thread")
+        }).collect();
+        WorkerPool { tx: Some(tx), workers }
+    }
+
+    /// Adds a job, which runs once a thread is free
+    pub(crate) fn queue<J: FnOnce() + Send + 'static>(&self, job: J) {
+        if let Some(ref tx) = self.tx {
+            tx.send(Box::new(job)).expect("Worker threads have stopped");
+        }
+    }
+
+    /// Waits for the queued jobs to finish, then stops the threads
+    pub(crate) fn shutdown(&mut self) {
+        self.tx.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
@@ -2,22 +2,45 @@
 extern crate num_cpus;
 extern crate log;
 extern crate priority_queue;
-extern crate jobpool;
 
-use std::sync::{Mutex,Arc,mpsc};
+use std::sync::{Condvar,Mutex,Arc,mpsc};
+use std::sync::atomic::{AtomicBool,AtomicUsize,Ordering};
+use std::cmp::Reverse;
 use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::any::Any;
+use std::error::Error;
+use std::fmt;
+use std::panic::{self,AssertUnwindSafe};
+use std::thread;
+use std::time::{Duration,Instant};
 
 use log::Level::{Trace,Debug as LDebug};
 use self::priority_queue::PriorityQueue;
-use self::jobpool::JobPool;
 
-use task::{BASS,DynArgs};
+use task::{BASS,DynArgs,SizeFn,TapFn};
+use metrics::RunMetrics;
 use graph::{Graph,Task,Handle,FnArgs};
+use pool::WorkerPool;
+
+#[cfg(feature = "rayon")]
+mod rayon_pool;
+
+#[cfg(feature = "rayon")]
+pub use self::rayon_pool::RayonScheduler;
+
+#[cfg(feature = "tokio")]
+mod tokio_pool;
+
+#[cfg(feature = "tokio")]
+pub use self::tokio_pool::AsyncScheduler;
 
 type DepGraph = HashMap<Arc<Handle>, HashSet<Arc<Handle>>>; 
 type ChainGraph = HashMap<Vec<Arc<Handle>>, HashSet<Arc<Handle>>>; 
 
+// Collects the metrics of a run, if they're being recorded
+type Recorder = Option<Arc<Mutex<RunMetrics>>>;
+
 // Keeps track of data that are needed by downstream computations
 #[derive(Debug)]
 struct DataStore<K: PartialEq + Hash + Eq, V> {
@@ -61,6 +84,403 @@ impl <K: PartialEq + Hash + Eq, V: Clone> DataStore<K,V> {
 pub trait Scheduler {
     /// Compute the given Graph, returning the value.
     fn compute(&self, graph: Arc<Graph>) -> Option<Arc<BASS>>; 
+
+    /// Compute the given Graph, reporting to `progress` as its tasks finish.  By
+    /// default the whole computation counts as a single task.
+    fn compute_with_progress(&self, graph: Arc<Graph>, progress: &Progress) -> Option<Arc<BASS>> {
+        progress.start(1);
+        let out = self.compute(graph);
+        progress.tick();
+        out
+    }
+
+    /// Compute the given Graph as with `compute_with_progress`, returning an error
+    /// rather than panicking if a task panics, and giving up once `token` is
+    /// cancelled.  Either way no task starts after that, though those already
+    /// running are allowed to finish.  By default the token is only checked before
+    /// the computation starts.
+    fn try_compute(
+        &self, 
+        graph: Arc<Graph>, 
+        progress: &Progress, 
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        if token.is_cancelled() {
+            return Err(RunError::Cancelled(Cancelled { completed: 0, total: 1 }));
+        }
+        panic::catch_unwind(AssertUnwindSafe(|| self.compute_with_progress(graph, progress)))
+            .map_err(|e| RunError::Failed(TaskError { stage: None, partition: None, message: panic_message(&*e) }))
+    }
+}
+
+/// Describes a task which panicked
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct TaskError {
+    /// Name of the stage the task belongs to, if known
+    pub stage: Option<String>,
+
+    /// Which partition of the stage the task computes, if it's one of many
+    pub partition: Option<usize>,
+
+    /// Message the task panicked with
+    pub message: String
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.stage, self.partition) {
+            (Some(stage), Some(part)) => write!(f, "task '{}' partition {} panicked: {}", stage, part, self.message),
+            (Some(stage), None)       => write!(f, "task '{}' panicked: {}", stage, self.message),
+            (None, _)                 => write!(f, "{}", self.message)
+        }
+    }
+}
+
+impl Error for TaskError {}
+
+/// Why a computation stopped before producing its value
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub enum RunError {
+    /// The computation was cancelled through its CancellationToken
+    Cancelled(Cancelled),
+
+    /// A task panicked, so the tasks depending on it couldn't run
+    Failed(TaskError)
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Cancelled(c) => fmt::Display::fmt(c, f),
+            RunError::Failed(e)    => fmt::Display::fmt(e, f)
+        }
+    }
+}
+
+impl Error for RunError {}
+
+/// Decides whether a failed task is run again, for tasks which fail transiently,
+/// like those reading from the network.  Only the failed task is retried: the
+/// outputs of the tasks it depends on are kept for it.  The default policy makes a
+/// single attempt.  Set with `GreedyScheduler::set_retry_policy`; LeveledScheduler
+/// never retries.
+/// ```
+/// use std::time::Duration;
+/// use tange::scheduler::{GreedyScheduler,RetryPolicy};
+///
+/// let mut scheduler = GreedyScheduler::new();
+/// scheduler.set_retry_policy(RetryPolicy::new(3)
+///     .with_backoff(Duration::from_millis(10))
+///     .retry_if(|e| e.message.contains("timed out")));
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    attempts: usize,
+    backoff: Duration,
+    retryable: Option<Retryable>
+}
+
+// Decides whether a task's error is worth retrying
+type Retryable = Arc<dyn Fn(&TaskError) -> bool + Send + Sync>;
+
+impl RetryPolicy {
+    /// Creates a policy running each task up to `max_attempts` times, retrying
+    /// immediately whatever the error
+    pub fn new(max_attempts: usize) -> Self {
+        RetryPolicy { attempts: max_attempts.max(1), backoff: Duration::from_secs(0), retryable: None }
+    }
+
+    /// Waits `backoff` before the first retry, doubling the wait for each retry
+    /// after that
+    pub fn with_backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Only retries tasks whose error `f` returns true for
+    pub fn retry_if<F: 'static + Send + Sync + Fn(&TaskError) -> bool>(mut self, f: F) -> Self {
+        self.retryable = Some(Arc::new(f));
+        self
+    }
+
+    /// Returns how many times a task is run before its error is returned
+    pub fn max_attempts(&self) -> usize {
+        self.attempts
+    }
+
+    // Whether a task which failed on its `attempt`th run should be run again
+    fn should_retry(&self, attempt: usize, e: &TaskError) -> bool {
+        attempt < self.attempts && self.retryable.as_ref().map(|f| f(e)).unwrap_or(true)
+    }
+
+    // How long to wait after the `attempt`th run before the next
+    fn delay(&self, attempt: usize) -> Duration {
+        self.backoff * 2u32.saturating_pow(attempt.saturating_sub(1) as u32)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy::new(1)
+    }
+}
+
+static GLOBAL_LIMIT_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Caps how many of the tasks tagged with it run at once, for stages which mustn't
+/// run wide, like those calling a rate limited service.  Tag tasks with
+/// `Deferred::with_limit`.  Clones share the cap, so tagging every partition of a
+/// stage with clones of one limit caps the stage as a whole, while other stages
+/// run as wide as the scheduler allows.  SerialScheduler runs a task at a time
+/// anyway, and the other schedulers hold tagged tasks back until they fit.
+/// ```
+/// use tange::deferred::{Deferred,tree_reduce};
+/// use tange::scheduler::{ConcurrencyLimit,GreedyScheduler};
+///
+/// let limit = ConcurrencyLimit::new(2);
+/// let calls: Vec<_> = (0..8usize)
+///     .map(|x| Deferred::lift(x, None).apply(|x| x * 2).with_limit(&limit))
+///     .collect();
+/// let total = tree_reduce(&calls, |x, y| x + y).unwrap();
+/// assert_eq!(total.run(&GreedyScheduler::new()), Some(56));
+/// ```
+#[derive(Clone,Debug,PartialEq,Eq)]
+pub struct ConcurrencyLimit {
+    id: usize,
+    max: usize
+}
+
+impl ConcurrencyLimit {
+    /// Creates a limit letting up to `max` tasks run at once; at least one always
+    /// can
+    pub fn new(max: usize) -> Self {
+        ConcurrencyLimit { id: GLOBAL_LIMIT_COUNT.fetch_add(1, Ordering::SeqCst), max: max.max(1) }
+    }
+
+    /// Returns the most tasks which run at once
+    pub fn max(&self) -> usize {
+        self.max
+    }
+}
+
+// Counts the running tasks under each concurrency limit, for holding back those
+// which would run too many at once
+#[derive(Default)]
+struct Throttle {
+    running: HashMap<usize, usize>
+}
+
+impl Throttle {
+    // Counts a chain under `limits` as running, unless one of them is reached, and
+    // returns whether it was
+    fn try_start(&mut self, limits: &[ConcurrencyLimit]) -> bool {
+        if limits.iter().any(|l| self.running.get(&l.id).cloned().unwrap_or(0) >= l.max) {
+            return false;
+        }
+        for l in limits {
+            *self.running.entry(l.id).or_insert(0) += 1;
+        }
+        true
+    }
+
+    // Counts a chain under `limits` as finished
+    fn finish(&mut self, limits: &[ConcurrencyLimit]) {
+        for l in limits {
+            if let Some(n) = self.running.get_mut(&l.id) {
+                *n -= 1;
+            }
+        }
+    }
+}
+
+/// Cancels a computation when tripped, from any thread, or once its deadline
+/// passes.  Clones share their state, so one can be handed to the scheduler while
+/// another is kept to cancel it.
+#[derive(Clone,Debug,Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>
+}
+
+impl CancellationToken {
+    /// Creates a token which isn't cancelled
+    pub fn new() -> Self {
+        CancellationToken::default()
+    }
+
+    /// Creates a token which cancels itself once `timeout` has passed
+    pub fn with_timeout(timeout: Duration) -> Self {
+        CancellationToken { deadline: Some(Instant::now() + timeout), ..CancellationToken::default() }
+    }
+
+    /// Cancels every computation using the token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the token has been cancelled, or its deadline has passed
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst) || self.deadline.is_some_and(|d| Instant::now() >= d)
+    }
+}
+
+/// Returned by a computation abandoned through its CancellationToken, with the
+/// number of its tasks which finished first.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct Cancelled {
+    /// Number of tasks which finished
+    pub completed: usize,
+
+    /// Number of tasks in the computation
+    pub total: usize
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Run cancelled after {} of {} tasks", self.completed, self.total)
+    }
+}
+
+impl Error for Cancelled {}
+
+/// Returned by a computation which didn't finish within its timeout, with the
+/// number of its tasks which did.
+#[derive(Clone,Copy,Debug,PartialEq,Eq)]
+pub struct TimeoutError {
+    /// Time the computation was allowed
+    pub timeout: Duration,
+
+    /// Number of tasks which finished
+    pub completed: usize,
+
+    /// Number of tasks in the computation
+    pub total: usize
+}
+
+impl TimeoutError {
+    /// Describes a computation with `timeout` which was cancelled when it passed
+    pub fn new(timeout: Duration, cancelled: Cancelled) -> Self {
+        TimeoutError { timeout, completed: cancelled.completed, total: cancelled.total }
+    }
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Run timed out after {:?} with {} of {} tasks finished", self.timeout, self.completed, self.total)
+    }
+}
+
+impl Error for TimeoutError {}
+
+/// Tracks how many of the tasks of a computation have finished.  Clones share
+/// their counts, so one can be polled from another thread while a scheduler
+/// updates the other.  The optional callback is called with the completed and
+/// total number of tasks each time one finishes, from the thread running the
+/// computation (or, with `RayonScheduler`, from the pool's threads) and with no
+/// scheduler locks held; it should be quick, since no new tasks are handed out
+/// while it runs.
+#[derive(Clone,Default)]
+pub struct Progress {
+    completed: Arc<AtomicUsize>,
+    total: Arc<AtomicUsize>,
+    callback: Option<Arc<dyn Fn(usize, usize) + Send + Sync>>
+}
+
+impl Progress {
+    /// Creates a Progress to be polled
+    pub fn new() -> Self {
+        Progress::default()
+    }
+
+    /// Creates a Progress calling `f` with the completed and total number of tasks
+    /// as each one finishes
+    pub fn with_callback<F: 'static + Send + Sync + Fn(usize, usize)>(f: F) -> Self {
+        Progress { callback: Some(Arc::new(f)), ..Progress::new() }
+    }
+
+    /// Returns the number of tasks finished so far
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of tasks in the computation, or zero if it hasn't started
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::SeqCst)
+    }
+
+    /// Starts counting a computation with `total` tasks
+    pub fn start(&self, total: usize) {
+        self.completed.store(0, Ordering::SeqCst);
+        self.total.store(total, Ordering::SeqCst);
+    }
+
+    /// Records that another task finished
+    pub fn tick(&self) {
+        let done = self.completed.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(ref f) = self.callback {
+            f(done, self.total());
+        }
+    }
+}
+
+// Called as a run starts or finishes
+type RunHook = Arc<dyn Fn() + Send + Sync>;
+
+// Called with the name and partition of each task as it finishes, how long it
+// took, and whether it succeeded
+type TaskEndHook = Arc<dyn Fn(&str, Option<usize>, Duration, bool) + Send + Sync>;
+
+// The functions registered with `SchedulerBuilder` to be called around a run and
+// each of its tasks.  Those called for tasks are called from the threads running
+// them.  A hook panicking is logged and otherwise ignored.
+#[derive(Clone,Default)]
+struct Hooks {
+    run_start: Vec<RunHook>,
+    task_start: Vec<TaskHook>,
+    task_end: Vec<TaskEndHook>,
+    run_end: Vec<RunHook>
+}
+
+impl Hooks {
+    fn run_start(&self) {
+        for f in self.run_start.iter() {
+            guard_hook("on_run_start", || f());
+        }
+    }
+
+    fn task_start(&self, graph: &DAG, handle: &Arc<Handle>) {
+        if let Some(&(ref name, part)) = graph.names.get(handle) {
+            for f in self.task_start.iter() {
+                guard_hook("on_task_start", || f(name, part));
+            }
+        }
+    }
+
+    fn task_end(&self, graph: &DAG, handle: &Arc<Handle>, elapsed: Duration, ok: bool) {
+        if let Some(&(ref name, part)) = graph.names.get(handle) {
+            for f in self.task_end.iter() {
+                guard_hook("on_task_end", || f(name, part, elapsed, ok));
+            }
+        }
+    }
+
+    fn run_end(&self) {
+        for f in self.run_end.iter() {
+            guard_hook("on_run_end", || f());
+        }
+    }
+
+    // Whether tasks need timing for the hooks
+    fn timed(&self) -> bool {
+        !self.task_end.is_empty()
+    }
+}
+
+// Calls a hook, logging rather than passing on its panic
+fn guard_hook<F: FnOnce()>(event: &str, f: F) {
+    if let Err(e) = panic::catch_unwind(AssertUnwindSafe(f)) {
+        warn!("{} hook panicked: {}", event, panic_message(&*e));
+    }
 }
 
 enum Limbo {
@@ -74,7 +494,25 @@ struct DAG {
     pub tasks: HashMap<Arc<Handle>, Arc<Task>>,
 
     /// Dependencies between tasks
-    pub dependencies: HashMap<Arc<Handle>, Option<FnArgs>>
+    pub dependencies: HashMap<Arc<Handle>, Option<FnArgs>>,
+
+    /// Name and partition of each task, for reporting failures
+    pub names: HashMap<Arc<Handle>, (String, Option<usize>)>,
+
+    /// Counts the output of the tasks that know how, for metrics
+    pub sizes: HashMap<Arc<Handle>, SizeFn>,
+
+    /// Receives the output of tasks which asked for it
+    pub taps: HashMap<Arc<Handle>, TapFn>,
+
+    /// Limits on how many tasks run at once, for tasks tagged with one
+    pub limits: HashMap<Arc<Handle>, ConcurrencyLimit>,
+
+    /// Estimated size of the output of tasks which have one, in bytes
+    pub bytes: HashMap<Arc<Handle>, usize>,
+
+    /// Tracks when each stage starts and finishes, if debug logging is enabled
+    pub stages: Option<Mutex<StageLog>>
  
 }
 
@@ -83,6 +521,11 @@ impl DAG {
     fn new(g: Arc<Graph>) -> Self {
         let mut tasks = HashMap::new();
         let mut dependencies = HashMap::new();
+        let mut names = HashMap::new();
+        let mut sizes = HashMap::new();
+        let mut taps = HashMap::new();
+        let mut limits = HashMap::new();
+        let mut bytes = HashMap::new();
 
         let mut stack = vec![g];
 
@@ -90,11 +533,40 @@ impl DAG {
 
         while !stack.is_empty() {
             trace!("Stack size: {}", stack.len());
-            let ag = stack.pop().unwrap();
+            let mut ag = stack.pop().unwrap();
             if !hs.contains(&ag.handle) {
+                // Cached steps computed by an earlier run don't need their arguments
+                if let Some(value) = ag.cached_value() {
+                    ag = ag.stored(value);
+                }
                 hs.insert(ag.handle.clone());
                 tasks.insert(ag.handle.clone(), ag.task.clone());
                 dependencies.insert(ag.handle.clone(), ag.args.clone());
+                names.insert(ag.handle.clone(), (ag.name.clone(), ag.part));
+                if let Some(ref size) = ag.size {
+                    sizes.insert(ag.handle.clone(), size.clone());
+                }
+                let tap = match (ag.tap.clone(), ag.cell.clone()) {
+                    (tap, Some(cell)) => {
+                        let keep: TapFn = Arc::new(move |v| {
+                            let _ = cell.set(v.clone());
+                            if let Some(ref tap) = tap {
+                                tap(v);
+                            }
+                        });
+                        Some(keep)
+                    },
+                    (tap, None) => tap
+                };
+                if let Some(tap) = tap {
+                    taps.insert(ag.handle.clone(), tap);
+                }
+                if let Some(ref limit) = ag.limit {
+                    limits.insert(ag.handle.clone(), limit.clone());
+                }
+                if let Some(b) = ag.bytes {
+                    bytes.insert(ag.handle.clone(), b);
+                }
                 if let Some(ref fns) = ag.args {
                     match fns {
                         FnArgs::Single(g) => stack.push(g.clone()),
@@ -106,9 +578,94 @@ impl DAG {
                 }
             }
         }
+        let stages = if log_enabled!(LDebug) {
+            Some(Mutex::new(StageLog::new(names.values().map(|n| n.0.as_str()))))
+        } else {
+            None
+        };
         DAG {
             tasks: tasks,
-            dependencies: dependencies
+            dependencies: dependencies,
+            names,
+            sizes,
+            taps,
+            limits,
+            bytes,
+            stages
+        }
+    }
+
+    // Estimates the bytes a chain's outputs take at once: the largest of its tasks',
+    // since each is dropped once the next has run
+    fn chain_bytes(&self, chain: &[Arc<Handle>]) -> usize {
+        chain.iter().filter_map(|h| self.bytes.get(h)).cloned().max().unwrap_or(0)
+    }
+
+    // Returns the limits of the tasks in a chain, each once
+    fn chain_limits(&self, chain: &[Arc<Handle>]) -> Vec<ConcurrencyLimit> {
+        let mut limits: Vec<ConcurrencyLimit> = Vec::new();
+        for limit in chain.iter().filter_map(|h| self.limits.get(h)) {
+            if !limits.contains(limit) {
+                limits.push(limit.clone());
+            }
+        }
+        limits
+    }
+
+    // Logs the start of the stage the task belongs to, if it's the stage's first
+    fn start_stage(&self, handle: &Arc<Handle>) {
+        if let (Some(stages), Some(name)) = (self.stages.as_ref(), self.names.get(handle)) {
+            stages.lock().unwrap().start(&name.0);
+        }
+    }
+
+    // Logs the end of the stage the task belongs to, if it's the stage's last
+    fn finish_stage(&self, handle: &Arc<Handle>) {
+        if let (Some(stages), Some(name)) = (self.stages.as_ref(), self.names.get(handle)) {
+            stages.lock().unwrap().finish(&name.0);
+        }
+    }
+}
+
+// Progress of a stage: all the tasks sharing a name
+struct StageState {
+    tasks: usize,
+    finished: usize,
+    started: Option<Instant>
+}
+
+// Logs each stage as its first task starts, and again once its last task is done
+struct StageLog {
+    stages: HashMap<String, StageState>
+}
+
+impl StageLog {
+    fn new<'a, I: Iterator<Item=&'a str>>(names: I) -> Self {
+        let mut stages = HashMap::new();
+        for name in names {
+            let e = stages.entry(name.to_owned())
+                .or_insert(StageState { tasks: 0, finished: 0, started: None });
+            e.tasks += 1;
+        }
+        StageLog { stages }
+    }
+
+    fn start(&mut self, name: &str) {
+        if let Some(stage) = self.stages.get_mut(name) {
+            if stage.started.is_none() {
+                stage.started = Some(Instant::now());
+                debug!("Stage '{}' started: {} tasks", name, stage.tasks);
+            }
+        }
+    }
+
+    fn finish(&mut self, name: &str) {
+        if let Some(stage) = self.stages.get_mut(name) {
+            stage.finished += 1;
+            if stage.finished == stage.tasks {
+                let elapsed = stage.started.map(|s| s.elapsed()).unwrap_or_default();
+                debug!("Stage '{}' finished: {} tasks in {:?}", name, stage.tasks, elapsed);
+            }
         }
     }
 }
@@ -224,8 +781,11 @@ fn generate_levels(collapsed: ChainGraph) -> Vec<Vec<Vec<Arc<Handle>>>> {
 fn run_task(
     graph: &DAG, 
     chain: &[Arc<Handle>], 
-    dsam: Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>> 
-) {
+    dsam: Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>>,
+    metrics: &Recorder,
+    retry: &RetryPolicy,
+    hooks: &Hooks
+) -> Result<(), TaskError> {
     // Pull out arguments from the datasource
     trace!("Reading dependencies for chain {:?}", chain[0]);
     let ot = graph.dependencies.get(&chain[0]);
@@ -240,28 +800,41 @@ fn run_task(
 
     for handle in chain {
         trace!("Processing handle: {:?}", handle);
-        let out = match graph.tasks.get(handle) {
-            Some(ref task) => {
-                let task_ref: &Task = &task;
-                match task_ref {
-                    Task::Input(ref input) => Some(input.read()),
-                    Task::Function(ref t) => {
-                        match largs {
-                            Some(Limbo::One(ref a)) => {
-                                t.eval(DynArgs::One(a))
-                            },
-                            Some(Limbo::Two(ref a, ref b)) => {
-                                t.eval(DynArgs::Two(a, b))
-                            },
-                            None => None
-                        }
+        let start = if metrics.is_some() || hooks.timed() { Some(Instant::now()) } else { None };
+        let elapsed = || start.map(|s| s.elapsed()).unwrap_or_default();
+        graph.start_stage(handle);
+        hooks.task_start(graph, handle);
+        let mut attempt = 1;
+        let out = loop {
+            let res = panic::catch_unwind(AssertUnwindSafe(|| eval_task(graph, handle, &largs)));
+            match res {
+                Ok(out) => break out,
+                Err(e)  => {
+                    let failure = task_failure(graph, handle, &*e);
+                    if !retry.should_retry(attempt, &failure) {
+                        error!("{}", failure);
+                        hooks.task_end(graph, handle, elapsed(), false);
+                        return Err(failure);
+                    }
+                    warn!("{}; retrying, attempt {} of {}", failure, attempt + 1, retry.max_attempts());
+                    if let Some(m) = metrics.as_ref() {
+                        m.lock().unwrap().record_retry(failure.stage.as_deref().unwrap_or(""));
                     }
+                    thread::sleep(retry.delay(attempt));
+                    attempt += 1;
                 }
-            },
-            None => None
+            }
         };
+        if let (Some(m), Some(start)) = (metrics.as_ref(), start) {
+            record_task(graph, handle, &out, start, m);
+        }
+        hooks.task_end(graph, handle, elapsed(), true);
+        graph.finish_stage(handle);
         if let Some(bass) = out {
-            largs = Some(Limbo::One(Arc::new(bass)));
+            if let Some(tap) = graph.taps.get(handle) {
+                tap(&bass);
+            }
+            largs = Some(Limbo::One(bass));
         }
     }
 
@@ -269,6 +842,83 @@ fn run_task(
         let mut ds = dsam.lock().unwrap();
         ds.insert(chain[chain.len() - 1].clone(), d);
     } 
+    Ok(())
+}
+
+// Runs a single task on the arguments read for it
+fn eval_task(graph: &DAG, handle: &Arc<Handle>, largs: &Option<Limbo>) -> Option<Arc<BASS>> {
+    match graph.tasks.get(handle) {
+        Some(ref task) => {
+            let task_ref: &Task = &task;
+            match task_ref {
+                Task::Input(ref input) => Some(Arc::new(input.read())),
+                Task::Stored(ref value) => Some(value.clone()),
+                Task::Function(ref t) => {
+                    match largs {
+                        Some(Limbo::One(ref a)) => {
+                            t.eval(DynArgs::One(a))
+                        },
+                        Some(Limbo::Two(ref a, ref b)) => {
+                            t.eval(DynArgs::Two(a, b))
+                        },
+                        None => None
+                    }.map(Arc::new)
+                },
+                // Other schedulers wait for the future on the task's thread
+                #[cfg(feature = "tokio")]
+                Task::Async(ref t) => {
+                    match largs {
+                        Some(Limbo::One(ref a))        => t.start(DynArgs::One(a)),
+                        Some(Limbo::Two(ref a, ref b)) => t.start(DynArgs::Two(a, b)),
+                        None                           => None
+                    }.map(|f| Arc::new(::futures::executor::block_on(f)))
+                }
+            }
+        },
+        None => None
+    }
+}
+
+// Adds the time a task took, and the size of its output, to the run's metrics
+fn record_task(graph: &DAG, handle: &Arc<Handle>, out: &Option<Arc<BASS>>, start: Instant, metrics: &Mutex<RunMetrics>) {
+    let elapsed = start.elapsed();
+    let elements = match (out, graph.sizes.get(handle)) {
+        (Some(bass), Some(size)) => size(bass),
+        _                        => None
+    };
+    let name = graph.names.get(handle).map(|n| n.0.as_str()).unwrap_or("");
+    metrics.lock().unwrap().record(name, elapsed, elements);
+}
+
+// Describes the panic of a task by its name, and its partition if known
+fn task_failure(graph: &DAG, handle: &Arc<Handle>, payload: &(dyn Any + Send)) -> TaskError {
+    let (stage, partition) = match graph.names.get(handle) {
+        Some(&(ref name, part)) => (Some(name.clone()), part),
+        None                    => (None, None)
+    };
+    TaskError { stage, partition, message: panic_message(payload) }
+}
+
+// Reads the message a panic was raised with
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload.downcast_ref::<String>().map(|s| s.as_str())
+        .or_else(|| payload.downcast_ref::<&str>().cloned())
+        .unwrap_or("Box<dyn Any>")
+        .into()
+}
+
+// Runs a chain of tasks, turning a panic from any of them into an error so the
+// scheduler can report it on the calling thread once its workers are idle.
+fn run_chain(
+    graph: &DAG, 
+    chain: &[Arc<Handle>], 
+    dsam: Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>>,
+    metrics: &Recorder,
+    retry: &RetryPolicy,
+    hooks: &Hooks
+) -> Result<(), TaskError> {
+    panic::catch_unwind(AssertUnwindSafe(|| run_task(graph, chain, dsam, metrics, retry, hooks)))
+        .unwrap_or_else(|e| Err(TaskError { stage: None, partition: None, message: panic_message(&*e) }))
 }
 
 // Finds chains of tasks that can be collapsed into a single task.  While this isn't
@@ -342,6 +992,54 @@ fn collapse_graph<K: Hash + Eq + Debug + Clone>(
     new_nodes
 }
 
+// Tasks run one after the other on the same thread
+type Chain = Vec<Arc<Handle>>;
+
+// A chain, its priority, and the chains it's still waiting on
+type Blocked = (Chain, usize, HashSet<Arc<Handle>>);
+
+// Chains waiting on others to finish, keyed by the first handle of each.  Along
+// with each is the number of chains it waited on to begin with, which
+// GreedyScheduler uses as its priority.
+struct Waiting {
+    chains: HashMap<Arc<Handle>, Blocked>,
+    outbound: DepGraph
+}
+
+impl Waiting {
+    fn new(outbound: DepGraph) -> Self {
+        Waiting { chains: HashMap::new(), outbound }
+    }
+
+    // Adds a chain which can't start until the chains ending in `deps` have
+    fn wait(&mut self, chain: Chain, deps: HashSet<Arc<Handle>>) {
+        self.chains.insert(chain[0].clone(), (chain, deps.len(), deps));
+    }
+
+    // Marks the chain ending with `handle` as finished, returning the chains which
+    // no longer wait on anything
+    fn finish(&mut self, handle: &Arc<Handle>) -> Vec<(Chain, usize)> {
+        let mut ready = Vec::new();
+        if let Some(out) = self.outbound.remove(handle) {
+            for head in out {
+                let done = match self.chains.get_mut(&head) {
+                    Some(entry) => {
+                        entry.2.remove(handle);
+                        entry.2.is_empty()
+                    },
+                    None => false
+                };
+                if done {
+                    if let Some((chain, priority, _)) = self.chains.remove(&head) {
+                        ready.push((chain, priority));
+                    }
+                }
+            }
+        }
+        ready
+    }
+}
+
 /// LeveledScheduler computes sets of mutually exclusive tasks that can be run
 /// concurrently.  Unlike GreedyScheduler, which will immediately consume the next
 /// available task regardless of level, LeveledScheduler will wait for an entire level
@@ -358,6 +1056,24 @@ impl Scheduler for LeveledScheduler{
         &self, 
         graph: Arc<Graph>
     ) -> Option<Arc<BASS>> {
+        self.compute_with_progress(graph, &Progress::new())
+    }
+
+    fn compute_with_progress(
+        &self, 
+        graph: Arc<Graph>,
+        progress: &Progress
+    ) -> Option<Arc<BASS>> {
+        self.try_compute(graph, progress, &CancellationToken::new())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_compute(
+        &self, 
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
         
         let out_handle = graph.handle.clone();
         let dag = Arc::new(DAG::new(graph));
@@ -368,6 +1084,7 @@ impl Scheduler for LeveledScheduler{
         let collapsed = collapse_graph(inbound);
 
         debug!("Number of Tasks to Run: {}", collapsed.len());
+        progress.start(collapsed.len());
         
         // Build the counts
         let mut counts: HashMap<Arc<Handle>,_> = HashMap::new();
@@ -389,44 +1106,426 @@ impl Scheduler for LeveledScheduler{
         let dsam = Arc::new(Mutex::new(raw_ds));
 
         for (i, level) in levels.into_iter().enumerate() {
-            let mut pool = JobPool::new(num_cpus::get());
+            if token.is_cancelled() {
+                debug!("Cancelled before level: {}", i);
+                return Err(RunError::Cancelled(Cancelled { completed: progress.completed(), total: progress.total() }));
+            }
+            let mut pool = WorkerPool::new(num_cpus::get());
+            let (tx, rx) = mpsc::channel();
+            let failed = Arc::new(AtomicBool::new(false));
+            let n_chains = level.len();
             debug!("Running level: {}", i);
-            for chain in level {
+            let start = |chain: Chain, limits: Vec<ConcurrencyLimit>| {
                 let g = dag.clone();
-                let c = chain.clone();
                 let d = dsam.clone();
-                pool.queue(move || { run_task(&g, &c, d); });
+                let thread_tx = tx.clone();
+                let t = token.clone();
+                let f = failed.clone();
+                pool.queue(move || { 
+                    // Chains still waiting for a thread are skipped once cancelled, or
+                    // once another has failed
+                    let res = if t.is_cancelled() || f.load(Ordering::SeqCst) { 
+                        None 
+                    } else { 
+                        Some(run_chain(&g, &chain, d, &None, &RetryPolicy::default(), &Hooks::default())) 
+                    };
+                    if let Some(Err(_)) = res {
+                        f.store(true, Ordering::SeqCst);
+                    }
+                    thread_tx.send((limits, res)).expect("Error sending thread!");
+                });
+            };
+
+            // Chains which would run too many tasks under a concurrency limit wait
+            // for some of those running to finish
+            let mut throttle = Throttle::default();
+            let mut held = Vec::new();
+            for chain in level {
+                let limits = dag.chain_limits(&chain);
+                if throttle.try_start(&limits) {
+                    start(chain, limits);
+                } else {
+                    held.push((chain, limits));
+                }
             }
 
-            // block until all are done
+            // block until all are done, reporting each as it finishes
+            let mut failure = None;
+            let mut skipped = false;
+            for _ in 0..n_chains {
+                let (limits, res) = rx.recv().unwrap();
+                match res {
+                    Some(Ok(())) => progress.tick(),
+                    Some(Err(e)) => if failure.is_none() { failure = Some(e) },
+                    None         => skipped = true
+                }
+                if !limits.is_empty() {
+                    throttle.finish(&limits);
+                    for (chain, limits) in std::mem::take(&mut held) {
+                        if throttle.try_start(&limits) {
+                            start(chain, limits);
+                        } else {
+                            held.push((chain, limits));
+                        }
+                    }
+                }
+            }
             pool.shutdown();
+
+            // Surface the first failure to the caller rather than running on without it
+            if let Some(e) = failure {
+                return Err(RunError::Failed(e));
+            }
+            if skipped {
+                debug!("Cancelled during level: {}", i);
+                return Err(RunError::Cancelled(Cancelled { completed: progress.completed(), total: progress.total() }));
+            }
         }
 
         debug!("Finished");
         let ret = {
             dsam.lock().unwrap().get(&out_handle)
         };
-        ret
+        Ok(ret)
+    }
+}
+
+/// SerialScheduler runs one task at a time on the calling thread, starting no
+/// threads at all.  Tasks run in the same order every time a graph is computed:
+/// each task's arguments, left before right, are computed before it.  Runs are
+/// reproducible and a panicking task has a plain single-threaded backtrace, which
+/// makes it the scheduler to reach for when debugging, in doctests, and in tests
+/// which need to be deterministic.
+/// ```
+/// use std::sync::{Arc,Mutex};
+/// use tange::deferred::Deferred;
+/// use tange::scheduler::SerialScheduler;
+///
+/// let visited = Arc::new(Mutex::new(Vec::new()));
+/// let v = visited.clone();
+/// let scheduler = SerialScheduler::new().on_task(move |name, _part| v.lock().unwrap().push(name.to_owned()));
+/// let a = Deferred::lift(1usize, "a".into());
+/// let b = Deferred::lift(2usize, "b".into()).apply(|x| x * 10).named("Times");
+/// assert_eq!(a.join(&b, |x, y| x + y).run(&scheduler), Some(21));
+/// assert_eq!(*visited.lock().unwrap(), vec!["a", "b", "Times", "Join"]);
+/// ```
+#[derive(Clone,Default)]
+pub struct SerialScheduler {
+    hook: Option<TaskHook>
+}
+
+// Called with the name and partition of each task as it starts
+type TaskHook = Arc<dyn Fn(&str, Option<usize>) + Send + Sync>;
+
+impl SerialScheduler {
+    /// Creates a SerialScheduler
+    pub fn new() -> Self {
+        SerialScheduler::default()
+    }
+
+    /// Calls `f` with the name and partition of each task just before it runs
+    pub fn on_task<F: 'static + Send + Sync + Fn(&str, Option<usize>)>(mut self, f: F) -> Self {
+        self.hook = Some(Arc::new(f));
+        self
+    }
+}
+
+// Orders the tasks of a DAG so each comes after its arguments, visiting left
+// arguments before right ones.
+fn serial_order(dag: &DAG, root: &Arc<Handle>) -> Vec<Arc<Handle>> {
+    let mut order = Vec::with_capacity(dag.tasks.len());
+    let mut seen = HashSet::new();
+    let mut stack = vec![(root.clone(), false)];
+    while let Some((handle, expanded)) = stack.pop() {
+        if expanded {
+            order.push(handle);
+            continue;
+        }
+        if !seen.insert(handle.clone()) {
+            continue;
+        }
+        stack.push((handle.clone(), true));
+        match dag.dependencies.get(&handle) {
+            Some(Some(FnArgs::Single(g)))    => stack.push((g.handle.clone(), false)),
+            Some(Some(FnArgs::Join(l, r)))   => {
+                stack.push((r.handle.clone(), false));
+                stack.push((l.handle.clone(), false));
+            },
+            _ => ()
+        }
+    }
+    order
+}
+
+impl Scheduler for SerialScheduler {
+    fn compute(&self, graph: Arc<Graph>) -> Option<Arc<BASS>> {
+        self.compute_with_progress(graph, &Progress::new())
+    }
+
+    fn compute_with_progress(&self, graph: Arc<Graph>, progress: &Progress) -> Option<Arc<BASS>> {
+        self.try_compute(graph, progress, &CancellationToken::new())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_compute(
+        &self,
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        let out_handle = graph.handle.clone();
+        let dag = DAG::new(graph);
+        let order = serial_order(&dag, &out_handle);
+        progress.start(order.len());
+
+        // Count every use of an output, so a task taking one output twice gets both
+        let mut counts: HashMap<Arc<Handle>, usize> = HashMap::new();
+        for args in dag.dependencies.values() {
+            match args {
+                Some(FnArgs::Single(g))  => *counts.entry(g.handle.clone()).or_insert(0) += 1,
+                Some(FnArgs::Join(l, r)) => {
+                    *counts.entry(l.handle.clone()).or_insert(0) += 1;
+                    *counts.entry(r.handle.clone()).or_insert(0) += 1;
+                },
+                None => ()
+            }
+        }
+        let dsam = Arc::new(Mutex::new(DataStore::new(HashMap::new(), counts)));
+
+        for (i, handle) in order.iter().enumerate() {
+            if token.is_cancelled() {
+                debug!("Cancelled after {}/{} of tasks", i, order.len());
+                return Err(RunError::Cancelled(Cancelled { completed: i, total: order.len() }));
+            }
+            if let (Some(hook), Some(name)) = (self.hook.as_ref(), dag.names.get(handle)) {
+                hook(&name.0, name.1);
+            }
+            run_chain(&dag, std::slice::from_ref(handle), dsam.clone(), &None, &RetryPolicy::default(), &Hooks::default())
+                .map_err(RunError::Failed)?;
+            progress.tick();
+        }
+        let ret = dsam.lock().unwrap().get(&out_handle);
+        Ok(ret)
     }
 }
 
 /// GreedyScheduler is the recommend scheduler for Tange-Core.  After computing the DAG
 /// from the Graph, it uses a priority heap to determine which task to execute next,
 /// biasing toward reduction.  That is, joins are preferred over an apply since it reduces
-/// the number of thunks by one.  Inputs are preferred last.
+/// the number of thunks by one.  Inputs are preferred last.  Among tasks of equal
+/// priority, those SerialScheduler would run first go first, so partitions tend to
+/// be read soon after they're computed.
 ///
-pub struct GreedyScheduler(usize);
+/// Workers pull tasks from a queue they share, and a worker finishing a task queues
+/// those it unblocks itself, so no worker sits idle while there's a task ready to
+/// run: a slow partition holds up only the tasks depending on it.
+pub struct GreedyScheduler {
+    threads: usize,
+    record_metrics: bool,
+    last_metrics: Mutex<Option<RunMetrics>>,
+    retry: RetryPolicy,
+    max_live_bytes: Option<usize>,
+    hooks: Hooks
+}
 
 impl GreedyScheduler {
 
     /// Creates a new GreedyScheduler with the default number of threads.
     pub fn new() -> Self {
-        GreedyScheduler(num_cpus::get())
+        GreedyScheduler::builder().build()
+    }
+
+    /// Starts configuring a GreedyScheduler, for setting several of its options
+    /// at once
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let scheduler = GreedyScheduler::builder().workers(4).record_metrics(true).build();
+    /// assert_eq!(scheduler.workers(), 4);
+    /// assert_eq!(Deferred::lift(2usize, None).apply(|x| x * 2).run(&scheduler), Some(4));
+    /// ```
+    pub fn builder() -> SchedulerBuilder {
+        SchedulerBuilder::new()
+    }
+
+    /// Returns the number of worker threads tasks run on
+    pub fn workers(&self) -> usize {
+        self.threads
     }
 
     /// Sets the number of threads to use.  By default, uses one thread per core.
     pub fn set_threads(&mut self, n_threads: usize) -> () {
-         self.0 = n_threads;
+         self.threads = n_threads.max(1);
+    }
+
+    /// Sets the policy deciding which failed tasks are run again.  By default tasks
+    /// aren't retried.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    /// Holds back tasks which would take the values computed but not yet read past
+    /// `bytes`, as estimated by `Deferred::size_hint_bytes`, until others have been
+    /// read.  Tasks without an estimate aren't counted, and one task always runs,
+    /// whatever the estimates, so a run never stalls.  By default there's no limit.
+    /// ```
+    /// use tange::deferred::{Deferred,tree_reduce};
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let mut scheduler = GreedyScheduler::new();
+    /// scheduler.set_max_live_bytes(32_000);
+    /// let parts: Vec<_> = (0..8usize)
+    ///     .map(|x| Deferred::lift(x, None).apply(|x| vec![*x; 1000]).size_hint_bytes(8000))
+    ///     .collect();
+    /// let all = tree_reduce(&parts, |x, y| x.iter().chain(y.iter()).cloned().collect()).unwrap();
+    /// assert_eq!(all.apply(|v| v.len()).run(&scheduler), Some(8000));
+    /// ```
+    pub fn set_max_live_bytes(&mut self, bytes: usize) {
+        self.max_live_bytes = Some(bytes);
+    }
+
+    /// Sets whether to time each task while running, so `last_run_metrics` can
+    /// report where the time went.  Off by default.
+    pub fn record_metrics(&mut self, enabled: bool) {
+        self.record_metrics = enabled;
+    }
+
+    /// Returns the metrics of the last run to finish, per stage, if they're being
+    /// recorded.
+    /// ```
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let mut scheduler = GreedyScheduler::new();
+    /// scheduler.record_metrics(true);
+    /// let def = Deferred::lift(2usize, None).apply(|x| x * 2).named("Double");
+    /// assert_eq!(def.run(&scheduler), Some(4));
+    /// let metrics = scheduler.last_run_metrics().unwrap();
+    /// assert_eq!(metrics.stage("Double").unwrap().tasks, 1);
+    /// println!("{}", metrics);
+    /// ```
+    pub fn last_run_metrics(&self) -> Option<RunMetrics> {
+        self.last_metrics.lock().unwrap().clone()
+    }
+}
+
+/// Configures and creates a GreedyScheduler, as returned by
+/// `GreedyScheduler::builder`.
+#[derive(Clone)]
+pub struct SchedulerBuilder {
+    workers: usize,
+    record_metrics: bool,
+    retry: RetryPolicy,
+    max_live_bytes: Option<usize>,
+    hooks: Hooks
+}
+
+impl SchedulerBuilder {
+    /// Starts with the defaults: one worker thread per logical CPU, no metrics,
+    /// and no retries
+    pub fn new() -> Self {
+        SchedulerBuilder {
+            workers: num_cpus::get(),
+            record_metrics: false,
+            retry: RetryPolicy::default(),
+            max_live_bytes: None,
+            hooks: Hooks::default()
+        }
+    }
+
+    /// Sets how many worker threads run tasks, and so how many tasks run at once.
+    /// Worker threads are named `tange-worker-0`, `tange-worker-1` and so on.  At
+    /// least one is always used.
+    pub fn workers(mut self, n: usize) -> Self {
+        self.workers = n.max(1);
+        self
+    }
+
+    /// Sets whether to record run metrics, as with `GreedyScheduler::record_metrics`
+    pub fn record_metrics(mut self, enabled: bool) -> Self {
+        self.record_metrics = enabled;
+        self
+    }
+
+    /// Sets the policy deciding which failed tasks are run again, as with
+    /// `GreedyScheduler::set_retry_policy`
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Sets the most bytes the values computed but not yet read take, as with
+    /// `GreedyScheduler::set_max_live_bytes`
+    pub fn max_live_bytes(mut self, bytes: usize) -> Self {
+        self.max_live_bytes = Some(bytes);
+        self
+    }
+
+    /// Calls `f` as each run starts, before any of its tasks.  Like the other hooks,
+    /// it may be registered several times, and a hook panicking is logged rather
+    /// than failing the run.
+    /// ```
+    /// use std::sync::{Arc,Mutex};
+    /// use tange::deferred::Deferred;
+    /// use tange::scheduler::GreedyScheduler;
+    ///
+    /// let events = Arc::new(Mutex::new(Vec::new()));
+    /// let (e1, e2, e3) = (events.clone(), events.clone(), events.clone());
+    /// let scheduler = GreedyScheduler::builder()
+    ///     .on_run_start(move || e1.lock().unwrap().push("start".to_owned()))
+    ///     .on_task_end(move |name, _part, _took, ok| e2.lock().unwrap().push(format!("{} {}", name, ok)))
+    ///     .on_run_end(move || e3.lock().unwrap().push("end".to_owned()))
+    ///     .build();
+    /// let def = Deferred::lift(2usize, "Two".into()).apply(|x| x * 2).named("Double");
+    /// assert_eq!(def.run(&scheduler), Some(4));
+    /// assert_eq!(*events.lock().unwrap(), vec!["start", "Two true", "Double true", "end"]);
+    /// ```
+    pub fn on_run_start<F: 'static + Send + Sync + Fn()>(mut self, f: F) -> Self {
+        self.hooks.run_start.push(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the name and partition of each task just before it runs, from
+    /// the worker thread running it.  Tasks being retried are started once.
+    pub fn on_task_start<F: 'static + Send + Sync + Fn(&str, Option<usize>)>(mut self, f: F) -> Self {
+        self.hooks.task_start.push(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` with the name and partition of each task once it's finished, from
+    /// the worker thread which ran it, along with how long it took, retries
+    /// included, and whether it succeeded
+    pub fn on_task_end<F>(mut self, f: F) -> Self
+        where F: 'static + Send + Sync + Fn(&str, Option<usize>, Duration, bool)
+    {
+        self.hooks.task_end.push(Arc::new(f));
+        self
+    }
+
+    /// Calls `f` as each run finishes, once its tasks have, whether it succeeded,
+    /// failed or was cancelled
+    pub fn on_run_end<F: 'static + Send + Sync + Fn()>(mut self, f: F) -> Self {
+        self.hooks.run_end.push(Arc::new(f));
+        self
+    }
+
+    /// Creates the GreedyScheduler
+    pub fn build(self) -> GreedyScheduler {
+        GreedyScheduler {
+            threads: self.workers,
+            record_metrics: self.record_metrics,
+            last_metrics: Mutex::new(None),
+            retry: self.retry,
+            max_live_bytes: self.max_live_bytes,
+            hooks: self.hooks
+        }
+    }
+}
+
+impl Default for SchedulerBuilder {
+    fn default() -> Self {
+        SchedulerBuilder::new()
     }
 }
 
@@ -436,6 +1535,24 @@ impl Scheduler for GreedyScheduler {
         &self, 
         graph: Arc<Graph>
     ) -> Option<Arc<BASS>> {
+        self.compute_with_progress(graph, &Progress::new())
+    }
+
+    fn compute_with_progress(
+        &self, 
+        graph: Arc<Graph>,
+        progress: &Progress
+    ) -> Option<Arc<BASS>> {
+        self.try_compute(graph, progress, &CancellationToken::new())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_compute(
+        &self, 
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
         
         let out_handle = graph.handle.clone();
 
@@ -444,33 +1561,38 @@ impl Scheduler for GreedyScheduler {
         
         debug!("Number of Tasks Specified: {}", dag.tasks.len());
 
-        let (inbound, mut outbound) = build_dep_graph(&dag);
+        let (inbound, outbound) = build_dep_graph(&dag);
 
         let collapsed = collapse_graph(inbound);
 
         let total_jobs = collapsed.len();
         debug!("Number of Tasks to Run: {}", total_jobs);
+        progress.start(total_jobs);
         
         // Build the counts
         let mut counts: HashMap<Arc<Handle>,_> = HashMap::new();
-        let mut queue = PriorityQueue::new();
-        for (chain, deps) in collapsed.iter() {
-            // Add the inputs
-            if deps.len() == 0 {
-                trace!("Adding intial chain: {:?}, Priority: {}", chain, 0usize);
-                queue.push(chain.clone(), 0usize);
-            }
-
+        let order: HashMap<_,_> = serial_order(&dag, &out_handle).into_iter()
+            .enumerate()
+            .map(|(i, h)| (h, i))
+            .collect();
+        let mut ready = PriorityQueue::new();
+        let mut waiting = Waiting::new(outbound);
+        for (chain, deps) in collapsed {
             for d in deps.iter() {
                 let e = counts.entry(d.clone()).or_insert(0usize);
                 *e += 1;
             }
-        }
 
-        // Make the graph a bit easier to work with
-        let mut head_map: HashMap<_,_> = collapsed.into_iter().map(|(chain, deps)| {
-            (chain[0].clone(), (chain, deps.len(), deps))
-        }).collect();
+            // Add the inputs
+            if deps.is_empty() {
+                let priority = chain_priority(&order, &chain, 0);
+                trace!("Adding intial chain: {:?}, Priority: {:?}", chain, priority);
+                ready.push(chain, priority);
+            } else {
+                trace!("Chain: {:?}, Deps: {:?}", chain, deps);
+                waiting.wait(chain, deps);
+            }
+        }
 
         // Load up the inputs
         let data: HashMap<Arc<Handle>,Arc<BASS>> = HashMap::new();
@@ -480,58 +1602,49 @@ impl Scheduler for GreedyScheduler {
         let dsam = Arc::new(Mutex::new(raw_ds));
 
         // Start the loop!
-
-        if log_enabled!(Trace) {
-            for (ref index, &(ref chain, ref _priority, ref deps)) in head_map.iter() {
-                trace!("Index: {:?}, Chain: {:?}, Deps: {:?}", index, chain, deps);
-            }
-        }
         debug!("Starting tasks...");
+        let metrics: Recorder = if self.record_metrics {
+            Some(Arc::new(Mutex::new(RunMetrics::default())))
+        } else {
+            None
+        };
+        let run = Arc::new(GreedyRun {
+            dag: dag.clone(),
+            dsam: dsam.clone(),
+            queue: Mutex::new(ReadyQueue {
+                ready,
+                held: Vec::new(),
+                waiting,
+                throttle: Throttle::default(),
+                live: LiveBytes::new(self.max_live_bytes),
+                running: 0,
+                stopped: false,
+                failure: None
+            }),
+            wake: Condvar::new(),
+            order,
+            metrics: metrics.clone(),
+            retry: self.retry.clone(),
+            hooks: self.hooks.clone(),
+            token: token.clone()
+        });
         let mut jobs_done = 0usize;
+        self.hooks.run_start();
         {
-            let mut pool = JobPool::new(self.0);
-            let mut free_threads = self.0;
+            let mut pool = WorkerPool::new(self.threads);
             let (tx, rx) = mpsc::channel();
-            loop {
-                // Queue up all free items
-                while free_threads > 0 && !queue.is_empty(){
-                    if let Some((chain, priority)) = queue.pop() {
-                        trace!("Training chain: {:?}, Priority: {}", chain, priority);
-                        let g = dag.clone();
-                        let c = chain.clone();
-                        let d = dsam.clone();
-                        let thread_tx = tx.clone();
-                        pool.queue(move || {
-                            run_task(&g, &c, d);
-                            thread_tx.send(c[c.len() - 1].clone())
-                                .expect("Error sending thread!");
-                        });
-                        free_threads -= 1;
-                    } 
-                }
+            for _ in 0..self.threads {
+                let (r, thread_tx) = (run.clone(), tx.clone());
+                pool.queue(move || r.work(&thread_tx));
+            }
+            drop(tx);
 
-                // Eat!
-                let handle = rx.recv().unwrap(); 
-                // Remove it as deps from remaining tasks
+            // Workers hand each other the chains they unblock, and report here
+            // once each finishes
+            for handle in rx {
                 trace!("{:?} finished", handle);
-                free_threads += 1;
-                if let Some(out) = outbound.remove(&handle) {
-                    for out_handle in out {
-                        trace!("Updating {:?}", out_handle);
-                        if let Some((chain, p, deps)) = head_map.get_mut(&out_handle) {
-                            trace!("Updating {:?}", out_handle);
-                            deps.remove(&handle);
-                            if deps.is_empty() {
-                                trace!("Adding new chain: {:?}, Priority: {}", chain, p);
-                                queue.push(chain.clone(), *p);
-                            } else {
-                                trace!("Remaining Deps: {:?}", deps);
-                            }
-                        }
-                    }
-                }
-
                 jobs_done += 1;
+                progress.tick();
                 if total_jobs > 10 && jobs_done % (total_jobs as f64 / 10.) as usize == 0 {
                     debug!("Finished {}/{} of jobs", jobs_done, total_jobs);
                     if log_enabled!(Trace) {
@@ -540,13 +1653,18 @@ impl Scheduler for GreedyScheduler {
                     }
 
                 }
-                // Are we done yet?
-                if free_threads == self.0 && queue.is_empty() {
-                    break
-                }
             }
             pool.shutdown();
         }
+        self.hooks.run_end();
+
+        // Running tasks have finished before the failure is handed to the caller
+        if let Some(e) = run.queue.lock().unwrap().failure.take() {
+            return Err(RunError::Failed(e));
+        }
+
+        let recorded = metrics.map(|m| m.lock().unwrap().clone());
+        *self.last_metrics.lock().unwrap() = recorded;
 
         if log_enabled!(Trace) {
             let ds = dsam.lock().unwrap();
@@ -556,11 +1674,179 @@ impl Scheduler for GreedyScheduler {
             }
         }
 
+        if jobs_done < total_jobs {
+            debug!("Cancelled after {}/{} of jobs", jobs_done, total_jobs);
+            return Err(RunError::Cancelled(Cancelled { completed: jobs_done, total: total_jobs }));
+        }
+
         debug!("Finished");
         let ret = {
             dsam.lock().unwrap().get(&out_handle)
         };
-        ret
+        Ok(ret)
+    }
+}
+
+// Chains ready to run, highest priority first, those held back by a concurrency
+// limit or the memory budget, and those still waiting on others
+struct ReadyQueue {
+    ready: PriorityQueue<Chain, Priority>,
+    held: Vec<(Chain, Priority)>,
+    waiting: Waiting,
+    throttle: Throttle,
+    live: LiveBytes,
+    running: usize,
+    stopped: bool,
+    failure: Option<TaskError>
+}
+
+// Estimates the bytes taken by the outputs of running chains, and of finished ones
+// whose outputs are yet to be read for the last time, for keeping them in budget
+struct LiveBytes {
+    budget: Option<usize>,
+    total: usize,
+    // Bytes reserved for the output of each chain, keyed by its last handle
+    outputs: HashMap<Arc<Handle>, usize>
+}
+
+impl LiveBytes {
+    fn new(budget: Option<usize>) -> Self {
+        LiveBytes { budget, total: 0, outputs: HashMap::new() }
+    }
+
+    // Whether a chain fits within the budget, taking into account the arguments
+    // it reads for the last time, which are released once it finishes.  One
+    // always fits while no others are running, so the run goes on.
+    fn fits(&self, dag: &DAG, chain: &[Arc<Handle>], ds: &DataStore<Arc<Handle>, Arc<BASS>>, running: usize) -> bool {
+        let budget = match self.budget {
+            Some(budget) => budget,
+            None         => return true
+        };
+        let freed: usize = chain_args(dag, chain).into_iter()
+            .filter(|h| ds.counts.get(*h) == Some(&1))
+            .filter_map(|h| self.outputs.get(h))
+            .sum();
+        running == 0 || self.total + dag.chain_bytes(chain) <= budget + freed
+    }
+
+    fn reserve(&mut self, handle: &Arc<Handle>, bytes: usize) {
+        if self.budget.is_some() && bytes > 0 {
+            self.total += bytes;
+            self.outputs.insert(handle.clone(), bytes);
+        }
+    }
+
+    // Releases the arguments a finished chain read for the last time
+    fn finish(&mut self, dag: &DAG, chain: &[Arc<Handle>], ds: &DataStore<Arc<Handle>, Arc<BASS>>) {
+        for h in chain_args(dag, chain) {
+            if !ds.data.contains_key(h) {
+                if let Some(bytes) = self.outputs.remove(h) {
+                    self.total -= bytes;
+                }
+            }
+        }
+    }
+}
+
+// Returns the handles of the arguments a chain reads
+fn chain_args<'a>(dag: &'a DAG, chain: &[Arc<Handle>]) -> Vec<&'a Arc<Handle>> {
+    match dag.dependencies.get(&chain[0]) {
+        Some(Some(FnArgs::Single(g)))      => vec![&g.handle],
+        Some(Some(FnArgs::Join(g1, g2))) => vec![&g1.handle, &g2.handle],
+        _                                  => Vec::new()
+    }
+}
+
+// Chains waiting on more others run first, and then those finishing earliest in
+// `serial_order`, so partitions tend to be read soon after they're computed
+type Priority = (usize, Reverse<usize>);
+
+fn chain_priority(order: &HashMap<Arc<Handle>, usize>, chain: &[Arc<Handle>], deps: usize) -> Priority {
+    (deps, Reverse(order.get(&chain[chain.len() - 1]).cloned().unwrap_or(0)))
+}
+
+// State shared by GreedyScheduler's workers during one computation
+struct GreedyRun {
+    dag: Arc<DAG>,
+    dsam: Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>>,
+    queue: Mutex<ReadyQueue>,
+    wake: Condvar,
+    // Position of each task in `serial_order`
+    order: HashMap<Arc<Handle>, usize>,
+    metrics: Recorder,
+    retry: RetryPolicy,
+    hooks: Hooks,
+    token: CancellationToken
+}
+
+impl GreedyRun {
+    // Runs chains until there are none left, or the run is cancelled or fails.
+    // Chains unblocked by one finishing are queued straight away, so whichever
+    // worker is idle picks them up without waiting on the others.
+    fn work(&self, done: &mpsc::Sender<Arc<Handle>>) {
+        while let Some(chain) = self.next() {
+            trace!("Running chain: {:?}", chain);
+            let res = run_chain(&self.dag, &chain, self.dsam.clone(), &self.metrics, &self.retry, &self.hooks);
+            let last = chain[chain.len() - 1].clone();
+            {
+                let mut q = self.queue.lock().unwrap();
+                q.running -= 1;
+                q.throttle.finish(&self.dag.chain_limits(&chain));
+                q.live.finish(&self.dag, &chain, &self.dsam.lock().unwrap());
+
+                // Chains held back may fit now
+                let held: Vec<_> = q.held.drain(..).collect();
+                for (next, priority) in held {
+                    q.ready.push(next, priority);
+                }
+                match res {
+                    Ok(()) => {
+                        for (next, deps) in q.waiting.finish(&last) {
+                            let priority = chain_priority(&self.order, &next, deps);
+                            trace!("Adding new chain: {:?}, Priority: {:?}", next, priority);
+                            q.ready.push(next, priority);
+                        }
+                        let _ = done.send(last);
+                    },
+                    Err(e) => {
+                        if q.failure.is_none() {
+                            q.failure = Some(e);
+                        }
+                        q.stopped = true;
+                    }
+                }
+            }
+            self.wake.notify_all();
+        }
+        self.wake.notify_all();
+    }
+
+    // Waits for a chain to run, or returns None once there won't be any more
+    fn next(&self) -> Option<Chain> {
+        let mut q = self.queue.lock().unwrap();
+        loop {
+            if self.token.is_cancelled() {
+                q.stopped = true;
+            }
+            if q.stopped {
+                return None;
+            }
+            while let Some((chain, priority)) = q.ready.pop() {
+                let fits = q.live.fits(&self.dag, &chain, &self.dsam.lock().unwrap(), q.running);
+                if fits && q.throttle.try_start(&self.dag.chain_limits(&chain)) {
+                    q.live.reserve(&chain[chain.len() - 1], self.dag.chain_bytes(&chain));
+                    q.running += 1;
+                    return Some(chain);
+                }
+                q.held.push((chain, priority));
+            }
+            if q.running == 0 {
+                // Nothing running can unblock anything else
+                q.stopped = true;
+                return None;
+            }
+            q = self.wake.wait(q).unwrap();
+        }
     }
 }
 
@@ -638,3 +1924,128 @@ mod size_test {
     }
 
 }
+
+#[cfg(test)]
+mod log_test {
+    use super::*;
+    use std::panic::{catch_unwind,AssertUnwindSafe};
+    use log::{Log,Level,LevelFilter,Metadata,Record};
+    use deferred::{Deferred,tree_reduce};
+
+    // Keeps every record logged, from any test
+    struct Capture(Mutex<Vec<(Level, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _metadata: &Metadata) -> bool { true }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    static CAPTURE: Capture = Capture(Mutex::new(Vec::new()));
+
+    // Returns the records mentioning `word`, so concurrent tests don't interfere
+    fn records(word: &str) -> Vec<(Level, String)> {
+        CAPTURE.0.lock().unwrap().iter().filter(|r| r.1.contains(word)).cloned().collect()
+    }
+
+    #[test]
+    fn test_stage_logging() {
+        let _ = log::set_logger(&CAPTURE);
+        log::set_max_level(LevelFilter::Debug);
+
+        let parts: Vec<_> = (0..3usize).map(|i| {
+            Deferred::lift(i, None).apply(|x| x * 2).named_part("LogDouble", i)
+        }).collect();
+        let total = tree_reduce(&parts, |x, y| x + y).unwrap()
+            .apply(|x| x + 1).named("LogTotal");
+        assert_eq!(total.run(&GreedyScheduler::new()), Some(7));
+
+        let logged: Vec<_> = records("'Log").into_iter()
+            .map(|(level, msg)| (level, msg.split(" in ").next().unwrap().to_owned()))
+            .collect();
+        assert_eq!(logged, vec![
+            (Level::Debug, "Stage 'LogDouble' started: 3 tasks".to_owned()),
+            (Level::Debug, "Stage 'LogDouble' finished: 3 tasks".to_owned()),
+            (Level::Debug, "Stage 'LogTotal' started: 1 tasks".to_owned()),
+            (Level::Debug, "Stage 'LogTotal' finished: 1 tasks".to_owned())
+        ]);
+
+        let failing = Deferred::lift(4usize, None)
+            .apply(|x| -> usize { panic!("bad input: {}", x) })
+            .named_part("LogFail", 2);
+        assert!(catch_unwind(AssertUnwindSafe(|| failing.run(&LeveledScheduler))).is_err());
+        assert_eq!(records("'LogFail'").last().unwrap(),
+                   &(Level::Error, "task 'LogFail' partition 2 panicked: bad input: 4".to_owned()));
+    }
+}
+
+#[cfg(test)]
+mod hook_test {
+    use super::*;
+    use deferred::Deferred;
+
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    // Records every event of the scheduler's runs, in order
+    fn recording(events: &Events, builder: SchedulerBuilder) -> GreedyScheduler {
+        let (e1, e2, e3, e4) = (events.clone(), events.clone(), events.clone(), events.clone());
+        builder
+            .on_run_start(move || e1.lock().unwrap().push("run start".into()))
+            .on_task_start(move |name, part| e2.lock().unwrap().push(format!("start {} {:?}", name, part)))
+            .on_task_end(move |name, part, _took, ok| e3.lock().unwrap().push(format!("end {} {:?} {}", name, part, ok)))
+            .on_run_end(move || e4.lock().unwrap().push("run end".into()))
+            .build()
+    }
+
+    #[test]
+    fn test_hooks() {
+        let events: Events = Arc::new(Mutex::new(Vec::new()));
+        let s = recording(&events, GreedyScheduler::builder().workers(1));
+        let a = Deferred::lift(1usize, "A".into());
+        let b = Deferred::lift(2usize, "B".into()).apply(|x| x * 10).named_part("Times", 1);
+        assert_eq!(a.join(&b, |x, y| x + y).named("Sum").run(&s), Some(21));
+        assert_eq!(*events.lock().unwrap(), vec![
+            "run start",
+            "start A None", "end A None true",
+            "start B None", "end B None true",
+            "start Times Some(1)", "end Times Some(1) true",
+            "start Sum None", "end Sum None true",
+            "run end"
+        ]);
+
+        events.lock().unwrap().clear();
+        let failing = Deferred::lift(5usize, "C".into())
+            .apply(|x| -> usize { panic!("bad input: {}", x) })
+            .named("Check");
+        assert!(failing.try_run(&s).is_err());
+        assert_eq!(*events.lock().unwrap(), vec![
+            "run start",
+            "start C None", "end C None true",
+            "start Check None", "end Check None false",
+            "run end"
+        ]);
+    }
+
+    #[test]
+    fn test_panicking_hooks() {
+        let events: Events = Arc::new(Mutex::new(Vec::new()));
+        let builder = GreedyScheduler::builder()
+            .workers(4)
+            .on_run_start(|| panic!("no pool"))
+            .on_task_start(|_, _| panic!("no span"))
+            .on_task_end(|_, _, _, _| panic!("no metric"));
+        let s = recording(&events, builder);
+        let parts: Vec<_> = (0..8usize).map(|i| Deferred::lift(i, None).apply(|x| x + 1)).collect();
+        let total = ::deferred::tree_reduce(&parts, |x, y| x + y).unwrap();
+        assert_eq!(total.run(&s), Some(36));
+
+        let events = events.lock().unwrap();
+        assert_eq!(events.first().map(|e| e.as_str()), Some("run start"));
+        assert_eq!(events.last().map(|e| e.as_str()), Some("run end"));
+        assert_eq!(events.iter().filter(|e| e.ends_with(" true")).count(), 8 + 8 + 7);
+    }
+}
@@ -0,0 +1,265 @@
+//! Defines RayonScheduler, which runs tasks on a rayon thread pool.
+extern crate rayon;
+
+use std::collections::HashMap;
+use std::sync::{Arc,Mutex};
+use std::sync::atomic::{AtomicBool,Ordering};
+
+use self::rayon::{Scope,ThreadPool};
+
+use task::BASS;
+use graph::{Graph,Handle};
+use super::{Chain,DAG,DataStore,Throttle,Waiting,Scheduler,Progress,CancellationToken,Cancelled,RunError,TaskError,RetryPolicy,Hooks};
+use super::{build_dep_graph,collapse_graph,run_chain};
+
+/// RayonScheduler runs tasks on rayon's global thread pool, or on a pool it's
+/// given, rather than starting threads of its own.  Applications already using
+/// rayon can share its threads with tange instead of running more threads than
+/// there are cores.  Tasks are handed to the pool as soon as the tasks they
+/// depend on have finished, and whichever thread calls `compute` helps run them
+/// if it belongs to the pool.  Progress callbacks are called from the pool's
+/// threads.
+///
+/// ```
+/// use tange::deferred::Deferred;
+/// use tange::scheduler::RayonScheduler;
+///
+/// let a = Deferred::lift(1usize, None);
+/// let b = a.apply(|x| x + 1);
+/// assert_eq!(a.join(&b, |x, y| x + y).run(&RayonScheduler::new()), Some(3));
+/// ```
+#[derive(Clone,Default)]
+pub struct RayonScheduler {
+    pool: Option<Arc<ThreadPool>>
+}
+
+impl RayonScheduler {
+    /// Creates a RayonScheduler running tasks on rayon's global pool
+    pub fn new() -> Self {
+        RayonScheduler { pool: None }
+    }
+
+    /// Creates a RayonScheduler running tasks on `pool`
+    pub fn with_pool(pool: Arc<ThreadPool>) -> Self {
+        RayonScheduler { pool: Some(pool) }
+    }
+}
+
+// State shared by the tasks of one computation
+struct Run<'a> {
+    dag: Arc<DAG>,
+    dsam: Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>>,
+    waiting: Mutex<Waiting>,
+    // Along with the running tasks under each limit, the chains held back until
+    // some of them finish
+    throttle: Mutex<(Throttle, Vec<Chain>)>,
+    failure: Mutex<Option<TaskError>>,
+    failed: AtomicBool,
+    progress: &'a Progress,
+    token: &'a CancellationToken
+}
+
+// Hands a chain to the pool, which then hands off the chains waiting on it, unless
+// it would run too many tasks under a concurrency limit
+fn dispatch<'s, 'a: 's>(scope: &Scope<'s>, run: &'s Run<'a>, chain: Chain) {
+    let limits = run.dag.chain_limits(&chain);
+    if !limits.is_empty() {
+        let mut throttle = run.throttle.lock().unwrap();
+        if !throttle.0.try_start(&limits) {
+            throttle.1.push(chain);
+            return;
+        }
+    }
+    scope.spawn(move |s| {
+        // Chains still waiting for a thread are skipped once cancelled, or once
+        // another has failed
+        if run.token.is_cancelled() || run.failed.load(Ordering::SeqCst) {
+            return;
+        }
+        let res = run_chain(&run.dag, &chain, run.dsam.clone(), &None, &RetryPolicy::default(), &Hooks::default());
+        if !limits.is_empty() {
+            // Chains held back may fit now
+            let held = {
+                let mut throttle = run.throttle.lock().unwrap();
+                throttle.0.finish(&limits);
+                std::mem::take(&mut throttle.1)
+            };
+            for next in held {
+                dispatch(s, run, next);
+            }
+        }
+        match res {
+            Ok(()) => {
+                let ready = run.waiting.lock().unwrap().finish(&chain[chain.len() - 1]);
+                run.progress.tick();
+                for (next, _) in ready {
+                    dispatch(s, run, next);
+                }
+            },
+            Err(e) => {
+                let mut failure = run.failure.lock().unwrap();
+                if failure.is_none() {
+                    *failure = Some(e);
+                }
+                run.failed.store(true, Ordering::SeqCst);
+            }
+        }
+    });
+}
+
+// Hands every chain which doesn't depend on another to the pool
+fn dispatch_all<'s, 'a: 's>(scope: &Scope<'s>, run: &'s Run<'a>, chains: Vec<Chain>) {
+    for chain in chains {
+        dispatch(scope, run, chain);
+    }
+}
+
+impl Scheduler for RayonScheduler {
+    fn compute(&self, graph: Arc<Graph>) -> Option<Arc<BASS>> {
+        self.compute_with_progress(graph, &Progress::new())
+    }
+
+    fn compute_with_progress(&self, graph: Arc<Graph>, progress: &Progress) -> Option<Arc<BASS>> {
+        self.try_compute(graph, progress, &CancellationToken::new())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_compute(
+        &self,
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        let out_handle = graph.handle.clone();
+        let dag = Arc::new(DAG::new(graph));
+        debug!("Number of Tasks Specified: {}", dag.tasks.len());
+
+        let (inbound, outbound) = build_dep_graph(&dag);
+        let collapsed = collapse_graph(inbound);
+        let total_jobs = collapsed.len();
+        debug!("Number of Tasks to Run: {}", total_jobs);
+        progress.start(total_jobs);
+
+        let mut counts: HashMap<Arc<Handle>, usize> = HashMap::new();
+        let mut initial = Vec::new();
+        let mut waiting = Waiting::new(outbound);
+        for (chain, deps) in collapsed {
+            for d in deps.iter() {
+                *counts.entry(d.clone()).or_insert(0) += 1;
+            }
+            if deps.is_empty() {
+                initial.push(chain);
+            } else {
+                waiting.wait(chain, deps);
+            }
+        }
+
+        let run = Run {
+            dag,
+            dsam: Arc::new(Mutex::new(DataStore::new(HashMap::new(), counts))),
+            waiting: Mutex::new(waiting),
+            throttle: Mutex::new((Throttle::default(), Vec::new())),
+            failure: Mutex::new(None),
+            failed: AtomicBool::new(false),
+            progress,
+            token
+        };
+        match self.pool {
+            Some(ref pool) => pool.scope(|s| dispatch_all(s, &run, initial)),
+            None           => rayon::scope(|s| dispatch_all(s, &run, initial))
+        }
+
+        if let Some(e) = run.failure.lock().unwrap().take() {
+            return Err(RunError::Failed(e));
+        }
+        let completed = progress.completed();
+        if completed < total_jobs {
+            debug!("Cancelled after {}/{} of jobs", completed, total_jobs);
+            return Err(RunError::Cancelled(Cancelled { completed, total: total_jobs }));
+        }
+        debug!("Finished");
+        let ret = run.dsam.lock().unwrap().get(&out_handle);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+mod rayon_test {
+    use super::*;
+    use self::rayon::ThreadPoolBuilder;
+    use self::rayon::prelude::*;
+    use deferred::{Deferred,tree_reduce};
+    use scheduler::GreedyScheduler;
+
+    fn pool(threads: usize) -> Arc<ThreadPool> {
+        Arc::new(ThreadPoolBuilder::new().num_threads(threads).build().unwrap())
+    }
+
+    fn summed(n: usize) -> Deferred<usize> {
+        let v: Vec<_> = (0..n).map(|x| Deferred::lift(x, None).apply(|x| x + 1)).collect();
+        tree_reduce(&v, |x, y| x + y).unwrap()
+    }
+
+    #[test]
+    fn test_tree_reduce() {
+        assert_eq!(summed(999).run(&RayonScheduler::new()), Some((1..1000).sum()));
+        assert_eq!(summed(999).run(&RayonScheduler::with_pool(pool(2))), Some((1..1000).sum()));
+        assert_eq!(Deferred::lift(3usize, None).run(&RayonScheduler::new()), Some(3));
+    }
+
+    #[test]
+    fn test_progress() {
+        let progress = Progress::new();
+        assert_eq!(summed(16).run_with_progress(&RayonScheduler::new(), &progress), Some(136));
+        assert_eq!(progress.completed(), progress.total());
+    }
+
+    #[test]
+    fn test_failure() {
+        let failing = Deferred::lift(5usize, None)
+            .apply(|x| -> usize { panic!("bad input: {}", x) })
+            .named_part("Check", 2);
+        let s = RayonScheduler::with_pool(pool(2));
+        match failing.join(&summed(8), |x, y| x + y).try_run(&s) {
+            Err(RunError::Failed(e)) => assert_eq!(e.message, "bad input: 5"),
+            other                    => panic!("expected a failure, got {:?}", other)
+        }
+        assert_eq!(summed(8).try_run(&s), Ok(Some(36)));
+
+        let token = CancellationToken::new();
+        token.cancel();
+        match summed(8).try_run_until(&s, &Progress::new(), &token) {
+            Err(RunError::Cancelled(c)) => assert_eq!(c.completed, 0),
+            other                       => panic!("expected a cancellation, got {:?}", other)
+        }
+    }
+
+    #[test]
+    fn test_limit() {
+        let most = ::deferred::def_test::check_limit(&RayonScheduler::with_pool(pool(6)));
+        assert!(most >= 3, "only {} unlimited tasks ran at once", most);
+    }
+
+    #[test]
+    fn test_interleaves_with_rayon() {
+        // Tasks use the pool they run on, while the pool runs unrelated work and
+        // starts several computations from its own threads
+        let p = pool(2);
+        let s = RayonScheduler::with_pool(p.clone());
+        let v: Vec<_> = (0..8usize).map(|x| {
+            Deferred::lift(x, None).apply(|x| (0..1000usize).into_par_iter().map(|y| y % (x + 1)).sum::<usize>())
+        }).collect();
+        let total = tree_reduce(&v, |x, y| x + y).unwrap();
+        let expected = total.run(&GreedyScheduler::new());
+        let results: Vec<_> = p.install(|| {
+            (0..6usize).into_par_iter().map(|i| {
+                let unrelated: usize = (0..10_000usize).into_par_iter().map(|y| y * i).sum();
+                (total.run(&s), unrelated)
+            }).collect()
+        });
+        for (i, (out, unrelated)) in results.into_iter().enumerate() {
+            assert_eq!(out, expected);
+            assert_eq!(unrelated, i * (0..10_000usize).sum::<usize>());
+        }
+    }
+}
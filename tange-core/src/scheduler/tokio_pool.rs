@@ -0,0 +1,327 @@
+//! Defines AsyncScheduler, which runs tasks on a tokio runtime.
+use std::collections::{HashMap,HashSet};
+use std::future::Future;
+use std::io;
+use std::panic::{self,AssertUnwindSafe};
+use std::sync::{Arc,Mutex,mpsc};
+
+use futures::FutureExt;
+use tokio::runtime::{Builder,Handle as Runtime,Runtime as OwnedRuntime};
+
+use task::{BASS,DynArgs};
+use graph::{Graph,Handle,Task};
+use super::{DAG,DataStore,Limbo,Throttle,Scheduler,Progress,CancellationToken,Cancelled,RunError,TaskError,RetryPolicy,Hooks};
+use super::{build_dep_graph,get_fnargs,run_chain,task_failure};
+
+type Outputs = Arc<Mutex<DataStore<Arc<Handle>, Arc<BASS>>>>;
+type Finished = mpsc::Sender<(Arc<Handle>, Result<(), TaskError>)>;
+
+/// AsyncScheduler runs tasks on a tokio runtime, for pipelines whose tasks spend
+/// most of their time waiting on IO.  Up to `concurrency` tasks run at once,
+/// however many cores there are: tasks from `Deferred::apply_async` run as
+/// futures, and the rest on the runtime's blocking threads.  Deferreds are
+/// evaluated from async code with `Deferred::run_async`, or from anywhere else
+/// with `run` as with any other scheduler.
+///
+/// ```
+/// use tange::deferred::Deferred;
+/// use tange::scheduler::AsyncScheduler;
+///
+/// let scheduler = AsyncScheduler::new(16).unwrap();
+/// let a = Deferred::lift(1usize, None);
+/// let b = a.apply(|x| x + 1);
+/// assert_eq!(a.join(&b, |x, y| x + y).run(&scheduler), Some(3));
+/// ```
+#[derive(Clone)]
+pub struct AsyncScheduler {
+    spawner: Spawner,
+
+    // Keeps a runtime the scheduler started running for as long as it's used
+    _runtime: Option<Arc<OwnedRuntime>>
+}
+
+// Hands tasks to a runtime.  Kept apart from the runtime itself, which can't be
+// dropped from its own threads.
+#[derive(Clone)]
+struct Spawner {
+    handle: Runtime,
+    concurrency: usize
+}
+
+impl AsyncScheduler {
+    /// Creates an AsyncScheduler running up to `concurrency` tasks at once on a
+    /// runtime of its own.  Returns an error if the runtime can't be started.
+    pub fn new(concurrency: usize) -> io::Result<Self> {
+        let runtime = Builder::new_multi_thread().enable_all().build()?;
+        let handle = runtime.handle().clone();
+        Ok(AsyncScheduler {
+            spawner: Spawner { handle, concurrency: concurrency.max(1) },
+            _runtime: Some(Arc::new(runtime))
+        })
+    }
+
+    /// Creates an AsyncScheduler running up to `concurrency` tasks at once on the
+    /// runtime behind `handle`, such as the one returned by
+    /// `tokio::runtime::Handle::current()`
+    pub fn with_handle(handle: Runtime, concurrency: usize) -> Self {
+        AsyncScheduler { spawner: Spawner { handle, concurrency: concurrency.max(1) }, _runtime: None }
+    }
+
+    /// Returns the most tasks run at once
+    pub fn concurrency(&self) -> usize {
+        self.spawner.concurrency
+    }
+
+    /// Computes the given Graph as with `try_compute`, without blocking the
+    /// calling thread.  The computation is driven from one of the runtime's
+    /// blocking threads.
+    pub fn compute_async(
+        &self,
+        graph: Arc<Graph>,
+        progress: Progress,
+        token: CancellationToken
+    ) -> impl Future<Output = Result<Option<Arc<BASS>>, RunError>> + Send {
+        let spawner = self.spawner.clone();
+        self.spawner.handle.spawn_blocking(move || spawner.compute(graph, &progress, &token))
+            .map(|joined| joined.unwrap_or_else(|e| {
+                Err(RunError::Failed(TaskError { stage: None, partition: None, message: e.to_string() }))
+            }))
+    }
+}
+
+impl Spawner {
+    // Starts a task, which reports to `done` once finished
+    fn start(&self, dag: &Arc<DAG>, handle: Arc<Handle>, dsam: &Outputs, done: Finished) {
+        if let Some(Task::Async(ref t)) = dag.tasks.get(&handle).map(|t| &**t) {
+            let largs = match dag.dependencies.get(&handle) {
+                Some(Some(args)) => get_fnargs(&mut dsam.lock().unwrap(), args),
+                _                => None
+            };
+            // Creating the future runs the task's function, which may panic too
+            let fut = panic::catch_unwind(AssertUnwindSafe(|| match largs {
+                Some(Limbo::One(ref a))        => t.start(DynArgs::One(a)),
+                Some(Limbo::Two(ref a, ref b)) => t.start(DynArgs::Two(a, b)),
+                None                           => None
+            }));
+            let fut = match fut {
+                Ok(fut) => fut,
+                Err(e)  => {
+                    let failure = task_failure(dag, &handle, &*e);
+                    let _ = done.send((handle, Err(failure)));
+                    return;
+                }
+            };
+            let (g2, h) = (dag.clone(), handle.clone());
+            let (g, d) = (dag.clone(), dsam.clone());
+            let finish = move |out: Result<Option<BASS>, TaskError>| {
+                let res = out.map(|out| if let Some(out) = out {
+                    let bass = Arc::new(out);
+                    if let Some(tap) = g.taps.get(&handle) {
+                        tap(&bass);
+                    }
+                    d.lock().unwrap().insert(handle.clone(), bass);
+                });
+                let _ = done.send((handle, res));
+            };
+            match fut {
+                Some(fut) => {
+                    self.handle.spawn(AssertUnwindSafe(fut).catch_unwind().map(move |out| {
+                        finish(out.map(Some).map_err(|e| task_failure(&g2, &h, &*e)))
+                    }));
+                },
+                None => finish(Ok(None))
+            }
+        } else {
+            let (g, d) = (dag.clone(), dsam.clone());
+            self.handle.spawn_blocking(move || {
+                let res = run_chain(&g, std::slice::from_ref(&handle), d, &None, &RetryPolicy::default(), &Hooks::default());
+                let _ = done.send((handle, res));
+            });
+        }
+    }
+
+    fn compute(
+        &self,
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        let out_handle = graph.handle.clone();
+        let dag = Arc::new(DAG::new(graph));
+        debug!("Number of Tasks Specified: {}", dag.tasks.len());
+
+        // Tasks aren't collapsed into chains, so that every future runs as one
+        let (inbound, mut outbound) = build_dep_graph(&dag);
+        let total_jobs = inbound.len();
+        progress.start(total_jobs);
+
+        let mut counts: HashMap<Arc<Handle>, usize> = HashMap::new();
+        let mut ready = Vec::new();
+        let mut waiting: HashMap<Arc<Handle>, HashSet<Arc<Handle>>> = HashMap::new();
+        for (handle, deps) in inbound {
+            for d in deps.iter() {
+                *counts.entry(d.clone()).or_insert(0) += 1;
+            }
+            if deps.is_empty() {
+                ready.push(handle);
+            } else {
+                waiting.insert(handle, deps);
+            }
+        }
+        let dsam: Outputs = Arc::new(Mutex::new(DataStore::new(HashMap::new(), counts)));
+
+        let (tx, rx) = mpsc::channel();
+        let mut throttle = Throttle::default();
+        let mut held = Vec::new();
+        let mut running = 0;
+        let mut jobs_done = 0;
+        let mut failure = None;
+        loop {
+            // Start whatever's ready, unless cancelled or a task has failed
+            // Tasks which would run too many under a concurrency limit are held
+            // back until one of those running finishes
+            while running < self.concurrency && failure.is_none() && !token.is_cancelled() {
+                match ready.pop() {
+                    Some(handle) => if throttle.try_start(&dag.chain_limits(std::slice::from_ref(&handle))) {
+                        self.start(&dag, handle, &dsam, tx.clone());
+                        running += 1;
+                    } else {
+                        held.push(handle);
+                    },
+                    None         => break
+                }
+            }
+            if running == 0 {
+                break
+            }
+
+            let (handle, res) = rx.recv().unwrap();
+            running -= 1;
+            let limits = dag.chain_limits(std::slice::from_ref(&handle));
+            if !limits.is_empty() {
+                throttle.finish(&limits);
+                ready.append(&mut held);
+            }
+            if let Err(e) = res {
+                // Let running tasks finish before handing the failure to the caller
+                failure = failure.or(Some(e));
+                continue
+            }
+            if let Some(out) = outbound.remove(&handle) {
+                for next in out {
+                    let unblocked = waiting.get_mut(&next).map(|deps| {
+                        deps.remove(&handle);
+                        deps.is_empty()
+                    }).unwrap_or(false);
+                    if unblocked {
+                        waiting.remove(&next);
+                        ready.push(next);
+                    }
+                }
+            }
+            jobs_done += 1;
+            progress.tick();
+        }
+
+        if let Some(e) = failure {
+            return Err(RunError::Failed(e));
+        }
+        if jobs_done < total_jobs {
+            debug!("Cancelled after {}/{} of jobs", jobs_done, total_jobs);
+            return Err(RunError::Cancelled(Cancelled { completed: jobs_done, total: total_jobs }));
+        }
+        let ret = dsam.lock().unwrap().get(&out_handle);
+        Ok(ret)
+    }
+}
+
+impl Scheduler for AsyncScheduler {
+    fn compute(&self, graph: Arc<Graph>) -> Option<Arc<BASS>> {
+        self.compute_with_progress(graph, &Progress::new())
+    }
+
+    fn compute_with_progress(&self, graph: Arc<Graph>, progress: &Progress) -> Option<Arc<BASS>> {
+        self.try_compute(graph, progress, &CancellationToken::new())
+            .unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Blocks the calling thread until the computation finishes, so it mustn't be
+    /// called from async code; use `compute_async` there instead.
+    fn try_compute(
+        &self,
+        graph: Arc<Graph>,
+        progress: &Progress,
+        token: &CancellationToken
+    ) -> Result<Option<Arc<BASS>>, RunError> {
+        self.spawner.compute(graph, progress, token)
+    }
+}
+
+#[cfg(test)]
+mod tokio_test {
+    use super::*;
+    use std::thread;
+    use std::time::{Duration,Instant};
+    use deferred::{Deferred,tree_reduce};
+
+    // Sums 0..n after passing each through `f`
+    fn summed<F: Fn(Deferred<usize>) -> Deferred<usize>>(n: usize, f: F) -> Deferred<usize> {
+        let v: Vec<_> = (0..n).map(|x| f(Deferred::lift(x, None))).collect();
+        tree_reduce(&v, |x, y| x + y).unwrap()
+    }
+
+    #[test]
+    fn test_blocking_concurrency() {
+        // 16 tasks of 50ms each, eight at a time, take two rounds whatever the
+        // number of cores
+        let def = summed(16, |d| d.apply(|x| { thread::sleep(Duration::from_millis(50)); x * 2 }));
+        let scheduler = AsyncScheduler::new(8).unwrap();
+        let start = Instant::now();
+        assert_eq!(def.run(&scheduler), Some(240));
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(100), "took {:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(400), "took {:?}", elapsed);
+    }
+
+    #[test]
+    fn test_async_tasks() {
+        let def = summed(32, |d| d.apply_async(|x| {
+            let x = *x;
+            tokio::time::sleep(Duration::from_millis(50)).map(move |_| x * 2)
+        }));
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let scheduler = AsyncScheduler::with_handle(runtime.handle().clone(), 32);
+        let start = Instant::now();
+        assert_eq!(runtime.block_on(def.run_async(&scheduler)), Some(992));
+        assert!(start.elapsed() < Duration::from_millis(800), "took {:?}", start.elapsed());
+
+        // Other schedulers wait for futures which don't need a runtime
+        let ready = summed(4, |d| d.apply_async(|x| std::future::ready(x + 1)));
+        assert_eq!(ready.run(&::scheduler::LeveledScheduler), Some(10));
+    }
+
+    #[test]
+    fn test_limit() {
+        let most = ::deferred::def_test::check_limit(&AsyncScheduler::new(6).unwrap());
+        assert!(most >= 3, "only {} unlimited tasks ran at once", most);
+    }
+
+    #[test]
+    fn test_failure() {
+        let scheduler = AsyncScheduler::new(4).unwrap();
+        let failing = Deferred::lift(5usize, None)
+            .apply_async(|x| -> std::future::Ready<usize> { panic!("bad input: {}", x) })
+            .named_part("Check", 2);
+        match failing.join(&summed(4, |d| d), |x, y| x + y).try_run(&scheduler) {
+            Err(RunError::Failed(e)) => assert_eq!(e.message, "bad input: 5"),
+            other                    => panic!("expected a failure, got {:?}", other)
+        }
+
+        let failing = Deferred::lift(5usize, None).apply(|x| -> usize { panic!("bad input: {}", x) });
+        match failing.try_run(&scheduler) {
+            Err(RunError::Failed(e)) => assert_eq!(e.message, "bad input: 5"),
+            other                    => panic!("expected a failure, got {:?}", other)
+        }
+        assert_eq!(summed(8, |d| d.apply(|x| x + 1)).try_run(&scheduler), Ok(Some(36)));
+    }
+}
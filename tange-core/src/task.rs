@@ -1,7 +1,25 @@
 use std::any::Any;
 use std::marker::PhantomData;
+use std::sync::{Arc,OnceLock};
+
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use futures::FutureExt;
 
 pub type BASS = Box<Any + Send + Sync>;
+
+/// Counts the elements within a task's output, if it knows how
+pub type SizeFn = Arc<dyn Fn(&BASS) -> Option<usize> + Send + Sync>;
+
+/// Receives a task's output as soon as it's computed
+pub type TapFn = Arc<dyn Fn(&Arc<BASS>) + Send + Sync>;
+
+/// Holds a task's output once the first run computing it finishes
+pub type ResultCell = Arc<OnceLock<Arc<BASS>>>;
+
 pub enum DynArgs<'a> {
     One(&'a BASS),
     Two(&'a BASS, &'a BASS)
@@ -60,3 +78,44 @@ impl <A: Any + Send + Sync, B: Any + Send + Sync, C: Any + Send + Sync, F: Send
 }
 
 
+
+/// The future an asynchronous task computes its output with
+#[cfg(feature = "tokio")]
+pub type BoxFuture = Pin<Box<dyn Future<Output = BASS> + Send>>;
+
+#[cfg(feature = "tokio")]
+pub trait DynAsync: Send + Sync {
+    fn start(&self, val: DynArgs) -> Option<BoxFuture>;
+}
+
+#[cfg(feature = "tokio")]
+pub struct AsyncFn<A,Fut,F: Fn(&A) -> Fut>(F,PhantomData<fn(&A) -> Fut>);
+
+#[cfg(feature = "tokio")]
+impl <A,Fut,F: Fn(&A) -> Fut> AsyncFn<A,Fut,F> {
+    pub fn new(f: F) -> Self {
+        AsyncFn(f, PhantomData)
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl <
+    A: Any + Send + Sync,
+    B: Any + Send + Sync,
+    Fut: 'static + Send + Future<Output = B>,
+    F: Send + Sync + Fn(&A) -> Fut
+> DynAsync for AsyncFn<A,Fut,F> {
+
+    fn start(&self, val: DynArgs) -> Option<BoxFuture> {
+        match val {
+            DynArgs::One(v) => v.downcast_ref::<A>().map(|a| {
+                let fut: BoxFuture = Box::pin(self.0(a).map(|b| {
+                    let bx: BASS = Box::new(b);
+                    bx
+                }));
+                fut
+            }),
+            _ => None
+        }
+    }
+}